@@ -0,0 +1,91 @@
+// Fills the document cache with thousands of documents and reports how much
+// heap it really holds. Run with `cargo bench --bench document_cache`.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+#[path = "../src/cache.rs"]
+#[allow(dead_code)]
+mod cache;
+
+use cache::LruCache;
+
+// Tracks live heap bytes so the numbers below are measured, not estimated
+struct CountingAllocator;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const MB: usize = 1024 * 1024;
+const CAPACITY_MB: usize = 100;
+const DOCUMENTS: usize = 5_000;
+
+#[derive(Clone)]
+struct Document {
+    id: String,
+    content: String,
+}
+
+fn document(index: usize) -> Document {
+    // 8-64 KB, roughly a short chapter
+    let size = 8 * 1024 + (index * 7919) % (56 * 1024);
+    Document {
+        id: format!("doc-{}", index),
+        content: "字".repeat(size / 3),
+    }
+}
+
+fn main() {
+    let baseline = LIVE_BYTES.load(Ordering::Relaxed);
+    let mut cache = LruCache::new(CAPACITY_MB * MB);
+    let mut peak = 0;
+
+    let started = Instant::now();
+    for index in 0..DOCUMENTS {
+        let document = document(index);
+        let size = document.id.len() + document.content.len() + std::mem::size_of::<Document>();
+        cache.insert(document.id.clone(), document, size);
+        // Re-read a small working set so it stays resident
+        let _ = cache.get(&format!("doc-{}", index % 50));
+        peak = peak.max(LIVE_BYTES.load(Ordering::Relaxed) - baseline);
+    }
+    let insert_time = started.elapsed();
+
+    let started = Instant::now();
+    let lookups = 100_000;
+    for index in 0..lookups {
+        let _ = cache.get(&format!("doc-{}", (index * 31) % DOCUMENTS));
+    }
+    let lookup_time = started.elapsed();
+
+    let stats = cache.stats();
+    let live = LIVE_BYTES.load(Ordering::Relaxed) - baseline;
+    println!("documents inserted: {}", DOCUMENTS);
+    println!("cache limit:        {} MB", CAPACITY_MB);
+    println!("cached documents:   {}", stats.entries);
+    println!("accounted bytes:    {:.1} MB", stats.bytes as f64 / MB as f64);
+    println!("live heap:          {:.1} MB", live as f64 / MB as f64);
+    println!("peak heap:          {:.1} MB", peak as f64 / MB as f64);
+    println!("evictions:          {}", stats.evictions);
+    println!("hits / misses:      {} / {}", stats.hits, stats.misses);
+    println!("insert:             {:.2} µs/doc", insert_time.as_secs_f64() * 1e6 / DOCUMENTS as f64);
+    println!("lookup:             {:.2} µs/op", lookup_time.as_secs_f64() * 1e6 / lookups as f64);
+
+    // Allow for map and index overhead on top of the accounted document sizes
+    assert!(stats.bytes <= CAPACITY_MB * MB);
+    assert!(peak <= CAPACITY_MB * MB + 8 * MB, "heap grew past the cache limit");
+}
@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::database::SemanticTerm;
+use crate::error::Result;
+use crate::search::MatchOffset;
+use crate::segment::{is_word_char, Segmenter, Token, TokenKind};
+
+// Term extraction. Candidates are runs of up to MAX_NGRAM tokens that do not
+// cross punctuation or a change of script; Chinese is segmented into words
+// first, so a candidate never starts or ends inside one. C-value favours
+// long, frequent phrases that also occur on their own rather than only
+// inside a longer one; TF-IDF against the rest of the library demotes
+// everyday words.
+
+// Cached analyses are stored under this prefix plus the content hash; bump
+// the version when the output changes so old results are recomputed
+pub const CACHE_KEY_PREFIX: &str = "terms:v2:";
+
+const MAX_NGRAM: usize = 4;
+const MIN_FREQUENCY: usize = 2;
+const MAX_TERMS: usize = 50;
+// Each term is stored with at most this many occurrences
+const MAX_OCCURRENCES: usize = 50;
+// Library-wide document frequency is looked up only for the best candidates
+const MAX_SCORED_CANDIDATES: usize = 200;
+const CONTEXT_CHARS: usize = 40;
+// Weight of C-value against TF-IDF in the confidence
+const C_VALUE_WEIGHT: f64 = 0.6;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "being", "but", "by", "can", "could", "did", "do", "does", "each", "for", "from", "had",
+    "has", "have", "he", "her", "his", "how", "i", "if", "in", "into", "is", "it", "its",
+    "may", "might", "more", "most", "no", "not", "of", "on", "one", "only", "or", "other",
+    "our", "she", "should", "so", "some", "such", "than", "that", "the", "their", "them",
+    "then", "there", "these", "they", "this", "those", "to", "up", "was", "we", "were",
+    "what", "when", "which", "who", "will", "with", "would", "you", "your",
+];
+
+// Particles and function characters; a Chinese word made only of these
+// never starts or ends a term
+const CJK_STOP_CHARS: &[char] = &[
+    '的', '了', '是', '在', '和', '与', '及', '或', '也', '就', '都', '而', '把', '被', '这',
+    '那', '个', '之', '为', '等', '着', '过', '对', '从', '将', '并', '其', '又', '我', '你',
+    '他', '她', '它', '们', '有', '一', '不', '中', '上', '下', '地', '得',
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TermOccurrence {
+    pub offset: MatchOffset,
+    pub context: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractedTerm {
+    pub term: String, // the most frequent spelling in the document
+    pub frequency: usize,
+    pub c_value: f64,
+    pub tf_idf: f64,
+    pub confidence: f64, // 0-1, relative to the best term in the document
+    pub occurrences: Vec<TermOccurrence>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentAnalysis {
+    pub document_id: String,
+    pub content_hash: String,
+    pub analyzed_at: DateTime<Utc>,
+    pub token_count: usize,
+    pub terms: Vec<ExtractedTerm>, // best first
+    #[serde(default)]
+    pub cached: bool, // served from analysis_cache without re-extracting
+}
+
+impl DocumentAnalysis {
+    // One row per stored occurrence, as kept in semantic_terms
+    pub fn semantic_terms(&self) -> Vec<SemanticTerm> {
+        self.terms
+            .iter()
+            .flat_map(|term| {
+                term.occurrences.iter().map(move |occurrence| SemanticTerm {
+                    id: Uuid::new_v4().to_string(),
+                    document_id: self.document_id.clone(),
+                    term: term.term.clone(),
+                    context: occurrence.context.clone(),
+                    position: occurrence.offset.char_start as i32,
+                    confidence: term.confidence,
+                    created_at: self.analyzed_at,
+                    offset: Some(occurrence.offset),
+                })
+            })
+            .collect()
+    }
+}
+
+// Whether two neighbouring tokens may belong to one term
+fn joinable(text: &str, previous: &Token, next: &Token) -> bool {
+    let gap = &text[previous.end..next.start];
+    match (previous.kind, next.kind) {
+        (TokenKind::Cjk, TokenKind::Cjk) => gap.is_empty(),
+        (TokenKind::Word, TokenKind::Word) => {
+            !gap.is_empty() && gap.chars().all(char::is_whitespace) && !gap.contains("\n\n")
+        }
+        _ => false,
+    }
+}
+
+fn is_stop_token(token: &Token) -> bool {
+    match token.kind {
+        TokenKind::Word => {
+            token.text.chars().all(|c| c.is_numeric())
+                || STOP_WORDS.contains(&token.text.to_lowercase().as_str())
+        }
+        TokenKind::Cjk => token.text.chars().all(|c| CJK_STOP_CHARS.contains(&c)),
+    }
+}
+
+struct Candidate {
+    parts: Vec<String>, // normalized tokens
+    occurrences: Vec<MatchOffset>,
+    spellings: HashMap<String, usize>,
+}
+
+impl Candidate {
+    fn key(parts: &[String], kind: TokenKind) -> String {
+        match kind {
+            TokenKind::Word => parts.join(" "),
+            TokenKind::Cjk => parts.concat(),
+        }
+    }
+
+    // Most frequent spelling; ties go to the one seen first
+    fn display(&self, text: &str) -> String {
+        let first = &self.occurrences[0];
+        let mut best = normalize_spaces(&text[first.start..first.end]);
+        let mut best_count = 0;
+        for occurrence in &self.occurrences {
+            let spelling = normalize_spaces(&text[occurrence.start..occurrence.end]);
+            let count = self.spellings.get(&spelling).copied().unwrap_or(0);
+            if count > best_count {
+                best = spelling;
+                best_count = count;
+            }
+        }
+        best
+    }
+}
+
+fn normalize_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn candidates(text: &str, tokens: &[Token]) -> HashMap<String, (TokenKind, Candidate)> {
+    let mut candidates: HashMap<String, (TokenKind, Candidate)> = HashMap::new();
+    for i in 0..tokens.len() {
+        let first = &tokens[i];
+        if is_stop_token(first) {
+            continue;
+        }
+        let mut parts = Vec::with_capacity(MAX_NGRAM);
+        for j in i..tokens.len().min(i + MAX_NGRAM) {
+            let last = &tokens[j];
+            if j > i && !joinable(text, &tokens[j - 1], last) {
+                break;
+            }
+            parts.push(last.text.to_lowercase());
+            if is_stop_token(last) {
+                continue;
+            }
+            // Single CJK characters and very short words are not terms
+            let too_short = match first.kind {
+                TokenKind::Cjk => last.char_end - first.char_start < 2,
+                TokenKind::Word => parts.len() == 1 && first.text.chars().count() < 3,
+            };
+            if too_short {
+                continue;
+            }
+
+            let key = Candidate::key(&parts, first.kind);
+            let (_, candidate) = candidates.entry(key).or_insert_with(|| {
+                (first.kind, Candidate {
+                    parts: parts.clone(),
+                    occurrences: Vec::new(),
+                    spellings: HashMap::new(),
+                })
+            });
+            candidate.occurrences.push(MatchOffset {
+                start: first.start,
+                end: last.end,
+                char_start: first.char_start,
+                char_end: last.char_end,
+            });
+            *candidate
+                .spellings
+                .entry(normalize_spaces(&text[first.start..last.end]))
+                .or_insert(0) += 1;
+        }
+    }
+    candidates
+}
+
+// C-value(a) = log2(|a| + 1) * (f(a) - mean f(b)) over the longer candidates
+// b that contain a; |a| + 1 keeps single words from scoring zero. Only
+// phrases frequent enough to be terms themselves count as containers, so a
+// fragment that never occurs outside one term scores zero.
+fn c_values(candidates: &HashMap<String, (TokenKind, Candidate)>) -> HashMap<&str, f64> {
+    let mut nested: HashMap<String, (usize, usize)> = HashMap::new(); // sum of f(b), count of b
+    for (kind, candidate) in candidates.values() {
+        let length = candidate.parts.len();
+        let frequency = candidate.occurrences.len();
+        if frequency < MIN_FREQUENCY {
+            continue;
+        }
+        let mut seen = HashSet::new();
+        for size in 1..length {
+            for start in 0..=length - size {
+                let key = Candidate::key(&candidate.parts[start..start + size], *kind);
+                if candidates.contains_key(&key) && seen.insert(key.clone()) {
+                    let entry = nested.entry(key).or_insert((0, 0));
+                    entry.0 += frequency;
+                    entry.1 += 1;
+                }
+            }
+        }
+    }
+
+    candidates
+        .iter()
+        .map(|(key, (_, candidate))| {
+            let weight = ((candidate.parts.len() + 1) as f64).log2();
+            let frequency = candidate.occurrences.len() as f64;
+            let independent = match nested.get(key) {
+                Some((sum, count)) => frequency - *sum as f64 / *count as f64,
+                None => frequency,
+            };
+            (key.as_str(), weight * independent)
+        })
+        .collect()
+}
+
+// About CONTEXT_CHARS characters on each side of `start..end`
+pub fn context_window(text: &str, start: usize, end: usize) -> String {
+    let mut from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT_CHARS - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let mut to = text[end..]
+        .char_indices()
+        .nth(CONTEXT_CHARS)
+        .map(|(i, _)| end + i)
+        .unwrap_or(text.len());
+
+    // Drop Latin words cut in half at either edge of the window
+    if text[..from].ends_with(is_word_char) {
+        if let Some(space) = text[from..start].find(char::is_whitespace) {
+            from += space;
+        }
+    }
+    if text[to..].starts_with(is_word_char) {
+        if let Some(space) = text[end..to].rfind(char::is_whitespace) {
+            to = end + space;
+        }
+    }
+    normalize_spaces(&text[from..to])
+}
+
+fn round(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+// `other_documents` is the library size without this document, and
+// `document_frequencies` counts, for each of the given terms, the other
+// documents that contain it. It is called once, so the library can be read
+// in a single pass.
+pub fn analyze<F>(
+    segmenter: &Segmenter,
+    document_id: &str,
+    content: &str,
+    content_hash: &str,
+    other_documents: usize,
+    document_frequencies: F,
+) -> Result<DocumentAnalysis>
+where
+    F: FnOnce(&[String]) -> Result<Vec<usize>>,
+{
+    let tokens = segmenter.tokenize(content);
+    let candidates = candidates(content, &tokens);
+    let c_values = c_values(&candidates);
+
+    let mut ranked: Vec<(&str, f64)> = c_values
+        .into_iter()
+        .filter(|(key, c_value)| *c_value > 0.0 && candidates[*key].1.occurrences.len() >= MIN_FREQUENCY)
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranked.truncate(MAX_SCORED_CANDIDATES);
+
+    let displayed: Vec<String> = ranked.iter().map(|(key, _)| candidates[*key].1.display(content)).collect();
+    let frequencies = document_frequencies(&displayed)?;
+
+    let token_count = tokens.len().max(1) as f64;
+    let mut scored = Vec::with_capacity(ranked.len());
+    for ((key, c_value), df) in ranked.into_iter().zip(frequencies) {
+        let (_, candidate) = &candidates[key];
+        let frequency = candidate.occurrences.len();
+        let idf = ((1 + other_documents) as f64 / (1 + df) as f64).ln() + 1.0;
+        let tf_idf = frequency as f64 / token_count * idf;
+        scored.push((key, frequency, c_value, tf_idf));
+    }
+
+    let max_c_value = scored.iter().map(|s| s.2).fold(0.0, f64::max);
+    let max_tf_idf = scored.iter().map(|s| s.3).fold(0.0, f64::max);
+    let mut terms: Vec<ExtractedTerm> = scored
+        .into_iter()
+        .map(|(key, frequency, c_value, tf_idf)| {
+            let (_, candidate) = &candidates[key];
+            let confidence = C_VALUE_WEIGHT * c_value / max_c_value
+                + (1.0 - C_VALUE_WEIGHT) * if max_tf_idf > 0.0 { tf_idf / max_tf_idf } else { 0.0 };
+            let occurrences = candidate
+                .occurrences
+                .iter()
+                .take(MAX_OCCURRENCES)
+                .map(|offset| TermOccurrence {
+                    context: context_window(content, offset.start, offset.end),
+                    offset: *offset,
+                })
+                .collect();
+            ExtractedTerm {
+                term: candidate.display(content),
+                frequency,
+                c_value: round(c_value),
+                tf_idf,
+                confidence: round(confidence.clamp(0.0, 1.0)),
+                occurrences,
+            }
+        })
+        .collect();
+    terms.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then_with(|| b.frequency.cmp(&a.frequency))
+            .then_with(|| a.term.cmp(&b.term))
+    });
+    terms.truncate(MAX_TERMS);
+
+    Ok(DocumentAnalysis {
+        document_id: document_id.to_string(),
+        content_hash: content_hash.to_string(),
+        analyzed_at: Utc::now(),
+        token_count: tokens.len(),
+        terms,
+        cached: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: &str = "Term extraction finds domain terms. Term extraction ranks phrases.";
+
+    #[test]
+    fn asks_for_all_document_frequencies_at_once() {
+        let mut calls = 0;
+        let analysis = analyze(&Segmenter::new(), "doc", CONTENT, "hash", 10, |terms| {
+            calls += 1;
+            assert!(terms.iter().any(|term| term == "Term extraction"));
+            Ok(vec![0; terms.len()])
+        })
+        .unwrap();
+        assert_eq!(calls, 1);
+        assert_eq!(analysis.document_id, "doc");
+        assert!(analysis.terms.iter().any(|term| term.term == "Term extraction" && term.frequency == 2));
+    }
+
+    #[test]
+    fn terms_common_in_the_library_score_lower() {
+        let tf_idf = |library_frequency: usize| {
+            let analysis = analyze(&Segmenter::new(), "doc", CONTENT, "hash", 10, |terms| {
+                Ok(terms
+                    .iter()
+                    .map(|term| if term == "Term extraction" { library_frequency } else { 0 })
+                    .collect())
+            })
+            .unwrap();
+            let term = analysis.terms.iter().find(|term| term.term == "Term extraction").unwrap();
+            term.tf_idf
+        };
+        assert!(tf_idf(10) < tf_idf(0));
+    }
+}
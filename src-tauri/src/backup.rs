@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::database::{AnalysisCache, Document, SemanticTerm, UNSORTED_COLLECTION_ID};
+
+pub const BACKUP_FORMAT: &str = "semantic-writing-assistant/document-backup";
+pub const BACKUP_VERSION: u32 = 1;
+pub const BACKUP_EXTENSION: &str = "backup.json";
+
+// Everything needed to bring a document back exactly as it was
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentBackup {
+    pub document: Document,
+    #[serde(default)]
+    pub semantic_terms: Vec<SemanticTerm>,
+    #[serde(default)]
+    pub analysis_cache: Vec<AnalysisCache>,
+}
+
+// The checksum covers the payload serialized with sorted keys, so it does
+// not depend on field order or formatting in the file.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFile {
+    format: String,
+    version: u32,
+    created_at: DateTime<Utc>,
+    checksum: String, // "sha256:<hex>"
+    payload: Value,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestoreOptions {
+    pub restore_original_id: bool, // otherwise the backup is restored as a new document
+}
+
+pub fn encode_backup(backup: &DocumentBackup) -> Result<String, String> {
+    let payload = serde_json::to_value(backup).map_err(|e| format!("Failed to serialize backup: {}", e))?;
+    let file = BackupFile {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        checksum: checksum(&payload)?,
+        payload,
+    };
+    serde_json::to_string_pretty(&file).map_err(|e| format!("Failed to serialize backup: {}", e))
+}
+
+pub fn decode_backup(text: &str) -> Result<DocumentBackup, String> {
+    let file: BackupFile = serde_json::from_str(text).map_err(|e| format!("Invalid backup file: {}", e))?;
+    if file.format != BACKUP_FORMAT {
+        return Err(format!("Not a document backup: {}", file.format));
+    }
+    if file.version > BACKUP_VERSION {
+        return Err(format!("Backup version {} is newer than this app supports", file.version));
+    }
+    if checksum(&file.payload)? != file.checksum {
+        return Err("Backup checksum does not match; the file is damaged or was edited".to_string());
+    }
+    serde_json::from_value(file.payload).map_err(|e| format!("Invalid backup contents: {}", e))
+}
+
+fn checksum(payload: &Value) -> Result<String, String> {
+    let bytes = serde_json::to_vec(payload).map_err(|e| format!("Failed to serialize backup: {}", e))?;
+    Ok(format!("sha256:{:x}", Sha256::digest(&bytes)))
+}
+
+// Reads the Markdown backups written by earlier versions: a fixed header,
+// a `---` separator, then the content verbatim
+pub fn decode_legacy_backup(text: &str, file_stem: &str) -> DocumentBackup {
+    const SEPARATOR: &str = "\n\n---\n\n";
+    let now = Utc::now();
+    let parse_time = |value: &str| {
+        NaiveDateTime::parse_from_str(value.trim(), "%Y-%m-%d %H:%M:%S UTC")
+            .ok()
+            .map(|time| time.and_utc())
+    };
+
+    let mut title = None;
+    let mut created_at = None;
+    let mut updated_at = None;
+    let mut content = text.to_string();
+    // Only the separator right after the header counts; the content itself
+    // may contain any number of rules
+    if let Some(header_end) = text.find("\nWord Count:") {
+        if let Some(offset) = text[header_end..].find(SEPARATOR) {
+            let header = &text[..header_end];
+            for line in header.lines() {
+                if let Some(value) = line.strip_prefix("# ") {
+                    title = Some(value.strip_suffix(" (Backup)").unwrap_or(value).to_string());
+                } else if let Some(value) = line.strip_prefix("Created: ") {
+                    created_at = parse_time(value);
+                } else if let Some(value) = line.strip_prefix("Last Modified: ") {
+                    updated_at = parse_time(value);
+                }
+            }
+            content = text[header_end + offset + SEPARATOR.len()..].to_string();
+        }
+    }
+
+    // Old backup names are "<title>_<YYYYmmdd>_<HHMMSS>.backup"
+    let fallback_title = || {
+        let stem = file_stem.trim_end_matches(".backup");
+        let parts: Vec<&str> = stem.rsplitn(3, '_').collect();
+        let name = if parts.len() == 3 { parts[2] } else { stem };
+        name.replace('_', " ")
+    };
+
+    DocumentBackup {
+        document: Document {
+            id: Uuid::new_v4().to_string(),
+            title: title.unwrap_or_else(fallback_title),
+            // Counted by storage when the backup is restored
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            content,
+            file_path: None,
+            created_at: created_at.unwrap_or(now),
+            updated_at: updated_at.unwrap_or(now),
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        },
+        semantic_terms: Vec::new(),
+        analysis_cache: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup(content: &str) -> DocumentBackup {
+        let mut backup = decode_legacy_backup(content, "draft");
+        backup.document.id = "doc-1".to_string();
+        backup
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let text = encode_backup(&backup("Some text\n\n---\n\nmore")).unwrap();
+        let decoded = decode_backup(&text).unwrap();
+        assert_eq!(decoded.document.id, "doc-1");
+        assert_eq!(decoded.document.content, "Some text\n\n---\n\nmore");
+    }
+
+    #[test]
+    fn rejects_edited_payload() {
+        let text = encode_backup(&backup("original")).unwrap();
+        let edited = text.replace("original", "changed");
+        assert_ne!(edited, text);
+        let error = decode_backup(&edited).unwrap_err();
+        assert!(error.contains("checksum"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_other_formats_and_newer_versions() {
+        let text = encode_backup(&backup("text")).unwrap();
+        let mut file: Value = serde_json::from_str(&text).unwrap();
+        file["format"] = Value::from("something-else");
+        assert!(decode_backup(&file.to_string()).unwrap_err().contains("Not a document backup"));
+
+        let mut file: Value = serde_json::from_str(&text).unwrap();
+        file["version"] = Value::from(BACKUP_VERSION + 1);
+        assert!(decode_backup(&file.to_string()).unwrap_err().contains("newer"));
+    }
+
+    #[test]
+    fn legacy_backup_keeps_rules_in_content() {
+        let text = "# Chapter (Backup)\n\n\
+                    Created: 2023-01-02 03:04:05 UTC\n\
+                    Last Modified: 2023-02-03 04:05:06 UTC\n\
+                    Word Count: 4\n\n---\n\n\
+                    Part one\n\n---\n\nPart two";
+        let backup = decode_legacy_backup(text, "Chapter_20230203_040506.backup");
+        assert_eq!(backup.document.title, "Chapter");
+        assert_eq!(backup.document.content, "Part one\n\n---\n\nPart two");
+        assert_eq!(backup.document.created_at.to_rfc3339(), "2023-01-02T03:04:05+00:00");
+        assert_eq!(backup.document.updated_at.to_rfc3339(), "2023-02-03T04:05:06+00:00");
+    }
+
+    #[test]
+    fn legacy_backup_without_header_keeps_everything() {
+        let text = "Just text\n\n---\n\nafter a rule";
+        let backup = decode_legacy_backup(text, "My_notes_20230203_040506.backup");
+        assert_eq!(backup.document.title, "My notes");
+        assert_eq!(backup.document.content, text);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+// Least-recently-used cache bounded by the total size of its values rather
+// than their number. Callers pass each value's size in bytes on insert.
+pub struct LruCache<V> {
+    capacity: usize, // bytes
+    entries: HashMap<String, CacheEntry<V>>,
+    order: BTreeMap<u64, String>, // last use -> key, oldest first
+    tick: u64,
+    generation: u64, // bumped by every insert, removal or clear
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+struct CacheEntry<V> {
+    value: V,
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub capacity_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            generation: 0,
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<V> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.order.remove(&entry.last_used);
+                self.order.insert(tick, key.to_string());
+                entry.last_used = tick;
+                self.hits += 1;
+                Some(entry.value.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // A value larger than the whole capacity is not cached at all
+    pub fn insert(&mut self, key: String, value: V, size: usize) {
+        self.remove(&key);
+        if size > self.capacity {
+            return;
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, CacheEntry { value, size, last_used: self.tick });
+        self.bytes += size;
+        self.evict_to_capacity();
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // For values read without holding off writers: caches the value only if
+    // nothing changed since `generation` was taken, so it cannot replace or
+    // resurrect a newer write
+    pub fn insert_if_unchanged(&mut self, key: String, value: V, size: usize, generation: u64) -> bool {
+        if self.generation != generation {
+            return false;
+        }
+        self.insert(key, value, size);
+        true
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        self.generation += 1;
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.last_used);
+        self.bytes -= entry.size;
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict_to_capacity();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            bytes: self.bytes,
+            capacity_bytes: self.capacity,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn evict_to_capacity(&mut self) {
+        while self.bytes > self.capacity {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.bytes -= entry.size;
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut cache = LruCache::new(10);
+        cache.insert("a".to_string(), 1, 4);
+        cache.insert("b".to_string(), 2, 4);
+        assert_eq!(cache.get("a"), Some(1)); // b is now the oldest
+        cache.insert("c".to_string(), 3, 4);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn tracks_bytes_across_replace_and_remove() {
+        let mut cache = LruCache::new(100);
+        cache.insert("a".to_string(), "x", 10);
+        cache.insert("b".to_string(), "y", 20);
+        cache.insert("a".to_string(), "z", 5);
+        assert_eq!(cache.stats().bytes, 25);
+        assert_eq!(cache.len(), 2);
+
+        assert_eq!(cache.remove("b"), Some("y"));
+        assert_eq!(cache.stats().bytes, 5);
+        cache.clear();
+        assert_eq!(cache.stats().bytes, 0);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn skips_values_larger_than_capacity() {
+        let mut cache = LruCache::new(10);
+        cache.insert("small".to_string(), 1, 5);
+        cache.insert("huge".to_string(), 2, 11);
+        assert_eq!(cache.get("huge"), None);
+        assert_eq!(cache.get("small"), Some(1));
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn shrinking_capacity_evicts() {
+        let mut cache = LruCache::new(30);
+        for (i, key) in ["a", "b", "c"].iter().enumerate() {
+            cache.insert(key.to_string(), i, 10);
+        }
+        cache.set_capacity(15);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("c"), Some(2));
+        assert_eq!(cache.stats().bytes, 10);
+    }
+
+    #[test]
+    fn insert_if_unchanged_refuses_stale_values() {
+        let mut cache = LruCache::new(100);
+        let generation = cache.generation();
+        cache.insert("doc".to_string(), "new", 3);
+        assert!(!cache.insert_if_unchanged("doc".to_string(), "old", 3, generation));
+        assert_eq!(cache.get("doc"), Some("new"));
+
+        let generation = cache.generation();
+        cache.remove("doc");
+        assert!(!cache.insert_if_unchanged("doc".to_string(), "old", 3, generation));
+        assert_eq!(cache.get("doc"), None);
+
+        let generation = cache.generation();
+        assert!(cache.insert_if_unchanged("doc".to_string(), "fresh", 5, generation));
+        assert_eq!(cache.get("doc"), Some("fresh"));
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = LruCache::new(10);
+        cache.insert("a".to_string(), 1, 1);
+        cache.get("a");
+        cache.get("a");
+        cache.get("b");
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+    }
+}
@@ -0,0 +1,509 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::context_window;
+use crate::database::{ConsistencyRule, RuleCategory, RuleScope};
+use crate::error::{AppError, Result};
+use crate::search::{self, is_cjk, MatchOffset};
+use crate::segment::{is_word_char, Segmenter, TokenKind};
+
+// Consistency checking. Each active rule lists alternative spellings of a
+// term, and may add a regex pattern; every place one of them appears is
+// reported together with the text the rule would replace it with.
+
+// Cached reports are stored under this prefix plus the content hash and a
+// hash of the rules and options they were checked against
+pub const CACHE_KEY_PREFIX: &str = "consistency:v2:";
+
+pub const MAX_PRIORITY: i32 = 1000;
+const MAX_PATTERN_CHARS: usize = 500;
+// Compiled size limit, so a pathological pattern fails on save instead of
+// slowing down every check
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool, // Latin matches may not start or end inside a word
+    pub cjk_boundaries: bool, // CJK matches must start and end between segmented words
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions {
+            case_sensitive: false,
+            whole_word: true,
+            cjk_boundaries: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning, // an alternative form of the term
+    Info,    // the preferred form written with different letter case
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsistencyIssue {
+    pub rule_id: String,
+    pub term: String,
+    pub found: String, // as written in the document
+    pub preferred_form: String,
+    pub replacement: String, // the preferred form, or the expanded pattern replacement
+    pub severity: Severity,
+    pub category: RuleCategory,
+    pub priority: i32,
+    pub offset: MatchOffset,
+    pub context: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    pub document_id: String,
+    pub content_hash: String,
+    pub checked_at: DateTime<Utc>,
+    pub rule_count: usize,
+    pub issues: Vec<ConsistencyIssue>, // in document order
+    #[serde(default)]
+    pub cached: bool, // served from analysis_cache without re-checking
+}
+
+fn compile(pattern: &str) -> std::result::Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).size_limit(PATTERN_SIZE_LIMIT).build()
+}
+
+// Checks a rule before it is saved. Whether a scoped collection or document
+// exists is left to the caller.
+pub fn validate(rule: &ConsistencyRule) -> Result<()> {
+    if rule.id.trim().is_empty() {
+        return Err(AppError::invalid("Rule id cannot be empty", "规则 ID 不能为空"));
+    }
+    if rule.term.trim().is_empty() {
+        return Err(AppError::invalid("Rule term cannot be empty", "规则术语不能为空"));
+    }
+    if rule.preferred_form.trim().is_empty() {
+        return Err(AppError::invalid("Preferred form cannot be empty", "首选形式不能为空"));
+    }
+
+    let mut seen = HashSet::new();
+    for alternative in &rule.alternatives {
+        if alternative.trim().is_empty() {
+            return Err(AppError::invalid("Alternatives cannot be empty", "替代形式不能为空"));
+        }
+        if *alternative == rule.preferred_form {
+            return Err(AppError::invalid(
+                format!("Alternative '{}' is the preferred form", alternative),
+                format!("替代形式“{}”与首选形式相同", alternative),
+            ));
+        }
+        if !seen.insert(alternative.as_str()) {
+            return Err(AppError::invalid(
+                format!("Alternative '{}' is listed twice", alternative),
+                format!("替代形式“{}”重复", alternative),
+            ));
+        }
+    }
+
+    match &rule.pattern {
+        Some(pattern) => validate_pattern(pattern, rule.replacement.as_deref())?,
+        None if rule.replacement.is_some() => {
+            return Err(AppError::invalid("A replacement needs a pattern", "替换模板需要配合正则表达式使用"));
+        }
+        None if rule.alternatives.is_empty() => {
+            return Err(AppError::invalid(
+                "A rule needs alternatives or a pattern",
+                "规则至少需要一个替代形式或正则表达式",
+            ));
+        }
+        None => {}
+    }
+
+    if let RuleScope::Collection(id) | RuleScope::Document(id) = &rule.scope {
+        if id.trim().is_empty() {
+            return Err(AppError::invalid("Rule scope needs an id", "规则范围缺少 ID"));
+        }
+    }
+    if !(-MAX_PRIORITY..=MAX_PRIORITY).contains(&rule.priority) {
+        return Err(AppError::invalid(
+            format!("Priority must be between {} and {}", -MAX_PRIORITY, MAX_PRIORITY),
+            format!("优先级必须在 {} 到 {} 之间", -MAX_PRIORITY, MAX_PRIORITY),
+        ));
+    }
+    Ok(())
+}
+
+fn validate_pattern(pattern: &str, replacement: Option<&str>) -> Result<()> {
+    if pattern.is_empty() {
+        return Err(AppError::invalid("Pattern cannot be empty", "正则表达式不能为空"));
+    }
+    if pattern.chars().count() > MAX_PATTERN_CHARS {
+        return Err(AppError::invalid(
+            format!("Pattern is longer than {} characters", MAX_PATTERN_CHARS),
+            format!("正则表达式超过 {} 个字符", MAX_PATTERN_CHARS),
+        ));
+    }
+    let regex = compile(pattern).map_err(|e| {
+        AppError::invalid(format!("Invalid pattern: {}", e), format!("正则表达式无效：{}", e))
+    })?;
+    if regex.is_match("") {
+        return Err(AppError::invalid("Pattern matches empty text", "正则表达式不能匹配空文本"));
+    }
+
+    for group in replacement.map(template_groups).unwrap_or_default() {
+        let exists = match group.parse::<usize>() {
+            Ok(index) => index < regex.captures_len(),
+            Err(_) => regex.capture_names().flatten().any(|name| name == group),
+        };
+        if !exists {
+            return Err(AppError::invalid(
+                format!("Replacement refers to missing group '{}'", group),
+                format!("替换模板引用了不存在的分组“{}”", group),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Group references in a replacement template, read the way
+// `Captures::expand` does: $$ is a literal dollar, ${name} is braced and
+// $name takes the longest run of letters, digits and underscores
+fn template_groups(template: &str) -> Vec<&str> {
+    let mut groups = Vec::new();
+    let mut rest = template;
+    while let Some(at) = rest.find('$') {
+        rest = &rest[at + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            if let Some(close) = braced.find('}') {
+                groups.push(&braced[..close]);
+                rest = &braced[close + 1..];
+            }
+        } else {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if length > 0 {
+                groups.push(&rest[..length]);
+                rest = &rest[length..];
+            }
+        }
+    }
+    groups
+}
+
+// Whether a rule with `scope` applies to `document_id`; `collections` holds
+// the document's collection and all of its ancestors
+pub fn in_scope(scope: &RuleScope, document_id: &str, collections: &HashSet<String>) -> bool {
+    match scope {
+        RuleScope::Global => true,
+        RuleScope::Collection(id) => collections.contains(id),
+        RuleScope::Document(id) => id == document_id,
+    }
+}
+
+fn find(text: &str, needle: &str, case_sensitive: bool) -> Vec<(usize, usize)> {
+    if case_sensitive {
+        text.match_indices(needle).map(|(start, found)| (start, start + found.len())).collect()
+    } else {
+        search::find_all(text, needle, false)
+    }
+}
+
+// Whether `start..end` may be reported under `options`; `boundaries` holds
+// the start and end of every segmented CJK word
+fn at_boundaries(text: &str, start: usize, end: usize, options: &CheckOptions, boundaries: &HashSet<usize>) -> bool {
+    let allowed = |inner: Option<char>, outer: Option<char>, at: usize| match inner {
+        Some(c) if is_cjk(c) => !options.cjk_boundaries || boundaries.contains(&at),
+        Some(c) if is_word_char(c) => !options.whole_word || !outer.is_some_and(is_word_char),
+        _ => true,
+    };
+    allowed(text[start..].chars().next(), text[..start].chars().next_back(), start)
+        && allowed(text[..end].chars().next_back(), text[end..].chars().next(), end)
+}
+
+pub fn check(
+    segmenter: &Segmenter,
+    document_id: &str,
+    content: &str,
+    content_hash: &str,
+    rules: &[ConsistencyRule],
+    options: CheckOptions,
+) -> ConsistencyReport {
+    let boundaries: HashSet<usize> = if options.cjk_boundaries {
+        segmenter
+            .tokenize(content)
+            .iter()
+            .filter(|token| token.kind == TokenKind::Cjk)
+            .flat_map(|token| [token.start, token.end])
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    let mut found: Vec<(usize, usize, String, &ConsistencyRule)> = Vec::new();
+    for rule in rules {
+        // Alternatives contained in the preferred form, e.g. "JS" in "JSX",
+        // are not reported where the preferred form itself is written
+        let preferred = find(content, &rule.preferred_form, true);
+        let reportable = |start: usize, end: usize, replacement: &str| {
+            start < end
+                && content[start..end] != *replacement
+                && !preferred.iter().any(|&(from, to)| from <= start && end <= to)
+                && at_boundaries(content, start, end, &options, &boundaries)
+        };
+
+        for alternative in &rule.alternatives {
+            if alternative.trim().is_empty() || *alternative == rule.preferred_form {
+                continue;
+            }
+            for (start, end) in find(content, alternative, options.case_sensitive) {
+                if reportable(start, end, &rule.preferred_form) {
+                    found.push((start, end, rule.preferred_form.clone(), rule));
+                }
+            }
+        }
+
+        // Patterns are matched as written; case_sensitive does not apply,
+        // since a pattern can set (?i) itself
+        let regex = match rule.pattern.as_deref().map(compile) {
+            Some(Ok(regex)) => regex,
+            Some(Err(e)) => {
+                eprintln!("Ignoring invalid pattern of consistency rule {}: {}", rule.id, e);
+                continue;
+            }
+            None => continue,
+        };
+        for captures in regex.captures_iter(content) {
+            let whole = captures.get(0).expect("group 0 is the whole match");
+            let mut replacement = String::new();
+            match &rule.replacement {
+                Some(template) => captures.expand(template, &mut replacement),
+                None => replacement.push_str(&rule.preferred_form),
+            }
+            if reportable(whole.start(), whole.end(), &replacement) {
+                found.push((whole.start(), whole.end(), replacement, rule));
+            }
+        }
+    }
+    // Two alternatives of a rule, or an alternative and the rule's pattern,
+    // can match the same text
+    found.sort_by(|a, b| (a.0, a.1, &a.3.id).cmp(&(b.0, b.1, &b.3.id)));
+    found.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1 && a.3.id == b.3.id);
+
+    // Character offsets are counted in one pass over the sorted matches
+    let mut issues = Vec::with_capacity(found.len());
+    let (mut byte, mut chars) = (0, 0);
+    for (start, end, replacement, rule) in found {
+        chars += content[byte..start].chars().count();
+        byte = start;
+        let text = &content[start..end];
+        issues.push(ConsistencyIssue {
+            rule_id: rule.id.clone(),
+            term: rule.term.clone(),
+            found: text.to_string(),
+            preferred_form: rule.preferred_form.clone(),
+            severity: if text.to_lowercase() == replacement.to_lowercase() {
+                Severity::Info
+            } else {
+                Severity::Warning
+            },
+            replacement,
+            category: rule.category,
+            priority: rule.priority,
+            offset: MatchOffset {
+                start,
+                end,
+                char_start: chars,
+                char_end: chars + text.chars().count(),
+            },
+            context: context_window(content, start, end),
+        });
+    }
+
+    ConsistencyReport {
+        document_id: document_id.to_string(),
+        content_hash: content_hash.to_string(),
+        checked_at: Utc::now(),
+        rule_count: rules.len(),
+        issues,
+        cached: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, preferred_form: &str, alternatives: &[&str]) -> ConsistencyRule {
+        ConsistencyRule {
+            id: id.to_string(),
+            term: preferred_form.to_string(),
+            preferred_form: preferred_form.to_string(),
+            alternatives: alternatives.iter().map(|a| a.to_string()).collect(),
+            pattern: None,
+            replacement: None,
+            scope: RuleScope::Global,
+            category: RuleCategory::Terminology,
+            priority: 0,
+            is_active: true,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn found(content: &str, rules: &[ConsistencyRule], options: CheckOptions) -> Vec<String> {
+        check(&Segmenter::new(), "doc", content, "hash", rules, options)
+            .issues
+            .into_iter()
+            .map(|issue| issue.found)
+            .collect()
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        let rules = [rule("js", "JavaScript", &["JS"])];
+        let content = "JS and js, but not JSON or ajs; js-based";
+        assert_eq!(found(content, &rules, CheckOptions::default()), vec!["JS", "js", "js"]);
+
+        let options = CheckOptions {
+            whole_word: false,
+            ..CheckOptions::default()
+        };
+        assert_eq!(found(content, &rules, options), vec!["JS", "js", "JS", "js", "js"]);
+    }
+
+    #[test]
+    fn respects_case_sensitivity() {
+        let rules = [rule("js", "JavaScript", &["JS"])];
+        let options = CheckOptions {
+            case_sensitive: true,
+            ..CheckOptions::default()
+        };
+        assert_eq!(found("JS and js", &rules, options), vec!["JS"]);
+    }
+
+    #[test]
+    fn matches_cjk_only_at_word_boundaries() {
+        let rules = [rule("ai", "AI", &["智能"])];
+        assert!(found("人工智能很好", &rules, CheckOptions::default()).is_empty());
+        assert_eq!(found("智能手表", &rules, CheckOptions::default()), vec!["智能"]);
+
+        let options = CheckOptions {
+            cjk_boundaries: false,
+            ..CheckOptions::default()
+        };
+        assert_eq!(found("人工智能很好", &rules, options), vec!["智能"]);
+    }
+
+    #[test]
+    fn skips_alternatives_inside_the_preferred_form() {
+        let rules = [rule("jsx", "JSX", &["JS"])];
+        let options = CheckOptions {
+            whole_word: false,
+            ..CheckOptions::default()
+        };
+        assert_eq!(found("JSX files and JS files", &rules, options), vec!["JS"]);
+    }
+
+    #[test]
+    fn reports_offsets_and_severity() {
+        let rules = [rule("gh", "GitHub", &["Github", "github"])];
+        let report = check(&Segmenter::new(), "doc", "中文 Github", "hash", &rules, CheckOptions::default());
+        assert_eq!(report.rule_count, 1);
+        let issues = &report.issues;
+        // Both alternatives match the same text case-insensitively
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Info);
+        assert_eq!(issues[0].replacement, "GitHub");
+        assert_eq!((issues[0].offset.start, issues[0].offset.end), (7, 13));
+        assert_eq!((issues[0].offset.char_start, issues[0].offset.char_end), (3, 9));
+    }
+
+    #[test]
+    fn applies_rule_scopes() {
+        let collections: HashSet<String> = ["drafts".to_string(), "root".to_string()].into_iter().collect();
+        assert!(in_scope(&RuleScope::Global, "doc", &collections));
+        assert!(in_scope(&RuleScope::Collection("root".to_string()), "doc", &collections));
+        assert!(!in_scope(&RuleScope::Collection("other".to_string()), "doc", &collections));
+        assert!(in_scope(&RuleScope::Document("doc".to_string()), "doc", &collections));
+        assert!(!in_scope(&RuleScope::Document("other".to_string()), "doc", &collections));
+    }
+
+    fn pattern_rule(pattern: &str, replacement: Option<&str>) -> ConsistencyRule {
+        let mut rule = rule("pattern", "color", &[]);
+        rule.pattern = Some(pattern.to_string());
+        rule.replacement = replacement.map(str::to_string);
+        rule
+    }
+
+    #[test]
+    fn reads_template_groups() {
+        assert_eq!(template_groups("$1-${name}x $word_2!"), vec!["1", "name", "word_2"]);
+        assert!(template_groups("$$1 costs $").is_empty());
+        assert!(template_groups("${unclosed").is_empty());
+    }
+
+    #[test]
+    fn validates_alternatives() {
+        assert!(validate(&rule("r", "GitHub", &["Github"])).is_ok());
+        assert!(validate(&rule("r", "GitHub", &[])).is_err());
+        assert!(validate(&rule("r", "GitHub", &["GitHub"])).is_err());
+        assert!(validate(&rule("r", "GitHub", &["Github", "Github"])).is_err());
+        assert!(validate(&rule("r", "GitHub", &[" "])).is_err());
+        assert!(validate(&rule(" ", "GitHub", &["Github"])).is_err());
+
+        let mut scoped = rule("r", "GitHub", &["Github"]);
+        scoped.scope = RuleScope::Collection(String::new());
+        assert!(validate(&scoped).is_err());
+
+        let mut urgent = rule("r", "GitHub", &["Github"]);
+        urgent.priority = MAX_PRIORITY + 1;
+        assert!(validate(&urgent).is_err());
+    }
+
+    #[test]
+    fn validates_patterns_and_replacements() {
+        assert!(validate(&pattern_rule(r"colou?r", None)).is_ok());
+        assert!(validate(&pattern_rule(r"(?P<word>\w+)our", Some("${word}or"))).is_ok());
+        assert!(validate(&pattern_rule(r"(\w+)our", Some("${1}or"))).is_ok());
+        assert!(validate(&pattern_rule(r"(\w+)our", Some("$1 or"))).is_ok());
+
+        assert!(validate(&pattern_rule("", None)).is_err());
+        assert!(validate(&pattern_rule("(unclosed", None)).is_err());
+        assert!(validate(&pattern_rule("a*", None)).is_err()); // matches empty text
+        assert!(validate(&pattern_rule(&"a".repeat(MAX_PATTERN_CHARS + 1), None)).is_err());
+        assert!(validate(&pattern_rule(r"(\w+)our", Some("${2}or"))).is_err());
+        // As in `Captures::expand`, this names the group "1or"
+        assert!(validate(&pattern_rule(r"(\w+)our", Some("$1or"))).is_err());
+        assert!(validate(&pattern_rule(r"(\w+)our", Some("${name}or"))).is_err());
+
+        let mut replacement_only = rule("r", "GitHub", &["Github"]);
+        replacement_only.replacement = Some("x".to_string());
+        assert!(validate(&replacement_only).is_err());
+    }
+
+    #[test]
+    fn expands_pattern_replacements() {
+        let rules = [pattern_rule(r"\b(?P<stem>\w+)our\b", Some("${stem}or"))];
+        let report = check(&Segmenter::new(), "doc", "The colour and flavour, colors", "hash", &rules, CheckOptions::default());
+        let fixes: Vec<(&str, &str)> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.found.as_str(), issue.replacement.as_str()))
+            .collect();
+        assert_eq!(fixes, vec![("colour", "color"), ("flavour", "flavor")]);
+    }
+
+    #[test]
+    fn reports_text_matched_twice_once() {
+        let mut both = rule("gh", "GitHub", &["Github"]);
+        both.pattern = Some("Git[Hh]ub".to_string());
+        assert_eq!(found("Github", &[both], CheckOptions::default()), vec!["Github"]);
+    }
+}
@@ -1,8 +1,35 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{params, params_from_iter, types::Value, Connection, DatabaseName, OpenFlags, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
+use std::time::Duration;
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, Entity, Result};
+use crate::migrations;
+use crate::search::{cjk_grams, is_cjk, IndexFilter, MatchOffset, SearchHit, SearchQuery};
+use crate::segment::TextCounts;
+
+// Selects the id of a collection and of all its descendants; the collection
+// id is bound to the single `?` parameter.
+macro_rules! collection_subtree_sql {
+    () => {
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT ?
+             UNION ALL
+             SELECT c.id FROM collections c JOIN subtree ON c.parent_id = subtree.id
+         )
+         SELECT id FROM subtree"
+    };
+}
+
+// Length of the plain-text excerpt in `DocumentSummary`, in characters
+const EXCERPT_CHARS: usize = 160;
+
+// Largest page `list_document_summaries` returns, whatever limit is asked for
+pub const MAX_PAGE_SIZE: usize = 500;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Document {
@@ -12,7 +39,250 @@ pub struct Document {
     pub file_path: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub word_count: i32, // CJK words plus Latin words
+    #[serde(default)]
+    pub char_count: i32, // not counting whitespace
+    #[serde(default)]
+    pub cjk_word_count: i32,
+    #[serde(default)]
+    pub latin_word_count: i32,
+    #[serde(default = "default_collection_id")]
+    pub collection_id: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, MetadataValue>,
+}
+
+// A user-defined metadata value, e.g. status, author, due date or target
+// word count. Serialized as `{ "type": "number", "value": 3000 }`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum MetadataValue {
+    Text(String),
+    Number(f64),
+    Boolean(bool),
+    Date(DateTime<Utc>),
+}
+
+impl MetadataValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            MetadataValue::Text(_) => "text",
+            MetadataValue::Number(_) => "number",
+            MetadataValue::Boolean(_) => "boolean",
+            MetadataValue::Date(_) => "date",
+        }
+    }
+
+    fn to_sql(&self) -> Value {
+        match self {
+            MetadataValue::Text(text) => Value::Text(text.clone()),
+            MetadataValue::Number(number) => Value::Real(*number),
+            MetadataValue::Boolean(flag) => Value::Integer(*flag as i64),
+            MetadataValue::Date(date) => Value::Text(date.to_rfc3339()),
+        }
+    }
+
+    fn from_sql(value_type: &str, value: Value) -> Option<Self> {
+        match (value_type, value) {
+            ("text", Value::Text(text)) => Some(MetadataValue::Text(text)),
+            ("number", Value::Real(number)) => Some(MetadataValue::Number(number)),
+            ("number", Value::Integer(number)) => Some(MetadataValue::Number(number as f64)),
+            ("boolean", Value::Integer(flag)) => Some(MetadataValue::Boolean(flag != 0)),
+            ("date", Value::Text(text)) => DateTime::parse_from_rfc3339(&text)
+                .ok()
+                .map(|date| MetadataValue::Date(date.with_timezone(&Utc))),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Contains,
+    Exists,
+    NotExists,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataPredicate {
+    pub key: String,
+    pub op: MetadataOp,
+    #[serde(default)]
+    pub value: Option<MetadataValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagInfo {
+    pub id: String,
+    pub name: String,
+    pub document_count: usize,
+}
+
+// Id of the built-in collection that holds documents not filed anywhere else
+pub const UNSORTED_COLLECTION_ID: &str = "unsorted";
+
+fn default_collection_id() -> String {
+    UNSORTED_COLLECTION_ID.to_string()
+}
+
+impl Document {
+    pub fn set_counts(&mut self, counts: TextCounts) {
+        self.word_count = counts.words();
+        self.char_count = counts.characters;
+        self.cjk_word_count = counts.cjk_words;
+        self.latin_word_count = counts.latin_words;
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Collection {
+    pub id: String,
+    pub name: String,
+    pub parent_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CollectionDeleteMode {
+    // Re-parent subcollections and documents to the deleted collection's parent
+    MoveToParent,
+    // Delete subcollections and every document inside them
+    Cascade,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionStats {
+    pub collection_id: String,
+    pub document_count: usize,
+    pub total_words: usize,
+    pub total_characters: usize,
+    pub subcollection_count: usize,
+}
+
+// Document metadata without the full content, for sidebar listings
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentSummary {
+    pub id: String,
+    pub title: String,
+    pub excerpt: String,
+    pub file_path: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub word_count: i32,
+    pub collection_id: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentSortField {
+    UpdatedAt,
+    CreatedAt,
+    Title,
+    WordCount,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DocumentListQuery {
+    pub offset: usize,
+    pub limit: usize,
+    pub sort_by: DocumentSortField,
+    pub sort_order: SortOrder,
+    pub title_prefix: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+    pub min_words: Option<i32>,
+    pub max_words: Option<i32>,
+    pub collection_id: Option<String>,
+    pub include_subcollections: bool,
+    pub all_tags: Vec<String>,     // documents must have every one of these
+    pub any_tags: Vec<String>,     // and at least one of these, if given
+    pub exclude_tags: Vec<String>, // and none of these
+    pub metadata: Vec<MetadataPredicate>,
+}
+
+impl Default for DocumentListQuery {
+    fn default() -> Self {
+        DocumentListQuery {
+            offset: 0,
+            limit: 50,
+            sort_by: DocumentSortField::UpdatedAt,
+            sort_order: SortOrder::Desc,
+            title_prefix: None,
+            created_after: None,
+            created_before: None,
+            updated_after: None,
+            updated_before: None,
+            min_words: None,
+            max_words: None,
+            collection_id: None,
+            include_subcollections: false,
+            all_tags: Vec::new(),
+            any_tags: Vec::new(),
+            exclude_tags: Vec::new(),
+            metadata: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentPage {
+    pub items: Vec<DocumentSummary>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentTotals {
+    pub total_documents: usize,
+    pub total_words: usize,
+    pub total_characters: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DocumentRevision {
+    pub id: String,
+    pub document_id: String,
+    pub revision_number: i32,
+    pub title: String,
+    pub content: String,
+    pub author: Option<String>,
+    pub word_count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+// Revision metadata without the content, for listing history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevisionSummary {
+    pub id: String,
+    pub document_id: String,
+    pub revision_number: i32,
+    pub title: String,
+    pub author: Option<String>,
     pub word_count: i32,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,18 +294,79 @@ pub struct SemanticTerm {
     pub position: i32,
     pub confidence: f64,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub offset: Option<MatchOffset>, // None for terms saved by older versions
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConsistencyRule {
     pub id: String,
     pub term: String,
     pub preferred_form: String,
-    pub alternatives: String, // JSON array of alternative forms
+    #[serde(default)]
+    pub alternatives: Vec<String>,
+    // Matched in addition to the alternatives. Each match is replaced by
+    // `replacement`, which may refer to groups as $1 or ${name}, or by the
+    // preferred form when there is no replacement.
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub replacement: Option<String>,
+    #[serde(default)]
+    pub scope: RuleScope,
+    #[serde(default)]
+    pub category: RuleCategory,
+    #[serde(default)]
+    pub priority: i32, // the higher one wins where the fixes of two rules overlap
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
 }
 
+// Which documents a rule applies to. A collection includes its
+// subcollections. Serialized as `{ "type": "collection", "id": "..." }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum RuleScope {
+    #[default]
+    Global,
+    Collection(String),
+    Document(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleCategory {
+    #[default]
+    Terminology,
+    Spelling,
+    Style,
+    Punctuation,
+    Other,
+}
+
+impl RuleCategory {
+    fn as_str(self) -> &'static str {
+        match self {
+            RuleCategory::Terminology => "terminology",
+            RuleCategory::Spelling => "spelling",
+            RuleCategory::Style => "style",
+            RuleCategory::Punctuation => "punctuation",
+            RuleCategory::Other => "other",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "terminology" => Some(RuleCategory::Terminology),
+            "spelling" => Some(RuleCategory::Spelling),
+            "style" => Some(RuleCategory::Style),
+            "punctuation" => Some(RuleCategory::Punctuation),
+            "other" => Some(RuleCategory::Other),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnalysisCache {
     pub id: String,
@@ -49,111 +380,216 @@ pub struct Database {
     conn: Connection,
 }
 
+// How long a connection waits on a lock held by another one before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 impl Database {
+    // Opens the read-write connection and brings the schema up to date. WAL
+    // lets readers on other connections run alongside it.
     pub fn new(db_path: &Path) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
+        let mut conn = Connection::open(db_path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        migrations::run(&mut conn)?;
         let db = Database { conn };
-        db.init_tables()?;
+        db.fill_in_documents()?;
         Ok(db)
     }
 
-    fn init_tables(&self) -> Result<()> {
-        // Documents table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS documents (
-                id TEXT PRIMARY KEY,
-                title TEXT NOT NULL,
-                content TEXT NOT NULL,
-                file_path TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                word_count INTEGER NOT NULL DEFAULT 0
-            )",
-            [],
-        )?;
-
-        // Semantic terms table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS semantic_terms (
-                id TEXT PRIMARY KEY,
-                document_id TEXT NOT NULL,
-                term TEXT NOT NULL,
-                context TEXT NOT NULL,
-                position INTEGER NOT NULL,
-                confidence REAL NOT NULL,
-                created_at TEXT NOT NULL,
-                FOREIGN KEY (document_id) REFERENCES documents (id)
-            )",
-            [],
-        )?;
-
-        // Consistency rules table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS consistency_rules (
-                id TEXT PRIMARY KEY,
-                term TEXT NOT NULL UNIQUE,
-                preferred_form TEXT NOT NULL,
-                alternatives TEXT NOT NULL,
-                is_active BOOLEAN NOT NULL DEFAULT 1,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        )?;
-
-        // Analysis cache table
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS analysis_cache (
-                id TEXT PRIMARY KEY,
-                document_id TEXT NOT NULL,
-                content_hash TEXT NOT NULL,
-                analysis_result TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                FOREIGN KEY (document_id) REFERENCES documents (id)
-            )",
-            [],
-        )?;
-
-        // Create indexes for better performance
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_documents_updated_at ON documents (updated_at)",
-            [],
-        )?;
-
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_semantic_terms_document_id ON semantic_terms (document_id)",
-            [],
-        )?;
+    // Read-only connection; any write through it fails
+    pub fn open_reader(db_path: &Path) -> Result<Self> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+        let conn = Connection::open_with_flags(db_path, flags)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(Database { conn })
+    }
 
-        self.conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_analysis_cache_document_id ON analysis_cache (document_id)",
-            [],
-        )?;
+    // Copies the live database to `path` with SQLite's online backup API,
+    // which stays consistent while the database is in use
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        Ok(self.conn.backup(DatabaseName::Main, path, None)?)
+    }
 
-        Ok(())
+    // Replaces the whole database with the one at `path`, then brings it up
+    // to the current schema
+    pub fn restore_from(&mut self, path: &Path) -> Result<()> {
+        self.conn.restore(DatabaseName::Main, path, None::<fn(rusqlite::backup::Progress)>)?;
+        migrations::run(&mut self.conn)?;
+        self.fill_in_documents()
     }
 
     // Document operations
     pub fn save_document(&self, document: &Document) -> Result<()> {
         self.conn.execute(
             "INSERT OR REPLACE INTO documents 
-             (id, title, content, file_path, created_at, updated_at, word_count)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+             (id, title, content, file_path, created_at, updated_at, word_count, collection_id,
+              char_count, cjk_word_count, latin_word_count, content_hash, grams_indexed)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 1)",
             [
                 &document.id,
                 &document.title,
                 &document.content,
                 document.file_path.as_deref().unwrap_or(""),
-                &document.created_at.to_rfc3339(),
-                &document.updated_at.to_rfc3339(),
+                &stored_timestamp(&document.created_at),
+                &stored_timestamp(&document.updated_at),
                 &document.word_count.to_string(),
+                &document.collection_id,
+                &document.char_count.to_string(),
+                &document.cjk_word_count.to_string(),
+                &document.latin_word_count.to_string(),
+                &content_hash(&document.content),
             ],
         )?;
+        self.index_grams(&document.id, &document.title, &document.content)
+    }
+
+    // Replaces a document's entries in the gram index that answers short CJK
+    // search terms
+    fn index_grams(&self, id: &str, title: &str, content: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM document_grams WHERE document_id = ?1", [id])?;
+        let mut insert = self.conn.prepare_cached("INSERT INTO document_grams (gram, document_id) VALUES (?1, ?2)")?;
+        let mut grams = cjk_grams(title);
+        grams.append(&mut cjk_grams(content));
+        for gram in grams {
+            insert.execute([gram.as_str(), id])?;
+        }
+        Ok(())
+    }
+
+    // Computes what SQL cannot for documents stored before it was kept:
+    // content hashes (migration 7) and grams (migration 2)
+    fn fill_in_documents(&self) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut stmt = self.conn.prepare("SELECT id, content FROM documents WHERE content_hash = ''")?;
+            let mut update = self.conn.prepare("UPDATE documents SET content_hash = ?2 WHERE id = ?1")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                let content: String = row.get(1)?;
+                update.execute([id, content_hash(&content)])?;
+            }
+
+            let mut stmt = self.conn.prepare("SELECT id, title, content FROM documents WHERE grams_indexed = 0")?;
+            let mut update = self.conn.prepare("UPDATE documents SET grams_indexed = 1 WHERE id = ?1")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                let title: String = row.get(1)?;
+                let content: String = row.get(2)?;
+                self.index_grams(&id, &title, &content)?;
+                update.execute([id])?;
+            }
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    // Id of a document with exactly this content, if there is one
+    pub fn find_document_by_content_hash(&self, hash: &str) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT id FROM documents WHERE content_hash = ?1 ORDER BY created_at LIMIT 1",
+                [hash],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    // Saves the document and records its new state as the next revision, in
+    // a single transaction.
+    pub fn save_document_with_revision(&self, document: &Document, author: Option<&str>) -> Result<DocumentRevision> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.save_document(document)?;
+        let revision = self.insert_revision(document, author)?;
+        tx.commit()?;
+        Ok(revision)
+    }
+
+    // Records the document's current state as its next revision; callers
+    // provide the transaction
+    fn insert_revision(&self, document: &Document, author: Option<&str>) -> Result<DocumentRevision> {
+        let revision_number: i32 = self.conn.query_row(
+            "SELECT COALESCE(MAX(revision_number), 0) + 1 FROM document_revisions WHERE document_id = ?1",
+            [&document.id],
+            |row| row.get(0),
+        )?;
+
+        let revision = DocumentRevision {
+            id: Uuid::new_v4().to_string(),
+            document_id: document.id.clone(),
+            revision_number,
+            title: document.title.clone(),
+            content: document.content.clone(),
+            author: author.map(|a| a.to_string()),
+            word_count: document.word_count,
+            created_at: document.updated_at,
+        };
+
+        self.conn.execute(
+            "INSERT INTO document_revisions
+             (id, document_id, revision_number, title, content, author, word_count, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                revision.id,
+                revision.document_id,
+                revision.revision_number,
+                revision.title,
+                revision.content,
+                revision.author,
+                revision.word_count,
+                revision.created_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(revision)
+    }
+
+    // Writes a document together with its tags, metadata, semantic terms and
+    // analysis results, replacing whatever was stored under its id, and
+    // records the restored state as a new revision.
+    pub fn restore_document(
+        &self,
+        document: &Document,
+        semantic_terms: &[SemanticTerm],
+        analysis_cache: &[AnalysisCache],
+    ) -> Result<DocumentRevision> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.save_document(document)?;
+
+        self.conn.execute("DELETE FROM document_tags WHERE document_id = ?1", [&document.id])?;
+        for name in &document.tags {
+            let tag_id = self.ensure_tag(name)?;
+            self.conn.execute(
+                "INSERT OR IGNORE INTO document_tags (document_id, tag_id) VALUES (?1, ?2)",
+                [document.id.as_str(), tag_id.as_str()],
+            )?;
+        }
+        self.delete_unused_tags()?;
+
+        self.conn.execute("DELETE FROM document_metadata WHERE document_id = ?1", [&document.id])?;
+        for (key, value) in &document.metadata {
+            self.set_document_metadata(&document.id, key, Some(value))?;
+        }
+
+        self.conn.execute("DELETE FROM semantic_terms WHERE document_id = ?1", [&document.id])?;
+        for term in semantic_terms {
+            self.insert_semantic_term(term)?;
+        }
+        for cache in analysis_cache {
+            self.save_analysis_cache(cache)?;
+        }
+
+        let revision = self.insert_revision(document, None)?;
+        tx.commit()?;
+        Ok(revision)
+    }
+
     pub fn get_document(&self, id: &str) -> Result<Option<Document>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, title, content, file_path, created_at, updated_at, word_count 
+            "SELECT id, title, content, file_path, created_at, updated_at, word_count, collection_id,
+                    char_count, cjk_word_count, latin_word_count
              FROM documents WHERE id = ?1"
         )?;
 
@@ -176,18 +612,30 @@ impl Database {
                     .map_err(|_| rusqlite::Error::InvalidColumnType(5, "updated_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&Utc),
                 word_count: row.get(6)?,
+                char_count: row.get(8)?,
+                cjk_word_count: row.get(9)?,
+                latin_word_count: row.get(10)?,
+                collection_id: row.get(7)?,
+                tags: Vec::new(),
+                metadata: BTreeMap::new(),
             })
         })?;
 
         match rows.next() {
-            Some(row) => Ok(Some(row?)),
+            Some(row) => {
+                let mut document = row?;
+                document.tags = self.get_document_tags(id)?;
+                document.metadata = self.get_document_metadata(id)?;
+                Ok(Some(document))
+            }
             None => Ok(None),
         }
     }
 
     pub fn list_documents(&self) -> Result<Vec<Document>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, title, content, file_path, created_at, updated_at, word_count 
+            "SELECT id, title, content, file_path, created_at, updated_at, word_count, collection_id,
+                    char_count, cjk_word_count, latin_word_count
              FROM documents ORDER BY updated_at DESC"
         )?;
 
@@ -210,17 +658,248 @@ impl Database {
                     .map_err(|_| rusqlite::Error::InvalidColumnType(5, "updated_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&Utc),
                 word_count: row.get(6)?,
+                char_count: row.get(8)?,
+                cjk_word_count: row.get(9)?,
+                latin_word_count: row.get(10)?,
+                collection_id: row.get(7)?,
+                tags: Vec::new(),
+                metadata: BTreeMap::new(),
             })
         })?;
 
+        let mut tags = self.get_all_document_tags()?;
         let mut documents = Vec::new();
         for document in document_iter {
-            documents.push(document?);
+            let mut document = document?;
+            document.tags = tags.remove(&document.id).unwrap_or_default();
+            document.metadata = self.get_document_metadata(&document.id)?;
+            documents.push(document);
         }
         Ok(documents)
     }
 
+    pub fn list_document_summaries(&self, query: &DocumentListQuery) -> Result<DocumentPage> {
+        let limit = query.limit.min(MAX_PAGE_SIZE);
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(prefix) = query.title_prefix.as_deref().filter(|p| !p.is_empty()) {
+            conditions.push("title LIKE ? ESCAPE '\\'".to_string());
+            let escaped = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            values.push(Value::Text(format!("{}%", escaped)));
+        }
+        let date_filters = [
+            ("created_at >= ?", query.created_after),
+            ("created_at < ?", query.created_before),
+            ("updated_at >= ?", query.updated_after),
+            ("updated_at < ?", query.updated_before),
+        ];
+        for (condition, date) in date_filters {
+            if let Some(date) = date {
+                conditions.push(condition.to_string());
+                values.push(Value::Text(stored_timestamp(&date)));
+            }
+        }
+        if let Some(collection_id) = &query.collection_id {
+            if query.include_subcollections {
+                conditions.push(concat!("collection_id IN (", collection_subtree_sql!(), ")").to_string());
+            } else {
+                conditions.push("collection_id = ?".to_string());
+            }
+            values.push(Value::Text(collection_id.clone()));
+        }
+        if let Some(min_words) = query.min_words {
+            conditions.push("word_count >= ?".to_string());
+            values.push(Value::Integer(min_words as i64));
+        }
+        if let Some(max_words) = query.max_words {
+            conditions.push("word_count <= ?".to_string());
+            values.push(Value::Integer(max_words as i64));
+        }
+
+        const TAGGED: &str = "SELECT dt.document_id FROM document_tags dt
+                              JOIN tags t ON t.id = dt.tag_id WHERE t.name";
+        for tag in &query.all_tags {
+            conditions.push(format!("id IN ({} = ?)", TAGGED));
+            values.push(Value::Text(tag.clone()));
+        }
+        if !query.any_tags.is_empty() {
+            let placeholders = vec!["?"; query.any_tags.len()].join(", ");
+            conditions.push(format!("id IN ({} IN ({}))", TAGGED, placeholders));
+            values.extend(query.any_tags.iter().map(|tag| Value::Text(tag.clone())));
+        }
+        if !query.exclude_tags.is_empty() {
+            let placeholders = vec!["?"; query.exclude_tags.len()].join(", ");
+            conditions.push(format!("id NOT IN ({} IN ({}))", TAGGED, placeholders));
+            values.extend(query.exclude_tags.iter().map(|tag| Value::Text(tag.clone())));
+        }
+
+        for predicate in &query.metadata {
+            let comparison = match predicate.op {
+                MetadataOp::Exists | MetadataOp::NotExists => None,
+                MetadataOp::Eq => Some("value = ?"),
+                MetadataOp::Ne => Some("value <> ?"),
+                MetadataOp::Lt => Some("value < ?"),
+                MetadataOp::Lte => Some("value <= ?"),
+                MetadataOp::Gt => Some("value > ?"),
+                MetadataOp::Gte => Some("value >= ?"),
+                MetadataOp::Contains => Some("instr(lower(value), lower(?)) > 0"),
+            };
+
+            values.push(Value::Text(predicate.key.clone()));
+            match (comparison, &predicate.value) {
+                (None, _) => {
+                    let negate = if let MetadataOp::NotExists = predicate.op { "NOT " } else { "" };
+                    conditions.push(format!(
+                        "id {}IN (SELECT document_id FROM document_metadata WHERE key = ?)", negate
+                    ));
+                }
+                (Some(comparison), Some(value)) => {
+                    conditions.push(format!(
+                        "id IN (SELECT document_id FROM document_metadata WHERE key = ? AND {})", comparison
+                    ));
+                    values.push(value.to_sql());
+                }
+                (Some(_), None) => {
+                    return Err(AppError::invalid(
+                        format!("Metadata predicate on '{}' needs a value", predicate.key),
+                        format!("元数据条件“{}”缺少比较值", predicate.key),
+                    ));
+                }
+            }
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM documents {}", where_clause),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        let sort_column = match query.sort_by {
+            DocumentSortField::UpdatedAt => "updated_at",
+            DocumentSortField::CreatedAt => "created_at",
+            DocumentSortField::Title => "title COLLATE NOCASE",
+            DocumentSortField::WordCount => "word_count",
+        };
+        let sort_order = match query.sort_order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+
+        // Only the first characters of the content are read for the excerpt
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT id, title, substr(content, 1, {}), file_path, created_at, updated_at, word_count, collection_id
+             FROM documents {} ORDER BY {} {}, id LIMIT {} OFFSET {}",
+            EXCERPT_CHARS * 2, where_clause, sort_column, sort_order, limit, query.offset
+        ))?;
+
+        let summary_iter = stmt.query_map(params_from_iter(values.iter()), |row| {
+            let head: String = row.get(2)?;
+            let created_at_str: String = row.get(4)?;
+            let updated_at_str: String = row.get(5)?;
+
+            Ok(DocumentSummary {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                excerpt: make_excerpt(&head),
+                file_path: {
+                    let path: Option<String> = row.get(3)?;
+                    path.filter(|p| !p.is_empty())
+                },
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+                updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(5, "updated_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+                word_count: row.get(6)?,
+                collection_id: row.get(7)?,
+                tags: Vec::new(),
+            })
+        })?;
+
+        let mut items = Vec::new();
+        for summary in summary_iter {
+            items.push(summary?);
+        }
+
+        let ids: Vec<&str> = items.iter().map(|item| item.id.as_str()).collect();
+        let mut tags = self.get_tags_of_documents(&ids)?;
+        for item in &mut items {
+            item.tags = tags.remove(&item.id).unwrap_or_default();
+        }
+
+        Ok(DocumentPage {
+            items,
+            total: total as usize,
+            offset: query.offset,
+            limit,
+        })
+    }
+
+    // Characters are the stored counts, so whitespace is left out as in the
+    // document's own statistics and no content has to be read
+    pub fn get_document_totals(&self) -> Result<DocumentTotals> {
+        Ok(self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(word_count), 0), COALESCE(SUM(char_count), 0)
+             FROM documents",
+            [],
+            |row| {
+                let total_documents: i64 = row.get(0)?;
+                let total_words: i64 = row.get(1)?;
+                let total_characters: i64 = row.get(2)?;
+                Ok(DocumentTotals {
+                    total_documents: total_documents as usize,
+                    total_words: total_words as usize,
+                    total_characters: total_characters as usize,
+                })
+            },
+        )?)
+    }
+
+    // Documents whose counts predate word segmentation, see migration 9
+    pub fn get_uncounted_document_ids(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("SELECT id FROM documents WHERE char_count < 0")?;
+        let id_iter = stmt.query_map([], |row| row.get(0))?;
+
+        let mut ids = Vec::new();
+        for id in id_iter {
+            ids.push(id?);
+        }
+        Ok(ids)
+    }
+
+    pub fn update_document_counts(&self, id: &str, counts: TextCounts) -> Result<()> {
+        self.conn.execute(
+            "UPDATE documents SET word_count = ?1, char_count = ?2, cjk_word_count = ?3, latin_word_count = ?4
+             WHERE id = ?5",
+            params![counts.words(), counts.characters, counts.cjk_words, counts.latin_words, id],
+        )?;
+        Ok(())
+    }
+
+    // Deletes the document with everything that refers to it, all or nothing
     pub fn delete_document(&self, id: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.delete_document_rows(id)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    // The deletes behind `delete_document`; callers provide the transaction
+    fn delete_document_rows(&self, id: &str) -> Result<()> {
+        // Delete tags and metadata
+        self.conn.execute("DELETE FROM document_tags WHERE document_id = ?1", [id])?;
+        self.conn.execute("DELETE FROM document_metadata WHERE document_id = ?1", [id])?;
+        self.delete_unused_tags()?;
+        // Delete revision history
+        self.conn.execute("DELETE FROM document_revisions WHERE document_id = ?1", [id])?;
         // Delete related semantic terms first
         self.conn.execute("DELETE FROM semantic_terms WHERE document_id = ?1", [id])?;
         // Delete analysis cache
@@ -230,51 +909,558 @@ impl Database {
         Ok(())
     }
 
-    // Semantic terms operations
-    pub fn save_semantic_terms(&self, terms: &[SemanticTerm]) -> Result<()> {
+    // Tag operations
+    pub fn add_document_tags(&self, document_id: &str, names: &[String]) -> Result<()> {
         let tx = self.conn.unchecked_transaction()?;
-        
-        for term in terms {
+
+        for name in names {
+            let tag_id = self.ensure_tag(name)?;
             tx.execute(
-                "INSERT OR REPLACE INTO semantic_terms 
-                 (id, document_id, term, context, position, confidence, created_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                [
-                    &term.id,
-                    &term.document_id,
-                    &term.term,
-                    &term.context,
-                    &term.position.to_string(),
-                    &term.confidence.to_string(),
-                    &term.created_at.to_rfc3339(),
-                ],
+                "INSERT OR IGNORE INTO document_tags (document_id, tag_id) VALUES (?1, ?2)",
+                [document_id, tag_id.as_str()],
             )?;
         }
-        
+
         tx.commit()?;
         Ok(())
     }
 
-    pub fn get_semantic_terms(&self, document_id: &str) -> Result<Vec<SemanticTerm>> {
+    pub fn remove_document_tags(&self, document_id: &str, names: &[String]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        for name in names {
+            tx.execute(
+                "DELETE FROM document_tags WHERE document_id = ?1
+                 AND tag_id IN (SELECT id FROM tags WHERE name = ?2)",
+                [document_id, name.as_str()],
+            )?;
+        }
+        self.delete_unused_tags()?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_document_tags(&self, document_id: &str) -> Result<Vec<String>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, document_id, term, context, position, confidence, created_at 
-             FROM semantic_terms WHERE document_id = ?1 ORDER BY position"
+            "SELECT t.name FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+             WHERE dt.document_id = ?1 ORDER BY t.name"
         )?;
+        let tag_iter = stmt.query_map([document_id], |row| row.get(0))?;
 
-        let term_iter = stmt.query_map([document_id], |row| {
-            let created_at_str: String = row.get(6)?;
-            
-            Ok(SemanticTerm {
-                id: row.get(0)?,
-                document_id: row.get(1)?,
-                term: row.get(2)?,
-                context: row.get(3)?,
-                position: row.get(4)?,
-                confidence: row.get(5)?,
-                created_at: DateTime::parse_from_rfc3339(&created_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
-                    .with_timezone(&Utc),
-            })
+        let mut tags = Vec::new();
+        for tag in tag_iter {
+            tags.push(tag?);
+        }
+        Ok(tags)
+    }
+
+    // Tags of each of the given documents in one query; documents without
+    // tags are left out
+    fn get_tags_of_documents(&self, ids: &[&str]) -> Result<HashMap<String, Vec<String>>> {
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        if ids.is_empty() {
+            return Ok(tags);
+        }
+
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT dt.document_id, t.name FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+             WHERE dt.document_id IN ({}) ORDER BY t.name",
+            placeholders
+        ))?;
+        let mut rows = stmt.query(params_from_iter(ids))?;
+        while let Some(row) = rows.next()? {
+            tags.entry(row.get(0)?).or_default().push(row.get(1)?);
+        }
+        Ok(tags)
+    }
+
+    fn get_all_document_tags(&self) -> Result<HashMap<String, Vec<String>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT dt.document_id, t.name FROM document_tags dt JOIN tags t ON t.id = dt.tag_id
+             ORDER BY t.name"
+        )?;
+        let mut rows = stmt.query([])?;
+
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        while let Some(row) = rows.next()? {
+            tags.entry(row.get(0)?).or_default().push(row.get(1)?);
+        }
+        Ok(tags)
+    }
+
+    pub fn list_tags(&self) -> Result<Vec<TagInfo>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT t.id, t.name, COUNT(dt.document_id) FROM tags t
+             LEFT JOIN document_tags dt ON dt.tag_id = t.id
+             GROUP BY t.id ORDER BY t.name"
+        )?;
+
+        let tag_iter = stmt.query_map([], |row| {
+            let document_count: i64 = row.get(2)?;
+            Ok(TagInfo {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                document_count: document_count as usize,
+            })
+        })?;
+
+        let mut tags = Vec::new();
+        for tag in tag_iter {
+            tags.push(tag?);
+        }
+        Ok(tags)
+    }
+
+    // Renames a tag across the library. Renaming onto an existing tag merges
+    // the two.
+    pub fn rename_tag(&self, old_name: &str, new_name: &str) -> Result<()> {
+        let existing: Option<String> = self.conn.query_row(
+            "SELECT id FROM tags WHERE name = ?1", [new_name], |row| row.get(0),
+        ).optional()?;
+        let old_id: Option<String> = self.conn.query_row(
+            "SELECT id FROM tags WHERE name = ?1", [old_name], |row| row.get(0),
+        ).optional()?;
+
+        match (old_id, existing) {
+            (Some(old_id), Some(existing)) if old_id != existing => {
+                self.merge_tags(&[old_name.to_string()], new_name)
+            }
+            (Some(old_id), _) => {
+                // Same tag (possibly a change of case only) or a free name
+                self.conn.execute("UPDATE tags SET name = ?2 WHERE id = ?1", [old_id.as_str(), new_name])?;
+                Ok(())
+            }
+            (None, _) => Err(AppError::not_found_id(Entity::Tag, old_name)),
+        }
+    }
+
+    // Moves every document tagged with one of `sources` to `target`, then
+    // deletes the source tags.
+    pub fn merge_tags(&self, sources: &[String], target: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let target_id = self.ensure_tag(target)?;
+
+        for source in sources {
+            let source_id: Option<String> = tx.query_row(
+                "SELECT id FROM tags WHERE name = ?1", [source], |row| row.get(0),
+            ).optional()?;
+            let source_id = match source_id {
+                Some(id) if id != target_id => id,
+                _ => continue,
+            };
+
+            tx.execute(
+                "INSERT OR IGNORE INTO document_tags (document_id, tag_id)
+                 SELECT document_id, ?2 FROM document_tags WHERE tag_id = ?1",
+                [source_id.as_str(), target_id.as_str()],
+            )?;
+            tx.execute("DELETE FROM document_tags WHERE tag_id = ?1", [&source_id])?;
+            tx.execute("DELETE FROM tags WHERE id = ?1", [&source_id])?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn ensure_tag(&self, name: &str) -> Result<String> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO tags (id, name, created_at) VALUES (?1, ?2, ?3)",
+            [Uuid::new_v4().to_string(), name.to_string(), Utc::now().to_rfc3339()],
+        )?;
+        Ok(self.conn.query_row("SELECT id FROM tags WHERE name = ?1", [name], |row| row.get(0))?)
+    }
+
+    fn delete_unused_tags(&self) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM tags WHERE id NOT IN (SELECT DISTINCT tag_id FROM document_tags)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    // Metadata operations
+    pub fn set_document_metadata(&self, document_id: &str, key: &str, value: Option<&MetadataValue>) -> Result<()> {
+        match value {
+            Some(value) => {
+                self.conn.execute(
+                    "INSERT OR REPLACE INTO document_metadata (document_id, key, value_type, value)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![document_id, key, value.type_name(), value.to_sql()],
+                )?;
+            }
+            None => {
+                self.conn.execute(
+                    "DELETE FROM document_metadata WHERE document_id = ?1 AND key = ?2",
+                    [document_id, key],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_document_metadata(&self, document_id: &str) -> Result<BTreeMap<String, MetadataValue>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT key, value_type, value FROM document_metadata WHERE document_id = ?1"
+        )?;
+        let mut rows = stmt.query([document_id])?;
+
+        let mut metadata = BTreeMap::new();
+        while let Some(row) = rows.next()? {
+            let value_type: String = row.get(1)?;
+            if let Some(value) = MetadataValue::from_sql(&value_type, row.get(2)?) {
+                metadata.insert(row.get(0)?, value);
+            }
+        }
+        Ok(metadata)
+    }
+
+    // Collection operations
+    pub fn save_collection(&self, collection: &Collection) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO collections (id, name, parent_id, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                collection.id,
+                collection.name,
+                collection.parent_id,
+                collection.created_at.to_rfc3339(),
+                collection.updated_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_collection(&self, id: &str) -> Result<Option<Collection>> {
+        Ok(self.conn.query_row(
+            "SELECT id, name, parent_id, created_at, updated_at FROM collections WHERE id = ?1",
+            [id],
+            collection_from_row,
+        ).optional()?)
+    }
+
+    pub fn list_collections(&self) -> Result<Vec<Collection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, parent_id, created_at, updated_at
+             FROM collections ORDER BY name COLLATE NOCASE"
+        )?;
+
+        let collection_iter = stmt.query_map([], collection_from_row)?;
+
+        let mut collections = Vec::new();
+        for collection in collection_iter {
+            collections.push(collection?);
+        }
+        Ok(collections)
+    }
+
+    // Returns the ids of the collection and all of its descendants
+    pub fn get_collection_subtree(&self, id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(collection_subtree_sql!())?;
+        let id_iter = stmt.query_map([id], |row| row.get(0))?;
+
+        let mut ids = Vec::new();
+        for id in id_iter {
+            ids.push(id?);
+        }
+        Ok(ids)
+    }
+
+    // Returns the ids of documents in the collection or any subcollection
+    pub fn get_collection_document_ids(&self, id: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(concat!(
+            "SELECT id FROM documents WHERE collection_id IN (", collection_subtree_sql!(), ")"
+        ))?;
+        let id_iter = stmt.query_map([id], |row| row.get(0))?;
+
+        let mut ids = Vec::new();
+        for id in id_iter {
+            ids.push(id?);
+        }
+        Ok(ids)
+    }
+
+    pub fn move_document_to_collection(&self, document_id: &str, collection_id: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE documents SET collection_id = ?2 WHERE id = ?1",
+            [document_id, collection_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    // Deletes a collection and returns the ids of any documents deleted with it
+    pub fn delete_collection(&self, id: &str, mode: CollectionDeleteMode) -> Result<Vec<String>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut deleted_documents = Vec::new();
+
+        match mode {
+            CollectionDeleteMode::MoveToParent => {
+                let parent_id: Option<String> = tx.query_row(
+                    "SELECT parent_id FROM collections WHERE id = ?1",
+                    [id],
+                    |row| row.get(0),
+                )?;
+                let document_target = parent_id.clone().unwrap_or_else(default_collection_id);
+
+                tx.execute(
+                    "UPDATE collections SET parent_id = ?2 WHERE parent_id = ?1",
+                    params![id, parent_id],
+                )?;
+                tx.execute(
+                    "UPDATE documents SET collection_id = ?2 WHERE collection_id = ?1",
+                    [id, document_target.as_str()],
+                )?;
+                tx.execute("DELETE FROM collections WHERE id = ?1", [id])?;
+            }
+            CollectionDeleteMode::Cascade => {
+                let subtree = self.get_collection_subtree(id)?;
+                deleted_documents = self.get_collection_document_ids(id)?;
+                for document_id in &deleted_documents {
+                    self.delete_document_rows(document_id)?;
+                }
+                for collection_id in subtree.iter().rev() {
+                    tx.execute("DELETE FROM collections WHERE id = ?1", [collection_id])?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(deleted_documents)
+    }
+
+    // Statistics for a collection including all of its subcollections
+    pub fn get_collection_stats(&self, id: &str) -> Result<CollectionStats> {
+        let (document_count, total_words, total_characters): (i64, i64, i64) = self.conn.query_row(
+            concat!(
+                "SELECT COUNT(*), COALESCE(SUM(word_count), 0), COALESCE(SUM(char_count), 0)
+                 FROM documents WHERE collection_id IN (", collection_subtree_sql!(), ")"
+            ),
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let subtree_size = self.get_collection_subtree(id)?.len();
+
+        Ok(CollectionStats {
+            collection_id: id.to_string(),
+            document_count: document_count as usize,
+            total_words: total_words as usize,
+            total_characters: total_characters as usize,
+            subcollection_count: subtree_size.saturating_sub(1),
+        })
+    }
+
+    // Revision history operations
+    pub fn list_revisions(&self, document_id: &str) -> Result<Vec<RevisionSummary>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, document_id, revision_number, title, author, word_count, created_at
+             FROM document_revisions WHERE document_id = ?1 ORDER BY revision_number DESC"
+        )?;
+
+        let revision_iter = stmt.query_map([document_id], |row| {
+            let created_at_str: String = row.get(6)?;
+
+            Ok(RevisionSummary {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                revision_number: row.get(2)?,
+                title: row.get(3)?,
+                author: row.get(4)?,
+                word_count: row.get(5)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut revisions = Vec::new();
+        for revision in revision_iter {
+            revisions.push(revision?);
+        }
+        Ok(revisions)
+    }
+
+    pub fn get_revision(&self, revision_id: &str) -> Result<Option<DocumentRevision>> {
+        Ok(self.conn.query_row(
+            "SELECT id, document_id, revision_number, title, content, author, word_count, created_at
+             FROM document_revisions WHERE id = ?1",
+            [revision_id],
+            |row| {
+                let created_at_str: String = row.get(7)?;
+
+                Ok(DocumentRevision {
+                    id: row.get(0)?,
+                    document_id: row.get(1)?,
+                    revision_number: row.get(2)?,
+                    title: row.get(3)?,
+                    content: row.get(4)?,
+                    author: row.get(5)?,
+                    word_count: row.get(6)?,
+                    created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                        .map_err(|_| rusqlite::Error::InvalidColumnType(7, "created_at".to_string(), rusqlite::types::Type::Text))?
+                        .with_timezone(&Utc),
+                })
+            },
+        ).optional()?)
+    }
+
+    // Deletes revisions beyond the newest `max_revisions` (0 = unlimited) and
+    // those created before `older_than`. The latest revision is always kept.
+    pub fn prune_revisions(&self, document_id: &str, max_revisions: usize, older_than: Option<DateTime<Utc>>) -> Result<usize> {
+        let mut deleted = 0;
+
+        if max_revisions > 0 {
+            deleted += self.conn.execute(
+                "DELETE FROM document_revisions WHERE document_id = ?1 AND id NOT IN (
+                     SELECT id FROM document_revisions WHERE document_id = ?1
+                     ORDER BY revision_number DESC LIMIT ?2
+                 )",
+                params![document_id, max_revisions as i64],
+            )?;
+        }
+
+        if let Some(cutoff) = older_than {
+            deleted += self.conn.execute(
+                "DELETE FROM document_revisions WHERE document_id = ?1 AND created_at < ?2
+                 AND revision_number < (
+                     SELECT MAX(revision_number) FROM document_revisions WHERE document_id = ?1
+                 )",
+                params![document_id, cutoff.to_rfc3339()],
+            )?;
+        }
+
+        Ok(deleted)
+    }
+
+    // Full-text search
+    pub fn search_documents(&self, query: &SearchQuery, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+
+        match query.to_fts5() {
+            Some(expression) => {
+                // bm25 is lower-is-better; weight title matches over content
+                let mut stmt = self.conn.prepare(
+                    "SELECT document_id, title, content, bm25(documents_fts, 0.0, 10.0, 1.0)
+                     FROM documents_fts WHERE documents_fts MATCH ?1 ORDER BY 4"
+                )?;
+                let mut rows = stmt.query([expression])?;
+                while let Some(row) = rows.next()? {
+                    let content: String = row.get(2)?;
+                    let rank: f64 = row.get(3)?;
+                    if let Some(hit) = query.build_hit(row.get(0)?, row.get(1)?, &content, Some(-rank)) {
+                        hits.push(hit);
+                        if hits.len() >= limit {
+                            break;
+                        }
+                    }
+                }
+            }
+            None => {
+                // Terms too short for the trigram index: look up candidates
+                // through the gram index where possible, otherwise scan, and
+                // rank in Rust
+                let (sql, params) = match query.to_index_filter() {
+                    Some(filter) => {
+                        let mut params = Vec::new();
+                        let condition = index_filter_sql(&filter, &mut params);
+                        (format!("SELECT id, title, content FROM documents WHERE {}", condition), params)
+                    }
+                    None => ("SELECT id, title, content FROM documents".to_string(), Vec::new()),
+                };
+                let mut stmt = self.conn.prepare(&sql)?;
+                let mut rows = stmt.query(params_from_iter(params))?;
+                while let Some(row) = rows.next()? {
+                    let content: String = row.get(2)?;
+                    if let Some(hit) = query.build_hit(row.get(0)?, row.get(1)?, &content, None) {
+                        hits.push(hit);
+                    }
+                }
+                hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+                hits.truncate(limit);
+            }
+        }
+
+        Ok(hits)
+    }
+
+    // Semantic terms operations
+    pub fn save_semantic_terms(&self, terms: &[SemanticTerm]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        
+        for term in terms {
+            self.insert_semantic_term(term)?;
+        }
+        
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn insert_semantic_term(&self, term: &SemanticTerm) -> Result<()> {
+        let offset = term.offset.as_ref();
+        self.conn.execute(
+            "INSERT OR REPLACE INTO semantic_terms 
+             (id, document_id, term, context, position, confidence, created_at,
+              byte_start, byte_end, char_start, char_end)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                term.id,
+                term.document_id,
+                term.term,
+                term.context,
+                term.position,
+                term.confidence,
+                term.created_at.to_rfc3339(),
+                offset.map(|o| o.start as i64),
+                offset.map(|o| o.end as i64),
+                offset.map(|o| o.char_start as i64),
+                offset.map(|o| o.char_end as i64),
+            ],
+        )?;
+        Ok(())
+    }
+
+    // Swaps in a fresh extraction result for one document
+    pub fn replace_semantic_terms(&self, document_id: &str, terms: &[SemanticTerm]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        self.conn.execute("DELETE FROM semantic_terms WHERE document_id = ?1", [document_id])?;
+        for term in terms {
+            self.insert_semantic_term(term)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_semantic_terms(&self, document_id: &str) -> Result<Vec<SemanticTerm>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, document_id, term, context, position, confidence, created_at,
+                    byte_start, byte_end, char_start, char_end
+             FROM semantic_terms WHERE document_id = ?1 ORDER BY position"
+        )?;
+
+        let term_iter = stmt.query_map([document_id], |row| {
+            let created_at_str: String = row.get(6)?;
+            let offsets: [Option<i64>; 4] = [row.get(7)?, row.get(8)?, row.get(9)?, row.get(10)?];
+            
+            Ok(SemanticTerm {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                term: row.get(2)?,
+                context: row.get(3)?,
+                position: row.get(4)?,
+                confidence: row.get(5)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(6, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+                offset: match offsets {
+                    [Some(start), Some(end), Some(char_start), Some(char_end)] => Some(MatchOffset {
+                        start: start as usize,
+                        end: end as usize,
+                        char_start: char_start as usize,
+                        char_end: char_end as usize,
+                    }),
+                    _ => None,
+                },
+            })
         })?;
 
         let mut terms = Vec::new();
@@ -286,17 +1472,31 @@ impl Database {
 
     // Consistency rules operations
     pub fn save_consistency_rule(&self, rule: &ConsistencyRule) -> Result<()> {
+        let alternatives = serde_json::to_string(&rule.alternatives)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize alternatives: {}", e)))?;
+        let (scope_type, scope_id) = match &rule.scope {
+            RuleScope::Global => ("global", None),
+            RuleScope::Collection(id) => ("collection", Some(id.as_str())),
+            RuleScope::Document(id) => ("document", Some(id.as_str())),
+        };
         self.conn.execute(
             "INSERT OR REPLACE INTO consistency_rules 
-             (id, term, preferred_form, alternatives, is_active, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            [
-                &rule.id,
-                &rule.term,
-                &rule.preferred_form,
-                &rule.alternatives,
-                &rule.is_active.to_string(),
-                &rule.created_at.to_rfc3339(),
+             (id, term, preferred_form, alternatives, pattern, replacement, scope_type, scope_id,
+              category, priority, is_active, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                rule.id,
+                rule.term,
+                rule.preferred_form,
+                alternatives,
+                rule.pattern,
+                rule.replacement,
+                scope_type,
+                scope_id,
+                rule.category.as_str(),
+                rule.priority,
+                rule.is_active,
+                rule.created_at.to_rfc3339(),
             ],
         )?;
         Ok(())
@@ -304,22 +1504,38 @@ impl Database {
 
     pub fn get_consistency_rules(&self) -> Result<Vec<ConsistencyRule>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, term, preferred_form, alternatives, is_active, created_at 
-             FROM consistency_rules WHERE is_active = 1 ORDER BY term"
+            "SELECT id, term, preferred_form, alternatives, pattern, replacement, scope_type, scope_id,
+                    category, priority, is_active, created_at
+             FROM consistency_rules WHERE is_active = 1 ORDER BY term, priority DESC, id"
         )?;
 
         let rule_iter = stmt.query_map([], |row| {
-            let created_at_str: String = row.get(5)?;
-            let is_active_str: String = row.get(4)?;
-            
+            let alternatives: String = row.get(3)?;
+            let scope_type: String = row.get(6)?;
+            let scope_id: Option<String> = row.get(7)?;
+            let category: String = row.get(8)?;
+            let created_at_str: String = row.get(11)?;
+
             Ok(ConsistencyRule {
                 id: row.get(0)?,
                 term: row.get(1)?,
                 preferred_form: row.get(2)?,
-                alternatives: row.get(3)?,
-                is_active: is_active_str == "1" || is_active_str.to_lowercase() == "true",
+                alternatives: serde_json::from_str(&alternatives)
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e)))?,
+                pattern: row.get(4)?,
+                replacement: row.get(5)?,
+                scope: match (scope_type.as_str(), scope_id) {
+                    ("global", _) => RuleScope::Global,
+                    ("collection", Some(id)) => RuleScope::Collection(id),
+                    ("document", Some(id)) => RuleScope::Document(id),
+                    _ => return Err(rusqlite::Error::InvalidColumnType(6, "scope_type".to_string(), rusqlite::types::Type::Text)),
+                },
+                category: RuleCategory::parse(&category)
+                    .ok_or_else(|| rusqlite::Error::InvalidColumnType(8, "category".to_string(), rusqlite::types::Type::Text))?,
+                priority: row.get(9)?,
+                is_active: row.get(10)?,
                 created_at: DateTime::parse_from_rfc3339(&created_at_str)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(5, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(11, "created_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&Utc),
             })
         })?;
@@ -373,4 +1589,566 @@ impl Database {
             None => Ok(None),
         }
     }
-}
\ No newline at end of file
+
+    pub fn get_analysis_caches(&self, document_id: &str) -> Result<Vec<AnalysisCache>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, document_id, content_hash, analysis_result, created_at 
+             FROM analysis_cache WHERE document_id = ?1 ORDER BY created_at"
+        )?;
+
+        let cache_iter = stmt.query_map([document_id], |row| {
+            let created_at_str: String = row.get(4)?;
+            
+            Ok(AnalysisCache {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                content_hash: row.get(2)?,
+                analysis_result: row.get(3)?,
+                created_at: DateTime::parse_from_rfc3339(&created_at_str)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "created_at".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc),
+            })
+        })?;
+
+        let mut caches = Vec::new();
+        for cache in cache_iter {
+            caches.push(cache?);
+        }
+        Ok(caches)
+    }
+
+    // Drops the cached results of one kind; keys of a kind share a prefix
+    pub fn delete_analysis_caches(&self, document_id: &str, key_prefix: &str) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM analysis_cache
+             WHERE document_id = ?1 AND substr(content_hash, 1, length(?2)) = ?2",
+            [document_id, key_prefix],
+        )?)
+    }
+
+    // Document frequency for term weighting: for each needle, the number of
+    // documents other than `exclude_id` whose content contains it, ignoring
+    // case; matches inside longer words count too. Needles of three or more
+    // characters are counted through the trigram index and shorter CJK ones
+    // through the gram index; only other short needles need a scan.
+    pub fn count_documents_containing(&self, needles: &[String], exclude_id: &str) -> Result<Vec<usize>> {
+        let mut by_trigram = self.conn.prepare_cached(
+            "SELECT COUNT(*) FROM documents_fts WHERE documents_fts MATCH ?1 AND document_id != ?2"
+        )?;
+        // Grams also cover the title, so candidates are checked against the content
+        let mut by_gram = self.conn.prepare_cached(
+            "SELECT COUNT(*) FROM document_grams g JOIN documents d ON d.id = g.document_id
+             WHERE g.gram = ?1 AND d.id != ?2 AND instr(d.content, ?1) > 0"
+        )?;
+        let mut by_scan = self.conn.prepare_cached(
+            r"SELECT COUNT(*) FROM documents WHERE id != ?2 AND content LIKE ?1 ESCAPE '\'"
+        )?;
+
+        let mut counts = Vec::with_capacity(needles.len());
+        for needle in needles {
+            let chars = needle.chars().count();
+            let count: i64 = if chars >= 3 {
+                let expression = format!("content : \"{}\"", needle.replace('"', "\"\""));
+                by_trigram.query_row(params![expression, exclude_id], |row| row.get(0))?
+            } else if chars > 0 && needle.chars().all(is_cjk) {
+                by_gram.query_row(params![needle, exclude_id], |row| row.get(0))?
+            } else {
+                let escaped = needle.replace('\\', r"\\").replace('%', r"\%").replace('_', r"\_");
+                by_scan.query_row(params![format!("%{}%", escaped), exclude_id], |row| row.get(0))?
+            };
+            counts.push(count as usize);
+        }
+        Ok(counts)
+    }
+}
+
+// Renders an index filter as a condition on `documents.id`, appending the
+// values of its placeholders to `params`
+fn index_filter_sql(filter: &IndexFilter, params: &mut Vec<String>) -> String {
+    match filter {
+        IndexFilter::Fts(expression) => {
+            params.push(expression.clone());
+            "id IN (SELECT document_id FROM documents_fts WHERE documents_fts MATCH ?)".to_string()
+        }
+        IndexFilter::Gram(gram) => {
+            params.push(gram.clone());
+            "id IN (SELECT document_id FROM document_grams WHERE gram = ?)".to_string()
+        }
+        IndexFilter::And(filters) | IndexFilter::Or(filters) => {
+            let operator = if matches!(filter, IndexFilter::And(_)) { " AND " } else { " OR " };
+            let conditions: Vec<String> = filters.iter().map(|f| index_filter_sql(f, params)).collect();
+            format!("({})", conditions.join(operator))
+        }
+    }
+}
+
+// Document timestamps are stored as RFC 3339 in UTC, as `to_rfc3339`
+// renders them. Compared as text they sort chronologically only while every
+// value has that form, so bounds in list filters go through here too.
+fn stored_timestamp(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::AutoSi, false)
+}
+
+// SHA-256 of the content in hex. Stored with each document so identical
+// content can be found through an index, e.g. when importing.
+pub fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+// Collapses whitespace and strips leading Markdown markers so excerpts read
+// as plain text in the sidebar.
+fn make_excerpt(head: &str) -> String {
+    let text = head
+        .lines()
+        .map(|line| line.trim_start_matches(['#', '>', '-', '*']).trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if text.chars().count() > EXCERPT_CHARS {
+        let mut excerpt: String = text.chars().take(EXCERPT_CHARS).collect();
+        excerpt.push('…');
+        excerpt
+    } else {
+        text
+    }
+}
+
+fn collection_from_row(row: &rusqlite::Row) -> rusqlite::Result<Collection> {
+    let created_at_str: String = row.get(3)?;
+    let updated_at_str: String = row.get(4)?;
+
+    Ok(Collection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+        created_at: DateTime::parse_from_rfc3339(&created_at_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(3, "created_at".to_string(), rusqlite::types::Type::Text))?
+            .with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&updated_at_str)
+            .map_err(|_| rusqlite::Error::InvalidColumnType(4, "updated_at".to_string(), rusqlite::types::Type::Text))?
+            .with_timezone(&Utc),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::Segmenter;
+
+    fn database() -> Database {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        Database { conn }
+    }
+
+    fn document(id: &str, title: &str, content: &str) -> Document {
+        let mut document = Document {
+            id: id.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        };
+        document.set_counts(Segmenter::new().count(content));
+        document
+    }
+
+    fn search(db: &Database, input: &str) -> Vec<String> {
+        let query = SearchQuery::parse(input, &Segmenter::new()).unwrap();
+        db.search_documents(&query, 10).unwrap().into_iter().map(|hit| hit.document_id).collect()
+    }
+
+    #[test]
+    fn searches_through_the_index_and_by_scan() {
+        let db = database();
+        db.save_document(&document("a", "Semantic notes", "Notes on 语义分析 and writing")).unwrap();
+        db.save_document(&document("b", "Other", "Semantic drift in 写作")).unwrap();
+        db.save_document(&document("c", "Unrelated", "Nothing to see")).unwrap();
+
+        // Title hits rank first
+        assert_eq!(search(&db, "semantic"), vec!["a", "b"]);
+        assert_eq!(search(&db, "semantic -drift"), vec!["a"]);
+        assert_eq!(search(&db, "语义分析"), vec!["a"]);
+        // Too short for the trigram index
+        assert_eq!(search(&db, "写作"), vec!["b"]);
+
+        // The index follows updates and deletes
+        db.save_document(&document("c", "Unrelated", "Semantic after all")).unwrap();
+        assert_eq!(search(&db, "semantic").len(), 3);
+        db.delete_document("a").unwrap();
+        assert_eq!(search(&db, "semantic"), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn ranks_short_cjk_queries_through_the_gram_index() {
+        let db = database();
+        db.save_document(&document("a", "A", "写作 once")).unwrap();
+        db.save_document(&document("b", "B", "写作, 写作 and 写作 again")).unwrap();
+        db.save_document(&document("c", "写作笔记", "Notes")).unwrap();
+        db.save_document(&document("d", "D", "写 and 作 apart")).unwrap();
+
+        assert_eq!(search(&db, "写作"), vec!["c", "b", "a"]);
+        assert_eq!(search(&db, "写"), vec!["c", "b", "a", "d"]);
+        assert_eq!(search(&db, "写作 OR apart"), vec!["c", "b", "a", "d"]);
+        assert_eq!(search(&db, "写作 -笔记"), vec!["b", "a"]);
+
+        // Candidates come from the index rather than a scan of every row
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM document_grams WHERE gram = '写作'"), 3);
+        db.delete_document("b").unwrap();
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM document_grams WHERE document_id = 'b'"), 0);
+
+        // Documents stored before the index existed are indexed on open
+        db.conn.execute_batch("DELETE FROM document_grams; UPDATE documents SET grams_indexed = 0").unwrap();
+        db.fill_in_documents().unwrap();
+        assert_eq!(search(&db, "写作"), vec!["c", "a"]);
+    }
+
+    #[test]
+    fn totals_use_stored_counts() {
+        let db = database();
+        let empty = db.get_document_totals().unwrap();
+        assert_eq!((empty.total_documents, empty.total_words, empty.total_characters), (0, 0, 0));
+
+        db.save_document(&document("a", "A", "我们喜欢写作")).unwrap();
+        db.save_document(&document("b", "B", "two words\n\n")).unwrap();
+        let totals = db.get_document_totals().unwrap();
+        assert_eq!(totals.total_documents, 2);
+        assert_eq!(totals.total_words, 5);
+        // Whitespace is not counted
+        assert_eq!(totals.total_characters, 6 + 8);
+    }
+
+    fn count(db: &Database, sql: &str) -> i64 {
+        db.conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn pages_summaries_with_their_tags() {
+        let db = database();
+        for i in 0..3 {
+            let id = format!("d{}", i);
+            db.save_document(&document(&id, &id, "")).unwrap();
+            db.add_document_tags(&id, &tags(&["shared", &id])).unwrap();
+        }
+
+        let query = DocumentListQuery {
+            limit: 2,
+            sort_by: DocumentSortField::Title,
+            sort_order: SortOrder::Asc,
+            ..Default::default()
+        };
+        let page = db.list_document_summaries(&query).unwrap();
+        assert_eq!((page.total, page.limit), (3, 2));
+        let items: Vec<(String, Vec<String>)> = page.items.into_iter().map(|item| (item.id, item.tags)).collect();
+        assert_eq!(items, vec![("d0".to_string(), tags(&["d0", "shared"])), ("d1".to_string(), tags(&["d1", "shared"]))]);
+
+        let page = db.list_document_summaries(&DocumentListQuery { limit: usize::MAX, ..Default::default() }).unwrap();
+        assert_eq!((page.items.len(), page.limit), (3, MAX_PAGE_SIZE));
+    }
+
+    #[test]
+    fn compares_date_bounds_in_the_stored_form() {
+        let db = database();
+        for (id, created_at) in [
+            ("early", "2024-03-01T09:59:59.999+00:00"),
+            ("exact", "2024-03-01T10:00:00+00:00"),
+            ("late", "2024-03-01T10:00:00.000001+00:00"),
+        ] {
+            let mut document = document(id, id, "");
+            document.created_at = DateTime::parse_from_rfc3339(created_at).unwrap().with_timezone(&Utc);
+            db.save_document(&document).unwrap();
+        }
+
+        // Bounds arrive with any offset and precision the frontend sends
+        let ids = |after: &str, before: &str| -> Vec<String> {
+            let query: DocumentListQuery = serde_json::from_value(serde_json::json!({
+                "created_after": after,
+                "created_before": before,
+            }))
+            .unwrap();
+            sorted(db.list_document_summaries(&query).unwrap().items.into_iter().map(|item| item.id).collect())
+        };
+        assert_eq!(ids("2024-03-01T18:00:00+08:00", "2024-03-01T18:00:00.5+08:00"), vec!["exact", "late"]);
+        assert_eq!(ids("2024-03-01T09:59:59.9990Z", "2024-03-01T10:00:00.000001Z"), vec!["early", "exact"]);
+    }
+
+    #[test]
+    fn deletes_documents_with_their_rows() {
+        let db = database();
+        db.save_document_with_revision(&document("a", "A", "text"), None).unwrap();
+        db.add_document_tags("a", &["draft".to_string()]).unwrap();
+        db.set_document_metadata("a", "status", Some(&MetadataValue::Text("new".to_string()))).unwrap();
+
+        db.delete_document("a").unwrap();
+        assert!(db.get_document("a").unwrap().is_none());
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM document_revisions"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM document_metadata"), 0);
+        assert!(db.list_tags().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_delete_keeps_everything() {
+        let db = database();
+        db.save_document_with_revision(&document("a", "A", "text"), None).unwrap();
+        db.add_document_tags("a", &["draft".to_string()]).unwrap();
+        db.conn
+            .execute_batch(
+                "CREATE TRIGGER refuse_delete BEFORE DELETE ON documents BEGIN
+                     SELECT RAISE(ABORT, 'refused');
+                 END;",
+            )
+            .unwrap();
+
+        assert!(db.delete_document("a").is_err());
+        assert_eq!(db.get_document("a").unwrap().unwrap().tags, vec!["draft"]);
+        assert_eq!(db.list_revisions("a").unwrap().len(), 1);
+    }
+
+    fn collection(db: &Database, id: &str, parent_id: Option<&str>) {
+        db.save_collection(&Collection {
+            id: id.to_string(),
+            name: id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .unwrap();
+    }
+
+    fn filed(db: &Database, id: &str, collection_id: &str, content: &str) {
+        let mut document = document(id, id, content);
+        document.collection_id = collection_id.to_string();
+        db.save_document(&document).unwrap();
+    }
+
+    // book > part > chapter, with one document in each
+    fn library() -> Database {
+        let db = database();
+        collection(&db, "book", None);
+        collection(&db, "part", Some("book"));
+        collection(&db, "chapter", Some("part"));
+        filed(&db, "d-book", "book", "one two");
+        filed(&db, "d-part", "part", "我们喜欢写作");
+        filed(&db, "d-chapter", "chapter", "three");
+        db
+    }
+
+    fn sorted(mut ids: Vec<String>) -> Vec<String> {
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn collects_subtrees_and_stats() {
+        let db = library();
+        assert_eq!(sorted(db.get_collection_subtree("part").unwrap()), vec!["chapter", "part"]);
+        assert_eq!(sorted(db.get_collection_document_ids("part").unwrap()), vec!["d-chapter", "d-part"]);
+
+        let stats = db.get_collection_stats("book").unwrap();
+        assert_eq!(stats.document_count, 3);
+        assert_eq!(stats.total_words, 2 + 3 + 1);
+        assert_eq!(stats.total_characters, 6 + 6 + 5);
+        assert_eq!(stats.subcollection_count, 2);
+
+        let query = DocumentListQuery {
+            collection_id: Some("part".to_string()),
+            ..DocumentListQuery::default()
+        };
+        assert_eq!(db.list_document_summaries(&query).unwrap().total, 1);
+        let query = DocumentListQuery {
+            include_subcollections: true,
+            ..query
+        };
+        assert_eq!(db.list_document_summaries(&query).unwrap().total, 2);
+    }
+
+    #[test]
+    fn deleting_a_collection_moves_its_contents_up() {
+        let db = library();
+        assert!(db.delete_collection("part", CollectionDeleteMode::MoveToParent).unwrap().is_empty());
+        assert!(db.get_collection("part").unwrap().is_none());
+        assert_eq!(db.get_collection("chapter").unwrap().unwrap().parent_id.as_deref(), Some("book"));
+        assert_eq!(db.get_document("d-part").unwrap().unwrap().collection_id, "book");
+
+        // Top-level collections hand their documents to Unsorted
+        db.delete_collection("book", CollectionDeleteMode::MoveToParent).unwrap();
+        assert_eq!(db.get_document("d-book").unwrap().unwrap().collection_id, UNSORTED_COLLECTION_ID);
+        assert_eq!(db.get_collection("chapter").unwrap().unwrap().parent_id, None);
+    }
+
+    #[test]
+    fn cascading_delete_removes_the_subtree() {
+        let db = library();
+        db.add_document_tags("d-chapter", &["draft".to_string()]).unwrap();
+
+        let deleted = db.delete_collection("part", CollectionDeleteMode::Cascade).unwrap();
+        assert_eq!(sorted(deleted), vec!["d-chapter", "d-part"]);
+        assert!(db.get_collection("chapter").unwrap().is_none());
+        assert!(db.get_document("d-chapter").unwrap().is_none());
+        assert!(db.get_document("d-book").unwrap().is_some());
+        assert!(db.list_tags().unwrap().is_empty());
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn tag_counts(db: &Database) -> Vec<(String, usize)> {
+        db.list_tags().unwrap().into_iter().map(|tag| (tag.name, tag.document_count)).collect()
+    }
+
+    #[test]
+    fn adds_removes_and_cleans_up_tags() {
+        let db = database();
+        db.save_document(&document("a", "A", "")).unwrap();
+        db.save_document(&document("b", "B", "")).unwrap();
+        db.add_document_tags("a", &tags(&["draft", "小说", "draft"])).unwrap();
+        db.add_document_tags("b", &tags(&["draft"])).unwrap();
+
+        assert_eq!(db.get_document_tags("a").unwrap(), vec!["draft", "小说"]);
+        assert_eq!(tag_counts(&db), vec![("draft".to_string(), 2), ("小说".to_string(), 1)]);
+
+        db.remove_document_tags("a", &tags(&["小说", "missing"])).unwrap();
+        assert_eq!(tag_counts(&db), vec![("draft".to_string(), 2)]);
+        assert_eq!(db.list_documents().unwrap().iter().map(|d| d.tags.len()).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn renames_and_merges_tags() {
+        let db = database();
+        db.save_document(&document("a", "A", "")).unwrap();
+        db.save_document(&document("b", "B", "")).unwrap();
+        db.add_document_tags("a", &tags(&["todo", "wip"])).unwrap();
+        db.add_document_tags("b", &tags(&["wip"])).unwrap();
+
+        db.rename_tag("todo", "Todo").unwrap();
+        assert_eq!(db.get_document_tags("a").unwrap(), vec!["Todo", "wip"]);
+
+        // Renaming onto an existing tag merges without duplicates
+        db.rename_tag("wip", "Todo").unwrap();
+        assert_eq!(tag_counts(&db), vec![("Todo".to_string(), 2)]);
+        assert!(matches!(db.rename_tag("missing", "x"), Err(AppError::NotFound { .. })));
+
+        db.merge_tags(&tags(&["Todo", "missing"]), "done").unwrap();
+        assert_eq!(tag_counts(&db), vec![("done".to_string(), 2)]);
+    }
+
+    #[test]
+    fn stores_typed_metadata() {
+        let db = database();
+        db.save_document(&document("a", "A", "")).unwrap();
+        let due = Utc::now();
+        let values = [
+            ("status", MetadataValue::Text("draft".to_string())),
+            ("target", MetadataValue::Number(3000.0)),
+            ("ratio", MetadataValue::Number(0.25)),
+            ("published", MetadataValue::Boolean(false)),
+            ("due", MetadataValue::Date(due)),
+        ];
+        for (key, value) in &values {
+            db.set_document_metadata("a", key, Some(value)).unwrap();
+        }
+        let metadata = db.get_document("a").unwrap().unwrap().metadata;
+        assert_eq!(metadata.len(), values.len());
+        for (key, value) in &values {
+            assert_eq!(metadata.get(*key), Some(value), "{}", key);
+        }
+
+        db.set_document_metadata("a", "status", None).unwrap();
+        assert!(!db.get_document_metadata("a").unwrap().contains_key("status"));
+    }
+
+    #[test]
+    fn filters_by_tags_and_metadata() {
+        let db = database();
+        for (id, target, labels) in [("a", 1000.0, &["novel", "draft"][..]), ("b", 5000.0, &["novel"][..]), ("c", 200.0, &["poem"][..])] {
+            db.save_document(&document(id, id, "")).unwrap();
+            db.add_document_tags(id, &tags(labels)).unwrap();
+            db.set_document_metadata(id, "target", Some(&MetadataValue::Number(target))).unwrap();
+        }
+        db.set_document_metadata("a", "status", Some(&MetadataValue::Text("In Review".to_string()))).unwrap();
+
+        let ids = |query: DocumentListQuery| -> Vec<String> {
+            sorted(db.list_document_summaries(&query).unwrap().items.into_iter().map(|item| item.id).collect())
+        };
+        let predicate = |key: &str, op: MetadataOp, value: Option<MetadataValue>| MetadataPredicate {
+            key: key.to_string(),
+            op,
+            value,
+        };
+
+        assert_eq!(ids(DocumentListQuery { all_tags: tags(&["novel", "draft"]), ..Default::default() }), vec!["a"]);
+        assert_eq!(ids(DocumentListQuery { any_tags: tags(&["draft", "poem"]), ..Default::default() }), vec!["a", "c"]);
+        assert_eq!(ids(DocumentListQuery { exclude_tags: tags(&["draft"]), ..Default::default() }), vec!["b", "c"]);
+        assert_eq!(
+            ids(DocumentListQuery {
+                metadata: vec![predicate("target", MetadataOp::Gte, Some(MetadataValue::Number(1000.0)))],
+                ..Default::default()
+            }),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            ids(DocumentListQuery {
+                metadata: vec![predicate("status", MetadataOp::Contains, Some(MetadataValue::Text("review".to_string())))],
+                ..Default::default()
+            }),
+            vec!["a"]
+        );
+        assert_eq!(
+            ids(DocumentListQuery {
+                metadata: vec![predicate("status", MetadataOp::NotExists, None)],
+                ..Default::default()
+            }),
+            vec!["b", "c"]
+        );
+        let missing_value = DocumentListQuery {
+            metadata: vec![predicate("target", MetadataOp::Lt, None)],
+            ..Default::default()
+        };
+        assert!(matches!(db.list_document_summaries(&missing_value), Err(AppError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn counts_documents_containing_each_needle() {
+        let db = database();
+        db.save_document(&document("a", "A", "Semantic Analysis of 语义")).unwrap();
+        db.save_document(&document("b", "B", "semantic drift")).unwrap();
+        db.save_document(&document("c", "C", "语义分析")).unwrap();
+        // Only the content counts
+        db.save_document(&document("d", "Semantic 语义 of", "Nothing here, 50% off")).unwrap();
+
+        let needles: Vec<String> = ["SEMANTIC", "语义", "absent", "OF", "0%"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(db.count_documents_containing(&needles, "none").unwrap(), vec![2, 2, 0, 2, 1]);
+        assert_eq!(db.count_documents_containing(&needles, "a").unwrap(), vec![1, 1, 0, 1, 1]);
+        assert!(db.count_documents_containing(&[], "a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn hashes_content_with_sha256() {
+        assert_eq!(content_hash("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_ne!(content_hash("abc"), content_hash("abc "));
+    }
+
+    #[test]
+    fn finds_documents_by_stored_hash() {
+        let db = database();
+        db.save_document(&document("a", "A", "same text")).unwrap();
+        assert_eq!(db.find_document_by_content_hash(&content_hash("same text")).unwrap(), Some("a".to_string()));
+        assert_eq!(db.find_document_by_content_hash(&content_hash("other")).unwrap(), None);
+
+        // Rows from before migration 7 are hashed when the database opens
+        db.conn.execute("UPDATE documents SET content_hash = ''", []).unwrap();
+        assert_eq!(db.find_document_by_content_hash(&content_hash("same text")).unwrap(), None);
+        db.fill_in_documents().unwrap();
+        assert_eq!(db.find_document_by_content_hash(&content_hash("same text")).unwrap(), Some("a".to_string()));
+    }
+}
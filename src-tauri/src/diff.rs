@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+use crate::search::is_cjk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffGranularity {
+    Line,
+    Word,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffChange {
+    pub kind: ChangeKind,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TextDiff {
+    pub granularity: DiffGranularity,
+    pub changes: Vec<DiffChange>,
+    pub insertions: usize, // number of inserted lines or words
+    pub deletions: usize,
+}
+
+impl DiffGranularity {
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value {
+            None | Some("line") => Ok(DiffGranularity::Line),
+            Some("word") => Ok(DiffGranularity::Word),
+            Some(other) => Err(format!("Unknown diff granularity: {}", other)),
+        }
+    }
+}
+
+pub fn diff_text(old: &str, new: &str, granularity: DiffGranularity) -> TextDiff {
+    let (old_tokens, new_tokens) = match granularity {
+        DiffGranularity::Line => (split_lines(old), split_lines(new)),
+        DiffGranularity::Word => (split_words(old), split_words(new)),
+    };
+
+    let mut changes: Vec<DiffChange> = Vec::new();
+    let mut insertions = 0;
+    let mut deletions = 0;
+
+    let mut push = |kind: ChangeKind, tokens: &[&str]| {
+        if tokens.is_empty() {
+            return;
+        }
+        let text = tokens.concat();
+        // Coalesce adjacent runs of the same kind into one change
+        match changes.last_mut() {
+            Some(last) if last.kind == kind => last.text.push_str(&text),
+            _ => changes.push(DiffChange { kind, text }),
+        }
+    };
+
+    for op in capture_diff_slices(Algorithm::Myers, &old_tokens, &new_tokens) {
+        match op {
+            DiffOp::Equal { old_index, len, .. } => {
+                push(ChangeKind::Equal, &old_tokens[old_index..old_index + len]);
+            }
+            DiffOp::Delete { old_index, old_len, .. } => {
+                let removed = &old_tokens[old_index..old_index + old_len];
+                deletions += count_units(removed, granularity);
+                push(ChangeKind::Delete, removed);
+            }
+            DiffOp::Insert { new_index, new_len, .. } => {
+                let added = &new_tokens[new_index..new_index + new_len];
+                insertions += count_units(added, granularity);
+                push(ChangeKind::Insert, added);
+            }
+            DiffOp::Replace { old_index, old_len, new_index, new_len } => {
+                let removed = &old_tokens[old_index..old_index + old_len];
+                let added = &new_tokens[new_index..new_index + new_len];
+                deletions += count_units(removed, granularity);
+                insertions += count_units(added, granularity);
+                push(ChangeKind::Delete, removed);
+                push(ChangeKind::Insert, added);
+            }
+        }
+    }
+
+    TextDiff {
+        granularity,
+        changes,
+        insertions,
+        deletions,
+    }
+}
+
+// Lines keep their trailing newline so that concatenating changes
+// reproduces the original text exactly.
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+// Splits text into words, whitespace runs and punctuation. Each CJK
+// character is its own token, since CJK text has no word separators.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut prev_class = None;
+
+    for (i, c) in text.char_indices() {
+        let class = if is_cjk(c) {
+            0
+        } else if c.is_alphanumeric() || c == '_' {
+            1
+        } else if c.is_whitespace() {
+            2
+        } else {
+            3
+        };
+
+        // CJK characters and punctuation never merge with their neighbours
+        let boundary = match prev_class {
+            Some(prev) => prev != class || class == 0 || class == 3,
+            None => false,
+        };
+        if boundary {
+            tokens.push(&text[start..i]);
+            start = i;
+        }
+        prev_class = Some(class);
+    }
+
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+fn count_units(tokens: &[&str], granularity: DiffGranularity) -> usize {
+    match granularity {
+        DiffGranularity::Line => tokens.len(),
+        DiffGranularity::Word => tokens
+            .iter()
+            .filter(|t| t.chars().any(|c| c.is_alphanumeric()))
+            .count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rebuild(diff: &TextDiff, skip: ChangeKind) -> String {
+        diff.changes
+            .iter()
+            .filter(|change| change.kind != skip)
+            .map(|change| change.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn splits_cjk_into_single_characters() {
+        assert_eq!(split_words("中文abc"), vec!["中", "文", "abc"]);
+        assert_eq!(split_words("写作，很好"), vec!["写", "作", "，", "很", "好"]);
+    }
+
+    #[test]
+    fn keeps_latin_words_and_whitespace_runs() {
+        assert_eq!(split_words("hello  world_1!?"), vec!["hello", "  ", "world_1", "!", "?"]);
+        assert!(split_words("").is_empty());
+    }
+
+    #[test]
+    fn changes_rebuild_both_texts() {
+        let old = "first line\nsecond line\nthird";
+        let new = "first line\nchanged line\nthird\nfourth";
+        for granularity in [DiffGranularity::Line, DiffGranularity::Word] {
+            let diff = diff_text(old, new, granularity);
+            assert_eq!(rebuild(&diff, ChangeKind::Insert), old);
+            assert_eq!(rebuild(&diff, ChangeKind::Delete), new);
+        }
+    }
+
+    #[test]
+    fn counts_lines_and_words() {
+        let diff = diff_text("a\nb\nc\n", "a\nx\nc\ny\n", DiffGranularity::Line);
+        assert_eq!((diff.insertions, diff.deletions), (2, 1));
+
+        // Whitespace and punctuation are not counted as words
+        let diff = diff_text("the quick fox", "the slow, brown fox", DiffGranularity::Word);
+        assert_eq!((diff.insertions, diff.deletions), (2, 1));
+    }
+
+    #[test]
+    fn diffs_cjk_per_character() {
+        let diff = diff_text("我喜欢写作", "我喜欢阅读", DiffGranularity::Word);
+        let kinds: Vec<(ChangeKind, &str)> = diff.changes.iter().map(|c| (c.kind, c.text.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ChangeKind::Equal, "我喜欢"),
+                (ChangeKind::Delete, "写作"),
+                (ChangeKind::Insert, "阅读"),
+            ]
+        );
+        assert_eq!((diff.insertions, diff.deletions), (2, 2));
+    }
+
+    #[test]
+    fn parses_granularity() {
+        assert_eq!(DiffGranularity::parse(None), Ok(DiffGranularity::Line));
+        assert_eq!(DiffGranularity::parse(Some("word")), Ok(DiffGranularity::Word));
+        assert!(DiffGranularity::parse(Some("char")).is_err());
+    }
+}
@@ -0,0 +1,1270 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use quick_xml::events::{BytesStart, Event as XmlEvent};
+use quick_xml::Reader;
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::database::Document;
+
+// East Asian font used for CJK runs so exported documents render Chinese text
+// with a proper face instead of the Latin fallback.
+const EAST_ASIA_FONT: &str = "Microsoft YaHei";
+const CODE_FONT: &str = "Consolas";
+
+pub struct ImportedDocx {
+    pub title: Option<String>,
+    pub content: String, // Markdown
+}
+
+// ---------------------------------------------------------------------------
+// Import
+// ---------------------------------------------------------------------------
+
+pub fn import_docx(path: &Path) -> Result<ImportedDocx, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to open DOCX file: {}", e))?;
+    import_docx_bytes(&bytes)
+}
+
+pub fn import_docx_bytes(bytes: &[u8]) -> Result<ImportedDocx, String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| format!("Invalid DOCX archive: {}", e))?;
+
+    let document_xml = read_part(&mut archive, "word/document.xml")?
+        .ok_or("DOCX archive has no word/document.xml")?;
+    let styles = read_part(&mut archive, "word/styles.xml")?
+        .map(|xml| parse_styles(&xml))
+        .transpose()?
+        .unwrap_or_default();
+    let numbering = read_part(&mut archive, "word/numbering.xml")?
+        .map(|xml| parse_numbering(&xml))
+        .transpose()?
+        .unwrap_or_default();
+    let links = read_part(&mut archive, "word/_rels/document.xml.rels")?
+        .map(|xml| parse_relationships(&xml))
+        .transpose()?
+        .unwrap_or_default();
+
+    let context = ImportContext { styles, numbering, links };
+
+    let mut body = BodyParser::new(&context);
+    run_parser(&document_xml, |event| body.handle(event))?;
+    let title = body.title.take();
+    let mut content = render_blocks(&body.blocks);
+
+    if let Some(footnotes_xml) = read_part(&mut archive, "word/footnotes.xml")? {
+        let footnotes = parse_footnotes(&footnotes_xml, &context)?;
+        let referenced: Vec<_> = footnotes
+            .into_iter()
+            .filter(|(id, _)| content.contains(&format!("[^{}]", id)))
+            .collect();
+        if !referenced.is_empty() {
+            content.push_str("\n\n");
+            let definitions: Vec<String> = referenced
+                .iter()
+                .map(|(id, text)| format!("[^{}]: {}", id, text))
+                .collect();
+            content.push_str(&definitions.join("\n"));
+        }
+    }
+
+    Ok(ImportedDocx { title, content })
+}
+
+fn read_part<R: Read + std::io::Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<Option<String>, String> {
+    let mut part = match archive.by_name(name) {
+        Ok(part) => part,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {} from DOCX: {}", name, e)),
+    };
+    let mut xml = String::new();
+    part.read_to_string(&mut xml)
+        .map_err(|e| format!("Failed to read {} from DOCX: {}", name, e))?;
+    Ok(Some(xml))
+}
+
+fn run_parser<F>(xml: &str, mut handle: F) -> Result<(), String>
+where
+    F: FnMut(XmlItem),
+{
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(XmlEvent::Start(e)) => handle(XmlItem::Start(&e)),
+            Ok(XmlEvent::Empty(e)) => {
+                handle(XmlItem::Start(&e));
+                handle(XmlItem::End(e.name().as_ref()));
+            }
+            Ok(XmlEvent::End(e)) => handle(XmlItem::End(e.name().as_ref())),
+            Ok(XmlEvent::Text(t)) => {
+                let text = t.unescape().map_err(|e| format!("Invalid DOCX XML: {}", e))?;
+                handle(XmlItem::Text(&text));
+            }
+            Ok(XmlEvent::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(format!("Invalid DOCX XML: {}", e)),
+        }
+    }
+    Ok(())
+}
+
+enum XmlItem<'a> {
+    Start(&'a BytesStart<'a>),
+    End(&'a [u8]),
+    Text(&'a str),
+}
+
+fn attr(e: &BytesStart, name: &str) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name.as_bytes())
+        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+}
+
+// A `<w:b/>`-style toggle is on unless its value explicitly turns it off
+fn toggle_on(e: &BytesStart) -> bool {
+    !matches!(attr(e, "w:val").as_deref(), Some("0") | Some("false") | Some("off") | Some("none"))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ParagraphKind {
+    Title,
+    Heading(usize),
+    Quote,
+    Code,
+    ListBullet,
+    ListNumber,
+    Normal,
+}
+
+#[derive(Default)]
+struct ImportContext {
+    styles: HashMap<String, String>,            // styleId -> lowercase style name
+    numbering: HashMap<(String, u32), bool>,    // (numId, level) -> ordered
+    links: HashMap<String, String>,             // relationship id -> hyperlink target
+}
+
+impl ImportContext {
+    fn paragraph_kind(&self, style_id: Option<&str>) -> ParagraphKind {
+        let style_id = match style_id {
+            Some(id) => id,
+            None => return ParagraphKind::Normal,
+        };
+        let name = self.styles.get(style_id).cloned().unwrap_or_else(|| style_id.to_lowercase());
+        let compact = name.replace(' ', "");
+
+        if compact == "title" {
+            ParagraphKind::Title
+        } else if let Some(level) = compact.strip_prefix("heading").and_then(|l| l.parse::<usize>().ok()) {
+            ParagraphKind::Heading(level.clamp(1, 6))
+        } else if compact == "quote" || compact == "intensequote" {
+            ParagraphKind::Quote
+        } else if matches!(compact.as_str(), "code" | "sourcecode" | "htmlpreformatted" | "plaintext") {
+            ParagraphKind::Code
+        } else if compact.starts_with("listbullet") {
+            ParagraphKind::ListBullet
+        } else if compact.starts_with("listnumber") {
+            ParagraphKind::ListNumber
+        } else {
+            ParagraphKind::Normal
+        }
+    }
+}
+
+fn parse_styles(xml: &str) -> Result<HashMap<String, String>, String> {
+    let mut styles = HashMap::new();
+    let mut current: Option<String> = None;
+
+    run_parser(xml, |item| match item {
+        XmlItem::Start(e) if e.name().as_ref() == b"w:style" => current = attr(e, "w:styleId"),
+        XmlItem::Start(e) if e.name().as_ref() == b"w:name" => {
+            if let (Some(id), Some(name)) = (current.clone(), attr(e, "w:val")) {
+                styles.insert(id, name.to_lowercase());
+            }
+        }
+        XmlItem::End(b"w:style") => current = None,
+        _ => {}
+    })?;
+
+    Ok(styles)
+}
+
+fn parse_numbering(xml: &str) -> Result<HashMap<(String, u32), bool>, String> {
+    let mut abstract_formats: HashMap<(String, u32), bool> = HashMap::new();
+    let mut num_to_abstract: HashMap<String, String> = HashMap::new();
+    let mut current_abstract: Option<String> = None;
+    let mut current_level: Option<u32> = None;
+    let mut current_num: Option<String> = None;
+
+    run_parser(xml, |item| match item {
+        XmlItem::Start(e) => match e.name().as_ref() {
+            b"w:abstractNum" => current_abstract = attr(e, "w:abstractNumId"),
+            b"w:lvl" => current_level = attr(e, "w:ilvl").and_then(|l| l.parse().ok()),
+            b"w:numFmt" => {
+                if let (Some(abstract_id), Some(level)) = (current_abstract.clone(), current_level) {
+                    let ordered = attr(e, "w:val").is_some_and(|f| f != "bullet" && f != "none");
+                    abstract_formats.insert((abstract_id, level), ordered);
+                }
+            }
+            b"w:num" => current_num = attr(e, "w:numId"),
+            b"w:abstractNumId" => {
+                if let (Some(num_id), Some(abstract_id)) = (current_num.clone(), attr(e, "w:val")) {
+                    num_to_abstract.insert(num_id, abstract_id);
+                }
+            }
+            _ => {}
+        },
+        XmlItem::End(b"w:abstractNum") => current_abstract = None,
+        XmlItem::End(b"w:num") => current_num = None,
+        _ => {}
+    })?;
+
+    let mut numbering = HashMap::new();
+    for (num_id, abstract_id) in num_to_abstract {
+        for level in 0..9 {
+            if let Some(ordered) = abstract_formats.get(&(abstract_id.clone(), level)) {
+                numbering.insert((num_id.clone(), level), *ordered);
+            }
+        }
+    }
+    Ok(numbering)
+}
+
+fn parse_relationships(xml: &str) -> Result<HashMap<String, String>, String> {
+    let mut links = HashMap::new();
+    run_parser(xml, |item| {
+        if let XmlItem::Start(e) = item {
+            if e.name().as_ref() == b"Relationship"
+                && attr(e, "Type").is_some_and(|t| t.ends_with("/hyperlink"))
+            {
+                if let (Some(id), Some(target)) = (attr(e, "Id"), attr(e, "Target")) {
+                    links.insert(id, target);
+                }
+            }
+        }
+    })?;
+    Ok(links)
+}
+
+fn parse_footnotes(xml: &str, context: &ImportContext) -> Result<Vec<(String, String)>, String> {
+    let mut footnotes = Vec::new();
+    let mut body = BodyParser::new(context);
+    let mut current: Option<String> = None;
+
+    run_parser(xml, |item| match item {
+        XmlItem::Start(e) if e.name().as_ref() == b"w:footnote" => {
+            // Separator footnotes carry a type and no user content
+            current = if attr(e, "w:type").is_some() { None } else { attr(e, "w:id") };
+            body.blocks.clear();
+        }
+        XmlItem::End(b"w:footnote") => {
+            if let Some(id) = current.take() {
+                let text: Vec<String> = body.blocks.drain(..).map(|b| b.text).collect();
+                footnotes.push((id, text.join(" ").trim().to_string()));
+            }
+        }
+        item => body.handle(item),
+    })?;
+
+    Ok(footnotes)
+}
+
+#[derive(Clone, Default, PartialEq)]
+struct RunFormat {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    code: bool, // set in a monospace font
+    link: Option<String>,
+}
+
+struct Segment {
+    text: String,
+    format: RunFormat,
+    raw: bool, // already Markdown, e.g. a footnote reference
+}
+
+enum BlockKind {
+    Paragraph(ParagraphKind),
+    ListItem { ordered: bool, level: u32 },
+    Table(Vec<Vec<String>>),
+}
+
+struct Block {
+    kind: BlockKind,
+    text: String,
+}
+
+struct BodyParser<'a> {
+    context: &'a ImportContext,
+    blocks: Vec<Block>,
+    title: Option<String>,
+    // Paragraph state
+    style_id: Option<String>,
+    num_id: Option<String>,
+    num_level: u32,
+    segments: Vec<Segment>,
+    // Run state
+    in_run_properties: bool,
+    format: RunFormat,
+    link: Option<String>,
+    in_text: bool,
+    // Table state; nested tables are flattened into their outer cell
+    table_depth: usize,
+    rows: Vec<Vec<String>>,
+    cell: Vec<String>,
+}
+
+impl<'a> BodyParser<'a> {
+    fn new(context: &'a ImportContext) -> Self {
+        BodyParser {
+            context,
+            blocks: Vec::new(),
+            title: None,
+            style_id: None,
+            num_id: None,
+            num_level: 0,
+            segments: Vec::new(),
+            in_run_properties: false,
+            format: RunFormat::default(),
+            link: None,
+            in_text: false,
+            table_depth: 0,
+            rows: Vec::new(),
+            cell: Vec::new(),
+        }
+    }
+
+    fn handle(&mut self, item: XmlItem) {
+        match item {
+            XmlItem::Start(e) => self.start(e),
+            XmlItem::End(name) => self.end(name),
+            XmlItem::Text(text) => {
+                if self.in_text {
+                    self.push_text(text.to_string(), false);
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, e: &BytesStart) {
+        match e.name().as_ref() {
+            b"w:p" => {
+                self.style_id = None;
+                self.num_id = None;
+                self.num_level = 0;
+                self.segments.clear();
+            }
+            b"w:pStyle" => self.style_id = attr(e, "w:val"),
+            b"w:numId" => self.num_id = attr(e, "w:val").filter(|id| id != "0"),
+            b"w:ilvl" => self.num_level = attr(e, "w:val").and_then(|l| l.parse().ok()).unwrap_or(0),
+            b"w:r" => self.format = RunFormat::default(),
+            b"w:rPr" => self.in_run_properties = true,
+            b"w:b" if self.in_run_properties => self.format.bold = toggle_on(e),
+            b"w:i" if self.in_run_properties => self.format.italic = toggle_on(e),
+            b"w:strike" | b"w:dstrike" if self.in_run_properties => self.format.strike = toggle_on(e),
+            b"w:rFonts" if self.in_run_properties => {
+                self.format.code = attr(e, "w:ascii").is_some_and(|font| is_monospace(&font));
+            }
+            b"w:hyperlink" => {
+                self.link = attr(e, "r:id")
+                    .and_then(|id| self.context.links.get(&id).cloned())
+                    .or_else(|| attr(e, "w:anchor").map(|anchor| format!("#{}", anchor)));
+            }
+            b"w:t" => self.in_text = true,
+            b"w:tab" if !self.in_run_properties => self.push_text("\t".to_string(), false),
+            b"w:br" | b"w:cr" => self.push_text("\n".to_string(), false),
+            b"w:footnoteReference" => {
+                if let Some(id) = attr(e, "w:id") {
+                    self.push_text(format!("[^{}]", id), true);
+                }
+            }
+            b"w:tbl" => {
+                self.table_depth += 1;
+                if self.table_depth == 1 {
+                    self.rows.clear();
+                }
+            }
+            b"w:tr" if self.table_depth == 1 => self.rows.push(Vec::new()),
+            b"w:tc" if self.table_depth == 1 => self.cell.clear(),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) {
+        match name {
+            b"w:rPr" => self.in_run_properties = false,
+            b"w:t" => self.in_text = false,
+            b"w:hyperlink" => self.link = None,
+            b"w:p" => self.finish_paragraph(),
+            b"w:tc" if self.table_depth == 1 => {
+                let text = self.cell.join(" ").replace('|', "\\|");
+                if let Some(row) = self.rows.last_mut() {
+                    row.push(text);
+                }
+            }
+            b"w:tbl" => {
+                self.table_depth = self.table_depth.saturating_sub(1);
+                if self.table_depth == 0 && !self.rows.is_empty() {
+                    let rows = std::mem::take(&mut self.rows);
+                    self.blocks.push(Block { kind: BlockKind::Table(rows), text: String::new() });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push_text(&mut self, text: String, raw: bool) {
+        let mut format = self.format.clone();
+        format.link = self.link.clone();
+        self.segments.push(Segment { text, format, raw });
+    }
+
+    fn finish_paragraph(&mut self) {
+        let segments = std::mem::take(&mut self.segments);
+        let plain: String = segments.iter().map(|s| s.text.as_str()).collect();
+        let text = render_segments(&segments);
+
+        if self.table_depth > 0 {
+            if !text.trim().is_empty() {
+                self.cell.push(text.trim().replace('\n', " "));
+            }
+            return;
+        }
+
+        let kind = self.context.paragraph_kind(self.style_id.as_deref());
+        let numbered = self.num_id.as_ref().map(|num_id| {
+            self.context.numbering.get(&(num_id.clone(), self.num_level)).copied().unwrap_or(false)
+        });
+
+        let kind = match (numbered, kind) {
+            (Some(ordered), ParagraphKind::Normal | ParagraphKind::ListBullet | ParagraphKind::ListNumber) => {
+                BlockKind::ListItem { ordered, level: self.num_level }
+            }
+            (None, ParagraphKind::ListBullet) => BlockKind::ListItem { ordered: false, level: 0 },
+            (None, ParagraphKind::ListNumber) => BlockKind::ListItem { ordered: true, level: 0 },
+            (_, kind) => BlockKind::Paragraph(kind),
+        };
+
+        // Consecutive code paragraphs form one block, blank lines included
+        if let BlockKind::Paragraph(ParagraphKind::Code) = kind {
+            match self.blocks.last_mut() {
+                Some(Block { kind: BlockKind::Paragraph(ParagraphKind::Code), text }) => {
+                    text.push('\n');
+                    text.push_str(&plain);
+                }
+                _ => self.blocks.push(Block { kind, text: plain }),
+            }
+            return;
+        }
+
+        if text.trim().is_empty() {
+            return;
+        }
+
+        // The first Title paragraph becomes the document title
+        if let BlockKind::Paragraph(ParagraphKind::Title) = kind {
+            if self.title.is_none() && self.blocks.is_empty() {
+                self.title = Some(plain.trim().to_string());
+                return;
+            }
+        }
+
+        self.blocks.push(Block { kind, text: text.trim().to_string() });
+    }
+}
+
+fn is_monospace(font: &str) -> bool {
+    let font = font.to_lowercase();
+    ["consolas", "courier", "menlo", "monaco", "lucida console", "source code", "mono"]
+        .iter()
+        .any(|name| font.contains(name))
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn render_segments(segments: &[Segment]) -> String {
+    // Merge adjacent runs with identical formatting; Word splits runs freely
+    let mut merged: Vec<(String, RunFormat)> = Vec::new();
+    for segment in segments {
+        let text = if segment.raw || segment.format.code {
+            segment.text.clone()
+        } else {
+            escape_markdown(&segment.text)
+        };
+        match merged.last_mut() {
+            Some((last, format)) if *format == segment.format => last.push_str(&text),
+            _ => merged.push((text, segment.format.clone())),
+        }
+    }
+
+    let mut out = String::new();
+    let mut i = 0;
+    while i < merged.len() {
+        let link = merged[i].1.link.clone();
+        let mut inner = String::new();
+        while i < merged.len() && merged[i].1.link == link {
+            inner.push_str(&wrap_format(&merged[i].0, &merged[i].1));
+            i += 1;
+        }
+        match link {
+            Some(url) => out.push_str(&format!("[{}]({})", inner, url)),
+            None => out.push_str(&inner),
+        }
+    }
+    out
+}
+
+// Wraps text in emphasis markers, keeping surrounding whitespace outside the
+// markers as Markdown requires.
+fn wrap_format(text: &str, format: &RunFormat) -> String {
+    if format.code && !text.trim().is_empty() {
+        // Double backticks with padding let the span contain a literal backtick
+        return if text.contains('`') {
+            format!("`` {} ``", text)
+        } else {
+            format!("`{}`", text)
+        };
+    }
+
+    let marker = match (format.bold, format.italic) {
+        (true, true) => "***",
+        (true, false) => "**",
+        (false, true) => "*",
+        (false, false) => "",
+    };
+    let strike = if format.strike { "~~" } else { "" };
+    if marker.is_empty() && strike.is_empty() {
+        return text.to_string();
+    }
+
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+    format!("{}{}{}{}{}{}{}", leading, strike, marker, trimmed, marker, strike, trailing)
+}
+
+fn render_blocks(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    // Whether the open top-level list is ordered; None outside lists
+    let mut open_list: Option<bool> = None;
+
+    for block in blocks {
+        let list = match block.kind {
+            BlockKind::ListItem { ordered, level } => Some((ordered, level)),
+            _ => None,
+        };
+        // Top-level items of another list type start a new list
+        let same_list = match (open_list, list) {
+            (Some(was_ordered), Some((ordered, 0))) => was_ordered == ordered,
+            (Some(_), Some(_)) => true,
+            _ => false,
+        };
+        if !out.is_empty() {
+            out.push_str(if same_list { "\n" } else { "\n\n" });
+        }
+
+        match &block.kind {
+            BlockKind::Paragraph(ParagraphKind::Title) => out.push_str(&format!("# {}", block.text)),
+            BlockKind::Paragraph(ParagraphKind::Heading(level)) => {
+                out.push_str(&format!("{} {}", "#".repeat(*level), block.text.replace('\n', " ")))
+            }
+            BlockKind::Paragraph(ParagraphKind::Code) => out.push_str(&format!("```\n{}\n```", block.text)),
+            BlockKind::Paragraph(ParagraphKind::Quote) => {
+                let quoted: Vec<String> = block.text.lines().map(|l| format!("> {}", l)).collect();
+                out.push_str(&quoted.join("\n"));
+            }
+            BlockKind::Paragraph(_) => out.push_str(&block.text.replace('\n', "  \n")),
+            BlockKind::ListItem { ordered, level } => {
+                let marker = if *ordered { "1." } else { "-" };
+                let indent = " ".repeat(*level as usize * if *ordered { 3 } else { 2 });
+                out.push_str(&format!("{}{} {}", indent, marker, block.text.replace('\n', " ")));
+            }
+            BlockKind::Table(rows) => out.push_str(&render_table(rows)),
+        }
+        open_list = match list {
+            Some((ordered, 0)) => Some(ordered),
+            Some(_) => open_list,
+            None => None,
+        };
+    }
+
+    out
+}
+
+fn render_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0).max(1);
+    let render_row = |row: &Vec<String>| {
+        let cells: Vec<&str> = (0..columns).map(|i| row.get(i).map(|c| c.as_str()).unwrap_or("")).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![render_row(&rows[0])];
+    lines.push(format!("|{}", " --- |".repeat(columns)));
+    lines.extend(rows[1..].iter().map(render_row));
+    lines.join("\n")
+}
+
+// ---------------------------------------------------------------------------
+// Export
+// ---------------------------------------------------------------------------
+
+pub fn export_docx(document: &Document) -> Result<Vec<u8>, String> {
+    let mut writer = BodyWriter::default();
+    writer.paragraph_with_style("Title", &document.title);
+    writer.write_markdown(&document.content);
+
+    let package = [
+        ("[Content_Types].xml", content_types_xml()),
+        ("_rels/.rels", ROOT_RELS_XML.to_string()),
+        ("docProps/core.xml", core_properties_xml(document)),
+        ("word/document.xml", writer.document_xml()),
+        ("word/styles.xml", styles_xml()),
+        ("word/numbering.xml", writer.numbering_xml()),
+        ("word/footnotes.xml", writer.footnotes_xml()),
+        ("word/_rels/document.xml.rels", writer.relationships_xml()),
+    ];
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, xml) in package {
+        zip.start_file(name, options).map_err(|e| format!("Failed to write DOCX: {}", e))?;
+        zip.write_all(xml.as_bytes()).map_err(|e| format!("Failed to write DOCX: {}", e))?;
+    }
+
+    let cursor = zip.finish().map_err(|e| format!("Failed to write DOCX: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Clone, Copy, Default)]
+struct InlineFormat {
+    bold: u32,
+    italic: u32,
+    strike: u32,
+}
+
+struct ListState {
+    num_id: usize,
+}
+
+#[derive(Default)]
+struct BodyWriter {
+    body: String,
+    // Paragraph being built, and the properties it will open with
+    paragraph: Option<String>,
+    pending_style: Option<&'static str>,
+    pending_numbering: Option<(usize, usize)>,
+    quote_depth: usize,
+    format: InlineFormat,
+    link_rel: Option<String>,
+    lists: Vec<ListState>,
+    ordered_lists: usize, // each ordered list restarts at its own start number
+    ordered_starts: Vec<u64>,
+    code_block: Option<String>,
+    // Tables
+    table_columns: usize,
+    cell_open: bool,
+    cell_has_paragraph: bool,
+    // Footnotes: label -> (id, body xml)
+    footnotes: Vec<(String, String)>,
+    footnote_ids: HashMap<String, usize>,
+    in_footnote: Option<usize>,
+    saved_body: Option<String>,
+    links: Vec<String>,
+}
+
+impl BodyWriter {
+    fn paragraph_with_style(&mut self, style: &'static str, text: &str) {
+        self.pending_style = Some(style);
+        self.ensure_paragraph();
+        self.push_run(text);
+        self.close_paragraph();
+    }
+
+    fn write_markdown(&mut self, markdown: &str) {
+        let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+
+        for event in Parser::new_ext(markdown, options) {
+            match event {
+                Event::Start(tag) => self.start_tag(tag),
+                Event::End(tag) => self.end_tag(tag),
+                Event::Text(text) => {
+                    if let Some(code) = self.code_block.as_mut() {
+                        code.push_str(&text);
+                    } else {
+                        self.ensure_paragraph();
+                        self.push_run(&text);
+                    }
+                }
+                Event::Code(code) => {
+                    self.ensure_paragraph();
+                    self.push_code_run(&code);
+                }
+                Event::Html(html) | Event::InlineHtml(html) => {
+                    self.ensure_paragraph();
+                    self.push_run(&html);
+                }
+                Event::SoftBreak => {
+                    self.ensure_paragraph();
+                    self.push_run(" ");
+                }
+                Event::HardBreak => {
+                    self.ensure_paragraph();
+                    self.write("<w:r><w:br/></w:r>");
+                }
+                Event::Rule => {
+                    self.close_paragraph();
+                    self.write(
+                        "<w:p><w:pPr><w:pBdr><w:bottom w:val=\"single\" w:sz=\"6\" w:space=\"1\" w:color=\"auto\"/></w:pBdr></w:pPr></w:p>",
+                    );
+                }
+                Event::FootnoteReference(label) => {
+                    let id = self.footnote_id(&label);
+                    self.ensure_paragraph();
+                    self.write(&format!(
+                        "<w:r><w:rPr><w:rStyle w:val=\"FootnoteReference\"/></w:rPr><w:footnoteReference w:id=\"{}\"/></w:r>",
+                        id
+                    ));
+                }
+                Event::TaskListMarker(checked) => {
+                    self.ensure_paragraph();
+                    self.push_run(if checked { "☑ " } else { "☐ " });
+                }
+                _ => {}
+            }
+        }
+        self.close_paragraph();
+    }
+
+    fn start_tag(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.close_paragraph(),
+            Tag::Heading { level, .. } => {
+                self.close_paragraph();
+                self.pending_style = Some(match level {
+                    HeadingLevel::H1 => "Heading1",
+                    HeadingLevel::H2 => "Heading2",
+                    HeadingLevel::H3 => "Heading3",
+                    HeadingLevel::H4 => "Heading4",
+                    HeadingLevel::H5 => "Heading5",
+                    HeadingLevel::H6 => "Heading6",
+                });
+            }
+            Tag::BlockQuote(_) => {
+                self.close_paragraph();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(_) => {
+                self.close_paragraph();
+                self.code_block = Some(String::new());
+            }
+            Tag::List(start) => {
+                self.close_paragraph();
+                let num_id = match start {
+                    Some(start) => {
+                        self.ordered_lists += 1;
+                        self.ordered_starts.push(start);
+                        // numId 1 is the shared bullet list; ordered lists follow
+                        self.ordered_lists + 1
+                    }
+                    None => 1,
+                };
+                self.lists.push(ListState { num_id });
+            }
+            Tag::Item => {
+                self.close_paragraph();
+                if let Some(list) = self.lists.last() {
+                    self.pending_numbering = Some((list.num_id, self.lists.len() - 1));
+                }
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.close_paragraph();
+                let id = self.footnote_id(&label);
+                self.in_footnote = Some(id);
+                self.saved_body = Some(std::mem::take(&mut self.body));
+            }
+            Tag::Table(alignments) => {
+                self.close_paragraph();
+                self.table_columns = alignments.len().max(1);
+                let width = 9000 / self.table_columns;
+                let grid: String = (0..self.table_columns)
+                    .map(|_| format!("<w:gridCol w:w=\"{}\"/>", width))
+                    .collect();
+                self.write(&format!(
+                    "<w:tbl><w:tblPr><w:tblStyle w:val=\"TableGrid\"/><w:tblW w:w=\"0\" w:type=\"auto\"/></w:tblPr><w:tblGrid>{}</w:tblGrid>",
+                    grid
+                ));
+            }
+            Tag::TableHead => self.write("<w:tr><w:trPr><w:tblHeader/></w:trPr>"),
+            Tag::TableRow => self.write("<w:tr>"),
+            Tag::TableCell => {
+                self.write("<w:tc><w:tcPr><w:tcW w:w=\"0\" w:type=\"auto\"/></w:tcPr>");
+                self.cell_open = true;
+                self.cell_has_paragraph = false;
+            }
+            Tag::Emphasis => self.format.italic += 1,
+            Tag::Strong => self.format.bold += 1,
+            Tag::Strikethrough => self.format.strike += 1,
+            Tag::Link { dest_url, .. } => {
+                self.ensure_paragraph();
+                if let Some(anchor) = dest_url.strip_prefix('#') {
+                    self.write(&format!("<w:hyperlink w:anchor=\"{}\">", escape_xml(anchor)));
+                } else {
+                    self.links.push(dest_url.to_string());
+                    let rel_id = format!("rIdLink{}", self.links.len());
+                    self.write(&format!("<w:hyperlink r:id=\"{}\">", rel_id));
+                }
+                self.link_rel = Some(dest_url.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn end_tag(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::Heading(_) => self.close_paragraph(),
+            TagEnd::BlockQuote(_) => {
+                self.close_paragraph();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => {
+                if let Some(code) = self.code_block.take() {
+                    for line in code.trim_end_matches('\n').split('\n') {
+                        self.pending_style = Some("Code");
+                        self.ensure_paragraph();
+                        self.push_code_run(line);
+                        self.close_paragraph();
+                    }
+                }
+            }
+            TagEnd::List(_) => {
+                self.close_paragraph();
+                self.lists.pop();
+            }
+            TagEnd::Item => {
+                self.close_paragraph();
+                self.pending_numbering = None;
+            }
+            TagEnd::FootnoteDefinition => {
+                self.close_paragraph();
+                if let (Some(id), Some(saved)) = (self.in_footnote.take(), self.saved_body.take()) {
+                    let footnote_body = std::mem::replace(&mut self.body, saved);
+                    self.footnotes.push((id.to_string(), footnote_body));
+                }
+            }
+            TagEnd::Table => self.write("</w:tbl>"),
+            TagEnd::TableHead | TagEnd::TableRow => self.write("</w:tr>"),
+            TagEnd::TableCell => {
+                self.close_paragraph();
+                if !self.cell_has_paragraph {
+                    self.write("<w:p/>");
+                }
+                self.write("</w:tc>");
+                self.cell_open = false;
+            }
+            TagEnd::Emphasis => self.format.italic = self.format.italic.saturating_sub(1),
+            TagEnd::Strong => self.format.bold = self.format.bold.saturating_sub(1),
+            TagEnd::Strikethrough => self.format.strike = self.format.strike.saturating_sub(1),
+            TagEnd::Link if self.link_rel.take().is_some() => self.write("</w:hyperlink>"),
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, xml: &str) {
+        match self.paragraph.as_mut() {
+            Some(paragraph) => paragraph.push_str(xml),
+            None => self.body.push_str(xml),
+        }
+    }
+
+    fn footnote_id(&mut self, label: &str) -> usize {
+        let next = self.footnote_ids.len() + 1;
+        *self.footnote_ids.entry(label.to_string()).or_insert(next)
+    }
+
+    fn ensure_paragraph(&mut self) {
+        if self.paragraph.is_some() {
+            return;
+        }
+
+        let mut properties = String::new();
+        let style = match (self.pending_style.take(), self.in_footnote) {
+            (Some(style), _) => Some(style),
+            (None, Some(_)) => Some("FootnoteText"),
+            (None, None) if self.quote_depth > 0 => Some("Quote"),
+            (None, None) if !self.lists.is_empty() => Some("ListParagraph"),
+            _ => None,
+        };
+        if let Some(style) = style {
+            properties.push_str(&format!("<w:pStyle w:val=\"{}\"/>", style));
+        }
+        match self.pending_numbering.take() {
+            Some((num_id, level)) => properties.push_str(&format!(
+                "<w:numPr><w:ilvl w:val=\"{}\"/><w:numId w:val=\"{}\"/></w:numPr>",
+                level.min(8),
+                num_id
+            )),
+            // Continuation paragraphs inside a list item line up with its text
+            None if !self.lists.is_empty() => properties.push_str(&format!(
+                "<w:ind w:left=\"{}\"/>",
+                720 * self.lists.len()
+            )),
+            None => {}
+        }
+
+        if self.cell_open {
+            self.cell_has_paragraph = true;
+        }
+        self.paragraph = Some(if properties.is_empty() {
+            "<w:p>".to_string()
+        } else {
+            format!("<w:p><w:pPr>{}</w:pPr>", properties)
+        });
+    }
+
+    fn close_paragraph(&mut self) {
+        if let Some(mut paragraph) = self.paragraph.take() {
+            paragraph.push_str("</w:p>");
+            self.body.push_str(&paragraph);
+        }
+    }
+
+    fn run_properties(&self, code: bool) -> String {
+        let mut properties = String::new();
+        if self.link_rel.is_some() {
+            properties.push_str("<w:rStyle w:val=\"Hyperlink\"/>");
+        }
+        if code {
+            properties.push_str(&format!(
+                "<w:rFonts w:ascii=\"{0}\" w:hAnsi=\"{0}\" w:cs=\"{0}\"/>",
+                CODE_FONT
+            ));
+        }
+        if self.format.bold > 0 {
+            properties.push_str("<w:b/><w:bCs/>");
+        }
+        if self.format.italic > 0 {
+            properties.push_str("<w:i/><w:iCs/>");
+        }
+        if self.format.strike > 0 {
+            properties.push_str("<w:strike/>");
+        }
+        if properties.is_empty() {
+            properties
+        } else {
+            format!("<w:rPr>{}</w:rPr>", properties)
+        }
+    }
+
+    fn push_run(&mut self, text: &str) {
+        let run = format!(
+            "<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>",
+            self.run_properties(false),
+            escape_xml(text)
+        );
+        self.write(&run);
+    }
+
+    fn push_code_run(&mut self, text: &str) {
+        let run = format!(
+            "<w:r>{}<w:t xml:space=\"preserve\">{}</w:t></w:r>",
+            self.run_properties(true),
+            escape_xml(text)
+        );
+        self.write(&run);
+    }
+
+    fn document_xml(&self) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+             <w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}\
+             <w:sectPr><w:pgSz w:w=\"11906\" w:h=\"16838\"/>\
+             <w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/>\
+             </w:sectPr></w:body></w:document>",
+            NS_W, NS_R, self.body
+        )
+    }
+
+    fn numbering_xml(&self) -> String {
+        let levels = |ordered: bool| -> String {
+            (0..9)
+                .map(|level| {
+                    let (format, text) = if ordered {
+                        ("decimal", format!("%{}.", level + 1))
+                    } else {
+                        ("bullet", ["•", "◦", "▪"][level % 3].to_string())
+                    };
+                    format!(
+                        "<w:lvl w:ilvl=\"{}\"><w:start w:val=\"1\"/><w:numFmt w:val=\"{}\"/>\
+                         <w:lvlText w:val=\"{}\"/><w:lvlJc w:val=\"left\"/>\
+                         <w:pPr><w:ind w:left=\"{}\" w:hanging=\"360\"/></w:pPr></w:lvl>",
+                        level,
+                        format,
+                        text,
+                        720 * (level + 1)
+                    )
+                })
+                .collect()
+        };
+
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+             <w:numbering xmlns:w=\"{}\">\
+             <w:abstractNum w:abstractNumId=\"0\"><w:multiLevelType w:val=\"hybridMultilevel\"/>{}</w:abstractNum>\
+             <w:abstractNum w:abstractNumId=\"1\"><w:multiLevelType w:val=\"hybridMultilevel\"/>{}</w:abstractNum>\
+             <w:num w:numId=\"1\"><w:abstractNumId w:val=\"0\"/></w:num>",
+            NS_W,
+            levels(false),
+            levels(true)
+        );
+        for (i, start) in self.ordered_starts.iter().enumerate() {
+            xml.push_str(&format!(
+                "<w:num w:numId=\"{}\"><w:abstractNumId w:val=\"1\"/>\
+                 <w:lvlOverride w:ilvl=\"0\"><w:startOverride w:val=\"{}\"/></w:lvlOverride></w:num>",
+                i + 2,
+                start
+            ));
+        }
+        xml.push_str("</w:numbering>");
+        xml
+    }
+
+    fn footnotes_xml(&self) -> String {
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+             <w:footnotes xmlns:w=\"{}\" xmlns:r=\"{}\">\
+             <w:footnote w:type=\"separator\" w:id=\"-1\"><w:p><w:r><w:separator/></w:r></w:p></w:footnote>\
+             <w:footnote w:type=\"continuationSeparator\" w:id=\"0\"><w:p><w:r><w:continuationSeparator/></w:r></w:p></w:footnote>",
+            NS_W, NS_R
+        );
+
+        // References without a definition still need a footnote to be valid
+        let mut ids: Vec<usize> = self.footnote_ids.values().copied().collect();
+        ids.sort();
+        for id in ids {
+            let body = self
+                .footnotes
+                .iter()
+                .find(|(footnote_id, _)| *footnote_id == id.to_string())
+                .map(|(_, body)| body.clone())
+                .unwrap_or_else(|| "<w:p/>".to_string());
+            xml.push_str(&format!("<w:footnote w:id=\"{}\">{}</w:footnote>", id, body));
+        }
+
+        xml.push_str("</w:footnotes>");
+        xml
+    }
+
+    fn relationships_xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+             <Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+             <Relationship Id=\"rIdStyles\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles\" Target=\"styles.xml\"/>\
+             <Relationship Id=\"rIdNumbering\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering\" Target=\"numbering.xml\"/>\
+             <Relationship Id=\"rIdFootnotes\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/footnotes\" Target=\"footnotes.xml\"/>",
+        );
+        for (i, target) in self.links.iter().enumerate() {
+            xml.push_str(&format!(
+                "<Relationship Id=\"rIdLink{}\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink\" Target=\"{}\" TargetMode=\"External\"/>",
+                i + 1,
+                escape_xml(target)
+            ));
+        }
+        xml.push_str("</Relationships>");
+        xml
+    }
+}
+
+const NS_W: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+const NS_R: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+const ROOT_RELS_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" Target=\"word/document.xml\"/>\
+<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" Target=\"docProps/core.xml\"/>\
+</Relationships>";
+
+fn content_types_xml() -> String {
+    let main = "application/vnd.openxmlformats-officedocument.wordprocessingml";
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+         <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+         <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+         <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+         <Override PartName=\"/word/document.xml\" ContentType=\"{0}.document.main+xml\"/>\
+         <Override PartName=\"/word/styles.xml\" ContentType=\"{0}.styles+xml\"/>\
+         <Override PartName=\"/word/numbering.xml\" ContentType=\"{0}.numbering+xml\"/>\
+         <Override PartName=\"/word/footnotes.xml\" ContentType=\"{0}.footnotes+xml\"/>\
+         <Override PartName=\"/docProps/core.xml\" ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\"/>\
+         </Types>",
+        main
+    )
+}
+
+fn core_properties_xml(document: &Document) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+         <cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\
+         <dc:title>{}</dc:title>\
+         <dcterms:created xsi:type=\"dcterms:W3CDTF\">{}</dcterms:created>\
+         <dcterms:modified xsi:type=\"dcterms:W3CDTF\">{}</dcterms:modified>\
+         </cp:coreProperties>",
+        escape_xml(&document.title),
+        document.created_at.format("%Y-%m-%dT%H:%M:%SZ"),
+        document.updated_at.format("%Y-%m-%dT%H:%M:%SZ")
+    )
+}
+
+fn styles_xml() -> String {
+    let heading = |level: usize, size: usize| {
+        format!(
+            "<w:style w:type=\"paragraph\" w:styleId=\"Heading{0}\"><w:name w:val=\"heading {0}\"/>\
+             <w:basedOn w:val=\"Normal\"/><w:next w:val=\"Normal\"/><w:qFormat/>\
+             <w:pPr><w:keepNext/><w:spacing w:before=\"240\" w:after=\"120\"/><w:outlineLvl w:val=\"{1}\"/></w:pPr>\
+             <w:rPr><w:b/><w:bCs/><w:sz w:val=\"{2}\"/><w:szCs w:val=\"{2}\"/></w:rPr></w:style>",
+            level,
+            level - 1,
+            size
+        )
+    };
+    let headings: String = [(1, 36), (2, 32), (3, 28), (4, 26), (5, 24), (6, 22)]
+        .iter()
+        .map(|(level, size)| heading(*level, *size))
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+         <w:styles xmlns:w=\"{ns}\">\
+         <w:docDefaults><w:rPrDefault><w:rPr>\
+         <w:rFonts w:ascii=\"Calibri\" w:hAnsi=\"Calibri\" w:eastAsia=\"{east_asia}\" w:cs=\"Calibri\"/>\
+         <w:sz w:val=\"22\"/><w:szCs w:val=\"22\"/><w:lang w:val=\"en-US\" w:eastAsia=\"zh-CN\"/>\
+         </w:rPr></w:rPrDefault>\
+         <w:pPrDefault><w:pPr><w:spacing w:after=\"160\" w:line=\"276\" w:lineRule=\"auto\"/></w:pPr></w:pPrDefault>\
+         </w:docDefaults>\
+         <w:style w:type=\"paragraph\" w:default=\"1\" w:styleId=\"Normal\"><w:name w:val=\"Normal\"/><w:qFormat/></w:style>\
+         <w:style w:type=\"paragraph\" w:styleId=\"Title\"><w:name w:val=\"Title\"/><w:basedOn w:val=\"Normal\"/>\
+         <w:next w:val=\"Normal\"/><w:qFormat/><w:pPr><w:spacing w:after=\"240\"/></w:pPr>\
+         <w:rPr><w:b/><w:bCs/><w:sz w:val=\"48\"/><w:szCs w:val=\"48\"/></w:rPr></w:style>\
+         {headings}\
+         <w:style w:type=\"paragraph\" w:styleId=\"Quote\"><w:name w:val=\"Quote\"/><w:basedOn w:val=\"Normal\"/>\
+         <w:qFormat/><w:pPr><w:ind w:left=\"720\" w:right=\"720\"/></w:pPr><w:rPr><w:i/><w:iCs/><w:color w:val=\"595959\"/></w:rPr></w:style>\
+         <w:style w:type=\"paragraph\" w:styleId=\"ListParagraph\"><w:name w:val=\"List Paragraph\"/>\
+         <w:basedOn w:val=\"Normal\"/><w:qFormat/><w:pPr><w:spacing w:after=\"60\"/><w:contextualSpacing/></w:pPr></w:style>\
+         <w:style w:type=\"paragraph\" w:styleId=\"Code\"><w:name w:val=\"Code\"/><w:basedOn w:val=\"Normal\"/>\
+         <w:pPr><w:spacing w:after=\"0\" w:line=\"240\" w:lineRule=\"auto\"/><w:shd w:val=\"clear\" w:color=\"auto\" w:fill=\"F2F2F2\"/></w:pPr>\
+         <w:rPr><w:rFonts w:ascii=\"{code}\" w:hAnsi=\"{code}\" w:cs=\"{code}\"/><w:sz w:val=\"20\"/></w:rPr></w:style>\
+         <w:style w:type=\"paragraph\" w:styleId=\"FootnoteText\"><w:name w:val=\"footnote text\"/><w:basedOn w:val=\"Normal\"/>\
+         <w:pPr><w:spacing w:after=\"0\"/></w:pPr><w:rPr><w:sz w:val=\"18\"/></w:rPr></w:style>\
+         <w:style w:type=\"character\" w:styleId=\"FootnoteReference\"><w:name w:val=\"footnote reference\"/>\
+         <w:rPr><w:vertAlign w:val=\"superscript\"/></w:rPr></w:style>\
+         <w:style w:type=\"character\" w:styleId=\"Hyperlink\"><w:name w:val=\"Hyperlink\"/>\
+         <w:rPr><w:color w:val=\"0563C1\"/><w:u w:val=\"single\"/></w:rPr></w:style>\
+         <w:style w:type=\"table\" w:styleId=\"TableGrid\"><w:name w:val=\"Table Grid\"/><w:tblPr><w:tblBorders>\
+         <w:top w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"auto\"/>\
+         <w:left w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"auto\"/>\
+         <w:bottom w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"auto\"/>\
+         <w:right w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"auto\"/>\
+         <w:insideH w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"auto\"/>\
+         <w:insideV w:val=\"single\" w:sz=\"4\" w:space=\"0\" w:color=\"auto\"/>\
+         </w:tblBorders></w:tblPr></w:style>\
+         </w:styles>",
+        ns = NS_W,
+        east_asia = EAST_ASIA_FONT,
+        code = CODE_FONT,
+        headings = headings
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn document(title: &str, content: &str) -> Document {
+        Document {
+            id: "doc".to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: "unsorted".to_string(),
+            tags: Vec::new(),
+            metadata: Default::default(),
+        }
+    }
+
+    fn round_trip(content: &str) -> ImportedDocx {
+        let bytes = export_docx(&document("Round trip", content)).unwrap();
+        import_docx_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    fn round_trips_block_structure() {
+        let markdown = "# Heading one\n\n\
+                        ## Heading two\n\n\
+                        Plain **bold** *italic* ~~struck~~ `code` and a [link](https://example.com/a).\n\n\
+                        - first\n\
+                        - second\n  \
+                          - nested\n\n\
+                        1. one\n\
+                        1. two\n\n\
+                        | Name | Value |\n\
+                        | --- | --- |\n\
+                        | a | 1 |\n\
+                        | 中文 | 2 |\n\n\
+                        > Quoted text\n\n\
+                        Text with a note.[^1]\n\n\
+                        [^1]: The footnote text.";
+        let imported = round_trip(markdown);
+        assert_eq!(imported.title.as_deref(), Some("Round trip"));
+        assert_eq!(imported.content, markdown);
+    }
+
+    #[test]
+    fn round_trips_code_blocks_and_line_breaks() {
+        let markdown = "```\nfn main() {\n\n    println!(\"hi\");\n}\n```\n\nfirst line  \nsecond line";
+        assert_eq!(round_trip(markdown).content, markdown);
+    }
+
+    #[test]
+    fn keeps_links_inside_tables_and_lists() {
+        let markdown = "- see [docs](https://example.com/docs)\n\n\
+                        | Link |\n\
+                        | --- |\n\
+                        | [home](https://example.com/) |";
+        assert_eq!(round_trip(markdown).content, markdown);
+    }
+
+    #[test]
+    fn rejects_non_docx_bytes() {
+        assert!(import_docx_bytes(b"not a zip").is_err());
+    }
+}
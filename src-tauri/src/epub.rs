@@ -0,0 +1,541 @@
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::database::Document;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EpubMetadata {
+    pub title: String,
+    pub author: Option<String>,
+    pub language: String, // BCP 47 tag, e.g. "zh-CN"
+    pub description: Option<String>,
+    pub cover_image_path: Option<String>,
+    pub identifier: Option<String>, // a urn:uuid is generated when unset
+}
+
+impl Default for EpubMetadata {
+    fn default() -> Self {
+        Self {
+            title: String::new(),
+            author: None,
+            language: "zh-CN".to_string(),
+            description: None,
+            cover_image_path: None,
+            identifier: None,
+        }
+    }
+}
+
+// Headings deeper than this stay out of the table of contents
+const TOC_DEPTH: usize = 3;
+
+const STYLESHEET: &str = "body{font-family:serif;line-height:1.7;margin:0 5%;}\
+h1,h2,h3,h4,h5,h6{font-family:sans-serif;line-height:1.3;page-break-after:avoid;}\
+h1{margin-top:2em;text-align:center;}\
+blockquote{margin:1em 1.5em;font-style:italic;}\
+pre{white-space:pre-wrap;font-size:.85em;}code{font-family:monospace;}\
+table{border-collapse:collapse;}th,td{border:1px solid #999;padding:.2em .5em;}\
+img{max-width:100%;}\
+.cover{text-align:center;margin:0;padding:0;}.cover img{max-height:100%;}";
+
+struct TocEntry {
+    level: usize,
+    title: String,
+    href: String,
+}
+
+struct Chapter {
+    file_name: String,
+    xhtml: String,
+    toc: Vec<TocEntry>,
+}
+
+struct Cover {
+    file_name: String,
+    media_type: &'static str,
+    data: Vec<u8>,
+}
+
+pub fn build_epub(documents: &[Document], metadata: &EpubMetadata) -> Result<Vec<u8>, String> {
+    if documents.is_empty() {
+        return Err("An EPUB needs at least one document".to_string());
+    }
+    if metadata.title.trim().is_empty() {
+        return Err("EPUB title cannot be empty".to_string());
+    }
+    if metadata.language.trim().is_empty() {
+        return Err("EPUB language cannot be empty".to_string());
+    }
+
+    let cover = metadata.cover_image_path.as_deref().map(load_cover).transpose()?;
+    let chapters: Vec<Chapter> = documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| render_chapter(document, index + 1, &metadata.language))
+        .collect();
+
+    let mut files: Vec<(String, Vec<u8>)> = vec![
+        ("mimetype".to_string(), b"application/epub+zip".to_vec()),
+        ("META-INF/container.xml".to_string(), CONTAINER_XML.as_bytes().to_vec()),
+        ("OEBPS/content.opf".to_string(), package_document(&chapters, metadata, cover.as_ref()).into_bytes()),
+        ("OEBPS/nav.xhtml".to_string(), navigation_document(&chapters, metadata).into_bytes()),
+        ("OEBPS/style.css".to_string(), STYLESHEET.as_bytes().to_vec()),
+    ];
+    if let Some(cover) = &cover {
+        files.push((format!("OEBPS/{}", cover.file_name), cover.data.clone()));
+        files.push(("OEBPS/cover.xhtml".to_string(), cover_page(cover, metadata).into_bytes()));
+    }
+    for chapter in &chapters {
+        files.push((format!("OEBPS/{}", chapter.file_name), chapter.xhtml.clone().into_bytes()));
+    }
+
+    validate_package(&files)?;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in &files {
+        // The mimetype entry must come first and be stored uncompressed
+        let method = if name == "mimetype" { CompressionMethod::Stored } else { CompressionMethod::Deflated };
+        zip.start_file(name.as_str(), SimpleFileOptions::default().compression_method(method))
+            .map_err(|e| format!("Failed to write EPUB: {}", e))?;
+        zip.write_all(data).map_err(|e| format!("Failed to write EPUB: {}", e))?;
+    }
+    let cursor = zip.finish().map_err(|e| format!("Failed to write EPUB: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+fn load_cover(path: &str) -> Result<Cover, String> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let (media_type, extension) = match extension.as_deref() {
+        Some("jpg") | Some("jpeg") => ("image/jpeg", "jpg"),
+        Some("png") => ("image/png", "png"),
+        Some("gif") => ("image/gif", "gif"),
+        Some("webp") => ("image/webp", "webp"),
+        Some("svg") => ("image/svg+xml", "svg"),
+        _ => return Err("Cover image must be a JPEG, PNG, GIF, WebP or SVG file".to_string()),
+    };
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read cover image: {}", e))?;
+    Ok(Cover {
+        file_name: format!("cover.{}", extension),
+        media_type,
+        data,
+    })
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_chapter(document: &Document, number: usize, language: &str) -> Chapter {
+    let file_name = format!("chapter-{:03}.xhtml", number);
+    let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+
+    let mut events: Vec<Event> = Parser::new_ext(&document.content, options)
+        .filter_map(|event| match event {
+            // Raw HTML is escaped; it is rarely well-formed XHTML
+            Event::Html(raw) | Event::InlineHtml(raw) => Some(Event::Text(raw)),
+            // Images are not packaged, so only their alt text is kept
+            Event::Start(Tag::Image { .. }) | Event::End(TagEnd::Image) => None,
+            event => Some(event),
+        })
+        .collect();
+
+    // Give every heading an anchor and collect the table of contents
+    let mut toc = Vec::new();
+    let mut heading_count = 0;
+    let mut index = 0;
+    while index < events.len() {
+        if let Event::Start(Tag::Heading { level, id, .. }) = &mut events[index] {
+            heading_count += 1;
+            let anchor = format!("h{}", heading_count);
+            *id = Some(CowStr::from(anchor.clone()));
+            let level = *level as usize;
+
+            let mut title = String::new();
+            for event in &events[index + 1..] {
+                match event {
+                    Event::End(TagEnd::Heading(_)) => break,
+                    Event::Text(text) | Event::Code(text) => title.push_str(text),
+                    _ => {}
+                }
+            }
+            if level <= TOC_DEPTH && !title.trim().is_empty() {
+                toc.push(TocEntry {
+                    level,
+                    title: title.trim().to_string(),
+                    href: format!("{}#{}", file_name, anchor),
+                });
+            }
+        }
+        index += 1;
+    }
+
+    let mut body = String::new();
+    html::push_html(&mut body, events.into_iter());
+
+    // Chapters open with the document title unless the content already does
+    let opens_with_title = toc.first().is_some_and(|entry| entry.level == 1 && entry.title == document.title.trim());
+    let mut entries = Vec::new();
+    if opens_with_title {
+        entries.push(TocEntry { level: 1, title: document.title.trim().to_string(), href: file_name.clone() });
+        entries.extend(toc.into_iter().skip(1).map(|entry| TocEntry { level: entry.level.max(2), ..entry }));
+    } else {
+        body = format!("<h1>{}</h1>\n{}", escape_xml(&document.title), body);
+        entries.push(TocEntry { level: 1, title: document.title.trim().to_string(), href: file_name.clone() });
+        entries.extend(toc.into_iter().map(|entry| TocEntry { level: entry.level + 1, ..entry }));
+    }
+
+    let xhtml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{lang}\" xml:lang=\"{lang}\">\n\
+         <head>\n<meta charset=\"utf-8\"/>\n<title>{title}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+         <body>\n<section epub:type=\"chapter\">\n{body}</section>\n</body>\n</html>\n",
+        lang = escape_xml(language),
+        title = escape_xml(&document.title),
+        body = body
+    );
+
+    Chapter { file_name, xhtml, toc: entries }
+}
+
+const CONTAINER_XML: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+<rootfiles>\n<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>\n</rootfiles>\n\
+</container>\n";
+
+fn package_document(chapters: &[Chapter], metadata: &EpubMetadata, cover: Option<&Cover>) -> String {
+    let identifier = metadata
+        .identifier
+        .clone()
+        .unwrap_or_else(|| format!("urn:uuid:{}", Uuid::new_v4()));
+
+    let mut meta = format!(
+        "<dc:identifier id=\"book-id\">{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n\
+         <meta property=\"dcterms:modified\">{}</meta>\n",
+        escape_xml(&identifier),
+        escape_xml(metadata.title.trim()),
+        escape_xml(metadata.language.trim()),
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    );
+    if let Some(author) = metadata.author.as_deref().filter(|a| !a.trim().is_empty()) {
+        meta.push_str(&format!("<dc:creator>{}</dc:creator>\n", escape_xml(author.trim())));
+    }
+    if let Some(description) = metadata.description.as_deref().filter(|d| !d.trim().is_empty()) {
+        meta.push_str(&format!("<dc:description>{}</dc:description>\n", escape_xml(description.trim())));
+    }
+
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         <item id=\"css\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    let mut spine = String::new();
+    if let Some(cover) = cover {
+        // EPUB 2 readers look for the cover through this meta element
+        meta.push_str("<meta name=\"cover\" content=\"cover-image\"/>\n");
+        manifest.push_str(&format!(
+            "<item id=\"cover-image\" href=\"{}\" media-type=\"{}\" properties=\"cover-image\"/>\n\
+             <item id=\"cover\" href=\"cover.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            cover.file_name, cover.media_type
+        ));
+        spine.push_str("<itemref idref=\"cover\" linear=\"no\"/>\n");
+    }
+    for (index, chapter) in chapters.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            index + 1,
+            chapter.file_name
+        ));
+        spine.push_str(&format!("<itemref idref=\"chapter-{}\"/>\n", index + 1));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n\
+         <manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+        escape_xml(metadata.language.trim()),
+        meta,
+        manifest,
+        spine
+    )
+}
+
+fn navigation_document(chapters: &[Chapter], metadata: &EpubMetadata) -> String {
+    // Build nested <ol> lists from the flat, levelled heading entries
+    let mut list = String::new();
+    let mut depth = 0;
+    for entry in chapters.iter().flat_map(|chapter| chapter.toc.iter()) {
+        let level = entry.level.clamp(1, depth + 1);
+        if level > depth {
+            list.push_str("\n<ol>");
+        } else {
+            list.push_str("</li>");
+            for _ in level..depth {
+                list.push_str("</ol></li>");
+            }
+        }
+        depth = level;
+        list.push_str(&format!("\n<li><a href=\"{}\">{}</a>", escape_xml(&entry.href), escape_xml(&entry.title)));
+    }
+    for _ in 0..depth {
+        list.push_str("</li></ol>");
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{lang}\" xml:lang=\"{lang}\">\n\
+         <head>\n<meta charset=\"utf-8\"/>\n<title>{title}</title>\n</head>\n\
+         <body>\n<nav epub:type=\"toc\" id=\"toc\">\n<h1>{title}</h1>{list}\n</nav>\n</body>\n</html>\n",
+        lang = escape_xml(metadata.language.trim()),
+        title = escape_xml(metadata.title.trim()),
+        list = list
+    )
+}
+
+fn cover_page(cover: &Cover, metadata: &EpubMetadata) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+         <head>\n<meta charset=\"utf-8\"/>\n<title>{title}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+         <body class=\"cover\">\n<section epub:type=\"cover\"><img src=\"{src}\" alt=\"{title}\"/></section>\n</body>\n</html>\n",
+        title = escape_xml(metadata.title.trim()),
+        src = cover.file_name
+    )
+}
+
+// Checks the package before it is written: required entries, every manifest
+// and spine reference resolves, and all XML parts are well-formed.
+fn validate_package(files: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let mut problems = Vec::new();
+    let names: HashSet<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+
+    if files.first().map(|(name, _)| name.as_str()) != Some("mimetype") {
+        problems.push("mimetype must be the first entry".to_string());
+    }
+    for required in ["META-INF/container.xml", "OEBPS/content.opf", "OEBPS/nav.xhtml"] {
+        if !names.contains(required) {
+            problems.push(format!("missing {}", required));
+        }
+    }
+
+    for (name, data) in files {
+        let is_xml = name.ends_with(".xhtml") || name.ends_with(".opf") || name.ends_with(".xml");
+        if !is_xml {
+            continue;
+        }
+        let text = match std::str::from_utf8(data) {
+            Ok(text) => text,
+            Err(_) => {
+                problems.push(format!("{} is not valid UTF-8", name));
+                continue;
+            }
+        };
+
+        let mut reader = Reader::from_str(text);
+        let mut manifest_ids = HashSet::new();
+        let mut spine_refs = Vec::new();
+        loop {
+            match reader.read_event() {
+                Ok(XmlEvent::Eof) => break,
+                Ok(XmlEvent::Start(e)) | Ok(XmlEvent::Empty(e)) if name.ends_with(".opf") => {
+                    let attribute = |key: &[u8]| {
+                        e.attributes()
+                            .flatten()
+                            .find(|a| a.key.as_ref() == key)
+                            .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+                    };
+                    match e.name().as_ref() {
+                        b"item" => {
+                            if let Some(id) = attribute(b"id") {
+                                manifest_ids.insert(id);
+                            }
+                            if let Some(href) = attribute(b"href") {
+                                if !names.contains(format!("OEBPS/{}", href).as_str()) {
+                                    problems.push(format!("manifest item {} does not exist", href));
+                                }
+                            }
+                        }
+                        b"itemref" => spine_refs.extend(attribute(b"idref")),
+                        _ => {}
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    problems.push(format!("{} is not well-formed: {}", name, e));
+                    break;
+                }
+            }
+        }
+        for idref in spine_refs.iter().filter(|idref| !manifest_ids.contains(*idref)) {
+            problems.push(format!("spine references unknown item {}", idref));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid EPUB structure: {}", problems.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::UNSORTED_COLLECTION_ID;
+    use std::collections::BTreeMap;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    fn document(title: &str, content: &str) -> Document {
+        Document {
+            id: title.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    fn metadata() -> EpubMetadata {
+        EpubMetadata {
+            title: "Notes & Essays".to_string(),
+            author: Some("Lin".to_string()),
+            description: Some("Collected notes".to_string()),
+            identifier: Some("urn:isbn:9780000000000".to_string()),
+            ..EpubMetadata::default()
+        }
+    }
+
+    fn read_entry(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut text = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    fn position(text: &str, needle: &str) -> usize {
+        text.find(needle).unwrap_or_else(|| panic!("{} not found in {}", needle, text))
+    }
+
+    #[test]
+    fn packages_chapters_in_order() {
+        let documents = vec![
+            document("第一章", "Opening text.\n\n## Section A\n\nBody\n\n### Detail\n\n#### Too deep"),
+            document("Two", "# Two\n\nRaw <b>html</b> stays text."),
+        ];
+        let bytes = build_epub(&documents, &metadata()).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+
+        {
+            let mimetype = archive.by_index(0).unwrap();
+            assert_eq!(mimetype.name(), "mimetype");
+            assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        }
+        assert_eq!(read_entry(&mut archive, "mimetype"), "application/epub+zip");
+
+        let opf = read_entry(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("<dc:title>Notes &amp; Essays</dc:title>"));
+        assert!(opf.contains("<dc:creator>Lin</dc:creator>"));
+        assert!(opf.contains("<dc:description>Collected notes</dc:description>"));
+        assert!(opf.contains("<dc:identifier id=\"book-id\">urn:isbn:9780000000000</dc:identifier>"));
+        assert!(opf.contains("<dc:language>zh-CN</dc:language>"));
+        assert!(position(&opf, "<itemref idref=\"chapter-1\"/>") < position(&opf, "<itemref idref=\"chapter-2\"/>"));
+        assert!(!opf.contains("cover"));
+
+        let nav = read_entry(&mut archive, "OEBPS/nav.xhtml");
+        let entries = [
+            "<a href=\"chapter-001.xhtml\">第一章</a>",
+            "<a href=\"chapter-001.xhtml#h1\">Section A</a>",
+            "<a href=\"chapter-001.xhtml#h2\">Detail</a>",
+            "<a href=\"chapter-002.xhtml\">Two</a>",
+        ];
+        let positions: Vec<usize> = entries.iter().map(|entry| position(&nav, entry)).collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(!nav.contains("Too deep"));
+
+        // A chapter gets the document title as its heading only when it has none
+        let first = read_entry(&mut archive, "OEBPS/chapter-001.xhtml");
+        assert!(first.contains("<h1>第一章</h1>"));
+        assert!(first.contains("<h2 id=\"h1\">Section A</h2>"));
+        let second = read_entry(&mut archive, "OEBPS/chapter-002.xhtml");
+        assert_eq!(second.matches("<h1").count(), 1);
+        assert!(second.contains("Raw &lt;b&gt;html&lt;/b&gt; stays text."));
+    }
+
+    #[test]
+    fn includes_a_cover_image() {
+        let path = std::env::temp_dir().join(format!("epub-cover-{}.PNG", std::process::id()));
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\nimage").unwrap();
+        let metadata = EpubMetadata {
+            cover_image_path: Some(path.to_string_lossy().to_string()),
+            ..metadata()
+        };
+        let bytes = build_epub(&[document("One", "Text")], &metadata);
+        let _ = std::fs::remove_file(&path);
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes.unwrap())).unwrap();
+        let opf = read_entry(&mut archive, "OEBPS/content.opf");
+        assert!(opf.contains("href=\"cover.png\" media-type=\"image/png\" properties=\"cover-image\""));
+        assert!(position(&opf, "<itemref idref=\"cover\" linear=\"no\"/>") < position(&opf, "<itemref idref=\"chapter-1\"/>"));
+        assert!(archive.by_name("OEBPS/cover.png").is_ok());
+        assert!(read_entry(&mut archive, "OEBPS/cover.xhtml").contains("<img src=\"cover.png\""));
+    }
+
+    #[test]
+    fn rejects_unsupported_covers_and_missing_input() {
+        let metadata = EpubMetadata { cover_image_path: Some("/tmp/cover.bmp".to_string()), ..metadata() };
+        let error = build_epub(&[document("One", "Text")], &metadata).unwrap_err();
+        assert!(error.starts_with("Cover image must be"), "{}", error);
+        assert!(load_cover("/tmp/no-extension").is_err());
+        assert!(matches!(load_cover("/no/such/cover.jpg"), Err(e) if e.starts_with("Failed to read cover image")));
+
+        assert!(build_epub(&[], &metadata).is_err());
+        let untitled = EpubMetadata { title: " ".to_string(), ..EpubMetadata::default() };
+        assert!(build_epub(&[document("One", "Text")], &untitled).is_err());
+    }
+
+    #[test]
+    fn validation_reports_broken_packages() {
+        let file = |name: &str, text: &str| (name.to_string(), text.as_bytes().to_vec());
+        let opf = "<package><manifest>\
+                   <item id=\"nav\" href=\"nav.xhtml\"/><item id=\"ch\" href=\"chapter-001.xhtml\"/>\
+                   </manifest><spine><itemref idref=\"ch\"/><itemref idref=\"gone\"/></spine></package>";
+        let files = vec![
+            file("META-INF/container.xml", CONTAINER_XML),
+            file("mimetype", "application/epub+zip"),
+            file("OEBPS/content.opf", opf),
+            file("OEBPS/nav.xhtml", "<html><body></html>"),
+        ];
+        let error = validate_package(&files).unwrap_err();
+        assert!(error.contains("mimetype must be the first entry"), "{}", error);
+        assert!(error.contains("manifest item chapter-001.xhtml does not exist"), "{}", error);
+        assert!(error.contains("spine references unknown item gone"), "{}", error);
+        assert!(error.contains("OEBPS/nav.xhtml is not well-formed"), "{}", error);
+
+        let error = validate_package(&[file("mimetype", "application/epub+zip")]).unwrap_err();
+        assert!(error.contains("missing OEBPS/content.opf"), "{}", error);
+    }
+}
@@ -0,0 +1,420 @@
+use std::fmt;
+use std::io;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+pub type Result<T, E = AppError> = std::result::Result<T, E>;
+
+// Things that can be missing; names are shown to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Document,
+    Collection,
+    Revision,
+    Tag,
+    Snapshot,
+    File,
+    Workspace,
+}
+
+impl Entity {
+    fn name_en(self) -> &'static str {
+        match self {
+            Entity::Document => "Document",
+            Entity::Collection => "Collection",
+            Entity::Revision => "Revision",
+            Entity::Tag => "Tag",
+            Entity::Snapshot => "Snapshot",
+            Entity::File => "File",
+            Entity::Workspace => "Workspace folder",
+        }
+    }
+
+    fn name_zh(self) -> &'static str {
+        match self {
+            Entity::Document => "文档",
+            Entity::Collection => "文集",
+            Entity::Revision => "修订版本",
+            Entity::Tag => "标签",
+            Entity::Snapshot => "快照",
+            Entity::File => "文件",
+            Entity::Workspace => "工作区文件夹",
+        }
+    }
+}
+
+// Broad groups the frontend can branch on without knowing every code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCategory {
+    Validation, // the request itself is wrong; fix the input
+    NotFound,
+    Conflict,   // valid request, but not in the library's current state
+    Storage,    // disk, file system or database trouble; may be transient
+    Corruption, // stored or imported data is damaged
+    Conversion, // the input was accepted but could not be rendered or read
+    Internal,
+}
+
+impl ErrorCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::Validation => "validation",
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::Conflict => "conflict",
+            ErrorCategory::Storage => "storage",
+            ErrorCategory::Corruption => "corruption",
+            ErrorCategory::Conversion => "conversion",
+            ErrorCategory::Internal => "internal",
+        }
+    }
+}
+
+// Every failure surfaced by the database, file and storage layers and by the
+// Tauri commands. Serialized as
+// `{ code, category, message_en, message_zh, details }`, where `details` is
+// the technical chain from the outermost context down to the root cause.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound { entity: Entity, id: Option<String> },
+    // Input errors carry their own message in both languages
+    InvalidInput { en: String, zh: String },
+    Conflict { en: String, zh: String },
+    // A path outside the folders the file commands may touch
+    AccessDenied(String),
+    UnsupportedFormat(String),
+    // Rendering to or reading from DOCX, PDF, LaTeX, EPUB and the like failed
+    Conversion(String),
+    Corrupt(String),
+    Database(rusqlite::Error),
+    Io(io::Error),
+    Busy(String),
+    LockPoisoned(&'static str), // what the lock guards
+    Internal(String),
+    // Describes what was being done when `source` happened
+    Context { context: String, source: Box<AppError> },
+}
+
+impl AppError {
+    pub fn not_found(entity: Entity) -> Self {
+        AppError::NotFound { entity, id: None }
+    }
+
+    pub fn not_found_id(entity: Entity, id: impl Into<String>) -> Self {
+        AppError::NotFound { entity, id: Some(id.into()) }
+    }
+
+    pub fn invalid(en: impl Into<String>, zh: impl Into<String>) -> Self {
+        AppError::InvalidInput { en: en.into(), zh: zh.into() }
+    }
+
+    pub fn conflict(en: impl Into<String>, zh: impl Into<String>) -> Self {
+        AppError::Conflict { en: en.into(), zh: zh.into() }
+    }
+
+    pub fn lock(what: &'static str) -> Self {
+        AppError::LockPoisoned(what)
+    }
+
+    pub fn context(self, context: impl Into<String>) -> Self {
+        AppError::Context { context: context.into(), source: Box::new(self) }
+    }
+
+    // The error underneath any context wrappers
+    pub fn root(&self) -> &AppError {
+        match self {
+            AppError::Context { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    // Stable identifier; the frontend matches on these, so never rename one
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NOT_FOUND",
+            AppError::InvalidInput { .. } => "INVALID_INPUT",
+            AppError::Conflict { .. } => "CONFLICT",
+            AppError::AccessDenied(_) => "ACCESS_DENIED",
+            AppError::UnsupportedFormat(_) => "UNSUPPORTED_FORMAT",
+            AppError::Conversion(_) => "CONVERSION_FAILED",
+            AppError::Corrupt(_) => "CORRUPT_DATA",
+            AppError::Database(e) => database_code(e),
+            AppError::Io(e) => io_code(e),
+            AppError::Busy(_) => "BUSY",
+            AppError::LockPoisoned(_) => "LOCK_POISONED",
+            AppError::Internal(_) => "INTERNAL",
+            AppError::Context { source, .. } => source.code(),
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match self.code() {
+            "INVALID_INPUT" | "ACCESS_DENIED" | "UNSUPPORTED_FORMAT" => ErrorCategory::Validation,
+            "CONVERSION_FAILED" => ErrorCategory::Conversion,
+            "NOT_FOUND" | "FILE_NOT_FOUND" => ErrorCategory::NotFound,
+            "CONFLICT" => ErrorCategory::Conflict,
+            "CORRUPT_DATA" => ErrorCategory::Corruption,
+            "LOCK_POISONED" | "INTERNAL" => ErrorCategory::Internal,
+            _ => ErrorCategory::Storage,
+        }
+    }
+
+    pub fn message_en(&self) -> String {
+        match self.root() {
+            AppError::NotFound { entity, .. } => format!("{} not found", entity.name_en()),
+            AppError::InvalidInput { en, .. } | AppError::Conflict { en, .. } => en.clone(),
+            _ => match self.code() {
+                "ACCESS_DENIED" => "This location is outside the folders the app may access",
+                "UNSUPPORTED_FORMAT" => "This file format is not supported",
+                "CONVERSION_FAILED" => "The document could not be converted",
+                "CORRUPT_DATA" => "The data is damaged and cannot be read",
+                "DISK_FULL" => "The disk is full",
+                "PERMISSION_DENIED" => "Permission denied",
+                "FILE_NOT_FOUND" => "File not found",
+                "IO_ERROR" => "A file could not be read or written",
+                "BUSY" => "The library is busy; please try again",
+                "DATABASE_ERROR" => "A database error occurred",
+                "LOCK_POISONED" => "An earlier failure left the app in a bad state; please restart it",
+                _ => "An unexpected error occurred",
+            }
+            .to_string(),
+        }
+    }
+
+    pub fn message_zh(&self) -> String {
+        match self.root() {
+            AppError::NotFound { entity, .. } => format!("找不到{}", entity.name_zh()),
+            AppError::InvalidInput { zh, .. } | AppError::Conflict { zh, .. } => zh.clone(),
+            _ => match self.code() {
+                "ACCESS_DENIED" => "该位置不在应用可访问的文件夹内",
+                "UNSUPPORTED_FORMAT" => "不支持此文件格式",
+                "CONVERSION_FAILED" => "文档格式转换失败",
+                "CORRUPT_DATA" => "数据已损坏，无法读取",
+                "DISK_FULL" => "磁盘空间不足",
+                "PERMISSION_DENIED" => "没有访问权限",
+                "FILE_NOT_FOUND" => "找不到文件",
+                "IO_ERROR" => "文件读写失败",
+                "BUSY" => "资料库正忙，请稍后重试",
+                "DATABASE_ERROR" => "数据库出错",
+                "LOCK_POISONED" => "先前的错误导致应用状态异常，请重启应用",
+                _ => "发生意外错误",
+            }
+            .to_string(),
+        }
+    }
+
+    // Technical messages from the outermost context to the root cause
+    pub fn details(&self) -> Vec<String> {
+        let mut details = Vec::new();
+        let mut current = self;
+        while let AppError::Context { context, source } = current {
+            details.push(context.clone());
+            current = source;
+        }
+        details.push(current.describe());
+
+        let mut cause = match current {
+            AppError::Database(e) => std::error::Error::source(e),
+            AppError::Io(e) => std::error::Error::source(e),
+            _ => None,
+        };
+        while let Some(error) = cause {
+            let message = error.to_string();
+            // rusqlite repeats the SQLite message in its source
+            if details.last() != Some(&message) {
+                details.push(message);
+            }
+            cause = error.source();
+        }
+        details
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            AppError::NotFound { entity, id: Some(id) } => format!("{} not found: {}", entity.name_en(), id),
+            AppError::NotFound { entity, id: None } => format!("{} not found", entity.name_en()),
+            AppError::InvalidInput { en, .. } | AppError::Conflict { en, .. } => en.clone(),
+            AppError::AccessDenied(detail)
+            | AppError::UnsupportedFormat(detail)
+            | AppError::Conversion(detail)
+            | AppError::Corrupt(detail)
+            | AppError::Busy(detail)
+            | AppError::Internal(detail) => detail.clone(),
+            AppError::Database(e) => e.to_string(),
+            AppError::Io(e) => e.to_string(),
+            AppError::LockPoisoned(what) => format!("Failed to acquire {} lock", what),
+            AppError::Context { context, .. } => context.clone(),
+        }
+    }
+}
+
+fn database_code(error: &rusqlite::Error) -> &'static str {
+    use rusqlite::ErrorCode;
+    match error.sqlite_error_code() {
+        Some(ErrorCode::DiskFull) => "DISK_FULL",
+        Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked) => "BUSY",
+        Some(ErrorCode::PermissionDenied) | Some(ErrorCode::ReadOnly) => "PERMISSION_DENIED",
+        Some(ErrorCode::DatabaseCorrupt) | Some(ErrorCode::NotADatabase) => "CORRUPT_DATA",
+        Some(ErrorCode::ConstraintViolation) => "CONFLICT",
+        Some(ErrorCode::SystemIoFailure) => "IO_ERROR",
+        _ => "DATABASE_ERROR",
+    }
+}
+
+fn io_code(error: &io::Error) -> &'static str {
+    match error.kind() {
+        io::ErrorKind::StorageFull => "DISK_FULL",
+        io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => "PERMISSION_DENIED",
+        io::ErrorKind::NotFound => "FILE_NOT_FOUND",
+        _ => "IO_ERROR",
+    }
+}
+
+// Reads like the old string errors: "Failed to save document: disk I/O error"
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Context { context, source } => write!(f, "{}: {}", context, source),
+            other => f.write_str(&other.describe()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Context { source, .. } => Some(source.as_ref()),
+            AppError::Database(e) => Some(e),
+            AppError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 5)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("category", self.category().as_str())?;
+        state.serialize_field("message_en", &self.message_en())?;
+        state.serialize_field("message_zh", &self.message_zh())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        AppError::Database(error)
+    }
+}
+
+impl From<io::Error> for AppError {
+    fn from(error: io::Error) -> Self {
+        AppError::Io(error)
+    }
+}
+
+impl From<zip::result::ZipError> for AppError {
+    fn from(error: zip::result::ZipError) -> Self {
+        match error {
+            zip::result::ZipError::Io(e) => AppError::Io(e),
+            other => AppError::Corrupt(other.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        if error.is_io() {
+            return AppError::Io(error.into());
+        }
+        AppError::Corrupt(error.to_string())
+    }
+}
+
+// Adds what was being attempted to any error that converts into AppError
+pub trait ResultExt<T> {
+    fn context(self, context: &str) -> Result<T>;
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<AppError>> ResultExt<T> for std::result::Result<T, E> {
+    fn context(self, context: &str) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<F: FnOnce() -> String>(self, context: F) -> Result<T> {
+        self.map_err(|e| e.into().context(context()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqlite_error(code: std::os::raw::c_int) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(code), None)
+    }
+
+    #[test]
+    fn maps_each_variant_to_a_code_and_category() {
+        let cases = [
+            (AppError::not_found(Entity::Document), "NOT_FOUND", ErrorCategory::NotFound),
+            (AppError::invalid("Bad", "错误"), "INVALID_INPUT", ErrorCategory::Validation),
+            (AppError::conflict("Taken", "已占用"), "CONFLICT", ErrorCategory::Conflict),
+            (AppError::UnsupportedFormat("x.bin".into()), "UNSUPPORTED_FORMAT", ErrorCategory::Validation),
+            (AppError::AccessDenied("/etc".into()), "ACCESS_DENIED", ErrorCategory::Validation),
+            (AppError::Conversion("pdf".into()), "CONVERSION_FAILED", ErrorCategory::Conversion),
+            (AppError::Corrupt("bad json".into()), "CORRUPT_DATA", ErrorCategory::Corruption),
+            (AppError::Busy("writer".into()), "BUSY", ErrorCategory::Storage),
+            (AppError::lock("cache"), "LOCK_POISONED", ErrorCategory::Internal),
+            (AppError::Internal("oops".into()), "INTERNAL", ErrorCategory::Internal),
+            (io::Error::new(io::ErrorKind::NotFound, "gone").into(), "FILE_NOT_FOUND", ErrorCategory::NotFound),
+            (io::Error::new(io::ErrorKind::PermissionDenied, "no").into(), "PERMISSION_DENIED", ErrorCategory::Storage),
+            (io::Error::new(io::ErrorKind::StorageFull, "full").into(), "DISK_FULL", ErrorCategory::Storage),
+            (io::Error::other("eh").into(), "IO_ERROR", ErrorCategory::Storage),
+            (sqlite_error(rusqlite::ffi::SQLITE_FULL).into(), "DISK_FULL", ErrorCategory::Storage),
+            (sqlite_error(rusqlite::ffi::SQLITE_BUSY).into(), "BUSY", ErrorCategory::Storage),
+            (sqlite_error(rusqlite::ffi::SQLITE_CORRUPT).into(), "CORRUPT_DATA", ErrorCategory::Corruption),
+            (sqlite_error(rusqlite::ffi::SQLITE_CONSTRAINT).into(), "CONFLICT", ErrorCategory::Conflict),
+            (rusqlite::Error::QueryReturnedNoRows.into(), "DATABASE_ERROR", ErrorCategory::Storage),
+        ];
+        for (error, code, category) in cases {
+            assert_eq!(error.code(), code, "{}", error);
+            assert_eq!(error.category(), category, "{}", error);
+        }
+    }
+
+    #[test]
+    fn context_keeps_the_root_code_and_messages() {
+        let error = AppError::Conversion("font missing".into())
+            .context("Failed to render PDF")
+            .context("Failed to export document");
+
+        assert_eq!(error.code(), "CONVERSION_FAILED");
+        assert_eq!(error.category(), ErrorCategory::Conversion);
+        assert_eq!(error.message_en(), "The document could not be converted");
+        assert_eq!(error.message_zh(), "文档格式转换失败");
+        assert_eq!(error.to_string(), "Failed to export document: Failed to render PDF: font missing");
+
+        let result: Result<()> = Err(io::Error::new(io::ErrorKind::NotFound, "gone")).context("Failed to read file");
+        assert_eq!(result.unwrap_err().code(), "FILE_NOT_FOUND");
+    }
+
+    #[test]
+    fn serializes_a_wrapped_error_for_the_frontend() {
+        let error = AppError::not_found_id(Entity::Collection, "c1").context("Failed to open collection");
+        let value = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "code": "NOT_FOUND",
+                "category": "not_found",
+                "message_en": "Collection not found",
+                "message_zh": "找不到文集",
+                "details": ["Failed to open collection", "Collection not found: c1"],
+            })
+        );
+    }
+}
@@ -1,10 +1,27 @@
-use std::fs;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::Utc;
 
-use crate::database::{Database, Document};
+use crate::backup::{self, DocumentBackup, BACKUP_EXTENSION};
+use crate::database::{Document, UNSORTED_COLLECTION_ID};
+use crate::docx;
+use crate::importer;
+use crate::epub::{self, EpubMetadata};
+use crate::error::{AppError, Result, ResultExt};
+use crate::html::{self, HtmlExportOptions};
+use crate::latex::{self, LatexExportOptions};
+use crate::pandoc::{self, PandocExportOptions};
+use crate::pdf::{self, PdfExportOptions};
+use crate::segment::Segmenter;
+
+// Backup names carry the time in this format, which sorts chronologically
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d_%H%M%S";
+const BACKUP_TIME_LEN: usize = 15;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileInfo {
@@ -22,8 +39,51 @@ pub struct ImportResult {
     pub message: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Markdown,
+    PandocMarkdown,
+    Latex,
+    Html,
+    Pdf,
+    Docx,
+    Text,
+}
+
+impl ExportFormat {
+    // Fallback when no format is chosen explicitly
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        match extension.as_deref() {
+            Some("md") | Some("markdown") => ExportFormat::Markdown,
+            Some("tex") | Some("latex") => ExportFormat::Latex,
+            Some("html") | Some("htm") => ExportFormat::Html,
+            Some("pdf") => ExportFormat::Pdf,
+            Some("docx") => ExportFormat::Docx,
+            _ => ExportFormat::Text,
+        }
+    }
+}
+
+// Format-specific settings; each format reads only its own section
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub format: Option<ExportFormat>, // picked from the file extension when unset
+    pub html: HtmlExportOptions,
+    pub pdf: PdfExportOptions,
+    pub latex: LatexExportOptions,
+    pub pandoc: PandocExportOptions,
+}
+
 pub struct FileHandler {
     app_data_dir: PathBuf,
+    // Keep the previous version of overwritten files as `<name>.bak`
+    keep_backups: AtomicBool,
 }
 
 impl FileHandler {
@@ -35,17 +95,29 @@ impl FileHandler {
             });
         }
         
-        FileHandler { app_data_dir }
+        FileHandler {
+            app_data_dir,
+            keep_backups: AtomicBool::new(false),
+        }
     }
 
-    pub fn get_app_data_dir(&self) -> &Path {
-        &self.app_data_dir
+    pub fn set_keep_backups(&self, keep_backups: bool) {
+        self.keep_backups.store(keep_backups, Ordering::Relaxed);
     }
 
     pub fn get_database_path(&self) -> PathBuf {
         self.app_data_dir.join("semantic_assistant.db")
     }
 
+    pub fn get_config_path(&self) -> PathBuf {
+        self.app_data_dir.join("config.json")
+    }
+
+    // Optional, see segment.rs
+    pub fn get_dictionary_path(&self) -> PathBuf {
+        self.app_data_dir.join("segment_dict.txt")
+    }
+
     pub fn get_documents_dir(&self) -> PathBuf {
         let docs_dir = self.app_data_dir.join("documents");
         if !docs_dir.exists() {
@@ -56,6 +128,30 @@ impl FileHandler {
         docs_dir
     }
 
+    // The only part of the app data directory the raw file commands can reach;
+    // the database, settings and logs next to it stay out of the webview's hands
+    pub fn get_files_dir(&self) -> PathBuf {
+        let files_dir = self.app_data_dir.join("files");
+        if !files_dir.exists() {
+            fs::create_dir_all(&files_dir).unwrap_or_else(|_| {
+                eprintln!("Failed to create files directory");
+            });
+        }
+        files_dir
+    }
+
+    pub fn get_workspaces_path(&self) -> PathBuf {
+        self.app_data_dir.join("workspaces.json")
+    }
+
+    pub fn get_audit_log_path(&self) -> PathBuf {
+        self.app_data_dir.join("logs").join("file_access.log")
+    }
+
+    pub fn get_snapshots_dir(&self) -> PathBuf {
+        self.app_data_dir.join("snapshots")
+    }
+
     pub fn get_backups_dir(&self) -> PathBuf {
         let backups_dir = self.app_data_dir.join("backups");
         if !backups_dir.exists() {
@@ -66,27 +162,31 @@ impl FileHandler {
         backups_dir
     }
 
-    pub fn read_file_content(&self, file_path: &str) -> Result<String, String> {
-        fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {}", e))
+    pub fn read_file_content(&self, file_path: &str) -> Result<String> {
+        fs::read_to_string(file_path).context("Failed to read file")
+    }
+
+    pub fn write_file_content(&self, file_path: &str, content: &str) -> Result<()> {
+        self.write_file_bytes(file_path, content.as_bytes())
     }
 
-    pub fn write_file_content(&self, file_path: &str, content: &str) -> Result<(), String> {
+    pub fn write_file_bytes(&self, file_path: &str, bytes: &[u8]) -> Result<()> {
         // Ensure parent directory exists
         if let Some(parent) = Path::new(file_path).parent() {
-            fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+            fs::create_dir_all(parent).context("Failed to create directory")?;
         }
-        
-        fs::write(file_path, content).map_err(|e| format!("Failed to write file: {}", e))
+
+        write_atomic(Path::new(file_path), bytes, self.keep_backups.load(Ordering::Relaxed))
     }
 
-    pub fn list_directory(&self, dir_path: &str) -> Result<Vec<FileInfo>, String> {
-        let entries = fs::read_dir(dir_path).map_err(|e| format!("Failed to read directory: {}", e))?;
+    pub fn list_directory(&self, dir_path: &str) -> Result<Vec<FileInfo>> {
+        let entries = fs::read_dir(dir_path).context("Failed to read directory")?;
         let mut files = Vec::new();
 
         for entry in entries {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let entry = entry.context("Failed to read entry")?;
             let path = entry.path();
-            let metadata = entry.metadata().map_err(|e| format!("Failed to read metadata: {}", e))?;
+            let metadata = entry.metadata().context("Failed to read metadata")?;
 
             if metadata.is_file() {
                 let file_info = FileInfo {
@@ -109,151 +209,137 @@ impl FileHandler {
         Ok(files)
     }
 
-    pub fn import_document(&self, file_path: &str, db: &Database) -> Result<ImportResult, String> {
-        let content = self.read_file_content(file_path)?;
-        let path = Path::new(file_path);
-        
-        let title = path.file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("Untitled")
-            .to_string();
-
-        let extension = path.extension()
-            .and_then(|ext| ext.to_str())
-            .map(|s| s.to_lowercase());
-
-        // Parse content based on file type
-        let processed_content = match extension.as_deref() {
-            Some("md") | Some("markdown") => content,
-            Some("txt") => content,
-            Some("docx") => {
-                // For now, treat as plain text. In a real implementation,
-                // you might want to use a library like docx-rs to parse DOCX files
-                content
-            }
-            _ => content,
-        };
-
-        let word_count = processed_content.split_whitespace().count() as i32;
+    // Reads and parses a file into a new, unsaved document
+    pub fn prepare_import(&self, file_path: &str, segmenter: &Segmenter) -> Result<Document> {
+        let bytes = fs::read(file_path).context("Failed to read file")?;
+        // Format and text encoding are detected from the content
+        let parsed = importer::parse_file(Path::new(file_path), &bytes)?;
+        let counts = segmenter.count(&parsed.content);
         let now = Utc::now();
-        
-        let document = Document {
+
+        Ok(Document {
             id: Uuid::new_v4().to_string(),
-            title,
-            content: processed_content,
+            title: parsed.title,
+            content: parsed.content,
             file_path: Some(file_path.to_string()),
             created_at: now,
             updated_at: now,
-            word_count,
-        };
-
-        match db.save_document(&document) {
-            Ok(_) => Ok(ImportResult {
-                success: true,
-                document_id: Some(document.id),
-                message: "Document imported successfully".to_string(),
-            }),
-            Err(e) => Ok(ImportResult {
-                success: false,
-                document_id: None,
-                message: format!("Failed to save document: {}", e),
-            }),
-        }
+            word_count: counts.words(),
+            char_count: counts.characters,
+            cjk_word_count: counts.cjk_words,
+            latin_word_count: counts.latin_words,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        })
     }
 
-    pub fn export_document(&self, document: &Document, export_path: &str) -> Result<(), String> {
-        let content = match Path::new(export_path).extension().and_then(|ext| ext.to_str()) {
-            Some("md") | Some("markdown") => {
-                format!("# {}\n\n{}", document.title, document.content)
+    pub fn export_document(&self, document: &Document, export_path: &str, options: &ExportOptions) -> Result<()> {
+        let format = options.format.unwrap_or_else(|| ExportFormat::from_path(export_path));
+        let content = match format {
+            ExportFormat::Docx => {
+                let bytes = docx::export_docx(document).map_err(AppError::Conversion)?;
+                return self.write_file_bytes(export_path, &bytes);
+            }
+            ExportFormat::Pdf => {
+                let bytes = pdf::export_pdf(document, &options.pdf).map_err(AppError::Conversion)?;
+                return self.write_file_bytes(export_path, &bytes);
             }
-            Some("txt") => document.content.clone(),
-            _ => document.content.clone(),
+            ExportFormat::Markdown => format!("# {}\n\n{}", document.title, document.content),
+            ExportFormat::PandocMarkdown => pandoc::render_pandoc_markdown(document, &options.pandoc),
+            ExportFormat::Latex => latex::render_latex(document, &options.latex).map_err(AppError::Conversion)?,
+            ExportFormat::Html => html::render_html(document, &options.html),
+            ExportFormat::Text => document.content.clone(),
         };
 
         self.write_file_content(export_path, &content)
     }
 
-    pub fn create_backup(&self, document: &Document) -> Result<String, String> {
+    // Writes the documents, in order, as the chapters of one EPUB 3 book
+    pub fn export_epub(&self, documents: &[Document], metadata: &EpubMetadata, export_path: &str) -> Result<()> {
+        let bytes = epub::build_epub(documents, metadata).map_err(AppError::Conversion)?;
+        self.write_file_bytes(export_path, &bytes)
+    }
+
+    // Writes `<title>_<time>_<document id>.backup.json`, then deletes the
+    // document's oldest backups beyond `max_backups` (0 = no cap)
+    pub fn create_backup(&self, backup: &DocumentBackup, max_backups: usize) -> Result<String> {
+        let safe_title: String = backup
+            .document
+            .title
+            .chars()
+            .map(|c| if c.is_whitespace() || "/\\:*?\"<>|".contains(c) { '_' } else { c })
+            .collect();
         let backup_filename = format!(
-            "{}_{}.backup.md",
-            document.title.replace(' ', "_").replace('/', "_"),
-            Utc::now().format("%Y%m%d_%H%M%S")
+            "{}_{}_{}.{}",
+            safe_title,
+            Utc::now().format(BACKUP_TIME_FORMAT),
+            backup.document.id,
+            BACKUP_EXTENSION
         );
-        
+
         let backup_path = self.get_backups_dir().join(&backup_filename);
-        let backup_content = format!(
-            "# {} (Backup)\n\nCreated: {}\nLast Modified: {}\nWord Count: {}\n\n---\n\n{}",
-            document.title,
-            document.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
-            document.updated_at.format("%Y-%m-%d %H:%M:%S UTC"),
-            document.word_count,
-            document.content
-        );
+        let backup_content = backup::encode_backup(backup).map_err(AppError::Internal)?;
 
         self.write_file_content(&backup_path.to_string_lossy(), &backup_content)?;
+        if max_backups > 0 {
+            self.prune_backups(&backup.document.id, max_backups)?;
+        }
         Ok(backup_path.to_string_lossy().to_string())
     }
 
-    pub fn list_backups(&self) -> Result<Vec<FileInfo>, String> {
+    // Backups are matched by the document id ending their name and ordered
+    // by the time before it, as titles may change between backups. Backups
+    // named without an id are never deleted.
+    fn prune_backups(&self, document_id: &str, max_backups: usize) -> Result<()> {
+        let suffix = format!("_{}.{}", document_id, BACKUP_EXTENSION);
+        let mut backups: Vec<(String, FileInfo)> = self
+            .list_backups()?
+            .into_iter()
+            .filter_map(|file| {
+                let stem = file.name.strip_suffix(&suffix)?;
+                let time_start = stem.len().checked_sub(BACKUP_TIME_LEN)?;
+                let time = stem.get(time_start..)?.to_string();
+                Some((time, file))
+            })
+            .collect();
+        if backups.len() <= max_backups {
+            return Ok(());
+        }
+
+        backups.sort_by(|a, b| b.0.cmp(&a.0));
+        for (_, file) in backups.into_iter().skip(max_backups) {
+            self.delete_file(&file.path)?;
+        }
+        Ok(())
+    }
+
+    pub fn list_backups(&self) -> Result<Vec<FileInfo>> {
         let backups_dir = self.get_backups_dir();
         self.list_directory(&backups_dir.to_string_lossy())
     }
 
-    pub fn restore_from_backup(&self, backup_path: &str, db: &Database) -> Result<ImportResult, String> {
-        // Read backup file and extract original content
-        let backup_content = self.read_file_content(backup_path)?;
-        
-        // Simple parsing - in a real implementation, you might want more sophisticated parsing
-        let content_start = backup_content.find("---\n\n").unwrap_or(0);
-        let content = if content_start > 0 {
-            backup_content[content_start + 5..].to_string()
-        } else {
-            backup_content
-        };
+    // Reads a JSON backup, verifying its checksum, or a legacy Markdown backup
+    pub fn read_backup(&self, backup_path: &str) -> Result<DocumentBackup> {
+        let text = self.read_file_content(backup_path)?;
+        if backup_path.ends_with(".json") || text.trim_start().starts_with('{') {
+            return backup::decode_backup(&text).map_err(AppError::Corrupt);
+        }
 
-        let backup_filename = Path::new(backup_path)
+        let file_stem = Path::new(backup_path)
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("Restored Document");
-
-        let title = backup_filename
-            .replace(".backup", "")
-            .replace('_', " ");
-
-        let word_count = content.split_whitespace().count() as i32;
-        let now = Utc::now();
-        
-        let document = Document {
-            id: Uuid::new_v4().to_string(),
-            title,
-            content,
-            file_path: None, // Restored documents don't have original file paths
-            created_at: now,
-            updated_at: now,
-            word_count,
-        };
-
-        match db.save_document(&document) {
-            Ok(_) => Ok(ImportResult {
-                success: true,
-                document_id: Some(document.id),
-                message: "Document restored successfully".to_string(),
-            }),
-            Err(e) => Ok(ImportResult {
-                success: false,
-                document_id: None,
-                message: format!("Failed to restore document: {}", e),
-            }),
-        }
+        Ok(backup::decode_legacy_backup(&text, file_stem))
     }
 
-    pub fn delete_file(&self, file_path: &str) -> Result<(), String> {
-        fs::remove_file(file_path).map_err(|e| format!("Failed to delete file: {}", e))
+    pub fn delete_file(&self, file_path: &str) -> Result<()> {
+        fs::remove_file(file_path).context("Failed to delete file")
     }
 
-    pub fn get_file_info(&self, file_path: &str) -> Result<FileInfo, String> {
+    pub fn get_file_info(&self, file_path: &str) -> Result<FileInfo> {
         let path = Path::new(file_path);
-        let metadata = fs::metadata(file_path).map_err(|e| format!("Failed to read file metadata: {}", e))?;
+        let metadata = fs::metadata(file_path).context("Failed to read file metadata")?;
 
         Ok(FileInfo {
             name: path.file_name()
@@ -268,4 +354,228 @@ impl FileHandler {
                 .map(|s| s.to_string()),
         })
     }
-}
\ No newline at end of file
+}
+
+// Replaces `path` with `bytes` so that a crash or a full disk leaves either
+// the old or the new contents, never a truncated file: the data goes to a
+// temp file beside the target, is synced, and is then renamed over it. An
+// existing file keeps its permissions, and a symlink keeps pointing at the
+// file it names.
+pub fn write_atomic(path: &Path, bytes: &[u8], keep_backup: bool) -> Result<()> {
+    let target = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::canonicalize(path).context("Failed to resolve symlink")?
+        }
+        _ => path.to_path_buf(),
+    };
+    let existing = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(AppError::Io(e).context("Failed to read file metadata")),
+    };
+
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| AppError::invalid(
+            format!("Not a file path: {}", path.display()),
+            format!("不是文件路径：{}", path.display()),
+        ))?
+        .to_string_lossy();
+    let parent = target.parent().unwrap_or(Path::new("."));
+    let temp_path = parent.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4().simple()));
+
+    let result = write_temp(&temp_path, bytes, existing.as_ref()).and_then(|()| {
+        if keep_backup && existing.is_some() {
+            keep_previous_version(&target)?;
+        }
+        fs::rename(&temp_path, &target).context("Failed to replace file")?;
+        sync_dir(parent);
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.with_context(|| format!("Failed to save {}; the existing file was left unchanged", path.display()))
+}
+
+fn write_temp(temp_path: &Path, bytes: &[u8], existing: Option<&fs::Metadata>) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp_path)
+        .context("Failed to create temporary file")?;
+
+    // Count what made it to disk so a short write is reported as such
+    let mut written = 0;
+    while written < bytes.len() {
+        match file.write(&bytes[written..]) {
+            Ok(0) => {
+                return Err(AppError::Io(io::ErrorKind::WriteZero.into())
+                    .context(format!("Only {} of {} bytes could be written", written, bytes.len())));
+            }
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                return Err(AppError::Io(e)
+                    .context(format!("Only {} of {} bytes could be written", written, bytes.len())));
+            }
+        }
+    }
+    file.sync_all().context("Failed to flush file to disk")?;
+
+    if let Some(metadata) = existing {
+        fs::set_permissions(temp_path, metadata.permissions())
+            .context("Failed to copy file permissions")?;
+    }
+    Ok(())
+}
+
+// Hard-links the current file as `<name>.bak`, copying where links are unsupported
+fn keep_previous_version(target: &Path) -> Result<()> {
+    let mut backup = target.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+
+    match fs::remove_file(&backup) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(AppError::Io(e).context("Failed to replace previous .bak file")),
+    }
+    if fs::hard_link(target, &backup).is_err() {
+        fs::copy(target, &backup).context("Failed to keep previous version")?;
+    }
+    Ok(())
+}
+
+// Makes the rename itself durable; directories cannot be opened on Windows
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn temp_dir(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("write-atomic-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn creates_and_replaces_files() {
+        let dir = temp_dir("replace");
+        let path = dir.0.join("doc.md");
+        write_atomic(&path, b"first", false).unwrap();
+        write_atomic(&path, b"second", false).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        // No temp files or backups are left behind
+        assert_eq!(entries(&dir.0), vec!["doc.md"]);
+    }
+
+    #[test]
+    fn keeps_previous_version_as_bak() {
+        let dir = temp_dir("bak");
+        let path = dir.0.join("doc.md");
+        write_atomic(&path, b"first", true).unwrap();
+        assert_eq!(entries(&dir.0), vec!["doc.md"]);
+
+        write_atomic(&path, b"second", true).unwrap();
+        write_atomic(&path, b"third", true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+        assert_eq!(fs::read(dir.0.join("doc.md.bak")).unwrap(), b"second");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_dir("permissions");
+        let path = dir.0.join("doc.md");
+        fs::write(&path, b"first").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        write_atomic(&path, b"second", false).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = temp_dir("symlink");
+        let target = dir.0.join("target.md");
+        let link = dir.0.join("link.md");
+        fs::write(&target, b"first").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"second", false).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"second");
+    }
+
+    #[test]
+    fn failed_write_leaves_no_temp_file() {
+        let dir = temp_dir("missing-parent");
+        let path = dir.0.join("missing").join("doc.md");
+        assert!(write_atomic(&path, b"text", false).is_err());
+        assert!(entries(&dir.0).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_newest_backups_of_each_document() {
+        let dir = temp_dir("prune-backups");
+        let handler = FileHandler::new(dir.0.clone());
+        let backups_dir = handler.get_backups_dir();
+        for name in [
+            "Old_title_20240101_000000_doc-a.backup.json",
+            "New_title_20240102_000000_doc-a.backup.json",
+            "Other_20240101_000000_doc-b.backup.json",
+            "Draft_20230101_000000.backup.json",
+        ] {
+            fs::write(backups_dir.join(name), "{}").unwrap();
+        }
+
+        let mut backup = backup::decode_legacy_backup("text", "New title");
+        backup.document.id = "doc-a".to_string();
+        let path = handler.create_backup(&backup, 2).unwrap();
+
+        let mut expected = vec![
+            "Draft_20230101_000000.backup.json".to_string(),
+            "New_title_20240102_000000_doc-a.backup.json".to_string(),
+            "Other_20240101_000000_doc-b.backup.json".to_string(),
+            Path::new(&path).file_name().unwrap().to_string_lossy().to_string(),
+        ];
+        expected.sort();
+        assert_eq!(entries(&backups_dir), expected);
+
+        // 0 keeps every backup
+        fs::write(backups_dir.join("Old_title_20240101_000000_doc-a.backup.json"), "{}").unwrap();
+        handler.create_backup(&backup, 0).unwrap();
+        assert_eq!(entries(&backups_dir).len(), 5);
+    }
+}
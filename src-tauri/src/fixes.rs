@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::context_window;
+use crate::consistency::ConsistencyIssue;
+use crate::error::{AppError, Result};
+use crate::search::MatchOffset;
+
+// Turns consistency issues into text edits. Where the matches of two issues
+// overlap only one can be applied; the plan keeps the issue of the rule with
+// the higher priority, then the longer match, then the earlier one, then the
+// smaller rule id, so the same issues always give the same plan.
+
+// Previews are cut from this many characters around an edit, which is more
+// than the context window shows on either side
+const PREVIEW_MARGIN_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixEdit {
+    pub id: String, // rule id and byte range, stable for unchanged content
+    pub rule_id: String,
+    pub offset: MatchOffset,
+    pub original: String,
+    pub replacement: String,
+    pub priority: i32,
+    #[serde(default)]
+    pub before: String, // context around the original text
+    #[serde(default)]
+    pub after: String, // the same context with the replacement
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedFix {
+    pub rule_id: String,
+    pub offset: MatchOffset,
+    pub original: String,
+    pub replacement: String,
+    // The planned edit that overlaps this one; None when the text at the
+    // offset no longer matches the issue
+    pub conflicts_with: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixPlan {
+    pub document_id: String,
+    pub content_hash: String, // apply_fixes refuses edits planned for other content
+    pub edits: Vec<FixEdit>, // in document order, never overlapping
+    pub skipped: Vec<SkippedFix>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedFixes {
+    pub document_id: String,
+    pub content: String,
+    pub content_hash: String,
+    pub revision_id: String,
+    pub revision_number: i32,
+    pub applied: usize,
+}
+
+fn edit_id(rule_id: &str, offset: &MatchOffset) -> String {
+    format!("{}@{}-{}", rule_id, offset.start, offset.end)
+}
+
+// Whether `start..end` is a non-empty range of `content` holding `text`
+fn matches_at(content: &str, start: usize, end: usize, text: &str) -> bool {
+    start < end && content.get(start..end) == Some(text)
+}
+
+fn preview(content: &str, start: usize, end: usize, replacement: &str) -> (String, String) {
+    let from = content[..start]
+        .char_indices()
+        .rev()
+        .nth(PREVIEW_MARGIN_CHARS - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let to = content[end..]
+        .char_indices()
+        .nth(PREVIEW_MARGIN_CHARS)
+        .map(|(i, _)| end + i)
+        .unwrap_or(content.len());
+
+    let prefix = &content[from..start];
+    let suffix = &content[end..to];
+    let replaced = format!("{}{}{}", prefix, replacement, suffix);
+    (
+        context_window(&content[from..to], start - from, end - from),
+        context_window(&replaced, prefix.len(), prefix.len() + replacement.len()),
+    )
+}
+
+pub fn plan(document_id: &str, content: &str, content_hash: &str, issues: Vec<ConsistencyIssue>) -> FixPlan {
+    let mut skipped = Vec::new();
+    let mut candidates = Vec::with_capacity(issues.len());
+    for issue in issues {
+        let offset = issue.offset;
+        if matches_at(content, offset.start, offset.end, &issue.found) {
+            candidates.push(issue);
+        } else {
+            skipped.push(SkippedFix {
+                rule_id: issue.rule_id,
+                offset,
+                original: issue.found,
+                replacement: issue.replacement,
+                conflicts_with: None,
+            });
+        }
+    }
+    candidates.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then((b.offset.end - b.offset.start).cmp(&(a.offset.end - a.offset.start)))
+            .then(a.offset.start.cmp(&b.offset.start))
+            .then(a.rule_id.cmp(&b.rule_id))
+    });
+
+    let mut edits: Vec<FixEdit> = Vec::new();
+    for issue in candidates {
+        let offset = issue.offset;
+        let overlapping = edits
+            .iter()
+            .find(|edit| offset.start < edit.offset.end && edit.offset.start < offset.end);
+        if let Some(edit) = overlapping {
+            // The same fix found twice, e.g. by an alternative and a pattern
+            if edit.offset.start == offset.start && edit.offset.end == offset.end && edit.replacement == issue.replacement {
+                continue;
+            }
+            skipped.push(SkippedFix {
+                rule_id: issue.rule_id,
+                offset,
+                original: issue.found,
+                replacement: issue.replacement,
+                conflicts_with: Some(edit.id.clone()),
+            });
+            continue;
+        }
+
+        let (before, after) = preview(content, offset.start, offset.end, &issue.replacement);
+        edits.push(FixEdit {
+            id: edit_id(&issue.rule_id, &offset),
+            rule_id: issue.rule_id,
+            offset,
+            original: issue.found,
+            replacement: issue.replacement,
+            priority: issue.priority,
+            before,
+            after,
+        });
+    }
+    edits.sort_by_key(|edit| edit.offset.start);
+    skipped.sort_by(|a, b| (a.offset.start, &a.rule_id).cmp(&(b.offset.start, &b.rule_id)));
+
+    FixPlan {
+        document_id: document_id.to_string(),
+        content_hash: content_hash.to_string(),
+        edits,
+        skipped,
+    }
+}
+
+// Applies all of `edits` or none. Every edit must still find its original
+// text, and no two may overlap.
+pub fn apply(content: &str, edits: &[FixEdit]) -> Result<String> {
+    let mut sorted: Vec<&FixEdit> = edits.iter().collect();
+    sorted.sort_by_key(|edit| (edit.offset.start, edit.offset.end));
+
+    let mut result = String::with_capacity(content.len());
+    let mut copied = 0;
+    for edit in sorted {
+        let (start, end) = (edit.offset.start, edit.offset.end);
+        if start < copied {
+            return Err(AppError::conflict(
+                format!("Fix {} overlaps another selected fix", edit.id),
+                format!("修正 {} 与其他选中的修正重叠", edit.id),
+            ));
+        }
+        if !matches_at(content, start, end, &edit.original) {
+            return Err(AppError::conflict(
+                format!("The text of fix {} has changed", edit.id),
+                format!("修正 {} 对应的文本已更改", edit.id),
+            ));
+        }
+        result.push_str(&content[copied..start]);
+        result.push_str(&edit.replacement);
+        copied = end;
+    }
+    result.push_str(&content[copied..]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consistency::Severity;
+    use crate::database::RuleCategory;
+
+    const CONTENT: &str = "I use Github and javascript daily.";
+
+    fn issue(rule_id: &str, found: &str, replacement: &str, priority: i32) -> ConsistencyIssue {
+        let start = CONTENT.find(found).expect("text is in CONTENT");
+        issue_at(rule_id, start, found, replacement, priority)
+    }
+
+    fn issue_at(rule_id: &str, start: usize, found: &str, replacement: &str, priority: i32) -> ConsistencyIssue {
+        ConsistencyIssue {
+            rule_id: rule_id.to_string(),
+            term: replacement.to_string(),
+            found: found.to_string(),
+            preferred_form: replacement.to_string(),
+            replacement: replacement.to_string(),
+            severity: Severity::Warning,
+            category: RuleCategory::Terminology,
+            priority,
+            offset: MatchOffset {
+                start,
+                end: start + found.len(),
+                char_start: start,
+                char_end: start + found.len(),
+            },
+            context: String::new(),
+        }
+    }
+
+    fn edit_ids(plan: &FixPlan) -> Vec<&str> {
+        plan.edits.iter().map(|edit| edit.id.as_str()).collect()
+    }
+
+    #[test]
+    fn plans_and_applies_edits_in_document_order() {
+        let issues = vec![
+            issue("js", "javascript", "JavaScript", 0),
+            issue("gh", "Github", "GitHub", 0),
+        ];
+        let plan = plan("doc", CONTENT, "hash", issues);
+        assert_eq!(edit_ids(&plan), vec!["gh@6-12", "js@17-27"]);
+        assert!(plan.skipped.is_empty());
+        assert_eq!(plan.edits[0].before, "I use Github and javascript daily.");
+        assert_eq!(plan.edits[0].after, "I use GitHub and javascript daily.");
+
+        let content = apply(CONTENT, &plan.edits).unwrap();
+        assert_eq!(content, "I use GitHub and JavaScript daily.");
+    }
+
+    #[test]
+    fn resolves_overlaps_by_priority_then_length() {
+        let issues = vec![
+            issue("short", "Git", "GIT", 0),
+            issue("long", "Github", "GitHub", 0),
+            issue("urgent", "hub", "Hub", 5),
+        ];
+        let plan = plan("doc", CONTENT, "hash", issues);
+        // "Git" ends where "hub" starts, so only the long match conflicts
+        assert_eq!(edit_ids(&plan), vec!["short@6-9", "urgent@9-12"]);
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(plan.skipped[0].rule_id, "long");
+        assert_eq!(plan.skipped[0].conflicts_with.as_deref(), Some("urgent@9-12"));
+        assert_eq!(apply(CONTENT, &plan.edits).unwrap(), "I use GITHub and javascript daily.");
+
+        let plan = super::plan("doc", CONTENT, "hash", vec![
+            issue("short", "Git", "GIT", 0),
+            issue("long", "Github", "GitHub", 0),
+        ]);
+        assert_eq!(edit_ids(&plan), vec!["long@6-12"]);
+    }
+
+    #[test]
+    fn breaks_ties_by_rule_id_and_merges_duplicates() {
+        let plan = plan("doc", CONTENT, "hash", vec![
+            issue("b", "Github", "GitHub", 0),
+            issue("a", "Github", "GITHUB", 0),
+            issue("c", "Github", "GITHUB", 0),
+        ]);
+        assert_eq!(edit_ids(&plan), vec!["a@6-12"]);
+        // c makes the same edit as a and is dropped; b conflicts with it
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(plan.skipped[0].rule_id, "b");
+    }
+
+    #[test]
+    fn skips_issues_whose_text_changed() {
+        let plan = plan("doc", CONTENT, "hash", vec![issue_at("gh", 0, "Github", "GitHub", 0)]);
+        assert!(plan.edits.is_empty());
+        assert_eq!(plan.skipped[0].conflicts_with, None);
+    }
+
+    #[test]
+    fn apply_rejects_overlapping_edits() {
+        let first = plan("doc", CONTENT, "hash", vec![issue("long", "Github", "GitHub", 0)]);
+        let second = plan("doc", CONTENT, "hash", vec![issue("short", "hub", "Hub", 0)]);
+        let edits: Vec<FixEdit> = first.edits.into_iter().chain(second.edits).collect();
+        assert!(matches!(apply(CONTENT, &edits), Err(AppError::Conflict { .. })));
+    }
+
+    #[test]
+    fn apply_rejects_edits_for_changed_text() {
+        let plan = plan("doc", CONTENT, "hash", vec![issue("gh", "Github", "GitHub", 0)]);
+        let changed = CONTENT.replace("Github", "Gitlab");
+        assert!(matches!(apply(&changed, &plan.edits), Err(AppError::Conflict { .. })));
+        // Nothing is applied to content that is too short either
+        assert!(apply("I use", &plan.edits).is_err());
+    }
+
+    #[test]
+    fn previews_are_cut_at_the_margin() {
+        let content = format!("{}Github{}", "前".repeat(150), "后".repeat(150));
+        let start = content.find("Github").unwrap();
+        let plan = plan("doc", &content, "hash", vec![issue_at("gh", start, "Github", "GitHub", 0)]);
+        let edit = &plan.edits[0];
+        assert!(edit.after.contains("GitHub"));
+        assert!(edit.before.chars().count() < content.chars().count());
+        assert_eq!(apply(&content, &plan.edits).unwrap(), content.replace("Github", "GitHub"));
+    }
+}
@@ -0,0 +1,435 @@
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Entity, Result, ResultExt};
+use crate::file_handler::{self, FileHandler};
+
+pub const MAX_READ_BYTES: u64 = 32 * 1024 * 1024;
+pub const MAX_WRITE_BYTES: usize = 32 * 1024 * 1024;
+const MAX_LISTED_FILES: usize = 10_000;
+// The audit log is rotated once, to `<name>.1`, when it grows past this
+const MAX_AUDIT_LOG_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    List,
+}
+
+impl Access {
+    fn as_str(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::List => "list",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct WorkspacesFile {
+    folders: Vec<PathBuf>,
+}
+
+// Limits the raw file commands the webview can call to the `files` folder in
+// the app data directory and to workspace folders the user picked in a
+// native dialog. Paths are canonicalized before the check, so neither `..`
+// nor a symlink can lead out of an allowed root.
+pub struct FsScope {
+    file_handler: Arc<FileHandler>,
+    files_dir: PathBuf, // canonical, like every workspace folder
+    backups_dir: PathBuf,
+    workspaces: RwLock<Vec<PathBuf>>,
+    workspaces_path: PathBuf,
+    audit_log_path: PathBuf,
+}
+
+impl FsScope {
+    pub fn new(file_handler: Arc<FileHandler>) -> Result<Self> {
+        let files_dir = fs::canonicalize(file_handler.get_files_dir())
+            .context("Failed to resolve files directory")?;
+        let backups_dir = fs::canonicalize(file_handler.get_backups_dir())
+            .context("Failed to resolve backups directory")?;
+        let workspaces_path = file_handler.get_workspaces_path();
+        let audit_log_path = file_handler.get_audit_log_path();
+
+        // Folders that were removed or renamed since are dropped
+        let mut workspaces = Vec::new();
+        for folder in Self::load_workspaces(&workspaces_path) {
+            match fs::canonicalize(&folder) {
+                Ok(folder) if folder.is_dir() => workspaces.push(folder),
+                _ => eprintln!("Workspace folder is no longer available: {}", folder.display()),
+            }
+        }
+
+        Ok(FsScope {
+            file_handler,
+            files_dir,
+            backups_dir,
+            workspaces: RwLock::new(workspaces),
+            workspaces_path,
+            audit_log_path,
+        })
+    }
+
+    fn load_workspaces(path: &Path) -> Vec<PathBuf> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                eprintln!("Failed to read workspace folders: {}", e);
+                return Vec::new();
+            }
+        };
+        match serde_json::from_str::<WorkspacesFile>(&text) {
+            Ok(file) => file.folders,
+            Err(e) => {
+                eprintln!("Ignoring invalid workspace folder list: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn save_workspaces(&self, folders: &[PathBuf]) -> Result<()> {
+        let file = WorkspacesFile { folders: folders.to_vec() };
+        let text = serde_json::to_string_pretty(&file)
+            .context("Failed to serialize workspace folders")?;
+        file_handler::write_atomic(&self.workspaces_path, text.as_bytes(), false)
+            .context("Failed to save workspace folders")
+    }
+
+    pub fn list_workspaces(&self) -> Result<Vec<String>> {
+        let workspaces = self.workspaces.read().map_err(|_| AppError::lock("workspaces"))?;
+        Ok(workspaces.iter().map(|folder| folder.to_string_lossy().to_string()).collect())
+    }
+
+    // Only call with a folder the user chose in a native dialog, never with a
+    // path that came from the webview
+    pub fn add_workspace(&self, folder: &Path) -> Result<String> {
+        let folder = fs::canonicalize(folder).context("Failed to resolve workspace folder")?;
+        if !folder.is_dir() {
+            return Err(AppError::invalid(
+                format!("Not a directory: {}", folder.display()),
+                format!("不是文件夹：{}", folder.display()),
+            ));
+        }
+
+        let mut workspaces = self.workspaces.write().map_err(|_| AppError::lock("workspaces"))?;
+        if !workspaces.contains(&folder) {
+            let mut updated = workspaces.clone();
+            updated.push(folder.clone());
+            self.save_workspaces(&updated)?;
+            *workspaces = updated;
+        }
+        Ok(folder.to_string_lossy().to_string())
+    }
+
+    pub fn remove_workspace(&self, folder: &str) -> Result<()> {
+        let mut workspaces = self.workspaces.write().map_err(|_| AppError::lock("workspaces"))?;
+        let updated: Vec<PathBuf> = workspaces
+            .iter()
+            .filter(|existing| existing.as_path() != Path::new(folder))
+            .cloned()
+            .collect();
+        if updated.len() == workspaces.len() {
+            return Err(AppError::not_found_id(Entity::Workspace, folder));
+        }
+        self.save_workspaces(&updated)?;
+        *workspaces = updated;
+        Ok(())
+    }
+
+    // Canonical form of `path` once it is known to lie inside an allowed root
+    pub fn resolve(&self, access: Access, path: &str) -> Result<PathBuf> {
+        self.resolve_within(access, path, None)
+    }
+
+    // Like resolve, but backups written by create_backup can be read as well
+    pub fn resolve_backup(&self, path: &str) -> Result<PathBuf> {
+        self.resolve_within(Access::Read, path, Some(self.backups_dir.as_path()))
+    }
+
+    fn resolve_within(&self, access: Access, path: &str, extra_root: Option<&Path>) -> Result<PathBuf> {
+        let requested = Path::new(path);
+        if !requested.is_absolute() {
+            return Err(self.deny(access, path, "path is not absolute"));
+        }
+
+        let resolved = match canonicalize_lenient(requested) {
+            Ok(resolved) => resolved,
+            Err(Escape::Parent) => return Err(self.deny(access, path, "'..' after a missing directory")),
+            Err(Escape::DanglingLink) => return Err(self.deny(access, path, "dangling symlink")),
+            Err(Escape::Io(e)) => return Err(AppError::Io(e).context("Failed to resolve path")),
+        };
+
+        let workspaces = self.workspaces.read().map_err(|_| AppError::lock("workspaces"))?;
+        let allowed = resolved.starts_with(&self.files_dir)
+            || extra_root.is_some_and(|root| resolved.starts_with(root))
+            || workspaces.iter().any(|folder| resolved.starts_with(folder));
+        if !allowed {
+            return Err(self.deny(access, path, "outside the allowed folders"));
+        }
+        Ok(resolved)
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String> {
+        let resolved = self.resolve(Access::Read, path)?;
+        let metadata = fs::metadata(&resolved).context("Failed to read file")?;
+        if !metadata.is_file() {
+            return Err(AppError::invalid(
+                format!("Not a file: {}", path),
+                format!("不是文件：{}", path),
+            ));
+        }
+        if metadata.len() > MAX_READ_BYTES {
+            return Err(AppError::invalid(
+                format!("File is larger than the {} MB limit", MAX_READ_BYTES / (1024 * 1024)),
+                format!("文件超过 {} MB 的上限", MAX_READ_BYTES / (1024 * 1024)),
+            ));
+        }
+        fs::read_to_string(&resolved).context("Failed to read file")
+    }
+
+    pub fn write(&self, path: &str, contents: &str) -> Result<()> {
+        if contents.len() > MAX_WRITE_BYTES {
+            return Err(AppError::invalid(
+                format!("Contents are larger than the {} MB limit", MAX_WRITE_BYTES / (1024 * 1024)),
+                format!("内容超过 {} MB 的上限", MAX_WRITE_BYTES / (1024 * 1024)),
+            ));
+        }
+        // Missing parents lie inside the root too; resolve() checked the whole path
+        let resolved = self.resolve(Access::Write, path)?;
+        self.file_handler.write_file_content(&resolved.to_string_lossy(), contents)
+    }
+
+    // Names of the files directly inside `path`
+    pub fn list_files(&self, path: &str) -> Result<Vec<String>> {
+        let resolved = self.resolve(Access::List, path)?;
+        let entries = fs::read_dir(&resolved).context("Failed to read directory")?;
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry.context("Failed to read entry")?;
+            if let Some(name) = entry.file_name().to_str() {
+                files.push(name.to_string());
+            }
+            if files.len() >= MAX_LISTED_FILES {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    // Records the attempt and returns the error for the caller
+    fn deny(&self, access: Access, path: &str, reason: &str) -> AppError {
+        let line = format!("{}\t{}\t{}\t{}\n", Utc::now().to_rfc3339(), access.as_str(), reason, path);
+        eprint!("Denied file access: {}", line);
+        if let Err(e) = self.append_audit_log(&line) {
+            eprintln!("Failed to write audit log: {}", e);
+        }
+        AppError::AccessDenied(format!("{} access to {} denied: {}", access.as_str(), path, reason))
+    }
+
+    fn append_audit_log(&self, line: &str) -> io::Result<()> {
+        if let Some(parent) = self.audit_log_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let too_large = fs::metadata(&self.audit_log_path)
+            .map(|metadata| metadata.len() > MAX_AUDIT_LOG_BYTES)
+            .unwrap_or(false);
+        if too_large {
+            let mut rotated = self.audit_log_path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.audit_log_path, rotated)?;
+        }
+        let mut log = OpenOptions::new().create(true).append(true).open(&self.audit_log_path)?;
+        log.write_all(line.as_bytes())
+    }
+}
+
+enum Escape {
+    Parent,
+    DanglingLink,
+    Io(io::Error),
+}
+
+// Like fs::canonicalize, but the trailing components may be missing, as for
+// a file about to be written. Those must be plain names: `..` there could
+// not be resolved against the real directory tree, and a dangling symlink
+// would be followed to wherever it points on write.
+fn canonicalize_lenient(path: &Path) -> std::result::Result<PathBuf, Escape> {
+    let mut existing = path;
+    let mut missing: Vec<OsString> = Vec::new();
+    loop {
+        match fs::canonicalize(existing) {
+            Ok(mut resolved) => {
+                for name in missing.iter().rev() {
+                    resolved.push(name);
+                }
+                return Ok(resolved);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if fs::symlink_metadata(existing).is_ok() {
+                    return Err(Escape::DanglingLink);
+                }
+                match existing.components().next_back() {
+                    Some(Component::Normal(name)) => missing.push(name.to_os_string()),
+                    Some(Component::CurDir) => {}
+                    _ => return Err(Escape::Parent),
+                }
+                existing = match existing.parent() {
+                    Some(parent) => parent,
+                    None => return Err(Escape::Io(e)),
+                };
+            }
+            Err(e) => return Err(Escape::Io(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        root: PathBuf,
+        scope: FsScope,
+        files: PathBuf,
+        workspace: PathBuf,
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    // An app data directory and one workspace folder under a fresh temp dir
+    fn fixture(name: &str) -> Fixture {
+        let root = std::env::temp_dir().join(format!("fs-scope-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("workspace")).unwrap();
+        let root = fs::canonicalize(&root).unwrap();
+
+        let file_handler = Arc::new(FileHandler::new(root.join("app")));
+        let scope = FsScope::new(file_handler).unwrap();
+        let workspace = PathBuf::from(scope.add_workspace(&root.join("workspace")).unwrap());
+        let files = scope.files_dir.clone();
+        Fixture { root, scope, files, workspace }
+    }
+
+    fn path(path: &Path) -> &str {
+        path.to_str().unwrap()
+    }
+
+    fn is_denied<T>(result: Result<T>) -> bool {
+        matches!(result, Err(AppError::AccessDenied(_)))
+    }
+
+    #[test]
+    fn allows_files_and_workspace_folders() {
+        let f = fixture("allowed");
+        let note = f.files.join("note.md");
+        f.scope.write(path(&note), "hello").unwrap();
+        assert_eq!(f.scope.read_to_string(path(&note)).unwrap(), "hello");
+
+        // Missing parents are created inside the root
+        let nested = f.workspace.join("drafts").join("a.md");
+        f.scope.write(path(&nested), "draft").unwrap();
+        assert_eq!(f.scope.list_files(path(&f.workspace.join("drafts"))).unwrap(), vec!["a.md"]);
+    }
+
+    #[test]
+    fn denies_app_internal_files() {
+        let f = fixture("internal");
+        let app = f.root.join("app");
+        fs::write(app.join("config.json"), "{}").unwrap();
+
+        assert!(is_denied(f.scope.read_to_string(path(&app.join("config.json")))));
+        assert!(is_denied(f.scope.write(path(&app.join("workspaces.json")), "{}")));
+        assert!(is_denied(f.scope.write(path(&app.join("semantic_assistant.db")), "")));
+        assert!(is_denied(f.scope.list_files(path(&app))));
+        assert_eq!(fs::read_to_string(app.join("config.json")).unwrap(), "{}");
+    }
+
+    #[test]
+    fn denies_parent_escapes() {
+        let f = fixture("parent");
+        fs::write(f.root.join("secret.txt"), "secret").unwrap();
+
+        let escape = format!("{}/../../secret.txt", path(&f.files));
+        assert!(is_denied(f.scope.read_to_string(&escape)));
+        let escape = format!("{}/../config.json", path(&f.files));
+        assert!(is_denied(f.scope.write(&escape, "{}")));
+        // `..` after a directory that does not exist cannot be resolved
+        let escape = format!("{}/missing/../../secret.txt", path(&f.workspace));
+        assert!(is_denied(f.scope.write(&escape, "x")));
+        assert!(is_denied(f.scope.read_to_string("secret.txt")));
+        assert_eq!(fs::read_to_string(f.root.join("secret.txt")).unwrap(), "secret");
+    }
+
+    #[test]
+    fn denies_sibling_with_common_prefix() {
+        let f = fixture("sibling");
+        let evil = PathBuf::from(format!("{}-evil", path(&f.workspace)));
+        fs::create_dir_all(&evil).unwrap();
+        fs::write(evil.join("a.txt"), "a").unwrap();
+
+        assert!(is_denied(f.scope.read_to_string(path(&evil.join("a.txt")))));
+        assert!(is_denied(f.scope.write(path(&evil.join("b.txt")), "b")));
+        assert!(is_denied(f.scope.list_files(path(&evil))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn denies_symlinks_out_of_a_root() {
+        use std::os::unix::fs::symlink;
+
+        let f = fixture("symlink");
+        let outside = f.root.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(&outside, f.workspace.join("link")).unwrap();
+        symlink(outside.join("secret.txt"), f.files.join("secret.txt")).unwrap();
+
+        assert!(is_denied(f.scope.read_to_string(path(&f.workspace.join("link").join("secret.txt")))));
+        assert!(is_denied(f.scope.read_to_string(path(&f.files.join("secret.txt")))));
+        assert!(is_denied(f.scope.list_files(path(&f.workspace.join("link")))));
+
+        // A file that does not exist yet, below a symlinked directory
+        assert!(is_denied(f.scope.write(path(&f.workspace.join("link").join("new.txt")), "x")));
+        assert!(!outside.join("new.txt").exists());
+
+        // A dangling link would be followed on write
+        symlink(outside.join("missing.txt"), f.workspace.join("dangling")).unwrap();
+        assert!(is_denied(f.scope.write(path(&f.workspace.join("dangling")), "x")));
+        assert!(!outside.join("missing.txt").exists());
+    }
+
+    #[test]
+    fn enforces_size_limits() {
+        let f = fixture("size");
+        let large = f.files.join("large.txt");
+        let too_long = "x".repeat(MAX_WRITE_BYTES + 1);
+        assert!(matches!(f.scope.write(path(&large), &too_long), Err(AppError::InvalidInput { .. })));
+        assert!(!large.exists());
+
+        fs::File::create(&large).unwrap().set_len(MAX_READ_BYTES + 1).unwrap();
+        assert!(matches!(f.scope.read_to_string(path(&large)), Err(AppError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn denials_are_logged() {
+        let f = fixture("audit");
+        let _ = f.scope.read_to_string("/etc/hostname");
+        let log = fs::read_to_string(f.root.join("app").join("logs").join("file_access.log")).unwrap();
+        assert!(log.contains("read\toutside the allowed folders\t/etc/hostname"));
+    }
+}
@@ -0,0 +1,576 @@
+use pulldown_cmark::{html, Event, Options, Parser};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::database::Document;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HtmlExportOptions {
+    pub standalone: bool,       // full page with <head>, otherwise a body fragment
+    pub embed_stylesheet: bool, // only applies to standalone pages
+}
+
+impl Default for HtmlExportOptions {
+    fn default() -> Self {
+        Self {
+            standalone: true,
+            embed_stylesheet: true,
+        }
+    }
+}
+
+pub struct ImportedHtml {
+    pub title: Option<String>,
+    pub content: String, // Markdown
+}
+
+// ---------------------------------------------------------------------------
+// Export
+// ---------------------------------------------------------------------------
+
+const STYLESHEET: &str = "body{margin:0;background:#fff;color:#1f2328;}\
+article{max-width:46rem;margin:0 auto;padding:3rem 1.5rem;\
+font:17px/1.75 -apple-system,BlinkMacSystemFont,\"Segoe UI\",\"PingFang SC\",\"Hiragino Sans GB\",\"Microsoft YaHei\",\"Noto Sans CJK SC\",sans-serif;}\
+h1,h2,h3,h4,h5,h6{line-height:1.3;margin:2em 0 .6em;}\
+h1{font-size:2em;}h2{font-size:1.5em;}h3{font-size:1.25em;}\
+a{color:#0969da;}\
+blockquote{margin:1em 0;padding:0 1em;color:#59636e;border-left:.25em solid #d1d9e0;}\
+code{font-family:ui-monospace,Consolas,monospace;font-size:.9em;background:#f6f8fa;padding:.15em .3em;border-radius:4px;}\
+pre{background:#f6f8fa;padding:1em;overflow:auto;border-radius:6px;}pre code{background:none;padding:0;}\
+table{border-collapse:collapse;margin:1em 0;}th,td{border:1px solid #d1d9e0;padding:.4em .8em;}\
+img{max-width:100%;}hr{border:0;border-top:1px solid #d1d9e0;margin:2em 0;}\
+.footnote-definition{font-size:.9em;color:#59636e;}";
+
+pub fn render_html(document: &Document, options: &HtmlExportOptions) -> String {
+    let parser_options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+
+    // Raw HTML embedded in a document is escaped rather than passed through,
+    // so an exported page can never carry scripts or tracking markup.
+    let events = Parser::new_ext(&document.content, parser_options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        event => event,
+    });
+
+    let mut body = String::new();
+    html::push_html(&mut body, events);
+
+    if !options.standalone {
+        return body;
+    }
+
+    let title = escape_html(&document.title);
+    let style = if options.embed_stylesheet {
+        format!("<style>{}</style>\n", STYLESHEET)
+    } else {
+        String::new()
+    };
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n{style}</head>\n<body>\n<article>\n<h1>{title}</h1>\n{body}</article>\n</body>\n</html>\n",
+        title = title,
+        style = style,
+        body = body
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ---------------------------------------------------------------------------
+// Import
+// ---------------------------------------------------------------------------
+
+// Elements whose whole subtree is dropped: active content, page chrome that
+// never carries prose, and form controls.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "head", "meta", "link", "iframe", "frame",
+    "object", "embed", "applet", "svg", "canvas", "audio", "video", "map", "form", "input",
+    "button", "select", "textarea",
+];
+
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "center", "dd", "details", "dialog", "div",
+    "dl", "dt", "fieldset", "figcaption", "figure", "footer", "h1", "h2", "h3", "h4", "h5", "h6",
+    "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section", "summary", "table", "tbody",
+    "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+// Query parameters appended by analytics and ad platforms
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "msclkid", "yclid", "mc_cid", "mc_eid", "_hsenc", "_hsmi", "igshid",
+    "spm", "scm", "share_source", "vd_source",
+];
+
+pub fn import_html(source: &str) -> ImportedHtml {
+    let document = Html::parse_document(source);
+
+    let title = Selector::parse("title")
+        .ok()
+        .and_then(|selector| document.select(&selector).next())
+        .map(|title| collapse_whitespace(&title.text().collect::<String>()).trim().to_string())
+        .filter(|title| !title.is_empty());
+
+    let root = Selector::parse("body")
+        .ok()
+        .and_then(|selector| document.select(&selector).next())
+        .unwrap_or_else(|| document.root_element());
+
+    let mut blocks = Vec::new();
+    convert_blocks(root, &mut blocks);
+
+    // Pages usually repeat the title as their first heading
+    if let Some(title) = &title {
+        let heading = format!("# {}", escape_markdown(title));
+        if let Some(index) = blocks.iter().position(|block| block.starts_with("# ")) {
+            if blocks[index] == heading {
+                blocks.remove(index);
+            }
+        }
+    }
+
+    ImportedHtml {
+        title,
+        content: blocks.join("\n\n"),
+    }
+}
+
+pub fn html_to_markdown(source: &str) -> String {
+    import_html(source).content
+}
+
+fn is_skipped(element: ElementRef) -> bool {
+    let value = element.value();
+    if SKIPPED_ELEMENTS.contains(&value.name()) {
+        return true;
+    }
+    if value.attr("hidden").is_some() || value.attr("aria-hidden") == Some("true") {
+        return true;
+    }
+    let style = value.attr("style").unwrap_or("").replace(' ', "").to_lowercase();
+    if style.contains("display:none") || style.contains("visibility:hidden") {
+        return true;
+    }
+
+    // 1x1 tracking pixels
+    value.name() == "img"
+        && ["width", "height"]
+            .iter()
+            .any(|dimension| matches!(value.attr(dimension).map(str::trim), Some("0") | Some("1")))
+}
+
+fn is_block(element: ElementRef) -> bool {
+    BLOCK_ELEMENTS.contains(&element.value().name())
+}
+
+// Converts the children of a block container, grouping runs of inline
+// content into paragraphs.
+fn convert_blocks(container: ElementRef, blocks: &mut Vec<String>) {
+    let mut paragraph = String::new();
+
+    for child in container.children() {
+        match child.value() {
+            Node::Text(text) => paragraph.push_str(&escape_markdown(&collapse_whitespace(text))),
+            Node::Element(_) => {
+                let element = match ElementRef::wrap(child) {
+                    Some(element) => element,
+                    None => continue,
+                };
+                if is_skipped(element) {
+                    continue;
+                }
+                if is_block(element) {
+                    flush_paragraph(&mut paragraph, blocks);
+                    convert_block(element, blocks);
+                } else {
+                    paragraph.push_str(&convert_inline(element));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    flush_paragraph(&mut paragraph, blocks);
+}
+
+fn flush_paragraph(paragraph: &mut String, blocks: &mut Vec<String>) {
+    let text = tidy_lines(paragraph);
+    if !text.is_empty() {
+        blocks.push(text);
+    }
+    paragraph.clear();
+}
+
+fn convert_block(element: ElementRef, blocks: &mut Vec<String>) {
+    let name = element.value().name();
+    match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            let text = tidy_lines(&convert_inline_children(element)).replace('\n', " ");
+            if !text.is_empty() {
+                blocks.push(format!("{} {}", "#".repeat(level), text));
+            }
+        }
+        "p" | "dt" | "summary" | "figcaption" => {
+            let text = tidy_lines(&convert_inline_children(element));
+            if !text.is_empty() {
+                blocks.push(text);
+            }
+        }
+        "blockquote" => {
+            let mut inner = Vec::new();
+            convert_blocks(element, &mut inner);
+            if !inner.is_empty() {
+                blocks.push(prefix_lines(&inner.join("\n\n"), "> ", "> "));
+            }
+        }
+        "ul" | "ol" => {
+            let list = convert_list(element);
+            if !list.is_empty() {
+                blocks.push(list);
+            }
+        }
+        "pre" => {
+            let code: String = element.text().collect();
+            let language = Selector::parse("code")
+                .ok()
+                .and_then(|selector| element.select(&selector).next())
+                .and_then(|code| code.value().attr("class"))
+                .and_then(|class| {
+                    class
+                        .split_whitespace()
+                        .find_map(|c| c.strip_prefix("language-").or_else(|| c.strip_prefix("lang-")))
+                })
+                .unwrap_or("")
+                .to_string();
+            let fence = if code.contains("```") { "~~~~" } else { "```" };
+            blocks.push(format!("{}{}\n{}\n{}", fence, language, code.trim_end_matches('\n'), fence));
+        }
+        "table" => {
+            let table = convert_table(element);
+            if !table.is_empty() {
+                blocks.push(table);
+            }
+        }
+        "hr" => blocks.push("---".to_string()),
+        _ => convert_blocks(element, blocks),
+    }
+}
+
+fn convert_list(list: ElementRef) -> String {
+    let ordered = list.value().name() == "ol";
+    let start: usize = list.value().attr("start").and_then(|s| s.trim().parse().ok()).unwrap_or(1);
+    let mut items = Vec::new();
+
+    let list_items = list.child_elements().filter(|e| e.value().name() == "li" && !is_skipped(*e));
+    for (number, item) in (start..).zip(list_items) {
+        let mut inner = Vec::new();
+        convert_blocks(item, &mut inner);
+
+        let marker = if ordered { format!("{}. ", number) } else { "- ".to_string() };
+        let indent = " ".repeat(marker.chars().count());
+        // Tight list: nested lists and paragraphs sit directly under the item
+        let body = if inner.is_empty() { String::new() } else { inner.join("\n") };
+        items.push(prefix_lines(&body, &marker, &indent));
+    }
+
+    items.join("\n")
+}
+
+fn convert_table(table: ElementRef) -> String {
+    let rows: Vec<Vec<String>> = Selector::parse("tr")
+        .ok()
+        .map(|selector| {
+            table
+                .select(&selector)
+                .map(|row| {
+                    row.child_elements()
+                        .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                        .map(|cell| {
+                            tidy_lines(&convert_inline_children(cell))
+                                .replace('\n', " ")
+                                .replace('|', "\\|")
+                        })
+                        .collect::<Vec<String>>()
+                })
+                .filter(|row| !row.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if rows.is_empty() {
+        return String::new();
+    }
+
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(1);
+    let render_row = |row: &Vec<String>| {
+        let cells: Vec<&str> = (0..columns).map(|i| row.get(i).map(|c| c.as_str()).unwrap_or("")).collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![render_row(&rows[0])];
+    lines.push(format!("|{}", " --- |".repeat(columns)));
+    lines.extend(rows[1..].iter().map(render_row));
+    lines.join("\n")
+}
+
+fn convert_inline_children(element: ElementRef) -> String {
+    let mut out = String::new();
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => out.push_str(&escape_markdown(&collapse_whitespace(text))),
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    if !is_skipped(child) {
+                        out.push_str(&convert_inline(child));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+fn convert_inline(element: ElementRef) -> String {
+    let value = element.value();
+    match value.name() {
+        "strong" | "b" => wrap_inline(&convert_inline_children(element), "**"),
+        "em" | "i" | "cite" | "dfn" => wrap_inline(&convert_inline_children(element), "*"),
+        "del" | "s" | "strike" => wrap_inline(&convert_inline_children(element), "~~"),
+        "code" | "kbd" | "samp" | "tt" => {
+            let code = collapse_whitespace(&element.text().collect::<String>());
+            if code.trim().is_empty() {
+                code
+            } else if code.contains('`') {
+                format!("`` {} ``", code.trim())
+            } else {
+                format!("`{}`", code.trim())
+            }
+        }
+        "a" => {
+            let text = convert_inline_children(element);
+            match value.attr("href").and_then(clean_url) {
+                Some(url) if !text.trim().is_empty() => format!("[{}]({})", text.trim(), url),
+                _ => text,
+            }
+        }
+        "img" => match value.attr("src").and_then(clean_url) {
+            Some(url) => format!("![{}]({})", escape_markdown(value.attr("alt").unwrap_or("").trim()), url),
+            None => String::new(),
+        },
+        "br" => "\n".to_string(),
+        // Block elements nested in inline context keep a word boundary
+        _ if is_block(element) => format!(" {} ", convert_inline_children(element)),
+        _ => convert_inline_children(element),
+    }
+}
+
+// Wraps text in an emphasis marker, keeping surrounding whitespace outside
+// the markers as Markdown requires.
+fn wrap_inline(text: &str, marker: &str) -> String {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return text.to_string();
+    }
+    let leading = if text.starts_with(char::is_whitespace) { " " } else { "" };
+    let trailing = if text.ends_with(char::is_whitespace) { " " } else { "" };
+    format!("{}{}{}{}{}", leading, marker, trimmed, marker, trailing)
+}
+
+// Drops script URLs and strips tracking query parameters.
+fn clean_url(url: &str) -> Option<String> {
+    let url = url.trim();
+    let scheme = url.split(':').next().unwrap_or("").to_lowercase();
+    if url.is_empty() || matches!(scheme.as_str(), "javascript" | "vbscript" | "data") {
+        return None;
+    }
+
+    let (without_fragment, fragment) = match url.find('#') {
+        Some(i) => (&url[..i], &url[i..]),
+        None => (url, ""),
+    };
+    let (base, query) = match without_fragment.find('?') {
+        Some(i) => (&without_fragment[..i], &without_fragment[i + 1..]),
+        None => return Some(url.replace(' ', "%20")),
+    };
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or("").to_lowercase();
+            !key.is_empty() && !key.starts_with("utm_") && !TRACKING_PARAMS.contains(&key.as_str())
+        })
+        .collect();
+
+    let cleaned = if kept.is_empty() {
+        format!("{}{}", base, fragment)
+    } else {
+        format!("{}?{}{}", base, kept.join("&"), fragment)
+    };
+    Some(cleaned.replace(' ', "%20"))
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(c);
+            in_space = false;
+        }
+    }
+    out
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Normalises spacing within each line of a paragraph; lines separated by
+// <br> become hard breaks.
+fn tidy_lines(text: &str) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("  \n")
+}
+
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    let mut out = String::new();
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let prefix = if i == 0 { first } else { rest };
+        if line.is_empty() {
+            out.push_str(prefix.trim_end());
+        } else {
+            out.push_str(prefix);
+            out.push_str(line);
+        }
+    }
+    if text.is_empty() {
+        out.push_str(first.trim_end());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn document(content: &str) -> Document {
+        Document {
+            id: "doc".to_string(),
+            title: "A <b>title</b>".to_string(),
+            content: content.to_string(),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: "unsorted".to_string(),
+            tags: Vec::new(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn import_drops_active_content() {
+        let source = r#"<html><head><title>Page</title><script>alert(1)</script></head>
+            <body onload="steal()">
+              <p onclick="steal()">Hello <img src="x.png" onerror="steal()" alt="pic"></p>
+              <script>document.cookie</script>
+              <style>p { color: red }</style>
+              <iframe src="https://example.com/ad"></iframe>
+              <div hidden>secret</div>
+              <p style="display: none">also secret</p>
+              <form><input value="field"><button>Send</button></form>
+            </body></html>"#;
+        let imported = import_html(source);
+        assert_eq!(imported.title.as_deref(), Some("Page"));
+        assert_eq!(imported.content, "Hello ![pic](x.png)");
+    }
+
+    #[test]
+    fn import_drops_script_urls_and_tracking() {
+        let source = r#"<p><a href="javascript:alert(1)">bad</a>
+            <a href=" JavaScript:alert(1)">worse</a>
+            <img src="data:image/png;base64,AAAA" alt="inline">
+            <img src="https://t.example.com/pixel.gif" width="1" height="1">
+            <a href="https://example.com/a?utm_source=x&id=3&fbclid=y#top">good</a></p>"#;
+        assert_eq!(html_to_markdown(source), "bad worse [good](https://example.com/a?id=3#top)");
+    }
+
+    #[test]
+    fn import_converts_structure() {
+        let source = "<title>Notes</title><h1>Notes</h1><h2>Part <em>one</em></h2>\
+            <ol start=\"3\"><li>three</li><li>four<ul><li>nested</li></ul></li></ol>\
+            <table><tr><th>a|b</th><th>c</th></tr><tr><td>1</td></tr></table>\
+            <blockquote><p>quoted</p></blockquote><pre><code class=\"language-rust\">fn main() {}\n</code></pre>";
+        assert_eq!(
+            html_to_markdown(source),
+            "## Part *one*\n\n\
+             3. three\n\
+             4. four\n   \
+                - nested\n\n\
+             | a\\|b | c |\n\
+             | --- | --- |\n\
+             | 1 |  |\n\n\
+             > quoted\n\n\
+             ```rust\nfn main() {}\n```"
+        );
+    }
+
+    #[test]
+    fn import_escapes_markdown_in_text() {
+        assert_eq!(html_to_markdown("<p>*not* [a](link) <b>x</b></p>"), "\\*not\\* \\[a\\](link) **x**");
+    }
+
+    #[test]
+    fn export_escapes_raw_html() {
+        let document = document("Text <script>alert(1)</script>\n\n<img src=x onerror=\"steal()\">\n\n**bold**");
+        let page = render_html(&document, &HtmlExportOptions::default());
+        assert!(!page.contains("<script>"));
+        assert!(!page.contains("<img"));
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(page.contains("<strong>bold</strong>"));
+        assert!(page.contains("<title>A &lt;b&gt;title&lt;/b&gt;</title>"));
+    }
+
+    #[test]
+    fn export_fragment_has_no_page_chrome() {
+        let options = HtmlExportOptions {
+            standalone: false,
+            embed_stylesheet: true,
+        };
+        assert_eq!(render_html(&document("# Hi"), &options), "<h1>Hi</h1>\n");
+    }
+}
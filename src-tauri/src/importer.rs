@@ -0,0 +1,665 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::Utc;
+use encoding_rs::{Encoding, GB18030};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::database::{content_hash, Document, UNSORTED_COLLECTION_ID};
+use crate::docx;
+use crate::error::{AppError, Entity, Result, ResultExt};
+use crate::html;
+use crate::pool::DbPool;
+use crate::segment::Segmenter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Markdown,
+    Text,
+    Docx,
+    Html,
+}
+
+pub struct ParsedImport {
+    pub title: String,
+    pub content: String, // Markdown
+    pub format: ImportFormat,
+    pub encoding: Option<&'static str>, // None for binary formats
+}
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdown", "mkd"];
+const TEXT_EXTENSIONS: &[&str] = &["txt", "text"];
+const HTML_EXTENSIONS: &[&str] = &["html", "htm", "xhtml"];
+
+// Formats are sniffed from the bytes first; the extension only decides
+// between formats the content cannot tell apart
+pub fn detect_format(path: &Path, bytes: &[u8]) -> Option<ImportFormat> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+
+    if bytes.starts_with(b"PK\x03\x04") {
+        // Zip entry names are stored uncompressed, so the main part is visible
+        let is_docx = bytes.windows(17).any(|window| window == b"word/document.xml");
+        return is_docx.then_some(ImportFormat::Docx);
+    }
+    if looks_binary(bytes) {
+        return None;
+    }
+
+    let head = decode_text(&bytes[..bytes.len().min(2048)])
+        .map(|(text, _)| text.trim_start().to_lowercase())
+        .unwrap_or_default();
+    if head.starts_with("<!doctype html")
+        || head.starts_with("<html")
+        || (head.starts_with('<') && (head.contains("<head") || head.contains("<body")))
+    {
+        return Some(ImportFormat::Html);
+    }
+
+    match extension.as_deref() {
+        Some(ext) if MARKDOWN_EXTENSIONS.contains(&ext) => Some(ImportFormat::Markdown),
+        Some(ext) if TEXT_EXTENSIONS.contains(&ext) => Some(ImportFormat::Text),
+        Some(ext) if HTML_EXTENSIONS.contains(&ext) => Some(ImportFormat::Html),
+        None if looks_like_markdown(&head) => Some(ImportFormat::Markdown),
+        None => Some(ImportFormat::Text),
+        // Source code, data files and the like are only taken when they read as Markdown
+        Some(_) => looks_like_markdown(&head).then_some(ImportFormat::Markdown),
+    }
+}
+
+fn looks_binary(bytes: &[u8]) -> bool {
+    if Encoding::for_bom(bytes).is_some() {
+        return false;
+    }
+    const SIGNATURES: &[&[u8]] = &[b"%PDF-", b"\x89PNG", b"\xFF\xD8\xFF", b"GIF8", b"\x7FELF", b"MZ"];
+    SIGNATURES.iter().any(|signature| bytes.starts_with(signature))
+        || bytes[..bytes.len().min(8000)].contains(&0)
+}
+
+fn looks_like_markdown(text: &str) -> bool {
+    text.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with("# ")
+            || line.starts_with("## ")
+            || line.starts_with("```")
+            || line.starts_with("> ")
+            || (line.starts_with('[') && line.contains("]("))
+    })
+}
+
+// UTF-8 and UTF-16 are recognised by their BOM, BOM-less text is tried as
+// UTF-8 and then as GB18030, which also covers GBK and GB2312
+pub fn decode_text(bytes: &[u8]) -> Result<(String, &'static str)> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        if had_errors {
+            return Err(AppError::Corrupt(format!("File is not valid {}", encoding.name())));
+        }
+        return Ok((text.into_owned(), encoding.name()));
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return Ok((text.to_string(), "UTF-8"));
+    }
+    let (text, had_errors) = GB18030.decode_without_bom_handling(bytes);
+    if had_errors {
+        return Err(AppError::UnsupportedFormat(
+            "Unrecognised text encoding; expected UTF-8, UTF-16 or GBK/GB18030".to_string(),
+        ));
+    }
+    Ok((text.into_owned(), GB18030.name()))
+}
+
+pub fn parse_file(path: &Path, bytes: &[u8]) -> Result<ParsedImport> {
+    let format = detect_format(path, bytes)
+        .ok_or_else(|| AppError::UnsupportedFormat("Unsupported or binary file".to_string()))?;
+    parse_file_as(path, bytes, format)
+}
+
+// Parses a file whose format the caller has already detected
+pub fn parse_file_as(path: &Path, bytes: &[u8], format: ImportFormat) -> Result<ParsedImport> {
+    let mut title = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("Untitled")
+        .to_string();
+
+    let (content, encoding) = match format {
+        ImportFormat::Docx => {
+            // A Title paragraph overrides the file name
+            let imported = docx::import_docx_bytes(bytes).map_err(AppError::Corrupt)?;
+            if let Some(docx_title) = imported.title {
+                title = docx_title;
+            }
+            (imported.content, None)
+        }
+        ImportFormat::Html => {
+            let (text, encoding) = decode_text(bytes)?;
+            let imported = html::import_html(&text);
+            if let Some(html_title) = imported.title {
+                title = html_title;
+            }
+            (imported.content, Some(encoding))
+        }
+        ImportFormat::Markdown | ImportFormat::Text => {
+            let (text, encoding) = decode_text(bytes)?;
+            (text, Some(encoding))
+        }
+    };
+
+    Ok(ParsedImport {
+        title,
+        content,
+        format,
+        encoding,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectoryImportOptions {
+    pub include: Vec<String>, // globs relative to the root; empty takes every supported file
+    pub exclude: Vec<String>, // matching directories are not descended into
+    pub recursive: bool,
+    pub include_hidden: bool, // dot files and dot directories
+    pub skip_duplicates: bool, // compared by content hash
+    pub collection_id: Option<String>,
+    pub max_file_size_mb: u64, // 0 = no limit
+}
+
+impl Default for DirectoryImportOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            recursive: true,
+            include_hidden: false,
+            skip_duplicates: true,
+            collection_id: None,
+            max_file_size_mb: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileImportStatus {
+    Imported,
+    Duplicate,
+    Unsupported,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileImportReport {
+    pub path: String,
+    pub status: FileImportStatus,
+    pub document_id: Option<String>, // the new document, or the existing one for duplicates
+    pub format: Option<ImportFormat>,
+    pub encoding: Option<String>,
+    pub message: String,
+    pub error_code: Option<String>, // AppError code when the file failed
+}
+
+impl FileImportReport {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            status: FileImportStatus::Failed,
+            document_id: None,
+            format: None,
+            encoding: None,
+            message: String::new(),
+            error_code: None,
+        }
+    }
+
+    fn fail(&mut self, error: AppError) {
+        self.status = FileImportStatus::Failed;
+        self.message = error.to_string();
+        self.error_code = Some(error.code().to_string());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryImportReport {
+    pub root: String,
+    pub total: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub unsupported: usize,
+    pub failed: usize,
+    pub files: Vec<FileImportReport>,
+}
+
+// Emitted as `import-progress` after each file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportProgress {
+    pub current: usize,
+    pub total: usize,
+    pub file: FileImportReport,
+}
+
+// Holds only a pool handle, so a folder import runs alongside other
+// commands; the writer is taken once per saved document.
+pub struct DirectoryImporter {
+    db: DbPool,
+    segmenter: Arc<Segmenter>,
+    options: DirectoryImportOptions,
+}
+
+impl DirectoryImporter {
+    pub fn new(db: DbPool, segmenter: Arc<Segmenter>, options: DirectoryImportOptions) -> Result<Self> {
+        if let Some(collection_id) = &options.collection_id {
+            let db = db.reader()?;
+            db.get_collection(collection_id)
+                .context("Failed to get collection")?
+                .ok_or(AppError::not_found(Entity::Collection))?;
+        }
+        Ok(Self { db, segmenter, options })
+    }
+
+    pub fn run(&self, root: &str, mut on_progress: impl FnMut(&ImportProgress)) -> Result<DirectoryImportReport> {
+        let root_path = Path::new(root);
+        if !root_path.is_dir() {
+            return Err(AppError::invalid(
+                format!("Not a directory: {}", root),
+                format!("不是文件夹：{}", root),
+            ));
+        }
+        let include = build_glob_set(&self.options.include)?;
+        let exclude = build_glob_set(&self.options.exclude)?;
+
+        let (files, mut reports) = self.collect_files(root_path, &include, &exclude);
+        let total = files.len() + reports.len();
+
+        // Walk errors and skipped links are reported first, then files in path order
+        for (index, report) in reports.iter().enumerate() {
+            on_progress(&ImportProgress {
+                current: index + 1,
+                total,
+                file: report.clone(),
+            });
+        }
+        for path in files {
+            let report = self.import_file(&path);
+            on_progress(&ImportProgress {
+                current: reports.len() + 1,
+                total,
+                file: report.clone(),
+            });
+            reports.push(report);
+        }
+
+        let count = |status| reports.iter().filter(|report| report.status == status).count();
+        Ok(DirectoryImportReport {
+            root: root.to_string(),
+            total,
+            imported: count(FileImportStatus::Imported),
+            duplicates: count(FileImportStatus::Duplicate),
+            unsupported: count(FileImportStatus::Unsupported),
+            failed: count(FileImportStatus::Failed),
+            files: reports,
+        })
+    }
+
+    fn collect_files(
+        &self,
+        root: &Path,
+        include: &Option<GlobSet>,
+        exclude: &Option<GlobSet>,
+    ) -> (Vec<std::path::PathBuf>, Vec<FileImportReport>) {
+        let relative = |path: &Path| path.strip_prefix(root).unwrap_or(path).to_path_buf();
+        let include_hidden = self.options.include_hidden;
+
+        let walker = WalkDir::new(root)
+            .min_depth(1)
+            .max_depth(if self.options.recursive { usize::MAX } else { 1 })
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| {
+                let hidden = entry.file_name().to_str().is_some_and(|name| name.starts_with('.'));
+                let excluded = exclude.as_ref().is_some_and(|set| set.is_match(relative(entry.path())));
+                (include_hidden || !hidden) && !excluded
+            });
+
+        let mut files = Vec::new();
+        let mut skipped = Vec::new();
+        for entry in walker {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() => {
+                    if include.as_ref().is_none_or(|set| set.is_match(relative(entry.path()))) {
+                        files.push(entry.into_path());
+                    }
+                }
+                // Links may point outside the folder, so they are listed but not followed
+                Ok(entry) if entry.path_is_symlink() => {
+                    if include.as_ref().is_none_or(|set| set.is_match(relative(entry.path()))) {
+                        let mut report = FileImportReport::new(entry.path());
+                        report.status = FileImportStatus::Unsupported;
+                        report.message = "Symbolic links are not followed".to_string();
+                        skipped.push(report);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let mut report = FileImportReport::new(e.path().unwrap_or_else(|| Path::new("")));
+                    report.fail(AppError::Io(e.into()).context("Failed to read directory entry"));
+                    skipped.push(report);
+                }
+            }
+        }
+        (files, skipped)
+    }
+
+    fn import_file(&self, path: &Path) -> FileImportReport {
+        let mut report = FileImportReport::new(path);
+
+        let size_limit = match self.options.max_file_size_mb {
+            0 => u64::MAX,
+            mb => mb.saturating_mul(1024 * 1024),
+        };
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() > size_limit => {
+                report.status = FileImportStatus::Unsupported;
+                report.message = format!("File is larger than {} MB", self.options.max_file_size_mb);
+                return report;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                report.fail(AppError::from(e).context("Failed to read file"));
+                return report;
+            }
+        }
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                report.fail(AppError::from(e).context("Failed to read file"));
+                return report;
+            }
+        };
+
+        let format = match detect_format(path, &bytes) {
+            Some(format) => format,
+            None => {
+                report.status = FileImportStatus::Unsupported;
+                report.message = "Unsupported or binary file".to_string();
+                return report;
+            }
+        };
+        report.format = Some(format);
+        let parsed = match parse_file_as(path, &bytes, format) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                report.fail(e);
+                return report;
+            }
+        };
+        report.encoding = parsed.encoding.map(|encoding| encoding.to_string());
+
+        // Files saved earlier in the same import are in the library too
+        if self.options.skip_duplicates {
+            let existing = self.db.reader().and_then(|db| {
+                db.find_document_by_content_hash(&content_hash(&parsed.content))
+                    .context("Failed to look up duplicates")
+            });
+            match existing {
+                Ok(Some(existing_id)) => {
+                    report.status = FileImportStatus::Duplicate;
+                    report.document_id = Some(existing_id);
+                    report.message = "Same content already in the library".to_string();
+                    return report;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    report.fail(e);
+                    return report;
+                }
+            }
+        }
+
+        let counts = self.segmenter.count(&parsed.content);
+        let now = Utc::now();
+        let document = Document {
+            id: Uuid::new_v4().to_string(),
+            title: parsed.title,
+            word_count: counts.words(),
+            char_count: counts.characters,
+            cjk_word_count: counts.cjk_words,
+            latin_word_count: counts.latin_words,
+            content: parsed.content,
+            file_path: Some(report.path.clone()),
+            created_at: now,
+            updated_at: now,
+            collection_id: self
+                .options
+                .collection_id
+                .clone()
+                .unwrap_or_else(|| UNSORTED_COLLECTION_ID.to_string()),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        };
+
+        let saved = self
+            .db
+            .writer()
+            .and_then(|db| {
+                db.save_document_with_revision(&document, None)
+                    .context("Failed to save document")
+            });
+        match saved {
+            Ok(_) => {
+                report.status = FileImportStatus::Imported;
+                report.document_id = Some(document.id);
+                report.message = "Document imported successfully".to_string();
+            }
+            Err(e) => report.fail(e),
+        }
+        report
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| {
+            AppError::invalid(
+                format!("Invalid glob pattern '{}': {}", pattern, e),
+                format!("无效的匹配模式“{}”：{}", pattern, e),
+            )
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map(Some)
+        .map_err(|e| AppError::Internal(format!("Failed to build glob patterns: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    struct Fixture {
+        dir: PathBuf,
+        pool: DbPool,
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // A library database next to a `files` folder to import from
+    fn fixture(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("importer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("files")).unwrap();
+        let pool = DbPool::open(&dir.join("library.db")).unwrap();
+        Fixture { dir, pool }
+    }
+
+    impl Fixture {
+        fn write(&self, relative: &str, bytes: &[u8]) {
+            let path = self.dir.join("files").join(relative);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, bytes).unwrap();
+        }
+
+        fn run(&self, options: DirectoryImportOptions) -> DirectoryImportReport {
+            let importer = DirectoryImporter::new(self.pool.clone(), Arc::new(Segmenter::new()), options).unwrap();
+            importer.run(&self.dir.join("files").to_string_lossy(), |_| {}).unwrap()
+        }
+
+        // Paths relative to the import folder, by status
+        fn paths(&self, report: &DirectoryImportReport, status: FileImportStatus) -> Vec<String> {
+            let root = self.dir.join("files");
+            report
+                .files
+                .iter()
+                .filter(|file| file.status == status)
+                .map(|file| Path::new(&file.path).strip_prefix(&root).unwrap().to_string_lossy().replace('\\', "/"))
+                .collect()
+        }
+    }
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = if big_endian { vec![0xFE, 0xFF] } else { vec![0xFF, 0xFE] };
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_each_supported_encoding() {
+        let text = "中文 text";
+        assert_eq!(decode_text(text.as_bytes()).unwrap(), (text.to_string(), "UTF-8"));
+        let with_bom = [b"\xEF\xBB\xBF".as_slice(), text.as_bytes()].concat();
+        assert_eq!(decode_text(&with_bom).unwrap(), (text.to_string(), "UTF-8"));
+        assert_eq!(decode_text(&utf16(text, false)).unwrap(), (text.to_string(), "UTF-16LE"));
+        assert_eq!(decode_text(&utf16(text, true)).unwrap(), (text.to_string(), "UTF-16BE"));
+
+        let (gbk, _, _) = GB18030.encode("简体中文，GBK 编码");
+        assert!(std::str::from_utf8(&gbk).is_err());
+        assert_eq!(decode_text(&gbk).unwrap(), ("简体中文，GBK 编码".to_string(), "gb18030"));
+
+        assert_eq!(decode_text(b"\x81\x20 broken").unwrap_err().code(), "UNSUPPORTED_FORMAT");
+    }
+
+    #[test]
+    fn detects_formats_from_content() {
+        let docx = docx::export_docx(&Document {
+            id: "d".to_string(),
+            title: "Title".to_string(),
+            content: "Text".to_string(),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        })
+        .unwrap();
+        let detect = |name: &str, bytes: &[u8]| detect_format(Path::new(name), bytes);
+
+        // Content wins over the extension
+        assert_eq!(detect("renamed.txt", &docx), Some(ImportFormat::Docx));
+        assert_eq!(detect("archive.docx", b"PK\x03\x04other zip"), None);
+        assert_eq!(detect("page.txt", b"<!DOCTYPE html><html><body>Hi</body></html>"), Some(ImportFormat::Html));
+        assert_eq!(detect("fragment.md", b"  <div><body>Hi</body></div>"), Some(ImportFormat::Html));
+        assert_eq!(detect("image.md", b"\x89PNG\r\n\x1a\n"), None);
+        assert_eq!(detect("notes.txt", b"text\0with nul"), None);
+        assert_eq!(detect("notes.txt", &utf16("UTF-16 has zero bytes", false)), Some(ImportFormat::Text));
+
+        // Otherwise the extension decides, and unknown ones must read as Markdown
+        assert_eq!(detect("notes.md", b"plain"), Some(ImportFormat::Markdown));
+        assert_eq!(detect("notes.TXT", b"# looks like a heading"), Some(ImportFormat::Text));
+        assert_eq!(detect("README", b"# Title\n\ntext"), Some(ImportFormat::Markdown));
+        assert_eq!(detect("LICENSE", b"plain"), Some(ImportFormat::Text));
+        assert_eq!(detect("main.rs", b"fn main() {}"), None);
+        assert_eq!(detect("notes.org", b"intro\n## Section"), Some(ImportFormat::Markdown));
+    }
+
+    #[test]
+    fn filters_files_by_glob_and_visibility() {
+        let fixture = fixture("globs");
+        fixture.write("a.md", b"# A");
+        fixture.write("b.txt", b"b");
+        fixture.write("drafts/c.md", b"# C");
+        fixture.write("deep/nested/d.md", b"# D");
+        fixture.write(".hidden/e.md", b"# E");
+        fixture.write(".f.md", b"# F");
+
+        let report = fixture.run(DirectoryImportOptions {
+            include: vec!["**/*.md".to_string()],
+            exclude: vec!["drafts".to_string()],
+            ..DirectoryImportOptions::default()
+        });
+        assert_eq!(fixture.paths(&report, FileImportStatus::Imported), vec!["a.md", "deep/nested/d.md"]);
+        assert_eq!(report.total, 2);
+
+        let report = fixture.run(DirectoryImportOptions {
+            recursive: false,
+            include_hidden: true,
+            skip_duplicates: false,
+            ..DirectoryImportOptions::default()
+        });
+        assert_eq!(fixture.paths(&report, FileImportStatus::Imported), vec![".f.md", "a.md", "b.txt"]);
+
+        let invalid = DirectoryImportOptions { include: vec!["[".to_string()], ..DirectoryImportOptions::default() };
+        let importer = DirectoryImporter::new(fixture.pool.clone(), Arc::new(Segmenter::new()), invalid).unwrap();
+        assert_eq!(importer.run(&fixture.dir.join("files").to_string_lossy(), |_| {}).unwrap_err().code(), "INVALID_INPUT");
+    }
+
+    #[test]
+    fn limits_file_size_unless_zero() {
+        let fixture = fixture("size");
+        fixture.write("small.txt", b"small");
+        fixture.write("large.txt", &vec![b'x'; 1024 * 1024 + 1]);
+
+        let report = fixture.run(DirectoryImportOptions { max_file_size_mb: 1, ..DirectoryImportOptions::default() });
+        assert_eq!(fixture.paths(&report, FileImportStatus::Imported), vec!["small.txt"]);
+        assert_eq!(fixture.paths(&report, FileImportStatus::Unsupported), vec!["large.txt"]);
+
+        let report = fixture.run(DirectoryImportOptions { max_file_size_mb: 0, ..DirectoryImportOptions::default() });
+        assert_eq!(fixture.paths(&report, FileImportStatus::Imported), vec!["large.txt"]);
+        assert_eq!(fixture.paths(&report, FileImportStatus::Duplicate), vec!["small.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_symlinks_without_following_them() {
+        let fixture = fixture("symlinks");
+        fixture.write("a.md", b"# A");
+        fs::write(fixture.dir.join("outside.md"), b"# Outside").unwrap();
+        std::os::unix::fs::symlink(fixture.dir.join("outside.md"), fixture.dir.join("files/link.md")).unwrap();
+
+        let progress = std::cell::RefCell::new(Vec::new());
+        let importer = DirectoryImporter::new(fixture.pool.clone(), Arc::new(Segmenter::new()), DirectoryImportOptions::default()).unwrap();
+        let report = importer
+            .run(&fixture.dir.join("files").to_string_lossy(), |event| progress.borrow_mut().push(event.current))
+            .unwrap();
+        assert_eq!(fixture.paths(&report, FileImportStatus::Unsupported), vec!["link.md"]);
+        assert_eq!(report.files[0].message, "Symbolic links are not followed");
+        assert_eq!(fixture.paths(&report, FileImportStatus::Imported), vec!["a.md"]);
+        assert_eq!((report.total, report.unsupported), (2, 1));
+        assert_eq!(progress.into_inner(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{Document, MetadataValue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatexDocumentClass {
+    Article, // top-level headings become \section
+    Report,  // top-level headings become \chapter
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LatexExportOptions {
+    pub document_class: LatexDocumentClass,
+    pub font_size_pt: u8, // 10, 11 or 12
+    pub paper: String,    // e.g. "a4paper", "letterpaper"
+    pub cjk: bool,        // loads ctex; compile with XeLaTeX
+    pub table_of_contents: bool,
+    pub bibliography: Option<String>, // .bib file for \cite keys
+    pub bibliography_style: String,
+    pub standalone: bool, // full document with preamble, otherwise the body only
+}
+
+impl Default for LatexExportOptions {
+    fn default() -> Self {
+        Self {
+            document_class: LatexDocumentClass::Article,
+            font_size_pt: 11,
+            paper: "a4paper".to_string(),
+            cjk: true,
+            table_of_contents: false,
+            bibliography: None,
+            bibliography_style: "plain".to_string(),
+            standalone: true,
+        }
+    }
+}
+
+impl LatexExportOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(10..=12).contains(&self.font_size_pt) {
+            return Err("LaTeX font size must be 10, 11 or 12 pt".to_string());
+        }
+        if self.paper.is_empty() || !self.paper.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("Invalid LaTeX paper size: {}", self.paper));
+        }
+        if self.bibliography_style.is_empty()
+            || !self.bibliography_style.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid bibliography style: {}", self.bibliography_style));
+        }
+        Ok(())
+    }
+}
+
+pub fn render_latex(document: &Document, options: &LatexExportOptions) -> Result<String, String> {
+    options.validate()?;
+
+    let parser_options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+    let events = merge_text(Parser::new_ext(&document.content, parser_options).collect());
+
+    // Footnote bodies are rendered up front so references can inline them
+    let no_footnotes = HashMap::new();
+    let mut footnotes = HashMap::new();
+    let mut body_events = Vec::new();
+    let mut current: Option<(String, Vec<Event>)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => current = Some((label.to_string(), Vec::new())),
+            Event::End(TagEnd::FootnoteDefinition) => {
+                if let Some((label, events)) = current.take() {
+                    let mut writer = LatexWriter::new(options.document_class, &no_footnotes);
+                    writer.run(events);
+                    footnotes.insert(label, writer.out.trim().to_string());
+                }
+            }
+            event => match &mut current {
+                Some((_, events)) => events.push(event),
+                None => body_events.push(event),
+            },
+        }
+    }
+
+    let mut writer = LatexWriter::new(options.document_class, &footnotes);
+    writer.run(body_events);
+    let body = writer.out.trim_end().to_string();
+
+    if !options.standalone {
+        return Ok(format!("{}\n", body));
+    }
+
+    let class = match options.document_class {
+        LatexDocumentClass::Article => "article",
+        LatexDocumentClass::Report => "report",
+    };
+    let author = match document.metadata.get("author") {
+        Some(MetadataValue::Text(author)) => escape_latex(author),
+        _ => String::new(),
+    };
+
+    let mut out = format!("\\documentclass[{}pt,{}]{{{}}}\n", options.font_size_pt, options.paper, class);
+    if options.cjk {
+        out.push_str("\\usepackage[UTF8]{ctex}\n");
+    } else {
+        out.push_str("\\usepackage[utf8]{inputenc}\n\\usepackage[T1]{fontenc}\n");
+    }
+    out.push_str(
+        "\\usepackage{graphicx}\n\\usepackage[normalem]{ulem}\n\\usepackage{hyperref}\n\n",
+    );
+    out.push_str(&format!("\\title{{{}}}\n", escape_latex(&document.title)));
+    out.push_str(&format!("\\author{{{}}}\n", author));
+    out.push_str(&format!("\\date{{{}}}\n\n", document.updated_at.format("%Y-%m-%d")));
+    out.push_str("\\begin{document}\n\\maketitle\n");
+    if options.table_of_contents {
+        out.push_str("\\tableofcontents\n");
+    }
+    out.push('\n');
+    out.push_str(&body);
+    out.push('\n');
+    if let Some(bibliography) = &options.bibliography {
+        let name = bibliography.strip_suffix(".bib").unwrap_or(bibliography);
+        out.push_str(&format!(
+            "\n\\bibliographystyle{{{}}}\n\\bibliography{{{}}}\n",
+            options.bibliography_style,
+            escape_path(name)
+        ));
+    }
+    out.push_str("\\end{document}\n");
+    Ok(out)
+}
+
+// The parser splits text at brackets, so citations only become visible
+// once adjacent text events are joined.
+fn merge_text(events: Vec<Event>) -> Vec<Event> {
+    let mut merged: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events {
+        if let Event::Text(text) = &event {
+            if let Some(Event::Text(previous)) = merged.last_mut() {
+                *previous = format!("{}{}", previous, text).into();
+                continue;
+            }
+        }
+        merged.push(event);
+    }
+    merged
+}
+
+pub fn escape_latex(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '^' => out.push_str("\\textasciicircum{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// URLs and file names keep their backslash-free form; only characters that
+// would break the argument are escaped
+fn escape_path(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '%' | '#' | '{' | '}' | '&' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\\' => out.push('/'),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// Replaces Pandoc-style citations such as `[@knuth1984]`,
+// `[@knuth1984, p. 12]` or `[@a; @b]` with \cite, escaping the rest
+fn escape_with_citations(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[@") {
+        let Some(length) = rest[start..].find(']') else { break };
+        match citation(&rest[start + 1..start + length]) {
+            Some(cite) => {
+                out.push_str(&escape_latex(&rest[..start]));
+                out.push_str(&cite);
+            }
+            None => out.push_str(&escape_latex(&rest[..start + length + 1])),
+        }
+        rest = &rest[start + length + 1..];
+    }
+    out.push_str(&escape_latex(rest));
+    out
+}
+
+fn citation(inner: &str) -> Option<String> {
+    let mut keys = Vec::new();
+    let mut locator = String::new();
+    for item in inner.split(';') {
+        let item = item.trim().strip_prefix('@')?;
+        let end = item
+            .find(|c: char| !(c.is_alphanumeric() || "_:.-+/".contains(c)))
+            .unwrap_or(item.len());
+        let key = item[..end].trim_end_matches(['.', ':']);
+        if key.is_empty() {
+            return None;
+        }
+        keys.push(key.to_string());
+        let suffix = item[key.len()..].trim().trim_start_matches(',').trim();
+        if !suffix.is_empty() {
+            locator = suffix.to_string();
+        }
+    }
+    if locator.is_empty() {
+        Some(format!("\\cite{{{}}}", keys.join(",")))
+    } else {
+        Some(format!("\\cite[{}]{{{}}}", escape_latex(&locator), keys.join(",")))
+    }
+}
+
+struct LatexWriter<'a> {
+    class: LatexDocumentClass,
+    footnotes: &'a HashMap<String, String>,
+    out: String,
+    lists: Vec<bool>, // true for enumerate
+    cell_index: usize,
+    in_code_block: bool,
+    in_image: bool,
+}
+
+impl<'a> LatexWriter<'a> {
+    fn new(class: LatexDocumentClass, footnotes: &'a HashMap<String, String>) -> Self {
+        Self {
+            class,
+            footnotes,
+            out: String::new(),
+            lists: Vec::new(),
+            cell_index: 0,
+            in_code_block: false,
+            in_image: false,
+        }
+    }
+
+    fn sectioning(&self, level: HeadingLevel) -> &'static str {
+        let depth = level as usize - 1 + if self.class == LatexDocumentClass::Article { 1 } else { 0 };
+        match depth {
+            0 => "chapter",
+            1 => "section",
+            2 => "subsection",
+            3 => "subsubsection",
+            4 => "paragraph",
+            _ => "subparagraph",
+        }
+    }
+
+    fn run(&mut self, events: Vec<Event>) {
+        for event in events {
+            match event {
+                Event::Start(tag) => self.start(tag),
+                Event::End(tag) => self.end(tag),
+                Event::Text(text) => {
+                    if self.in_code_block {
+                        self.out.push_str(&text);
+                    } else if !self.in_image {
+                        self.out.push_str(&escape_with_citations(&text));
+                    }
+                }
+                Event::Code(code) => self.out.push_str(&format!("\\texttt{{{}}}", escape_latex(&code))),
+                // Raw HTML has no LaTeX meaning; keep it visible as text
+                Event::Html(raw) | Event::InlineHtml(raw) => self.out.push_str(&escape_latex(&raw)),
+                Event::FootnoteReference(label) => {
+                    if let Some(footnote) = self.footnotes.get(label.as_ref()) {
+                        self.out.push_str(&format!("\\footnote{{{}}}", footnote));
+                    }
+                }
+                Event::SoftBreak => self.out.push('\n'),
+                Event::HardBreak => self.out.push_str("\\\\\n"),
+                Event::Rule => self.out.push_str("\\par\\noindent\\rule{\\linewidth}{0.4pt}\\par\n\n"),
+                _ => {}
+            }
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => {}
+            Tag::Heading { level, .. } => {
+                let command = self.sectioning(level);
+                self.out.push_str(&format!("\\{}{{", command));
+            }
+            Tag::BlockQuote(_) => self.out.push_str("\\begin{quote}\n"),
+            Tag::CodeBlock(kind) => {
+                if let CodeBlockKind::Fenced(language) = &kind {
+                    if !language.is_empty() {
+                        self.out.push_str(&format!("% language: {}\n", language));
+                    }
+                }
+                self.out.push_str("\\begin{verbatim}\n");
+                self.in_code_block = true;
+            }
+            Tag::List(start) => {
+                let ordered = start.is_some();
+                if !self.out.is_empty() && !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str(if ordered { "\\begin{enumerate}\n" } else { "\\begin{itemize}\n" });
+                self.lists.push(ordered);
+                if let Some(start) = start.filter(|&start| start != 1) {
+                    let depth = self.lists.iter().filter(|&&ordered| ordered).count();
+                    if depth <= 4 {
+                        let counter = ["enumi", "enumii", "enumiii", "enumiv"][depth - 1];
+                        self.out.push_str(&format!("\\setcounter{{{}}}{{{}}}\n", counter, start.saturating_sub(1)));
+                    }
+                }
+            }
+            Tag::Item => self.out.push_str("\\item "),
+            Tag::Table(alignments) => {
+                let columns: String = alignments
+                    .iter()
+                    .map(|alignment| match alignment {
+                        Alignment::Center => "c|",
+                        Alignment::Right => "r|",
+                        _ => "l|",
+                    })
+                    .collect();
+                self.out.push_str(&format!("\\begin{{center}}\n\\begin{{tabular}}{{|{}}}\n\\hline\n", columns));
+            }
+            Tag::TableHead | Tag::TableRow => self.cell_index = 0,
+            Tag::TableCell => {
+                if self.cell_index > 0 {
+                    self.out.push_str(" & ");
+                }
+                self.cell_index += 1;
+            }
+            Tag::Emphasis => self.out.push_str("\\emph{"),
+            Tag::Strong => self.out.push_str("\\textbf{"),
+            Tag::Strikethrough => self.out.push_str("\\sout{"),
+            Tag::Link { dest_url, .. } => self.out.push_str(&format!("\\href{{{}}}{{", escape_path(&dest_url))),
+            Tag::Image { dest_url, .. } => {
+                self.out.push_str(&format!("\\includegraphics[width=\\linewidth]{{{}}}", escape_path(&dest_url)));
+                self.in_image = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.out.push_str(if self.lists.is_empty() { "\n\n" } else { "\n" }),
+            TagEnd::Heading(_) => self.out.push_str("}\n\n"),
+            TagEnd::BlockQuote(_) => self.out.push_str("\\end{quote}\n\n"),
+            TagEnd::CodeBlock => {
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str("\\end{verbatim}\n\n");
+                self.in_code_block = false;
+            }
+            TagEnd::List(ordered) => {
+                self.lists.pop();
+                self.out.push_str(if ordered { "\\end{enumerate}\n" } else { "\\end{itemize}\n" });
+                if self.lists.is_empty() {
+                    self.out.push('\n');
+                }
+            }
+            TagEnd::Item if !self.out.ends_with('\n') => self.out.push('\n'),
+            TagEnd::Table => self.out.push_str("\\end{tabular}\n\\end{center}\n\n"),
+            TagEnd::TableHead | TagEnd::TableRow => self.out.push_str(" \\\\\n\\hline\n"),
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link => self.out.push('}'),
+            TagEnd::Image => self.in_image = false,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn document(content: &str) -> Document {
+        Document {
+            id: "doc".to_string(),
+            title: "Costs & 100% of $x_1".to_string(),
+            content: content.to_string(),
+            file_path: None,
+            created_at: Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap(),
+            updated_at: Utc.with_ymd_and_hms(2024, 3, 2, 8, 0, 0).unwrap(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: "unsorted".to_string(),
+            tags: Vec::new(),
+            metadata: Default::default(),
+        }
+    }
+
+    fn body(content: &str) -> String {
+        let options = LatexExportOptions {
+            standalone: false,
+            ..LatexExportOptions::default()
+        };
+        render_latex(&document(content), &options).unwrap()
+    }
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(
+            escape_latex(r"\ { } $ & # _ % ^ ~ 中文"),
+            r"\textbackslash{} \{ \} \$ \& \# \_ \% \textasciicircum{} \textasciitilde{} 中文"
+        );
+        assert_eq!(escape_path(r"dir\a_b%20#x.png"), r"dir/a_b\%20\#x.png");
+    }
+
+    #[test]
+    fn converts_citations() {
+        assert_eq!(escape_with_citations("see [@knuth1984]."), r"see \cite{knuth1984}.");
+        assert_eq!(escape_with_citations("[@a; @b, p. 12]"), r"\cite[p. 12]{a,b}");
+        assert_eq!(escape_with_citations("[not @cited] and [@] 50%"), r"[not @cited] and [@] 50\%");
+        assert_eq!(escape_with_citations("open [@key"), "open [@key");
+    }
+
+    #[test]
+    fn renders_blocks() {
+        let latex = body(
+            "# Top & tail\n\n## Sub_section\n\nSome *em* **strong** `a_b` [link](https://x.org/?a=1&b=2).\n\n\
+             - one\n- two\n\n3. three\n4. four\n\n```rust\nlet x = a_b & c;\n```\n\nNote.[^n]\n\n[^n]: Foot_note.",
+        );
+        assert_eq!(
+            latex,
+            "\\section{Top \\& tail}\n\n\
+             \\subsection{Sub\\_section}\n\n\
+             Some \\emph{em} \\textbf{strong} \\texttt{a\\_b} \\href{https://x.org/?a=1\\&b=2}{link}.\n\n\
+             \\begin{itemize}\n\\item one\n\\item two\n\\end{itemize}\n\n\
+             \\begin{enumerate}\n\\setcounter{enumi}{2}\n\\item three\n\\item four\n\\end{enumerate}\n\n\
+             % language: rust\n\\begin{verbatim}\nlet x = a_b & c;\n\\end{verbatim}\n\n\
+             Note.\\footnote{Foot\\_note.}\n"
+        );
+    }
+
+    #[test]
+    fn report_class_starts_at_chapters() {
+        let options = LatexExportOptions {
+            document_class: LatexDocumentClass::Report,
+            standalone: false,
+            ..LatexExportOptions::default()
+        };
+        let latex = render_latex(&document("# One\n\n## Two"), &options).unwrap();
+        assert_eq!(latex, "\\chapter{One}\n\n\\section{Two}\n");
+    }
+
+    #[test]
+    fn renders_standalone_preamble() {
+        let options = LatexExportOptions {
+            bibliography: Some("refs.bib".to_string()),
+            ..LatexExportOptions::default()
+        };
+        let latex = render_latex(&document("Text"), &options).unwrap();
+        assert!(latex.starts_with("\\documentclass[11pt,a4paper]{article}\n\\usepackage[UTF8]{ctex}\n"));
+        assert!(latex.contains("\\title{Costs \\& 100\\% of \\$x\\_1}\n"));
+        assert!(latex.contains("\\date{2024-03-02}\n"));
+        assert!(latex.ends_with("\\bibliographystyle{plain}\n\\bibliography{refs}\n\\end{document}\n"));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let invalid = [
+            LatexExportOptions { font_size_pt: 9, ..LatexExportOptions::default() },
+            LatexExportOptions { paper: "a4paper]{x}".to_string(), ..LatexExportOptions::default() },
+            LatexExportOptions { bibliography_style: "plain}".to_string(), ..LatexExportOptions::default() },
+        ];
+        for options in &invalid {
+            assert!(render_latex(&document("Text"), options).is_err());
+        }
+    }
+}
@@ -1,20 +1,67 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use tauri::{Manager, State};
-use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_dialog::DialogExt;
+use std::sync::Arc;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::Duration;
 
+mod analysis;
+mod backup;
+mod cache;
+mod consistency;
 mod database;
+mod diff;
+mod docx;
+mod epub;
+mod error;
+mod html;
+mod latex;
 mod file_handler;
+mod fixes;
+mod fs_scope;
+mod importer;
+mod migrations;
+mod pandoc;
+mod pdf;
+mod pool;
+mod search;
+mod segment;
+mod snapshot;
 mod storage;
 
-use database::{Document, SemanticTerm, ConsistencyRule, AnalysisCache};
-use file_handler::{FileInfo, ImportResult};
+use analysis::DocumentAnalysis;
+use backup::RestoreOptions;
+use consistency::{CheckOptions, ConsistencyIssue, ConsistencyReport};
+use database::{Collection, CollectionDeleteMode, CollectionStats, Document, MetadataValue, TagInfo, DocumentListQuery, DocumentPage, DocumentRevision, RevisionSummary, SemanticTerm, ConsistencyRule, AnalysisCache};
+use diff::TextDiff;
+use epub::EpubMetadata;
+use error::{AppError, Result, ResultExt};
+use file_handler::{ExportOptions, FileInfo, ImportResult};
+use fs_scope::Access;
+use fixes::{AppliedFixes, FixEdit, FixPlan};
+use importer::{DirectoryImportOptions, DirectoryImportReport};
+use search::SearchHit;
+use snapshot::{SnapshotInfo, SnapshotRestoreResult, SnapshotVerification};
 use storage::{StorageService, StorageConfig, StorageStats};
 
 // Global storage service state
-type StorageState = Arc<Mutex<StorageService>>;
+type StorageState = Arc<StorageService>;
+
+// Runs a storage call on the blocking thread pool, so database and file work
+// never stalls the async runtime and independent commands run side by side
+async fn with_storage<T, F>(storage: &StorageState, task: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&StorageService) -> Result<T> + Send + 'static,
+{
+    let storage = Arc::clone(storage);
+    tauri::async_runtime::spawn_blocking(move || task(&storage))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()).context("Storage task failed"))?
+}
 
 // Learn more about Tauri commands at https://tauri.app/v2/guides/features/command
 #[tauri::command]
@@ -28,9 +75,12 @@ async fn create_document(
     storage: State<'_, StorageState>,
     title: String,
     content: String,
-) -> Result<String, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.create_document(title, content)
+    collection_id: Option<String>,
+) -> Result<String> {
+    with_storage(&storage, move |storage| {
+        storage.create_document(title, content, collection_id)
+    })
+    .await
 }
 
 #[tauri::command]
@@ -39,83 +89,498 @@ async fn update_document(
     id: String,
     title: Option<String>,
     content: Option<String>,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.update_document(id, title, content)
+    author: Option<String>,
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.update_document(id, title, content, author)
+    })
+    .await
 }
 
 #[tauri::command]
 async fn get_document(
     storage: State<'_, StorageState>,
     id: String,
-) -> Result<Option<Document>, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.get_document(&id)
+) -> Result<Option<Document>> {
+    with_storage(&storage, move |storage| {
+        storage.get_document(&id)
+    })
+    .await
 }
 
 #[tauri::command]
 async fn list_documents(
     storage: State<'_, StorageState>,
-) -> Result<Vec<Document>, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.list_documents()
+) -> Result<Vec<Document>> {
+    with_storage(&storage, move |storage| {
+        storage.list_documents()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_document_summaries(
+    storage: State<'_, StorageState>,
+    query: Option<DocumentListQuery>,
+) -> Result<DocumentPage> {
+    with_storage(&storage, move |storage| {
+        storage.list_document_summaries(&query.unwrap_or_default())
+    })
+    .await
 }
 
 #[tauri::command]
 async fn delete_document(
     storage: State<'_, StorageState>,
     id: String,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.delete_document(&id)
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.delete_document(&id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn search_documents(
+    storage: State<'_, StorageState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<SearchHit>> {
+    with_storage(&storage, move |storage| {
+        storage.search_documents(&query, limit)
+    })
+    .await
+}
+
+// Tag and metadata commands
+#[tauri::command]
+async fn add_document_tags(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    tags: Vec<String>,
+) -> Result<Vec<String>> {
+    with_storage(&storage, move |storage| {
+        storage.add_document_tags(&document_id, tags)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn remove_document_tags(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    tags: Vec<String>,
+) -> Result<Vec<String>> {
+    with_storage(&storage, move |storage| {
+        storage.remove_document_tags(&document_id, tags)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_tags(
+    storage: State<'_, StorageState>,
+) -> Result<Vec<TagInfo>> {
+    with_storage(&storage, move |storage| {
+        storage.list_tags()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn rename_tag(
+    storage: State<'_, StorageState>,
+    old_name: String,
+    new_name: String,
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.rename_tag(&old_name, new_name)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn merge_tags(
+    storage: State<'_, StorageState>,
+    sources: Vec<String>,
+    target: String,
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.merge_tags(sources, target)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn set_document_metadata(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    key: String,
+    value: Option<MetadataValue>,
+) -> Result<BTreeMap<String, MetadataValue>> {
+    with_storage(&storage, move |storage| {
+        storage.set_document_metadata(&document_id, &key, value)
+    })
+    .await
+}
+
+// Collection commands
+#[tauri::command]
+async fn create_collection(
+    storage: State<'_, StorageState>,
+    name: String,
+    parent_id: Option<String>,
+) -> Result<Collection> {
+    with_storage(&storage, move |storage| {
+        storage.create_collection(name, parent_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_collections(
+    storage: State<'_, StorageState>,
+) -> Result<Vec<Collection>> {
+    with_storage(&storage, move |storage| {
+        storage.list_collections()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn rename_collection(
+    storage: State<'_, StorageState>,
+    id: String,
+    name: String,
+) -> Result<Collection> {
+    with_storage(&storage, move |storage| {
+        storage.rename_collection(&id, name)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn move_collection(
+    storage: State<'_, StorageState>,
+    id: String,
+    parent_id: Option<String>,
+) -> Result<Collection> {
+    with_storage(&storage, move |storage| {
+        storage.move_collection(&id, parent_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn delete_collection(
+    storage: State<'_, StorageState>,
+    id: String,
+    mode: CollectionDeleteMode,
+) -> Result<Vec<String>> {
+    with_storage(&storage, move |storage| {
+        storage.delete_collection(&id, mode)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn move_document(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    collection_id: String,
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.move_document(&document_id, &collection_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_collection_stats(
+    storage: State<'_, StorageState>,
+    id: String,
+) -> Result<CollectionStats> {
+    with_storage(&storage, move |storage| {
+        storage.get_collection_stats(&id)
+    })
+    .await
+}
+
+// Revision history commands
+#[tauri::command]
+async fn list_document_revisions(
+    storage: State<'_, StorageState>,
+    document_id: String,
+) -> Result<Vec<RevisionSummary>> {
+    with_storage(&storage, move |storage| {
+        storage.list_revisions(&document_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_document_revision(
+    storage: State<'_, StorageState>,
+    revision_id: String,
+) -> Result<Option<DocumentRevision>> {
+    with_storage(&storage, move |storage| {
+        storage.get_revision(&revision_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn diff_document_revisions(
+    storage: State<'_, StorageState>,
+    from_revision_id: String,
+    to_revision_id: String,
+    granularity: Option<String>,
+) -> Result<TextDiff> {
+    with_storage(&storage, move |storage| {
+        storage.diff_revisions(&from_revision_id, &to_revision_id, granularity.as_deref())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn restore_document_revision(
+    storage: State<'_, StorageState>,
+    revision_id: String,
+    author: Option<String>,
+) -> Result<Document> {
+    with_storage(&storage, move |storage| {
+        storage.restore_revision(&revision_id, author)
+    })
+    .await
 }
 
 // File operations commands
+
+const IMPORT_FILTERS: &[(&str, &[&str])] = &[
+    ("Documents", &["txt", "md", "markdown", "docx", "html", "htm"]),
+    ("All Files", &["*"]),
+];
+const EXPORT_FILTERS: &[(&str, &[&str])] = &[
+    ("Markdown Files", &["md"]),
+    ("Word Documents", &["docx"]),
+    ("Web Pages", &["html"]),
+    ("PDF Documents", &["pdf"]),
+    ("LaTeX Documents", &["tex"]),
+    ("Text Files", &["txt"]),
+    ("All Files", &["*"]),
+];
+const EPUB_FILTERS: &[(&str, &[&str])] = &[("EPUB Books", &["epub"])];
+const BACKUP_FILTERS: &[(&str, &[&str])] = &[
+    ("Backup Files", &["json", "md"]),
+    ("All Files", &["*"]),
+];
+
+enum Pick {
+    File,
+    Folder,
+    Save,
+}
+
+// A path from the webview has to pass `resolve`, so it stays inside the
+// folders the file scope allows. Without one the user picks the path in a
+// native dialog, which may point anywhere. None when the dialog is cancelled.
+fn choose_path(
+    app: &AppHandle,
+    path: Option<String>,
+    resolve: impl FnOnce(&str) -> Result<PathBuf>,
+    pick: Pick,
+    filters: &[(&str, &[&str])],
+) -> Result<Option<PathBuf>> {
+    if let Some(path) = path {
+        return resolve(&path).map(Some);
+    }
+
+    let mut dialog = app.dialog().file();
+    for (name, extensions) in filters {
+        dialog = dialog.add_filter(*name, extensions);
+    }
+    let chosen = match pick {
+        Pick::File => dialog.blocking_pick_file(),
+        Pick::Folder => dialog.blocking_pick_folder(),
+        Pick::Save => dialog.blocking_save_file(),
+    };
+    match chosen {
+        Some(chosen) => chosen
+            .as_path()
+            .map(|path| Some(path.to_path_buf()))
+            .ok_or_else(|| AppError::invalid(
+                "The chosen file is not on the local file system",
+                "所选文件不在本地文件系统中",
+            )),
+        None => Ok(None),
+    }
+}
+
 #[tauri::command]
 async fn import_document(
+    app: AppHandle,
+    storage: State<'_, StorageState>,
+    file_path: Option<String>,
+) -> Result<Option<ImportResult>> {
+    with_storage(&storage, move |storage| {
+        let resolve = |path: &str| storage.fs_scope().resolve(Access::Read, path);
+        match choose_path(&app, file_path, resolve, Pick::File, IMPORT_FILTERS)? {
+            Some(path) => storage.import_document(&path.to_string_lossy()).map(Some),
+            None => Ok(None),
+        }
+    })
+    .await
+}
+
+// Reports each file through `import-progress` events. Files are read and
+// converted off the async runtime and saved one at a time, so other commands
+// keep working during a long import.
+#[tauri::command]
+async fn import_directory(
+    app: AppHandle,
     storage: State<'_, StorageState>,
-    file_path: String,
-) -> Result<ImportResult, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.import_document(&file_path)
+    dir_path: Option<String>,
+    options: Option<DirectoryImportOptions>,
+) -> Result<Option<DirectoryImportReport>> {
+    with_storage(&storage, move |storage| {
+        let resolve = |path: &str| storage.fs_scope().resolve(Access::List, path);
+        let dir_path = match choose_path(&app, dir_path, resolve, Pick::Folder, &[])? {
+            Some(dir_path) => dir_path,
+            None => return Ok(None),
+        };
+        let importer = storage.directory_importer(options.unwrap_or_default())?;
+        importer.run(&dir_path.to_string_lossy(), |progress| {
+            if let Err(e) = app.emit("import-progress", progress) {
+                eprintln!("Failed to emit import progress: {}", e);
+            }
+        })
+        .map(Some)
+    })
+    .await
 }
 
+// Both exports resolve to the path written, or None when cancelled
 #[tauri::command]
 async fn export_document(
+    app: AppHandle,
     storage: State<'_, StorageState>,
     id: String,
-    export_path: String,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.export_document(&id, &export_path)
+    export_path: Option<String>,
+    options: Option<ExportOptions>,
+) -> Result<Option<String>> {
+    with_storage(&storage, move |storage| {
+        let resolve = |path: &str| storage.fs_scope().resolve(Access::Write, path);
+        let export_path = match choose_path(&app, export_path, resolve, Pick::Save, EXPORT_FILTERS)? {
+            Some(export_path) => export_path.to_string_lossy().to_string(),
+            None => return Ok(None),
+        };
+        storage.export_document(&id, &export_path, options)?;
+        Ok(Some(export_path))
+    })
+    .await
 }
 
 #[tauri::command]
-async fn save_file(path: String, contents: String) -> Result<(), String> {
-    std::fs::write(path, contents).map_err(|e| e.to_string())
+async fn export_epub(
+    app: AppHandle,
+    storage: State<'_, StorageState>,
+    document_ids: Vec<String>,
+    collection_id: Option<String>,
+    metadata: EpubMetadata,
+    export_path: Option<String>,
+) -> Result<Option<String>> {
+    with_storage(&storage, move |storage| {
+        let resolve = |path: &str| storage.fs_scope().resolve(Access::Write, path);
+        let export_path = match choose_path(&app, export_path, resolve, Pick::Save, EPUB_FILTERS)? {
+            Some(export_path) => export_path.to_string_lossy().to_string(),
+            None => return Ok(None),
+        };
+        storage.export_epub(document_ids, collection_id, metadata, &export_path)?;
+        Ok(Some(export_path))
+    })
+    .await
 }
 
 #[tauri::command]
-async fn read_file(path: String) -> Result<String, String> {
-    std::fs::read_to_string(path).map_err(|e| e.to_string())
+async fn convert_html_to_markdown(html: String) -> Result<String> {
+    Ok(html::html_to_markdown(&html))
 }
 
+// Raw file access is limited to the `files` folder in the app data directory
+// and the workspace folders the user granted; see fs_scope
 #[tauri::command]
-async fn list_files(dir_path: String) -> Result<Vec<String>, String> {
-    let entries = std::fs::read_dir(dir_path).map_err(|e| e.to_string())?;
-    let mut files = Vec::new();
-    
-    for entry in entries {
-        let entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path();
-        if let Some(file_name) = path.file_name() {
-            if let Some(file_str) = file_name.to_str() {
-                files.push(file_str.to_string());
-            }
-        }
-    }
-    
-    Ok(files)
+async fn save_file(
+    storage: State<'_, StorageState>,
+    path: String,
+    contents: String,
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.fs_scope().write(&path, &contents)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn read_file(
+    storage: State<'_, StorageState>,
+    path: String,
+) -> Result<String> {
+    with_storage(&storage, move |storage| {
+        storage.fs_scope().read_to_string(&path)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_files(
+    storage: State<'_, StorageState>,
+    dir_path: String,
+) -> Result<Vec<String>> {
+    with_storage(&storage, move |storage| {
+        storage.fs_scope().list_files(&dir_path)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_workspace_folders(
+    storage: State<'_, StorageState>,
+) -> Result<Vec<String>> {
+    with_storage(&storage, move |storage| {
+        storage.fs_scope().list_workspaces()
+    })
+    .await
+}
+
+// The folder comes from the native picker, never from the webview, so a
+// script cannot grant itself access. Resolves to None when cancelled.
+#[tauri::command]
+async fn add_workspace_folder(
+    app: AppHandle,
+    storage: State<'_, StorageState>,
+) -> Result<Option<String>> {
+    with_storage(&storage, move |storage| {
+        let Some(folder) = app.dialog().file().blocking_pick_folder() else {
+            return Ok(None);
+        };
+        let folder = folder.as_path().ok_or_else(|| AppError::invalid(
+            "The chosen folder is not on the local file system",
+            "所选文件夹不在本地文件系统中",
+        ))?;
+        storage.fs_scope().add_workspace(folder).map(Some)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn remove_workspace_folder(
+    storage: State<'_, StorageState>,
+    path: String,
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.fs_scope().remove_workspace(&path)
+    })
+    .await
 }
 
 // Semantic analysis commands
@@ -124,18 +589,33 @@ async fn save_semantic_terms(
     storage: State<'_, StorageState>,
     document_id: String,
     terms: Vec<SemanticTerm>,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.save_semantic_terms(&document_id, terms)
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.save_semantic_terms(&document_id, terms)
+    })
+    .await
 }
 
 #[tauri::command]
 async fn get_semantic_terms(
     storage: State<'_, StorageState>,
     document_id: String,
-) -> Result<Vec<SemanticTerm>, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.get_semantic_terms(&document_id)
+) -> Result<Vec<SemanticTerm>> {
+    with_storage(&storage, move |storage| {
+        storage.get_semantic_terms(&document_id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn analyze_document(
+    storage: State<'_, StorageState>,
+    id: String,
+) -> Result<DocumentAnalysis> {
+    with_storage(&storage, move |storage| {
+        storage.analyze_document(&id)
+    })
+    .await
 }
 
 // Consistency rules commands
@@ -143,17 +623,61 @@ async fn get_semantic_terms(
 async fn save_consistency_rule(
     storage: State<'_, StorageState>,
     rule: ConsistencyRule,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.save_consistency_rule(rule)
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.save_consistency_rule(rule)
+    })
+    .await
 }
 
 #[tauri::command]
 async fn get_consistency_rules(
     storage: State<'_, StorageState>,
-) -> Result<Vec<ConsistencyRule>, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.get_consistency_rules()
+) -> Result<Vec<ConsistencyRule>> {
+    with_storage(&storage, move |storage| {
+        storage.get_consistency_rules()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn check_consistency(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    options: Option<CheckOptions>,
+) -> Result<ConsistencyReport> {
+    with_storage(&storage, move |storage| {
+        storage.check_consistency(&document_id, options.unwrap_or_default())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn plan_fixes(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    rule_ids: Option<Vec<String>>,
+    issues: Option<Vec<ConsistencyIssue>>,
+    options: Option<CheckOptions>,
+) -> Result<FixPlan> {
+    with_storage(&storage, move |storage| {
+        storage.plan_fixes(&document_id, rule_ids, issues, options.unwrap_or_default())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn apply_fixes(
+    storage: State<'_, StorageState>,
+    document_id: String,
+    content_hash: String,
+    edits: Vec<FixEdit>,
+    author: Option<String>,
+) -> Result<AppliedFixes> {
+    with_storage(&storage, move |storage| {
+        storage.apply_fixes(&document_id, &content_hash, edits, author)
+    })
+    .await
 }
 
 // Analysis cache commands
@@ -161,9 +685,11 @@ async fn get_consistency_rules(
 async fn save_analysis_cache(
     storage: State<'_, StorageState>,
     cache: AnalysisCache,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.save_analysis_cache(cache)
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.save_analysis_cache(cache)
+    })
+    .await
 }
 
 #[tauri::command]
@@ -171,9 +697,11 @@ async fn get_analysis_cache(
     storage: State<'_, StorageState>,
     document_id: String,
     content_hash: String,
-) -> Result<Option<AnalysisCache>, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.get_analysis_cache(&document_id, &content_hash)
+) -> Result<Option<AnalysisCache>> {
+    with_storage(&storage, move |storage| {
+        storage.get_analysis_cache(&document_id, &content_hash)
+    })
+    .await
 }
 
 // Backup commands
@@ -181,26 +709,108 @@ async fn get_analysis_cache(
 async fn create_backup(
     storage: State<'_, StorageState>,
     document_id: String,
-) -> Result<String, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.create_backup(&document_id)
+) -> Result<String> {
+    with_storage(&storage, move |storage| {
+        storage.create_backup(&document_id)
+    })
+    .await
 }
 
 #[tauri::command]
 async fn list_backups(
     storage: State<'_, StorageState>,
-) -> Result<Vec<FileInfo>, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.list_backups()
+) -> Result<Vec<FileInfo>> {
+    with_storage(&storage, move |storage| {
+        storage.list_backups()
+    })
+    .await
 }
 
+// Paths listed by list_backups are accepted as well as those in the file scope
 #[tauri::command]
 async fn restore_from_backup(
+    app: AppHandle,
+    storage: State<'_, StorageState>,
+    backup_path: Option<String>,
+    options: Option<RestoreOptions>,
+) -> Result<Option<ImportResult>> {
+    with_storage(&storage, move |storage| {
+        let resolve = |path: &str| storage.fs_scope().resolve_backup(path);
+        match choose_path(&app, backup_path, resolve, Pick::File, BACKUP_FILTERS)? {
+            Some(path) => storage.restore_from_backup(&path.to_string_lossy(), options).map(Some),
+            None => Ok(None),
+        }
+    })
+    .await
+}
+
+// Library snapshot commands
+#[tauri::command]
+async fn create_snapshot(
+    storage: State<'_, StorageState>,
+) -> Result<SnapshotInfo> {
+    with_storage(&storage, move |storage| {
+        storage.create_snapshot()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn list_snapshots(
+    storage: State<'_, StorageState>,
+) -> Result<Vec<SnapshotInfo>> {
+    with_storage(&storage, move |storage| {
+        storage.list_snapshots()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn verify_snapshot(
+    storage: State<'_, StorageState>,
+    id: String,
+) -> Result<SnapshotVerification> {
+    with_storage(&storage, move |storage| {
+        storage.verify_snapshot(&id)
+    })
+    .await
+}
+
+#[tauri::command]
+async fn restore_snapshot(
     storage: State<'_, StorageState>,
-    backup_path: String,
-) -> Result<ImportResult, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.restore_from_backup(&backup_path)
+    id: String,
+) -> Result<SnapshotRestoreResult> {
+    with_storage(&storage, move |storage| {
+        storage.restore_snapshot(&id)
+    })
+    .await
+}
+
+// How often the scheduler checks whether a snapshot is due
+const SNAPSHOT_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+// Takes scheduled snapshots in the background and announces each one with a
+// `snapshot-created` event
+fn spawn_snapshot_scheduler(app: AppHandle, storage: StorageState) {
+    tauri::async_runtime::spawn(async move {
+        let start = tokio::time::Instant::now() + Duration::from_secs(60);
+        let mut ticker = tokio::time::interval_at(start, SNAPSHOT_CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let result = with_storage(&storage, |storage| storage.run_scheduled_snapshot()).await;
+
+            match result {
+                Ok(Some(info)) => {
+                    if let Err(e) = app.emit("snapshot-created", &info) {
+                        eprintln!("Failed to emit snapshot event: {}", e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Scheduled snapshot failed: {}", e),
+            }
+        }
+    });
 }
 
 // Utility commands
@@ -208,25 +818,59 @@ async fn restore_from_backup(
 async fn calculate_content_hash(
     storage: State<'_, StorageState>,
     content: String,
-) -> Result<String, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    Ok(storage.calculate_content_hash(&content))
+) -> Result<String> {
+    with_storage(&storage, move |storage| {
+        Ok(storage.calculate_content_hash(&content))
+    })
+    .await
 }
 
 #[tauri::command]
 async fn get_storage_stats(
     storage: State<'_, StorageState>,
-) -> Result<StorageStats, String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.get_storage_stats()
+) -> Result<StorageStats> {
+    with_storage(&storage, move |storage| {
+        storage.get_storage_stats()
+    })
+    .await
 }
 
 #[tauri::command]
 async fn clear_document_cache(
     storage: State<'_, StorageState>,
-) -> Result<(), String> {
-    let storage = storage.lock().map_err(|_| "Failed to acquire storage lock")?;
-    storage.clear_document_cache()
+) -> Result<()> {
+    with_storage(&storage, move |storage| {
+        storage.clear_document_cache()
+    })
+    .await
+}
+
+// Settings commands
+#[tauri::command]
+async fn get_storage_config(
+    storage: State<'_, StorageState>,
+) -> Result<StorageConfig> {
+    with_storage(&storage, move |storage| {
+        storage.get_config()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn update_storage_config(
+    app: AppHandle,
+    storage: State<'_, StorageState>,
+    config: StorageConfig,
+) -> Result<StorageConfig> {
+    let config = with_storage(&storage, move |storage| {
+        storage.update_config(config)
+    })
+    .await?;
+    // Every window refreshes its copy, e.g. the auto-save interval
+    if let Err(e) = app.emit("storage-config-changed", &config) {
+        eprintln!("Failed to emit storage config change: {}", e);
+    }
+    Ok(config)
 }
 
 fn main() {
@@ -240,10 +884,12 @@ fn main() {
 
             // Initialize storage service
             let storage_service = StorageService::new(app_data_dir)
-                .map_err(|e| format!("Failed to initialize storage service: {}", e))?;
+                .context("Failed to initialize storage service")?;
 
             // Store as global state
-            app.manage(Arc::new(Mutex::new(storage_service)));
+            let storage: StorageState = Arc::new(storage_service);
+            app.manage(Arc::clone(&storage));
+            spawn_snapshot_scheduler(app.handle().clone(), storage);
 
             Ok(())
         })
@@ -257,19 +903,51 @@ fn main() {
             update_document,
             get_document,
             list_documents,
+            list_document_summaries,
             delete_document,
+            search_documents,
+            // Tags and metadata
+            add_document_tags,
+            remove_document_tags,
+            list_tags,
+            rename_tag,
+            merge_tags,
+            set_document_metadata,
+            // Collections
+            create_collection,
+            list_collections,
+            rename_collection,
+            move_collection,
+            delete_collection,
+            move_document,
+            get_collection_stats,
+            // Revision history
+            list_document_revisions,
+            get_document_revision,
+            diff_document_revisions,
+            restore_document_revision,
             // File operations
             import_document,
+            import_directory,
             export_document,
+            export_epub,
+            convert_html_to_markdown,
             save_file,
             read_file,
             list_files,
+            list_workspace_folders,
+            add_workspace_folder,
+            remove_workspace_folder,
             // Semantic analysis
             save_semantic_terms,
             get_semantic_terms,
+            analyze_document,
             // Consistency rules
             save_consistency_rule,
             get_consistency_rules,
+            check_consistency,
+            plan_fixes,
+            apply_fixes,
             // Analysis cache
             save_analysis_cache,
             get_analysis_cache,
@@ -277,10 +955,18 @@ fn main() {
             create_backup,
             list_backups,
             restore_from_backup,
+            // Library snapshots
+            create_snapshot,
+            list_snapshots,
+            verify_snapshot,
+            restore_snapshot,
             // Utilities
             calculate_content_hash,
             get_storage_stats,
             clear_document_cache,
+            // Settings
+            get_storage_config,
+            update_storage_config,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,494 @@
+use rusqlite::{ffi, Connection, Error, Result};
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+// Ordered list of schema migrations. Never edit a migration that has shipped;
+// append a new one with the next version number instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        sql: "
+            CREATE TABLE IF NOT EXISTS documents (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                file_path TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                word_count INTEGER NOT NULL DEFAULT 0
+            );
+
+            CREATE TABLE IF NOT EXISTS semantic_terms (
+                id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL,
+                term TEXT NOT NULL,
+                context TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                confidence REAL NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (document_id) REFERENCES documents (id)
+            );
+
+            CREATE TABLE IF NOT EXISTS consistency_rules (
+                id TEXT PRIMARY KEY,
+                term TEXT NOT NULL UNIQUE,
+                preferred_form TEXT NOT NULL,
+                alternatives TEXT NOT NULL,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS analysis_cache (
+                id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL,
+                content_hash TEXT NOT NULL,
+                analysis_result TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (document_id) REFERENCES documents (id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_documents_updated_at ON documents (updated_at);
+            CREATE INDEX IF NOT EXISTS idx_semantic_terms_document_id ON semantic_terms (document_id);
+            CREATE INDEX IF NOT EXISTS idx_analysis_cache_document_id ON analysis_cache (document_id);
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "Full-text search index over documents",
+        // Standalone (not external-content) FTS table: `save_document` uses
+        // INSERT OR REPLACE, whose implicit delete does not fire DELETE
+        // triggers, so the insert trigger clears any stale row itself.
+        // Terms too short for trigrams use `document_grams`, which is filled
+        // in Rust: existing documents keep grams_indexed = 0 here and are
+        // indexed when the database is opened.
+        sql: "
+            CREATE VIRTUAL TABLE IF NOT EXISTS documents_fts USING fts5(
+                document_id UNINDEXED,
+                title,
+                content,
+                tokenize = 'trigram'
+            );
+
+            INSERT INTO documents_fts (document_id, title, content)
+                SELECT id, title, content FROM documents;
+
+            CREATE TRIGGER IF NOT EXISTS documents_fts_insert AFTER INSERT ON documents BEGIN
+                DELETE FROM documents_fts WHERE document_id = new.id;
+                INSERT INTO documents_fts (document_id, title, content)
+                    VALUES (new.id, new.title, new.content);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS documents_fts_update AFTER UPDATE ON documents BEGIN
+                DELETE FROM documents_fts WHERE document_id = old.id;
+                INSERT INTO documents_fts (document_id, title, content)
+                    VALUES (new.id, new.title, new.content);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS documents_fts_delete AFTER DELETE ON documents BEGIN
+                DELETE FROM documents_fts WHERE document_id = old.id;
+            END;
+
+            CREATE TABLE IF NOT EXISTS document_grams (
+                gram TEXT NOT NULL,
+                document_id TEXT NOT NULL,
+                PRIMARY KEY (gram, document_id)
+            ) WITHOUT ROWID;
+
+            CREATE INDEX IF NOT EXISTS idx_document_grams_document ON document_grams (document_id);
+
+            ALTER TABLE documents ADD COLUMN grams_indexed INTEGER NOT NULL DEFAULT 0;
+
+            CREATE TRIGGER IF NOT EXISTS document_grams_delete AFTER DELETE ON documents BEGIN
+                DELETE FROM document_grams WHERE document_id = old.id;
+            END;
+        ",
+    },
+    Migration {
+        version: 3,
+        description: "Document revision history",
+        sql: "
+            CREATE TABLE IF NOT EXISTS document_revisions (
+                id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL,
+                revision_number INTEGER NOT NULL,
+                title TEXT NOT NULL,
+                content TEXT NOT NULL,
+                author TEXT,
+                word_count INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (document_id) REFERENCES documents (id),
+                UNIQUE (document_id, revision_number)
+            );
+
+            -- Seed history with the current state of every existing document
+            INSERT INTO document_revisions
+                (id, document_id, revision_number, title, content, author, word_count, created_at)
+                SELECT lower(hex(randomblob(16))), id, 1, title, content, NULL, word_count, updated_at
+                FROM documents;
+        ",
+    },
+    Migration {
+        version: 4,
+        description: "Indexes for sorted and filtered document listing",
+        sql: "
+            CREATE INDEX IF NOT EXISTS idx_documents_created_at ON documents (created_at);
+            CREATE INDEX IF NOT EXISTS idx_documents_title ON documents (title);
+            CREATE INDEX IF NOT EXISTS idx_documents_word_count ON documents (word_count);
+        ",
+    },
+    Migration {
+        version: 5,
+        description: "Nested collections with existing documents moved to Unsorted",
+        sql: "
+            CREATE TABLE IF NOT EXISTS collections (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                parent_id TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (parent_id) REFERENCES collections (id)
+            );
+
+            INSERT OR IGNORE INTO collections (id, name, parent_id, created_at, updated_at)
+                VALUES ('unsorted', 'Unsorted', NULL,
+                        strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'),
+                        strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now'));
+
+            -- SQLite cannot add a REFERENCES column with a non-NULL default,
+            -- so the link to collections (id) is maintained by the app.
+            ALTER TABLE documents ADD COLUMN collection_id TEXT NOT NULL DEFAULT 'unsorted';
+
+            CREATE INDEX IF NOT EXISTS idx_collections_parent_id ON collections (parent_id);
+            CREATE INDEX IF NOT EXISTS idx_documents_collection_id ON documents (collection_id);
+        ",
+    },
+    Migration {
+        version: 6,
+        description: "Document tags and typed metadata",
+        // `value` is declared without a type so numbers, booleans and text
+        // keep their storage class and compare naturally in predicates.
+        sql: "
+            CREATE TABLE IF NOT EXISTS tags (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS document_tags (
+                document_id TEXT NOT NULL,
+                tag_id TEXT NOT NULL,
+                PRIMARY KEY (document_id, tag_id),
+                FOREIGN KEY (document_id) REFERENCES documents (id),
+                FOREIGN KEY (tag_id) REFERENCES tags (id)
+            );
+
+            CREATE TABLE IF NOT EXISTS document_metadata (
+                document_id TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value_type TEXT NOT NULL,
+                value,
+                PRIMARY KEY (document_id, key),
+                FOREIGN KEY (document_id) REFERENCES documents (id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_document_tags_tag_id ON document_tags (tag_id);
+            CREATE INDEX IF NOT EXISTS idx_document_metadata_key ON document_metadata (key, value);
+        ",
+    },
+    Migration {
+        version: 7,
+        description: "Stored content hashes",
+        // SQLite has no SHA-256, so existing documents keep an empty hash
+        // here and are hashed when the database is opened.
+        sql: "
+            ALTER TABLE documents ADD COLUMN content_hash TEXT NOT NULL DEFAULT '';
+            CREATE INDEX IF NOT EXISTS idx_documents_content_hash ON documents (content_hash);
+        ",
+    },
+    Migration {
+        version: 8,
+        description: "Byte and character offsets for extracted terms",
+        // Terms saved by older versions keep NULL offsets; only `position` is known
+        sql: "
+            ALTER TABLE semantic_terms ADD COLUMN byte_start INTEGER;
+            ALTER TABLE semantic_terms ADD COLUMN byte_end INTEGER;
+            ALTER TABLE semantic_terms ADD COLUMN char_start INTEGER;
+            ALTER TABLE semantic_terms ADD COLUMN char_end INTEGER;
+
+            CREATE INDEX IF NOT EXISTS idx_analysis_cache_lookup ON analysis_cache (document_id, content_hash);
+        ",
+    },
+    Migration {
+        version: 9,
+        description: "Character and per-script word counts",
+        // Counting needs the segmenter, so existing documents are marked with
+        // char_count -1 and counted again when storage starts. The search
+        // index only has to follow changes to the indexed columns.
+        sql: "
+            ALTER TABLE documents ADD COLUMN char_count INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE documents ADD COLUMN cjk_word_count INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE documents ADD COLUMN latin_word_count INTEGER NOT NULL DEFAULT 0;
+
+            DROP TRIGGER IF EXISTS documents_fts_update;
+            CREATE TRIGGER documents_fts_update AFTER UPDATE OF title, content ON documents BEGIN
+                DELETE FROM documents_fts WHERE document_id = old.id;
+                INSERT INTO documents_fts (document_id, title, content)
+                    VALUES (new.id, new.title, new.content);
+            END;
+
+            UPDATE documents SET char_count = -1;
+        ",
+    },
+    Migration {
+        version: 10,
+        description: "Typed, scoped and prioritized consistency rules",
+        // Several rules may now share a term (e.g. one per folder), so the
+        // table is rebuilt without UNIQUE(term). Alternatives that were not
+        // a JSON array keep only their string elements, or become empty, and
+        // is_active stored as the text 'true' becomes 1.
+        sql: "
+            CREATE TABLE consistency_rules_new (
+                id TEXT PRIMARY KEY,
+                term TEXT NOT NULL,
+                preferred_form TEXT NOT NULL,
+                alternatives TEXT NOT NULL DEFAULT '[]',
+                pattern TEXT,
+                replacement TEXT,
+                scope_type TEXT NOT NULL DEFAULT 'global',
+                scope_id TEXT,
+                category TEXT NOT NULL DEFAULT 'terminology',
+                priority INTEGER NOT NULL DEFAULT 0,
+                is_active INTEGER NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL
+            );
+
+            INSERT INTO consistency_rules_new (id, term, preferred_form, alternatives, is_active, created_at)
+            SELECT id, term, preferred_form,
+                   CASE WHEN json_valid(alternatives) AND json_type(alternatives) = 'array' THEN
+                       (SELECT json_group_array(value) FROM json_each(consistency_rules.alternatives)
+                        WHERE type = 'text' AND trim(value) != '')
+                   ELSE '[]' END,
+                   CASE WHEN is_active IN (1, '1', 'true') THEN 1 ELSE 0 END,
+                   created_at
+            FROM consistency_rules;
+
+            DROP TABLE consistency_rules;
+            ALTER TABLE consistency_rules_new RENAME TO consistency_rules;
+
+            CREATE INDEX IF NOT EXISTS idx_consistency_rules_term ON consistency_rules (term);
+            CREATE INDEX IF NOT EXISTS idx_consistency_rules_scope ON consistency_rules (scope_type, scope_id);
+        ",
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+// Brings the database up to the latest schema version. Each migration runs in
+// its own transaction together with the `user_version` bump, so a failure
+// leaves the database at the last successfully applied version.
+pub fn run(conn: &mut Connection) -> Result<u32> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(Error::SqliteFailure(
+            ffi::Error::new(ffi::SQLITE_CANTOPEN),
+            Some(format!(
+                "Database schema version {} is newer than the latest supported version {}",
+                current, latest
+            )),
+        ));
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql).map_err(|e| {
+            eprintln!(
+                "Migration {} ({}) failed: {}",
+                migration.version, migration.description, e
+            );
+            e
+        })?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A database as the given schema version left it
+    fn database_at(version: u32) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+            conn.execute_batch(migration.sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+        conn
+    }
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).unwrap();
+        let names = stmt.query_map([], |row| row.get(1)).unwrap();
+        names.map(|name| name.unwrap()).collect()
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn insert_document(conn: &Connection, id: &str, content: &str) {
+        conn.execute(
+            "INSERT INTO documents (id, title, content, file_path, created_at, updated_at, word_count)
+             VALUES (?1, 'Title', ?2, NULL, '2024-01-01T00:00:00+00:00', '2024-01-02T00:00:00+00:00', 3)",
+            [id, content],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn creates_latest_schema_from_scratch() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run(&mut conn).unwrap(), latest_version());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert_eq!(
+            columns(&conn, "documents"),
+            vec![
+                "id", "title", "content", "file_path", "created_at", "updated_at", "word_count",
+                "grams_indexed", "collection_id", "content_hash", "char_count", "cjk_word_count",
+                "latin_word_count",
+            ]
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM collections WHERE id = 'unsorted'"), 1);
+
+        // Running again is a no-op
+        assert_eq!(run(&mut conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn upgrades_unversioned_database_and_keeps_rows() {
+        // Databases from before migrations have the initial tables at version 0
+        let mut conn = database_at(1);
+        conn.pragma_update(None, "user_version", 0).unwrap();
+        insert_document(&conn, "d1", "semantic analysis of 语义分析");
+        conn.execute(
+            "INSERT INTO semantic_terms VALUES ('t1', 'd1', 'semantic', 'semantic analysis', 0, 0.9, '2024-01-01T00:00:00+00:00')",
+            [],
+        )
+        .unwrap();
+
+        run(&mut conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+
+        let (title, content, word_count): (String, String, i64) = conn
+            .query_row("SELECT title, content, word_count FROM documents WHERE id = 'd1'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((title.as_str(), content.as_str(), word_count), ("Title", "semantic analysis of 语义分析", 3));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM semantic_terms WHERE document_id = 'd1' AND byte_start IS NULL"), 1);
+        // v2 indexes existing content, v3 seeds one revision per document
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM documents_fts WHERE documents_fts MATCH '语义分析'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM document_revisions WHERE document_id = 'd1' AND revision_number = 1"), 1);
+    }
+
+    #[test]
+    fn moves_existing_documents_to_unsorted() {
+        let mut conn = database_at(4);
+        insert_document(&conn, "d1", "text");
+
+        run(&mut conn).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM documents WHERE collection_id = 'unsorted'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM collections WHERE id = 'unsorted' AND parent_id IS NULL"), 1);
+    }
+
+    #[test]
+    fn marks_existing_documents_for_recount() {
+        let mut conn = database_at(8);
+        insert_document(&conn, "d1", "old text");
+
+        run(&mut conn).unwrap();
+        assert_eq!(count(&conn, "SELECT char_count FROM documents WHERE id = 'd1'"), -1);
+
+        // New rows start counted, and updating counts leaves the search index alone
+        insert_document(&conn, "d2", "new text");
+        assert_eq!(count(&conn, "SELECT char_count FROM documents WHERE id = 'd2'"), 0);
+        conn.execute("UPDATE documents SET char_count = 7 WHERE id = 'd1'", []).unwrap();
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM documents_fts WHERE document_id = 'd1'"), 1);
+    }
+
+    #[test]
+    fn rebuilds_consistency_rules_without_unique_terms() {
+        let mut conn = database_at(9);
+        let rules = [
+            ("r1", "JS", "[\"JS\", 3, \"\", \"js\"]", "true"),
+            ("r2", "x", "not json", "1"),
+            ("r3", "y", "{\"a\": 1}", "0"),
+        ];
+        for (id, term, alternatives, is_active) in rules {
+            conn.execute(
+                "INSERT INTO consistency_rules VALUES (?1, ?2, 'Preferred', ?3, ?4, '2024-01-01T00:00:00+00:00')",
+                [id, term, alternatives, is_active],
+            )
+            .unwrap();
+        }
+
+        run(&mut conn).unwrap();
+        assert_eq!(
+            columns(&conn, "consistency_rules"),
+            vec![
+                "id", "term", "preferred_form", "alternatives", "pattern", "replacement", "scope_type",
+                "scope_id", "category", "priority", "is_active", "created_at",
+            ]
+        );
+        let alternatives = |id: &str| -> String {
+            conn.query_row("SELECT alternatives FROM consistency_rules WHERE id = ?1", [id], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(alternatives("r1"), "[\"JS\",\"js\"]");
+        assert_eq!(alternatives("r2"), "[]");
+        assert_eq!(alternatives("r3"), "[]");
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM consistency_rules WHERE is_active = 1"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM consistency_rules WHERE scope_type = 'global' AND priority = 0"), 3);
+
+        // Several rules may now share a term
+        conn.execute(
+            "INSERT INTO consistency_rules (id, term, preferred_form, created_at)
+             VALUES ('r4', 'JS', 'JavaScript', '2024-01-01T00:00:00+00:00')",
+            [],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn refuses_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        assert!(run(&mut conn).is_err());
+        assert_eq!(current_version(&conn).unwrap(), latest_version() + 1);
+    }
+
+    #[test]
+    fn failed_migration_keeps_previous_version() {
+        let mut conn = database_at(4);
+        // v5 creates this index; a table of the same name makes it fail
+        conn.execute_batch("CREATE TABLE idx_collections_parent_id (x)").unwrap();
+        assert!(run(&mut conn).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 4);
+        assert!(!columns(&conn, "documents").contains(&"collection_id".to_string()));
+    }
+}
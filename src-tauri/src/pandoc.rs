@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+
+use crate::database::{Document, MetadataValue};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PandocExportOptions {
+    pub include_timestamps: bool, // `date` (created) and `updated`
+    pub include_tags: bool,       // written as `keywords`
+    pub include_metadata: bool,   // user metadata as top-level fields
+}
+
+impl Default for PandocExportOptions {
+    fn default() -> Self {
+        Self {
+            include_timestamps: true,
+            include_tags: true,
+            include_metadata: true,
+        }
+    }
+}
+
+// Markdown with a YAML metadata block, ready for `pandoc -s`
+pub fn render_pandoc_markdown(document: &Document, options: &PandocExportOptions) -> String {
+    let mut fields: Vec<(String, String)> = vec![("title".to_string(), yaml_string(&document.title))];
+    if options.include_timestamps {
+        fields.push(("date".to_string(), document.created_at.to_rfc3339()));
+        fields.push(("updated".to_string(), document.updated_at.to_rfc3339()));
+    }
+    if options.include_tags && !document.tags.is_empty() {
+        let tags: Vec<String> = document.tags.iter().map(|tag| yaml_string(tag)).collect();
+        fields.push(("keywords".to_string(), format!("[{}]", tags.join(", "))));
+    }
+    if options.include_metadata {
+        for (key, value) in &document.metadata {
+            // Built-in fields win over user metadata of the same name
+            if fields.iter().any(|(existing, _)| existing == key) {
+                continue;
+            }
+            fields.push((key.clone(), yaml_value(value)));
+        }
+    }
+
+    let mut out = String::from("---\n");
+    for (key, value) in &fields {
+        out.push_str(&format!("{}: {}\n", yaml_key(key), value));
+    }
+    out.push_str("---\n\n");
+    out.push_str(document.content.trim_end());
+    out.push('\n');
+    out
+}
+
+fn yaml_key(key: &str) -> String {
+    let plain = !key.is_empty()
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        && !key.starts_with('-');
+    if plain {
+        key.to_string()
+    } else {
+        yaml_string(key)
+    }
+}
+
+// Double-quoted scalars are always safe, whatever the text looks like
+fn yaml_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn yaml_value(value: &MetadataValue) -> String {
+    match value {
+        MetadataValue::Text(text) => yaml_string(text),
+        MetadataValue::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => format!("{}", *number as i64),
+        MetadataValue::Number(number) => number.to_string(),
+        MetadataValue::Boolean(flag) => flag.to_string(),
+        MetadataValue::Date(date) => date.to_rfc3339(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn document() -> Document {
+        let mut metadata = std::collections::BTreeMap::new();
+        metadata.insert("title".to_string(), MetadataValue::Text("ignored".to_string()));
+        metadata.insert("draft".to_string(), MetadataValue::Boolean(true));
+        metadata.insert("pages".to_string(), MetadataValue::Number(12.0));
+        metadata.insert("ratio".to_string(), MetadataValue::Number(0.5));
+        metadata.insert("due date".to_string(), MetadataValue::Text("line\nbreak".to_string()));
+        Document {
+            id: "doc".to_string(),
+            title: "Quote \"this\": yes".to_string(),
+            content: "Body text\n\n".to_string(),
+            file_path: None,
+            created_at: Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap(),
+            updated_at: Utc.with_ymd_and_hms(2024, 3, 2, 8, 0, 0).unwrap(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: "unsorted".to_string(),
+            tags: vec!["小说".to_string(), "draft".to_string()],
+            metadata,
+        }
+    }
+
+    #[test]
+    fn writes_yaml_metadata_block() {
+        let markdown = render_pandoc_markdown(&document(), &PandocExportOptions::default());
+        assert_eq!(
+            markdown,
+            "---\n\
+             title: \"Quote \\\"this\\\": yes\"\n\
+             date: 2024-03-01T08:00:00+00:00\n\
+             updated: 2024-03-02T08:00:00+00:00\n\
+             keywords: [\"小说\", \"draft\"]\n\
+             draft: true\n\
+             \"due date\": \"line\\nbreak\"\n\
+             pages: 12\n\
+             ratio: 0.5\n\
+             ---\n\n\
+             Body text\n"
+        );
+    }
+
+    #[test]
+    fn leaves_out_disabled_fields() {
+        let options = PandocExportOptions {
+            include_timestamps: false,
+            include_tags: false,
+            include_metadata: false,
+        };
+        let markdown = render_pandoc_markdown(&document(), &options);
+        assert_eq!(markdown, "---\ntitle: \"Quote \\\"this\\\": yes\"\n---\n\nBody text\n");
+    }
+
+    #[test]
+    fn quotes_unusual_keys() {
+        assert_eq!(yaml_key("author_name"), "author_name");
+        assert_eq!(yaml_key("-x"), "\"-x\"");
+        assert_eq!(yaml_key("a: b"), "\"a: b\"");
+        assert_eq!(yaml_string("tab\there\u{1}"), "\"tab\\there\\u0001\"");
+    }
+}
@@ -0,0 +1,1032 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::types::{CidFontType, FontFlags, SystemInfo, TextRenderingMode};
+use pdf_writer::{Content, Date, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use ttf_parser::{name_id, Face, GlyphId};
+
+use crate::database::Document;
+use crate::search::is_cjk;
+
+const MM: f32 = 72.0 / 25.4;
+const LINE_SPACING: f32 = 1.6;
+const FONT_NAME: Name<'static> = Name(b"F1");
+
+// Fonts tried in order when no font is configured. CJK-capable faces come
+// first; DejaVu is a last resort that renders Latin text only.
+const FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/STHeiti Light.ttc",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/usr/share/fonts/wqy-microhei/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-zenhei.ttc",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+];
+
+// CJK punctuation that must not start a line
+const NO_LINE_START: &str = "，。、；：！？）】」』》〉”’,.;:!?)]}";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    A4,
+    A5,
+    Letter,
+    Legal,
+    Custom { width_mm: f32, height_mm: f32 },
+}
+
+impl PageSize {
+    fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0 * MM, 297.0 * MM),
+            PageSize::A5 => (148.0 * MM, 210.0 * MM),
+            PageSize::Letter => (612.0, 792.0),
+            PageSize::Legal => (612.0, 1008.0),
+            PageSize::Custom { width_mm, height_mm } => (width_mm * MM, height_mm * MM),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageMargins {
+    pub top_mm: f32,
+    pub right_mm: f32,
+    pub bottom_mm: f32,
+    pub left_mm: f32,
+}
+
+impl Default for PageMargins {
+    fn default() -> Self {
+        Self {
+            top_mm: 25.0,
+            right_mm: 20.0,
+            bottom_mm: 25.0,
+            left_mm: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfExportOptions {
+    pub page_size: PageSize,
+    pub margins: PageMargins,
+    pub font_size: f32,
+    pub font_path: Option<String>, // TrueType/OpenType file or collection; searched when unset
+    pub title_page: bool,
+    pub page_numbers: bool,
+}
+
+impl Default for PdfExportOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            margins: PageMargins::default(),
+            font_size: 11.0,
+            font_path: None,
+            title_page: true,
+            page_numbers: true,
+        }
+    }
+}
+
+impl PdfExportOptions {
+    fn validate(&self) -> Result<(), String> {
+        let (width, height) = self.page_size.dimensions();
+        let margins = &self.margins;
+        if !(6.0..=36.0).contains(&self.font_size) {
+            return Err("Font size must be between 6 and 36 points".to_string());
+        }
+        if [margins.top_mm, margins.right_mm, margins.bottom_mm, margins.left_mm].iter().any(|m| *m < 0.0) {
+            return Err("Page margins cannot be negative".to_string());
+        }
+        let content_width = width - (margins.left_mm + margins.right_mm) * MM;
+        let content_height = height - (margins.top_mm + margins.bottom_mm) * MM;
+        if content_width < 50.0 * MM || content_height < 50.0 * MM {
+            return Err("Page margins leave too little room for content".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn export_pdf(document: &Document, options: &PdfExportOptions) -> Result<Vec<u8>, String> {
+    options.validate()?;
+
+    let (font_data, font_index) = load_font(options.font_path.as_deref())?;
+    let face = Face::parse(&font_data, font_index).map_err(|e| format!("Failed to parse font: {}", e))?;
+
+    let blocks = parse_blocks(&document.content);
+    let mut composer = Composer::new(&face, options);
+    if options.title_page {
+        composer.title_page(document);
+    }
+    for block in &blocks {
+        composer.block(block);
+    }
+
+    write_pdf(document, composer, &font_data, font_index, &face)
+}
+
+fn load_font(font_path: Option<&str>) -> Result<(Vec<u8>, u32), String> {
+    let path = match font_path {
+        Some(path) => path.to_string(),
+        None => FONT_CANDIDATES
+            .iter()
+            .find(|candidate| Path::new(candidate).exists())
+            .map(|candidate| candidate.to_string())
+            .ok_or("No usable font found for PDF export; set a font path")?,
+    };
+    let data = std::fs::read(&path).map_err(|e| format!("Failed to read font {}: {}", path, e))?;
+    Ok((data, 0))
+}
+
+// ---------------------------------------------------------------------------
+// Markdown to layout blocks
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct SpanStyle {
+    bold: bool,
+    italic: bool,
+    code: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Span {
+    text: String,
+    style: SpanStyle,
+}
+
+#[derive(Debug)]
+enum Block {
+    Heading { level: usize, spans: Vec<Span> },
+    Paragraph { spans: Vec<Span>, indent: usize, quote: bool },
+    ListItem { depth: usize, marker: String, spans: Vec<Span> },
+    Code(String),
+    Table(Vec<Vec<Vec<Span>>>),
+    Rule,
+}
+
+fn parse_blocks(markdown: &str) -> Vec<Block> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+
+    let mut blocks = Vec::new();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut style = SpanStyle::default();
+    let mut heading: Option<usize> = None;
+    let mut quote_depth = 0;
+    let mut lists: Vec<Option<u64>> = Vec::new(); // next number for ordered lists
+    let mut pending_marker: Option<String> = None;
+    let mut code: Option<String> = None;
+    let mut table: Option<Vec<Vec<Vec<Span>>>> = None;
+
+    // Inline content ends up in a heading, table cell, list item or paragraph
+    // depending on the enclosing block.
+    let flush = |spans: &mut Vec<Span>,
+                 blocks: &mut Vec<Block>,
+                 heading: Option<usize>,
+                 pending_marker: &mut Option<String>,
+                 lists: &Vec<Option<u64>>,
+                 quote_depth: usize| {
+        if spans.is_empty() {
+            return;
+        }
+        let spans = std::mem::take(spans);
+        if let Some(level) = heading {
+            blocks.push(Block::Heading { level, spans });
+        } else if let Some(marker) = pending_marker.take() {
+            blocks.push(Block::ListItem { depth: lists.len().saturating_sub(1), marker, spans });
+        } else {
+            blocks.push(Block::Paragraph { spans, indent: lists.len() + quote_depth, quote: quote_depth > 0 });
+        }
+    };
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                heading = Some(match level {
+                    HeadingLevel::H1 => 1,
+                    HeadingLevel::H2 => 2,
+                    HeadingLevel::H3 => 3,
+                    HeadingLevel::H4 => 4,
+                    HeadingLevel::H5 => 5,
+                    HeadingLevel::H6 => 6,
+                })
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                flush(&mut spans, &mut blocks, heading, &mut pending_marker, &lists, quote_depth);
+                heading = None;
+            }
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Item) if table.is_none() => {
+                flush(&mut spans, &mut blocks, heading, &mut pending_marker, &lists, quote_depth);
+            }
+            Event::Start(Tag::BlockQuote(_)) => quote_depth += 1,
+            Event::End(TagEnd::BlockQuote(_)) => quote_depth -= 1,
+            Event::Start(Tag::List(start)) => {
+                flush(&mut spans, &mut blocks, heading, &mut pending_marker, &lists, quote_depth);
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                flush(&mut spans, &mut blocks, heading, &mut pending_marker, &lists, quote_depth);
+                pending_marker = Some(match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => "•".to_string(),
+                });
+            }
+            Event::Start(Tag::CodeBlock(_)) => code = Some(String::new()),
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some(text) = code.take() {
+                    blocks.push(Block::Code(text.trim_end_matches('\n').to_string()));
+                }
+            }
+            Event::Start(Tag::Table(_)) => table = Some(Vec::new()),
+            Event::End(TagEnd::Table) => {
+                if let Some(rows) = table.take() {
+                    blocks.push(Block::Table(rows));
+                }
+            }
+            Event::Start(Tag::TableHead) | Event::Start(Tag::TableRow) => {
+                if let Some(rows) = table.as_mut() {
+                    rows.push(Vec::new());
+                }
+            }
+            Event::End(TagEnd::TableCell) => {
+                if let Some(row) = table.as_mut().and_then(|rows| rows.last_mut()) {
+                    row.push(std::mem::take(&mut spans));
+                }
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => spans.push(Span {
+                text: format!("[{}] ", label),
+                style: SpanStyle::default(),
+            }),
+            Event::Start(Tag::Emphasis) => style.italic = true,
+            Event::End(TagEnd::Emphasis) => style.italic = false,
+            Event::Start(Tag::Strong) => style.bold = true,
+            Event::End(TagEnd::Strong) => style.bold = false,
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => match code.as_mut() {
+                Some(code) => code.push_str(&text),
+                None => spans.push(Span { text: text.to_string(), style }),
+            },
+            Event::Code(text) => spans.push(Span {
+                text: text.to_string(),
+                style: SpanStyle { code: true, ..style },
+            }),
+            Event::SoftBreak => spans.push(Span { text: " ".to_string(), style }),
+            Event::HardBreak => spans.push(Span { text: "\n".to_string(), style }),
+            Event::FootnoteReference(label) => spans.push(Span {
+                text: format!("[{}]", label),
+                style: SpanStyle::default(),
+            }),
+            Event::Rule => blocks.push(Block::Rule),
+            _ => {}
+        }
+    }
+
+    flush(&mut spans, &mut blocks, heading, &mut pending_marker, &lists, quote_depth);
+    blocks
+}
+
+// ---------------------------------------------------------------------------
+// Layout
+// ---------------------------------------------------------------------------
+
+struct Piece {
+    text: String,
+    style: SpanStyle,
+    width: f32,
+    space: bool,
+}
+
+struct Line {
+    pieces: Vec<Piece>,
+}
+
+struct Composer<'a> {
+    face: &'a Face<'a>,
+    units_per_em: f32,
+    page_width: f32,
+    page_height: f32,
+    left: f32,
+    right: f32,
+    top: f32,
+    bottom: f32,
+    font_size: f32,
+    pages: Vec<Content>,
+    body_start: usize, // index of the first numbered page
+    y: f32,
+    used_glyphs: BTreeMap<u16, char>,
+    page_numbers: bool,
+}
+
+impl<'a> Composer<'a> {
+    fn new(face: &'a Face<'a>, options: &PdfExportOptions) -> Self {
+        let (page_width, page_height) = options.page_size.dimensions();
+        let margins = &options.margins;
+        Composer {
+            face,
+            units_per_em: face.units_per_em() as f32,
+            page_width,
+            page_height,
+            left: margins.left_mm * MM,
+            right: page_width - margins.right_mm * MM,
+            top: page_height - margins.top_mm * MM,
+            bottom: margins.bottom_mm * MM,
+            font_size: options.font_size,
+            pages: Vec::new(),
+            body_start: 0,
+            y: 0.0,
+            used_glyphs: BTreeMap::new(),
+            page_numbers: options.page_numbers,
+        }
+    }
+
+    fn content_width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Content::new());
+        self.y = self.top;
+    }
+
+    fn page(&mut self) -> &mut Content {
+        if self.pages.is_empty() {
+            self.new_page();
+        }
+        self.pages.last_mut().expect("page exists")
+    }
+
+    fn ensure_space(&mut self, height: f32) {
+        if self.pages.is_empty() || (self.y - height < self.bottom && self.y < self.top) {
+            self.new_page();
+        }
+    }
+
+    fn at_page_top(&self) -> bool {
+        self.y >= self.top
+    }
+
+    fn char_width(&self, c: char, size: f32) -> f32 {
+        let glyph = self.face.glyph_index(c).unwrap_or(GlyphId(0));
+        let advance = self.face.glyph_hor_advance(glyph).unwrap_or(0) as f32;
+        advance / self.units_per_em * size
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.char_width(c, size)).sum()
+    }
+
+    fn encode(&mut self, text: &str) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(text.len() * 2);
+        for c in text.chars() {
+            let glyph = self.face.glyph_index(c).map(|g| g.0).unwrap_or(0);
+            self.used_glyphs.entry(glyph).or_insert(c);
+            encoded.extend_from_slice(&glyph.to_be_bytes());
+        }
+        encoded
+    }
+
+    // Breaks styled text into lines no wider than `width`. Latin words wrap at
+    // spaces; CJK text may break between any two characters except before
+    // closing punctuation.
+    fn wrap(&self, spans: &[Span], width: f32, size: f32) -> Vec<Line> {
+        let mut tokens: Vec<Piece> = Vec::new();
+        for span in spans {
+            let mut word = String::new();
+            let push_word = |word: &mut String, tokens: &mut Vec<Piece>| {
+                if !word.is_empty() {
+                    let text = std::mem::take(word);
+                    tokens.push(Piece { width: self.text_width(&text, size), text, style: span.style, space: false });
+                }
+            };
+            for c in span.text.chars() {
+                if c == '\n' {
+                    push_word(&mut word, &mut tokens);
+                    tokens.push(Piece { text: "\n".to_string(), style: span.style, width: 0.0, space: true });
+                } else if c.is_whitespace() {
+                    push_word(&mut word, &mut tokens);
+                    tokens.push(Piece { text: " ".to_string(), style: span.style, width: self.char_width(' ', size), space: true });
+                } else if NO_LINE_START.contains(c) && word.is_empty() && tokens.last().is_some_and(|t| !t.space) {
+                    let last = tokens.last_mut().expect("checked above");
+                    last.text.push(c);
+                    last.width += self.char_width(c, size);
+                } else if is_cjk(c) {
+                    push_word(&mut word, &mut tokens);
+                    word.push(c);
+                    push_word(&mut word, &mut tokens);
+                } else {
+                    word.push(c);
+                }
+            }
+            push_word(&mut word, &mut tokens);
+        }
+
+        let mut lines = vec![Line { pieces: Vec::new() }];
+        let mut line_width = 0.0;
+        for token in tokens {
+            let line = lines.last_mut().expect("at least one line");
+            if token.text == "\n" {
+                lines.push(Line { pieces: Vec::new() });
+                line_width = 0.0;
+                continue;
+            }
+            if token.space && line.pieces.is_empty() {
+                continue;
+            }
+            if line_width + token.width > width && !line.pieces.is_empty() && !token.space {
+                while line.pieces.last().is_some_and(|p| p.space) {
+                    line.pieces.pop();
+                }
+                lines.push(Line { pieces: Vec::new() });
+                line_width = 0.0;
+            }
+
+            // A single token wider than the line (e.g. a long URL) is split by character
+            if token.width > width {
+                let mut chunk = String::new();
+                let mut chunk_width = 0.0;
+                for c in token.text.chars() {
+                    let w = self.char_width(c, size);
+                    if chunk_width + w > width && !chunk.is_empty() {
+                        let line = lines.last_mut().expect("at least one line");
+                        line.pieces.push(Piece { text: std::mem::take(&mut chunk), style: token.style, width: chunk_width, space: false });
+                        lines.push(Line { pieces: Vec::new() });
+                        chunk_width = 0.0;
+                    }
+                    chunk.push(c);
+                    chunk_width += w;
+                }
+                let line = lines.last_mut().expect("at least one line");
+                line.pieces.push(Piece { text: chunk, style: token.style, width: chunk_width, space: false });
+                line_width = chunk_width;
+                continue;
+            }
+
+            line_width += token.width;
+            lines.last_mut().expect("at least one line").pieces.push(token);
+        }
+
+        lines.retain(|line| !line.pieces.is_empty());
+        lines
+    }
+
+    fn draw_text(&mut self, x: f32, baseline: f32, text: &str, style: SpanStyle, size: f32) {
+        let encoded = self.encode(text);
+        let content = self.page();
+        content.begin_text();
+        content.set_font(FONT_NAME, size);
+        if style.code {
+            content.set_fill_rgb(0.55, 0.1, 0.1);
+        }
+        if style.bold {
+            // Faux bold: stroke the outline in the fill colour
+            content.set_text_rendering_mode(TextRenderingMode::FillStroke);
+            content.set_line_width(size * 0.035);
+        }
+        let skew = if style.italic { 0.21 } else { 0.0 };
+        content.set_text_matrix([1.0, 0.0, skew, 1.0, x, baseline]);
+        content.show(Str(&encoded));
+        if style.bold {
+            content.set_text_rendering_mode(TextRenderingMode::Fill);
+        }
+        if style.code {
+            content.set_fill_gray(0.0);
+        }
+        content.end_text();
+    }
+
+    fn draw_line(&mut self, line: &Line, x: f32, size: f32) {
+        let line_height = size * LINE_SPACING;
+        let baseline = self.y - (line_height + size * 0.7) / 2.0;
+        let mut cursor = x;
+        for piece in &line.pieces {
+            if !piece.space {
+                self.draw_text(cursor, baseline, &piece.text, piece.style, size);
+            }
+            cursor += piece.width;
+        }
+        self.y -= line_height;
+    }
+
+    fn paragraph(&mut self, spans: &[Span], x: f32, size: f32, style: SpanStyle, quote: bool) {
+        let spans: Vec<Span> = spans
+            .iter()
+            .map(|span| Span {
+                text: span.text.clone(),
+                style: SpanStyle {
+                    bold: span.style.bold || style.bold,
+                    italic: span.style.italic || style.italic,
+                    code: span.style.code,
+                },
+            })
+            .collect();
+        let line_height = size * LINE_SPACING;
+        for line in self.wrap(&spans, self.right - x, size) {
+            self.ensure_space(line_height);
+            if quote {
+                let top = self.y;
+                let content = self.page();
+                content.set_stroke_gray(0.75);
+                content.set_line_width(2.0);
+                content.move_to(x - 9.0, top);
+                content.line_to(x - 9.0, top - line_height);
+                content.stroke();
+                content.set_stroke_gray(0.0);
+            }
+            self.draw_line(&line, x, size);
+        }
+    }
+
+    fn space(&mut self, height: f32) {
+        if !self.at_page_top() {
+            self.y -= height;
+        }
+    }
+
+    fn title_page(&mut self, document: &Document) {
+        self.new_page();
+        let size = (self.font_size * 2.4).min(40.0);
+        let title = [Span { text: document.title.clone(), style: SpanStyle { bold: true, ..SpanStyle::default() } }];
+        let lines = self.wrap(&title, self.content_width(), size);
+
+        self.y = self.bottom + (self.top - self.bottom) * 0.62;
+        for line in lines {
+            let width: f32 = line.pieces.iter().map(|p| p.width).sum();
+            let x = self.left + (self.content_width() - width) / 2.0;
+            self.draw_line(&line, x, size);
+        }
+
+        let subtitle = document.updated_at.format("%Y-%m-%d").to_string();
+        let width = self.text_width(&subtitle, self.font_size);
+        self.y -= self.font_size;
+        let x = self.left + (self.content_width() - width) / 2.0;
+        let baseline = self.y - self.font_size;
+        self.draw_text(x, baseline, &subtitle, SpanStyle::default(), self.font_size);
+
+        // Body text starts on a fresh, numbered page
+        self.body_start = self.pages.len();
+        self.pages.push(Content::new());
+        self.y = self.top;
+    }
+
+    fn block(&mut self, block: &Block) {
+        let size = self.font_size;
+        let indent_step = size * 2.0;
+        match block {
+            Block::Heading { level, spans } => {
+                let scale = [1.8, 1.5, 1.3, 1.15, 1.05, 1.0][(*level).clamp(1, 6) - 1];
+                let heading_size = size * scale;
+                self.space(heading_size * 0.8);
+                // Keep the heading with at least one following line
+                self.ensure_space(heading_size * LINE_SPACING + size * LINE_SPACING * 2.0);
+                let x = self.left;
+                self.paragraph(spans, x, heading_size, SpanStyle { bold: true, ..SpanStyle::default() }, false);
+                self.y -= heading_size * 0.3;
+            }
+            Block::Paragraph { spans, indent, quote } => {
+                let x = self.left + *indent as f32 * indent_step;
+                self.paragraph(spans, x, size, SpanStyle::default(), *quote);
+                self.y -= size * 0.6;
+            }
+            Block::ListItem { depth, marker, spans } => {
+                let x = self.left + (*depth as f32 + 1.0) * indent_step;
+                self.ensure_space(size * LINE_SPACING);
+                let marker_width = self.text_width(marker, size);
+                let baseline = self.y - (size * LINE_SPACING + size * 0.7) / 2.0;
+                self.draw_text(x - marker_width - size * 0.5, baseline, marker, SpanStyle::default(), size);
+                self.paragraph(spans, x, size, SpanStyle::default(), false);
+                self.y -= size * 0.25;
+            }
+            Block::Code(code) => {
+                let code_size = size * 0.9;
+                let line_height = code_size * LINE_SPACING;
+                let width = self.content_width() - code_size * 2.0;
+                self.space(size * 0.3);
+                for source_line in code.split('\n') {
+                    let spans = [Span { text: source_line.to_string(), style: SpanStyle::default() }];
+                    let mut lines = self.wrap(&spans, width, code_size);
+                    if lines.is_empty() {
+                        lines.push(Line { pieces: Vec::new() });
+                    }
+                    for line in lines {
+                        self.ensure_space(line_height);
+                        let (x, y, box_width) = (self.left, self.y, self.content_width());
+                        let content = self.page();
+                        content.set_fill_gray(0.95);
+                        content.rect(x, y - line_height, box_width, line_height);
+                        content.fill_nonzero();
+                        content.set_fill_gray(0.0);
+                        self.draw_line(&line, x + code_size, code_size);
+                    }
+                }
+                self.y -= size * 0.8;
+            }
+            Block::Table(rows) => self.table(rows),
+            Block::Rule => {
+                self.ensure_space(size);
+                let (left, right, y) = (self.left, self.right, self.y - size / 2.0);
+                let content = self.page();
+                content.set_stroke_gray(0.7);
+                content.set_line_width(0.75);
+                content.move_to(left, y);
+                content.line_to(right, y);
+                content.stroke();
+                content.set_stroke_gray(0.0);
+                self.y -= size;
+            }
+        }
+    }
+
+    fn table(&mut self, rows: &[Vec<Vec<Span>>]) {
+        let size = self.font_size * 0.95;
+        let line_height = size * LINE_SPACING;
+        let padding = size * 0.4;
+        let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        if columns == 0 {
+            return;
+        }
+        let column_width = self.content_width() / columns as f32;
+
+        self.space(size * 0.3);
+        for (index, row) in rows.iter().enumerate() {
+            let style = SpanStyle { bold: index == 0, ..SpanStyle::default() };
+            let cells: Vec<Vec<Line>> = (0..columns)
+                .map(|column| {
+                    let spans: Vec<Span> = row
+                        .get(column)
+                        .map(|cell| {
+                            cell.iter()
+                                .map(|span| Span { text: span.text.clone(), style: SpanStyle { bold: style.bold || span.style.bold, ..span.style } })
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    self.wrap(&spans, column_width - padding * 2.0, size)
+                })
+                .collect();
+            let line_count = cells.iter().map(|lines| lines.len()).max().unwrap_or(1).max(1);
+            let row_height = line_count as f32 * line_height + padding * 2.0;
+
+            self.ensure_space(row_height);
+            let (top, left) = (self.y, self.left);
+            {
+                let content = self.page();
+                content.set_stroke_gray(0.6);
+                content.set_line_width(0.5);
+                for column in 0..columns {
+                    content.rect(left + column as f32 * column_width, top - row_height, column_width, row_height);
+                }
+                content.stroke();
+                content.set_stroke_gray(0.0);
+            }
+            for (column, lines) in cells.iter().enumerate() {
+                self.y = top - padding;
+                for line in lines {
+                    self.draw_line(line, left + column as f32 * column_width + padding, size);
+                }
+            }
+            self.y = top - row_height;
+        }
+        self.y -= self.font_size * 0.8;
+    }
+
+    fn number_pages(&mut self) {
+        if !self.page_numbers {
+            return;
+        }
+        let total = self.pages.len() - self.body_start;
+        let size = self.font_size * 0.8;
+        let baseline = self.bottom / 2.0 - size / 2.0;
+        for index in self.body_start..self.pages.len() {
+            let label = format!("{} / {}", index - self.body_start + 1, total);
+            let x = (self.page_width - self.text_width(&label, size)) / 2.0;
+            let encoded = self.encode(&label);
+            let content = &mut self.pages[index];
+            content.begin_text();
+            content.set_font(FONT_NAME, size);
+            content.set_fill_gray(0.4);
+            content.set_text_matrix([1.0, 0.0, 0.0, 1.0, x, baseline]);
+            content.show(Str(&encoded));
+            content.set_fill_gray(0.0);
+            content.end_text();
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PDF objects
+// ---------------------------------------------------------------------------
+
+fn write_pdf(document: &Document, mut composer: Composer, font_data: &[u8], font_index: u32, face: &Face) -> Result<Vec<u8>, String> {
+    if composer.pages.is_empty() {
+        composer.new_page();
+    }
+    composer.number_pages();
+
+    let mut next_id = 1;
+    let mut alloc = || {
+        let id = Ref::new(next_id);
+        next_id += 1;
+        id
+    };
+    let catalog_id = alloc();
+    let pages_id = alloc();
+    let info_id = alloc();
+    let type0_id = alloc();
+    let cid_id = alloc();
+    let descriptor_id = alloc();
+    let font_file_id = alloc();
+    let cmap_id = alloc();
+    let page_ids: Vec<(Ref, Ref)> = composer.pages.iter().map(|_| (alloc(), alloc())).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.set_version(1, 7);
+    pdf.catalog(catalog_id).pages(pages_id);
+    pdf.pages(pages_id)
+        .kids(page_ids.iter().map(|(page_id, _)| *page_id))
+        .count(page_ids.len() as i32);
+
+    let created = document.created_at;
+    let mut info = pdf.document_info(info_id);
+    info.title(TextStr(&document.title));
+    info.creation_date(
+        Date::new(chrono::Datelike::year(&created) as u16)
+            .month(chrono::Datelike::month(&created) as u8)
+            .day(chrono::Datelike::day(&created) as u8),
+    );
+    info.finish();
+
+    let media_box = Rect::new(0.0, 0.0, composer.page_width, composer.page_height);
+    let pages = std::mem::take(&mut composer.pages);
+    for ((page_id, content_id), content) in page_ids.iter().zip(pages) {
+        let mut page = pdf.page(*page_id);
+        page.media_box(media_box).parent(pages_id).contents(*content_id);
+        page.resources().fonts().pair(FONT_NAME, type0_id);
+        page.finish();
+
+        let compressed = compress_to_vec_zlib(&content.finish(), 6);
+        pdf.stream(*content_id, &compressed).filter(Filter::FlateDecode);
+    }
+
+    // Font: a subset of the face embedded as a CID-keyed font with glyph ids
+    // as character codes.
+    let glyphs: Vec<u16> = composer.used_glyphs.keys().copied().collect();
+    let subset = subsetter::subset(font_data, font_index, subsetter::Profile::pdf(&glyphs))
+        .map_err(|e| format!("Failed to subset font: {}", e))?;
+    let is_cff = face.tables().cff.is_some();
+
+    let postscript_name = face
+        .names()
+        .into_iter()
+        .find(|name| name.name_id == name_id::POST_SCRIPT_NAME)
+        .and_then(|name| name.to_string())
+        .unwrap_or_else(|| "Font".to_string());
+    let base_font = format!("SWASUB+{}", postscript_name.replace(' ', ""));
+    let system_info = SystemInfo {
+        registry: Str(b"Adobe"),
+        ordering: Str(b"Identity"),
+        supplement: 0,
+    };
+
+    let scale = 1000.0 / composer.units_per_em;
+    pdf.type0_font(type0_id)
+        .base_font(Name(base_font.as_bytes()))
+        .encoding_predefined(Name(b"Identity-H"))
+        .descendant_font(cid_id)
+        .to_unicode(cmap_id);
+
+    let mut cid_font = pdf.cid_font(cid_id);
+    cid_font
+        .subtype(if is_cff { CidFontType::Type0 } else { CidFontType::Type2 })
+        .base_font(Name(base_font.as_bytes()))
+        .system_info(system_info)
+        .font_descriptor(descriptor_id)
+        .default_width(0.0);
+    if !is_cff {
+        cid_font.cid_to_gid_map_predefined(Name(b"Identity"));
+    }
+    let mut widths = cid_font.widths();
+    for glyph in &glyphs {
+        let advance = face.glyph_hor_advance(GlyphId(*glyph)).unwrap_or(0) as f32 * scale;
+        widths.consecutive(*glyph, [advance]);
+    }
+    widths.finish();
+    cid_font.finish();
+
+    let bbox = face.global_bounding_box();
+    let mut descriptor = pdf.font_descriptor(descriptor_id);
+    descriptor
+        .name(Name(base_font.as_bytes()))
+        .flags(FontFlags::SYMBOLIC)
+        .bbox(Rect::new(
+            bbox.x_min as f32 * scale,
+            bbox.y_min as f32 * scale,
+            bbox.x_max as f32 * scale,
+            bbox.y_max as f32 * scale,
+        ))
+        .italic_angle(0.0)
+        .ascent(face.ascender() as f32 * scale)
+        .descent(face.descender() as f32 * scale)
+        .cap_height(face.capital_height().unwrap_or(face.ascender()) as f32 * scale)
+        .stem_v(80.0);
+    if is_cff {
+        descriptor.font_file3(font_file_id);
+    } else {
+        descriptor.font_file2(font_file_id);
+    }
+    descriptor.finish();
+
+    let compressed_font = compress_to_vec_zlib(&subset, 6);
+    let mut font_stream = pdf.stream(font_file_id, &compressed_font);
+    font_stream.filter(Filter::FlateDecode);
+    if is_cff {
+        font_stream.pair(Name(b"Subtype"), Name(b"OpenType"));
+    } else {
+        font_stream.pair(Name(b"Length1"), subset.len() as i32);
+    }
+    font_stream.finish();
+
+    let mut cmap = pdf_writer::types::UnicodeCmap::new(Name(b"Custom"), system_info);
+    // Glyph 0 is .notdef, used for characters the font lacks
+    for (glyph, c) in composer.used_glyphs.iter().filter(|(glyph, _)| **glyph != 0) {
+        cmap.pair(*glyph, *c);
+    }
+    pdf.cmap(cmap_id, &cmap.finish()).system_info(system_info);
+
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::UNSORTED_COLLECTION_ID;
+    use chrono::Utc;
+
+    // Export needs a system font; machines without any skip these tests
+    fn font() -> Option<String> {
+        let font = FONT_CANDIDATES.iter().find(|candidate| Path::new(candidate).exists());
+        if font.is_none() {
+            eprintln!("No font found, skipping PDF export test");
+        }
+        font.map(|font| font.to_string())
+    }
+
+    fn document(title: &str, content: &str) -> Document {
+        Document {
+            id: "doc".to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 0,
+            char_count: 0,
+            cjk_word_count: 0,
+            latin_word_count: 0,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    // A 100 × 100 mm page holding 15 code lines at 10 pt with 10 mm margins
+    fn small_page(font: String, margin_mm: f32) -> PdfExportOptions {
+        PdfExportOptions {
+            page_size: PageSize::Custom { width_mm: 100.0, height_mm: 100.0 },
+            margins: PageMargins { top_mm: margin_mm, right_mm: margin_mm, bottom_mm: margin_mm, left_mm: margin_mm },
+            font_size: 10.0,
+            font_path: Some(font),
+            title_page: false,
+            page_numbers: true,
+        }
+    }
+
+    fn code_block(lines: usize) -> String {
+        let body: Vec<String> = (1..=lines).map(|line| format!("line {}", line)).collect();
+        format!("```\n{}\n```", body.join("\n"))
+    }
+
+    fn page_count(pdf: &[u8]) -> usize {
+        let text = String::from_utf8_lossy(pdf);
+        let start = text.find("/Count ").expect("page tree") + "/Count ".len();
+        let digits: String = text[start..].chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().unwrap()
+    }
+
+    fn contains(pdf: &[u8], needle: &str) -> bool {
+        pdf.windows(needle.len()).any(|window| window == needle.as_bytes())
+    }
+
+    #[test]
+    fn code_blocks_break_across_pages() {
+        let font = match font() {
+            Some(font) => font,
+            None => return,
+        };
+        let export = |lines, margin_mm| {
+            export_pdf(&document("Code", &code_block(lines)), &small_page(font.clone(), margin_mm)).unwrap()
+        };
+
+        let pdf = export(15, 10.0);
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert_eq!(page_count(&pdf), 1);
+        assert_eq!(page_count(&export(16, 10.0)), 2);
+        assert_eq!(page_count(&export(30, 10.0)), 2);
+        assert_eq!(page_count(&export(31, 10.0)), 3);
+        // 20 mm margins leave room for 11 lines
+        assert_eq!(page_count(&export(30, 20.0)), 3);
+
+        let mut options = small_page(font.clone(), 10.0);
+        options.page_size = PageSize::Custom { width_mm: 100.0, height_mm: 200.0 };
+        assert_eq!(page_count(&export_pdf(&document("Code", &code_block(31)), &options).unwrap()), 1);
+    }
+
+    #[test]
+    fn long_tables_and_lines_do_not_overflow() {
+        let font = match font() {
+            Some(font) => font,
+            None => return,
+        };
+        let mut table = String::from("| Term | Definition |\n|---|---|\n");
+        for row in 0..60 {
+            table.push_str(&format!("| term {} | a definition long enough to wrap inside its narrow column |\n", row));
+        }
+        let pdf = export_pdf(&document("Table", &table), &small_page(font.clone(), 10.0)).unwrap();
+        assert!(page_count(&pdf) > 5);
+
+        // A single unbreakable word wider than the page is split by character
+        let long_line = format!("```\n{}\n```\n\n{}", "x".repeat(500), "y".repeat(500));
+        let pdf = export_pdf(&document("Long", &long_line), &small_page(font, 10.0)).unwrap();
+        assert!(page_count(&pdf) >= 1);
+    }
+
+    #[test]
+    fn empty_and_title_only_documents_export() {
+        let font = match font() {
+            Some(font) => font,
+            None => return,
+        };
+        let options = small_page(font, 10.0);
+        assert_eq!(page_count(&export_pdf(&document("", ""), &options).unwrap()), 1);
+
+        let options = PdfExportOptions { title_page: true, ..options };
+        let pdf = export_pdf(&document("Only a title", ""), &options).unwrap();
+        assert_eq!(page_count(&pdf), 2);
+        assert!(contains(&pdf, "(Only a title)"));
+    }
+
+    #[test]
+    fn embeds_a_subset_of_the_font() {
+        let font = match font() {
+            Some(font) => font,
+            None => return,
+        };
+        let content = "# 语义分析\n\n中文段落，包含标点。Some Latin text too.";
+        let options = PdfExportOptions { font_path: Some(font.clone()), ..PdfExportOptions::default() };
+        let pdf = export_pdf(&document("中文文档", content), &options).unwrap();
+
+        assert!(contains(&pdf, "/BaseFont /SWASUB+"));
+        assert!(contains(&pdf, "/FontFile2") || contains(&pdf, "/FontFile3"));
+        let font_size = std::fs::metadata(&font).unwrap().len() as usize;
+        assert!(pdf.len() < font_size / 2, "{} bytes for a {} byte font", pdf.len(), font_size);
+
+        // CJK characters are mapped back to text when the font covers them
+        let data = std::fs::read(&font).unwrap();
+        let face = Face::parse(&data, 0).unwrap();
+        if face.glyph_index('语').is_some() {
+            assert!(contains(&pdf, "<8BED>"));
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_options() {
+        let bad_size = PdfExportOptions { font_size: 2.0, ..PdfExportOptions::default() };
+        assert!(export_pdf(&document("Title", "text"), &bad_size).is_err());
+
+        let margins = PageMargins { top_mm: 85.0, right_mm: 20.0, bottom_mm: 85.0, left_mm: 20.0 };
+        let no_room = PdfExportOptions { page_size: PageSize::A5, margins, ..PdfExportOptions::default() };
+        assert!(export_pdf(&document("Title", "text"), &no_room).unwrap_err().contains("too little room"));
+
+        let missing = PdfExportOptions { font_path: Some("/no/such/font.ttf".to_string()), ..PdfExportOptions::default() };
+        assert!(export_pdf(&document("Title", "text"), &missing).unwrap_err().starts_with("Failed to read font"));
+    }
+}
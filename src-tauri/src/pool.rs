@@ -0,0 +1,183 @@
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::database::Database;
+use crate::error::{AppError, Result, ResultExt};
+
+const READER_CONNECTIONS: usize = 4;
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// One writer connection plus a few read-only ones over the same WAL
+// database. Readers never wait for the writer and see the last committed
+// state; writes are serialized on the writer connection.
+#[derive(Clone)]
+pub struct DbPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    writer: Mutex<Database>,
+    readers: Mutex<Vec<Database>>,
+    reader_returned: Condvar,
+}
+
+impl DbPool {
+    pub fn open(db_path: &Path) -> Result<Self> {
+        // The writer runs migrations, so it must exist before any reader
+        let writer = Database::new(db_path)
+            .context("Failed to initialize database")?;
+        let readers = (0..READER_CONNECTIONS)
+            .map(|_| Database::open_reader(db_path))
+            .collect::<Result<Vec<_>>>()
+            .context("Failed to open database reader")?;
+
+        Ok(DbPool {
+            inner: Arc::new(PoolInner {
+                writer: Mutex::new(writer),
+                readers: Mutex::new(readers),
+                reader_returned: Condvar::new(),
+            }),
+        })
+    }
+
+    pub fn writer(&self) -> Result<MutexGuard<'_, Database>> {
+        self.inner.writer.lock().map_err(|_| AppError::lock("database"))
+    }
+
+    // Waits for a free reader when all of them are busy
+    pub fn reader(&self) -> Result<PooledReader<'_>> {
+        let mut idle = self.inner.readers.lock().map_err(|_| AppError::lock("database connection"))?;
+        loop {
+            if let Some(connection) = idle.pop() {
+                return Ok(PooledReader {
+                    pool: &self.inner,
+                    connection: Some(connection),
+                });
+            }
+            let (guard, wait) = self.inner.reader_returned
+                .wait_timeout(idle, ACQUIRE_TIMEOUT)
+                .map_err(|_| AppError::lock("database connection"))?;
+            idle = guard;
+            if wait.timed_out() && idle.is_empty() {
+                return Err(AppError::Busy("Timed out waiting for a database connection".to_string()));
+            }
+        }
+    }
+}
+
+// A read-only connection borrowed from the pool; returned when dropped
+pub struct PooledReader<'a> {
+    pool: &'a PoolInner,
+    connection: Option<Database>,
+}
+
+impl Deref for PooledReader<'_> {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.connection.as_ref().expect("reader used after release")
+    }
+}
+
+impl Drop for PooledReader<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            if let Ok(mut idle) = self.pool.readers.lock() {
+                idle.push(connection);
+                self.pool.reader_returned.notify_one();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Document, UNSORTED_COLLECTION_ID};
+    use chrono::Utc;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    struct Fixture {
+        dir: PathBuf,
+        pool: DbPool,
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn fixture(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("pool-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let pool = DbPool::open(&dir.join("library.db")).unwrap();
+        Fixture { dir, pool }
+    }
+
+    fn document(id: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            title: id.to_string(),
+            content: format!("Content of {}", id),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 3,
+            char_count: 10,
+            cjk_word_count: 0,
+            latin_word_count: 3,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn readers_cannot_write() {
+        let fixture = fixture("read-only");
+
+        let error = fixture.pool.reader().unwrap().save_document(&document("a")).unwrap_err();
+        assert_eq!(error.code(), "PERMISSION_DENIED");
+        assert!(fixture.pool.reader().unwrap().get_document("a").unwrap().is_none());
+    }
+
+    #[test]
+    fn writes_go_through_the_write_ahead_log() {
+        let fixture = fixture("wal");
+
+        fixture.pool.writer().unwrap().save_document(&document("a")).unwrap();
+        assert!(fixture.dir.join("library.db-wal").exists());
+        // Committed writes are visible to readers straight away
+        assert!(fixture.pool.reader().unwrap().get_document("a").unwrap().is_some());
+    }
+
+    #[test]
+    fn reads_run_while_the_writer_is_held() {
+        let fixture = fixture("concurrent");
+        fixture.pool.writer().unwrap().save_document(&document("a")).unwrap();
+
+        let writer = fixture.pool.writer().unwrap();
+        let pool = fixture.pool.clone();
+        let found = thread::spawn(move || pool.reader().unwrap().get_document("a").unwrap().is_some())
+            .join()
+            .unwrap();
+        assert!(found);
+        drop(writer);
+    }
+
+    #[test]
+    fn returned_readers_are_reused() {
+        let fixture = fixture("reuse");
+
+        for _ in 0..READER_CONNECTIONS * 3 {
+            let readers: Vec<_> = (0..READER_CONNECTIONS).map(|_| fixture.pool.reader().unwrap()).collect();
+            assert!(readers.iter().all(|reader| reader.get_document("missing").unwrap().is_none()));
+        }
+    }
+}
@@ -0,0 +1,658 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::segment::Segmenter;
+
+// Number of characters kept on each side of the first match in a snippet.
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+// The trigram tokenizer cannot match terms shorter than three characters,
+// which is common for Chinese words. Short CJK terms are looked up in the
+// `document_grams` index instead; anything else that short falls back to a
+// scan.
+const TRIGRAM_MIN_CHARS: usize = 3;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MatchOffset {
+    pub start: usize, // byte offset
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub document_id: String,
+    pub title: String,
+    pub snippet: String, // HTML-escaped, matches wrapped in <mark>
+    pub score: f64,
+    pub title_matches: Vec<MatchOffset>,
+    pub content_matches: Vec<MatchOffset>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryNode {
+    Term(String),
+    Prefix(String),
+    Phrase(String),
+    And(Vec<QueryNode>),
+    Or(Vec<QueryNode>),
+    Not(Box<QueryNode>),
+}
+
+// Narrows a query down to candidate documents through the indexes; every
+// candidate is still verified with `SearchQuery::build_hit`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexFilter {
+    Fts(String),  // FTS5 MATCH expression for the trigram index
+    Gram(String), // one or two CJK characters in `document_grams`
+    And(Vec<IndexFilter>),
+    Or(Vec<IndexFilter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Prefix(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+// A parsed search query. Supported syntax:
+//   foo bar        both terms (implicit AND)
+//   foo OR bar     either term
+//   NOT foo, -foo  exclude documents containing the term
+//   "foo bar"      exact phrase
+//   foo*           term at the start of a word
+//   ( ... )        grouping
+// Matching is case-insensitive and substring-based, which suits CJK text
+// where words are not separated by whitespace. A term written in CJK
+// characters only is segmented, so 术语提取 matches 术语 and 提取 anywhere
+// in the document; quote it to require the exact sequence.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    root: QueryNode,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str, segmenter: &Segmenter) -> Result<Self, String> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err("Search query is empty".to_string());
+        }
+
+        let mut parser = Parser { tokens, pos: 0, segmenter };
+        let root = parser.parse_or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err("Unexpected ')' in search query".to_string());
+        }
+        if !has_positive_term(&root) {
+            return Err("Search query must contain at least one term that is not negated".to_string());
+        }
+
+        Ok(SearchQuery { root })
+    }
+
+    // Renders the query as an FTS5 MATCH expression for the trigram index, or
+    // None if some term is too short for the index to answer.
+    pub fn to_fts5(&self) -> Option<String> {
+        node_to_fts5(&self.root)
+    }
+
+    // Like `to_fts5`, but also answers short CJK terms from the gram index.
+    // None only if some term is neither long enough nor CJK.
+    pub fn to_index_filter(&self) -> Option<IndexFilter> {
+        node_to_filter(&self.root)
+    }
+
+    pub fn matches(&self, title: &str, content: &str) -> bool {
+        eval(&self.root, title, content)
+    }
+
+    pub fn build_hit(&self, document_id: String, title: String, content: &str, score: Option<f64>) -> Option<SearchHit> {
+        if !self.matches(&title, content) {
+            return None;
+        }
+
+        let mut needles = Vec::new();
+        collect_positive(&self.root, false, &mut needles);
+
+        let title_matches = find_all_needles(&title, &needles);
+        let content_matches = find_all_needles(content, &needles);
+
+        // Without an FTS rank, weight title hits like the bm25 column weights do
+        let score = score.unwrap_or((title_matches.len() * 10 + content_matches.len()) as f64);
+        let snippet = build_snippet(content, &content_matches);
+
+        Some(SearchHit {
+            document_id,
+            title,
+            snippet,
+            score,
+            title_matches,
+            content_matches,
+        })
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    segmenter: &'a Segmenter,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<QueryNode, String> {
+        let mut nodes = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            nodes.push(self.parse_and()?);
+        }
+        Ok(if nodes.len() == 1 { nodes.remove(0) } else { QueryNode::Or(nodes) })
+    }
+
+    fn parse_and(&mut self) -> Result<QueryNode, String> {
+        let mut nodes = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    nodes.push(self.parse_unary()?);
+                }
+                Some(Token::Or) | Some(Token::RParen) | None => break,
+                Some(_) => nodes.push(self.parse_unary()?),
+            }
+        }
+        Ok(if nodes.len() == 1 { nodes.remove(0) } else { QueryNode::And(nodes) })
+    }
+
+    fn parse_unary(&mut self) -> Result<QueryNode, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(QueryNode::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<QueryNode, String> {
+        let token = self.tokens.get(self.pos).cloned()
+            .ok_or("Search query ends unexpectedly")?;
+        self.pos += 1;
+
+        match token {
+            Token::Word(word) if word.chars().all(is_cjk) => {
+                let mut terms: Vec<QueryNode> = self
+                    .segmenter
+                    .cut(&word)
+                    .into_iter()
+                    .map(|(start, end)| QueryNode::Term(word[start..end].to_string()))
+                    .collect();
+                Ok(if terms.len() == 1 { terms.remove(0) } else { QueryNode::And(terms) })
+            }
+            Token::Word(word) => Ok(QueryNode::Term(word)),
+            Token::Prefix(word) => Ok(QueryNode::Prefix(word)),
+            Token::Phrase(phrase) => Ok(QueryNode::Phrase(phrase)),
+            Token::LParen => {
+                let node = self.parse_or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err("Missing ')' in search query".to_string());
+                }
+                self.pos += 1;
+                Ok(node)
+            }
+            Token::RParen => Err("Unexpected ')' in search query".to_string()),
+            Token::And | Token::Or | Token::Not => {
+                Err("Operator is missing a search term".to_string())
+            }
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::LParen);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::RParen);
+        } else if c == '-' {
+            chars.next();
+            tokens.push(Token::Not);
+        } else if c == '"' || c == '“' {
+            chars.next();
+            let mut phrase = String::new();
+            let mut closed = false;
+            for c in chars.by_ref() {
+                if c == '"' || c == '”' {
+                    closed = true;
+                    break;
+                }
+                phrase.push(c);
+            }
+            if !closed {
+                return Err("Unterminated phrase in search query".to_string());
+            }
+            let phrase = phrase.trim().to_string();
+            if !phrase.is_empty() {
+                tokens.push(Token::Phrase(phrase));
+            }
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == '“' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(match word.as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => match word.strip_suffix('*') {
+                    Some(prefix) if !prefix.is_empty() => Token::Prefix(prefix.to_string()),
+                    Some(_) => return Err("Prefix search needs at least one character before '*'".to_string()),
+                    None => Token::Word(word),
+                },
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn has_positive_term(node: &QueryNode) -> bool {
+    match node {
+        QueryNode::Term(_) | QueryNode::Prefix(_) | QueryNode::Phrase(_) => true,
+        QueryNode::And(nodes) => nodes.iter().any(has_positive_term),
+        QueryNode::Or(nodes) => nodes.iter().all(has_positive_term),
+        QueryNode::Not(_) => false,
+    }
+}
+
+fn node_to_fts5(node: &QueryNode) -> Option<String> {
+    match node {
+        // Trigram matching is substring based, so prefix terms are narrowed
+        // down to word starts when the hit is verified in Rust.
+        QueryNode::Term(text) | QueryNode::Prefix(text) | QueryNode::Phrase(text) => {
+            if text.chars().count() < TRIGRAM_MIN_CHARS {
+                None
+            } else {
+                Some(format!("\"{}\"", text.replace('"', "\"\"")))
+            }
+        }
+        QueryNode::And(nodes) => {
+            // FTS5 only accepts NOT as a binary operator, so negated clauses
+            // are appended after the positive ones. Clauses the index cannot
+            // answer are left out; that only widens the candidate set, and
+            // every hit is verified in Rust.
+            let mut positive = Vec::new();
+            let mut negative = Vec::new();
+            for node in nodes {
+                match node {
+                    QueryNode::Not(inner) => negative.extend(node_to_fts5(inner)),
+                    _ => positive.extend(node_to_fts5(node)),
+                }
+            }
+            if positive.is_empty() {
+                return None;
+            }
+            let mut expr = format!("({})", positive.join(" AND "));
+            for clause in negative {
+                expr = format!("({} NOT {})", expr, clause);
+            }
+            Some(expr)
+        }
+        QueryNode::Or(nodes) => {
+            let clauses = nodes.iter().map(node_to_fts5).collect::<Option<Vec<_>>>()?;
+            Some(format!("({})", clauses.join(" OR ")))
+        }
+        QueryNode::Not(_) => None,
+    }
+}
+
+fn node_to_filter(node: &QueryNode) -> Option<IndexFilter> {
+    match node {
+        QueryNode::Term(text) | QueryNode::Prefix(text) | QueryNode::Phrase(text) => {
+            let chars = text.chars().count();
+            if chars >= TRIGRAM_MIN_CHARS {
+                node_to_fts5(node).map(IndexFilter::Fts)
+            } else if chars > 0 && text.chars().all(is_cjk) {
+                Some(IndexFilter::Gram(text.clone()))
+            } else {
+                None
+            }
+        }
+        QueryNode::And(nodes) => {
+            // As in `node_to_fts5`, clauses the indexes cannot answer only
+            // widen the candidate set
+            let mut filters: Vec<IndexFilter> = nodes
+                .iter()
+                .filter(|node| !matches!(node, QueryNode::Not(_)))
+                .filter_map(node_to_filter)
+                .collect();
+            match filters.len() {
+                0 => None,
+                1 => filters.pop(),
+                _ => Some(IndexFilter::And(filters)),
+            }
+        }
+        QueryNode::Or(nodes) => {
+            let filters = nodes.iter().map(node_to_filter).collect::<Option<Vec<_>>>()?;
+            Some(IndexFilter::Or(filters))
+        }
+        QueryNode::Not(_) => None,
+    }
+}
+
+// Every single CJK character in `text` and every pair of adjacent ones:
+// what the gram index stores so that terms of one or two characters, too
+// short for the trigram index, can still be looked up.
+pub fn cjk_grams(text: &str) -> BTreeSet<String> {
+    let mut grams = BTreeSet::new();
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        if is_cjk(c) {
+            grams.insert(c.to_string());
+            if let Some(p) = prev {
+                grams.insert([p, c].iter().collect());
+            }
+            prev = Some(c);
+        } else {
+            prev = None;
+        }
+    }
+    grams
+}
+
+fn eval(node: &QueryNode, title: &str, content: &str) -> bool {
+    match node {
+        QueryNode::Term(text) | QueryNode::Phrase(text) => {
+            !find_all(title, text, false).is_empty() || !find_all(content, text, false).is_empty()
+        }
+        QueryNode::Prefix(text) => {
+            !find_all(title, text, true).is_empty() || !find_all(content, text, true).is_empty()
+        }
+        QueryNode::And(nodes) => nodes.iter().all(|n| eval(n, title, content)),
+        QueryNode::Or(nodes) => nodes.iter().any(|n| eval(n, title, content)),
+        QueryNode::Not(inner) => !eval(inner, title, content),
+    }
+}
+
+// Collects the terms that should be highlighted, skipping negated ones.
+fn collect_positive<'a>(node: &'a QueryNode, negated: bool, out: &mut Vec<(&'a str, bool)>) {
+    match node {
+        QueryNode::Term(text) | QueryNode::Phrase(text) if !negated => out.push((text, false)),
+        QueryNode::Prefix(text) if !negated => out.push((text, true)),
+        QueryNode::And(nodes) | QueryNode::Or(nodes) => {
+            for n in nodes {
+                collect_positive(n, negated, out);
+            }
+        }
+        QueryNode::Not(inner) => collect_positive(inner, !negated, out),
+        _ => {}
+    }
+}
+
+fn find_all_needles(haystack: &str, needles: &[(&str, bool)]) -> Vec<MatchOffset> {
+    let mut ranges: Vec<(usize, usize)> = needles
+        .iter()
+        .flat_map(|(needle, word_start)| find_all(haystack, needle, *word_start))
+        .collect();
+    ranges.sort();
+
+    // Merge overlapping ranges so highlights never nest
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(start, end)| MatchOffset {
+            start,
+            end,
+            char_start: haystack[..start].chars().count(),
+            char_end: haystack[..end].chars().count(),
+        })
+        .collect()
+}
+
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // Hiragana, Katakana
+        | 0x3400..=0x4DBF   // CJK Extension A
+        | 0x4E00..=0x9FFF   // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF   // Hangul Syllables
+        | 0xF900..=0xFAFF   // CJK Compatibility Ideographs
+        | 0x20000..=0x2FA1F // CJK Extensions B-F
+    )
+}
+
+fn chars_eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+// Returns the byte ranges of every case-insensitive occurrence of `needle`.
+// With `word_start`, occurrences must not continue a Latin word.
+pub fn find_all(haystack: &str, needle: &str, word_start: bool) -> Vec<(usize, usize)> {
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut skip_until = 0;
+
+    for (start, c) in haystack.char_indices() {
+        if start >= skip_until && chars_eq_ignore_case(c, needle[0]) {
+            let mut end = start;
+            let mut matched = 0;
+            for h in haystack[start..].chars() {
+                if matched == needle.len() || !chars_eq_ignore_case(h, needle[matched]) {
+                    break;
+                }
+                matched += 1;
+                end += h.len_utf8();
+            }
+
+            let at_word_start = match prev {
+                Some(p) => !p.is_alphanumeric() || is_cjk(p) || is_cjk(c),
+                None => true,
+            };
+
+            if matched == needle.len() && (!word_start || at_word_start) {
+                matches.push((start, end));
+                skip_until = end;
+            }
+        }
+        prev = Some(c);
+    }
+
+    matches
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn build_snippet(content: &str, matches: &[MatchOffset]) -> String {
+    let total_chars = content.chars().count();
+    let (window_start, window_end) = match matches.first() {
+        Some(first) => (
+            first.char_start.saturating_sub(SNIPPET_CONTEXT_CHARS),
+            (first.char_end + SNIPPET_CONTEXT_CHARS).min(total_chars),
+        ),
+        None => (0, (SNIPPET_CONTEXT_CHARS * 2).min(total_chars)),
+    };
+
+    let byte_at = |char_idx: usize| {
+        content.char_indices().nth(char_idx).map(|(i, _)| i).unwrap_or(content.len())
+    };
+    let start = byte_at(window_start);
+    let end = byte_at(window_end);
+
+    let mut snippet = String::new();
+    if window_start > 0 {
+        snippet.push('…');
+    }
+
+    let mut cursor = start;
+    for m in matches.iter().filter(|m| m.start >= start && m.end <= end) {
+        snippet.push_str(&escape_html(&content[cursor..m.start]));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(&content[m.start..m.end]));
+        snippet.push_str("</mark>");
+        cursor = m.end;
+    }
+    snippet.push_str(&escape_html(&content[cursor..end]));
+
+    if window_end < total_chars {
+        snippet.push('…');
+    }
+    snippet.replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<SearchQuery, String> {
+        SearchQuery::parse(input, &Segmenter::new())
+    }
+
+    fn term(text: &str) -> QueryNode {
+        QueryNode::Term(text.to_string())
+    }
+
+    #[test]
+    fn parses_operators_and_grouping() {
+        assert_eq!(parse("foo bar").unwrap().root, QueryNode::And(vec![term("foo"), term("bar")]));
+        assert_eq!(
+            parse("foo OR bar AND -baz").unwrap().root,
+            QueryNode::Or(vec![
+                term("foo"),
+                QueryNode::And(vec![term("bar"), QueryNode::Not(Box::new(term("baz")))]),
+            ])
+        );
+        assert_eq!(
+            parse("(a OR b) “two words” pre*").unwrap().root,
+            QueryNode::And(vec![
+                QueryNode::Or(vec![term("a"), term("b")]),
+                QueryNode::Phrase("two words".to_string()),
+                QueryNode::Prefix("pre".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn segments_cjk_terms_but_not_phrases() {
+        assert_eq!(parse("我们喜欢").unwrap().root, QueryNode::And(vec![term("我们"), term("喜欢")]));
+        assert_eq!(parse("\"我们喜欢\"").unwrap().root, QueryNode::Phrase("我们喜欢".to_string()));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for input in ["", "   ", "(foo", "foo)", "\"open", "*", "foo AND", "-foo", "NOT a NOT b", "a OR -b"] {
+            assert!(parse(input).is_err(), "{:?} should not parse", input);
+        }
+    }
+
+    #[test]
+    fn renders_fts5_expressions() {
+        assert_eq!(parse("hello world").unwrap().to_fts5().as_deref(), Some("(\"hello\" AND \"world\")"));
+        assert_eq!(node_to_fts5(&term("say \"hi\"")).as_deref(), Some("\"say \"\"hi\"\"\""));
+        assert_eq!(
+            parse("hello -world").unwrap().to_fts5().as_deref(),
+            Some("((\"hello\") NOT \"world\")")
+        );
+        assert_eq!(parse("one OR three").unwrap().to_fts5().as_deref(), Some("(\"one\" OR \"three\")"));
+        // Short terms cannot use the trigram index
+        assert_eq!(parse("写作").unwrap().to_fts5(), None);
+        assert_eq!(parse("hello ab").unwrap().to_fts5().as_deref(), Some("(\"hello\")"));
+        assert_eq!(parse("hello OR ab").unwrap().to_fts5(), None);
+    }
+
+    #[test]
+    fn answers_short_cjk_terms_from_the_gram_index() {
+        let gram = |text: &str| IndexFilter::Gram(text.to_string());
+        assert_eq!(parse("写作").unwrap().to_index_filter(), Some(gram("写作")));
+        assert_eq!(
+            parse("写作 OR semantic").unwrap().to_index_filter(),
+            Some(IndexFilter::Or(vec![gram("写作"), IndexFilter::Fts("\"semantic\"".to_string())]))
+        );
+        assert_eq!(
+            parse("写 ab -作").unwrap().to_index_filter(),
+            Some(gram("写"))
+        );
+        // Short Latin terms match inside words, which no index here covers
+        assert_eq!(parse("ab").unwrap().to_index_filter(), None);
+        assert_eq!(parse("写作 OR ab").unwrap().to_index_filter(), None);
+    }
+
+    #[test]
+    fn collects_cjk_unigrams_and_bigrams() {
+        let grams: Vec<String> = cjk_grams("写作 ab 中文").into_iter().collect();
+        assert_eq!(grams, vec!["中", "中文", "作", "写", "写作", "文"]);
+    }
+
+    #[test]
+    fn matches_case_insensitively_and_prefixes_at_word_starts() {
+        let query = parse("SEMANTIC*").unwrap();
+        assert!(query.matches("", "Semantics of text"));
+        assert!(!query.matches("", "asemantic"));
+        assert!(query.matches("", "中文semantic")); // CJK ends a word
+
+        let query = parse("draft -final").unwrap();
+        assert!(query.matches("Draft", "notes"));
+        assert!(!query.matches("Draft", "the FINAL version"));
+    }
+
+    #[test]
+    fn builds_hits_with_offsets_and_snippets() {
+        let query = parse("语义 OR 语义分析").unwrap();
+        let content = "前言 <语义分析> 后记";
+        let hit = query.build_hit("doc".to_string(), "语义".to_string(), content, None).unwrap();
+        // Overlapping matches are merged into one highlight
+        assert_eq!(hit.content_matches.len(), 1);
+        let offset = &hit.content_matches[0];
+        assert_eq!(&content[offset.start..offset.end], "语义分析");
+        assert_eq!((offset.char_start, offset.char_end), (4, 8));
+        assert_eq!(hit.snippet, "前言 &lt;<mark>语义分析</mark>&gt; 后记");
+        assert_eq!(hit.score, 11.0);
+
+        assert!(query.build_hit("doc".to_string(), "x".to_string(), "nothing", None).is_none());
+    }
+
+    #[test]
+    fn snippets_are_cut_around_the_first_match() {
+        let content = format!("{}needle{}", "a ".repeat(50), "b\n".repeat(50));
+        let hit = parse("needle").unwrap().build_hit("doc".to_string(), String::new(), &content, Some(1.0)).unwrap();
+        assert!(hit.snippet.starts_with('…') && hit.snippet.ends_with('…'));
+        assert!(hit.snippet.contains("<mark>needle</mark>"));
+        assert!(!hit.snippet.contains('\n'));
+        assert_eq!(hit.score, 1.0);
+    }
+}
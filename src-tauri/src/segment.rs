@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::search::is_cjk;
+
+// Dictionary-based word segmentation for Chinese, in the style of jieba:
+// every dictionary word that starts at a character is an edge of a DAG over
+// the run of CJK text, and the segmentation is the path whose words have
+// the highest joint probability. Characters no word covers stand alone.
+//
+// The built-in dictionary only holds about 1,300 common words, enough for
+// everyday prose but not for names or technical vocabulary. A full jieba
+// dict.txt, or any file in its format, placed in the app data directory as
+// segment_dict.txt is loaded on top of it at startup.
+
+const BUILTIN_DICTIONARY: &str = include_str!("segment_dict.txt");
+// User words (terms from the consistency rules) are weighted like the most
+// common dictionary words, so they win over any split into shorter words
+const USER_WORD_FREQUENCY: u64 = 100_000;
+// Longer user words are ignored; they would make every lookup slower
+const MAX_WORD_CHARS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Word,
+    Cjk, // a dictionary word or a single Han, kana or Hangul character
+}
+
+#[derive(Debug, Clone)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub kind: TokenKind,
+    pub start: usize, // byte offset
+    pub end: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+// Characters exclude whitespace; Chinese text is counted in words like
+// whitespace-separated scripts are
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextCounts {
+    pub characters: i32,
+    pub cjk_words: i32,
+    pub latin_words: i32,
+}
+
+impl TextCounts {
+    pub fn words(&self) -> i32 {
+        self.cjk_words + self.latin_words
+    }
+}
+
+// Words map to their frequency; prefixes of longer words are kept with
+// frequency 0 so the DAG search knows when to stop extending a match
+#[derive(Debug, Default)]
+struct Dictionary {
+    words: HashMap<String, u64>,
+    total: u64,
+}
+
+impl Dictionary {
+    fn insert(&mut self, word: &str, frequency: u64) {
+        let mut ends: Vec<usize> = word.char_indices().skip(1).map(|(i, _)| i).collect();
+        ends.push(word.len());
+        for &end in &ends[..ends.len() - 1] {
+            self.words.entry(word[..end].to_string()).or_insert(0);
+        }
+        let entry = self.words.entry(word.to_string()).or_insert(0);
+        self.total = self.total - *entry + frequency;
+        *entry = frequency;
+    }
+
+    fn builtin() -> Self {
+        let mut dictionary = Dictionary::default();
+        dictionary.load(BUILTIN_DICTIONARY);
+        dictionary
+    }
+
+    // Reads "word frequency [tag]" lines as in jieba's dict.txt, skipping
+    // comments and lines without a frequency. Returns the number of words.
+    fn load(&mut self, text: &str) -> usize {
+        let mut loaded = 0;
+        for line in text.lines() {
+            let line = line.trim_start_matches('\u{feff}').trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            if let (Some(word), Some(frequency)) = (fields.next(), fields.next()) {
+                if let Ok(frequency) = frequency.parse() {
+                    self.insert(word, frequency);
+                    loaded += 1;
+                }
+            }
+        }
+        loaded
+    }
+}
+
+#[derive(Debug)]
+pub struct Segmenter {
+    builtin: Arc<Dictionary>,
+    user: Dictionary,
+    log_total: f64,
+}
+
+impl Segmenter {
+    pub fn new() -> Self {
+        Self::from_dictionary(Dictionary::builtin())
+    }
+
+    // The built-in dictionary extended by `text`, a dictionary in jieba's
+    // format; its frequencies replace those of the built-in words. Fails if
+    // `text` holds no words at all, which means it is not a dictionary.
+    pub fn with_dictionary(text: &str) -> Result<Self, String> {
+        let mut dictionary = Dictionary::builtin();
+        if dictionary.load(text) == 0 {
+            return Err("No words found in the segmentation dictionary".to_string());
+        }
+        Ok(Self::from_dictionary(dictionary))
+    }
+
+    fn from_dictionary(dictionary: Dictionary) -> Self {
+        let log_total = (dictionary.total.max(1) as f64).ln();
+        Segmenter {
+            builtin: Arc::new(dictionary),
+            user: Dictionary::default(),
+            log_total,
+        }
+    }
+
+    // A segmenter sharing this one's dictionary, with `words` added as user
+    // words. Words with anything but CJK characters are skipped, since only
+    // runs of CJK text are segmented.
+    pub fn with_user_words<I, S>(&self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut user = Dictionary::default();
+        for word in words {
+            let word = word.as_ref().trim();
+            let length = word.chars().count();
+            if (2..=MAX_WORD_CHARS).contains(&length) && word.chars().all(is_cjk) {
+                let frequency = self.builtin.words.get(word).copied().unwrap_or(0);
+                user.insert(word, frequency.max(USER_WORD_FREQUENCY));
+            }
+        }
+        let log_total = ((self.builtin.total + user.total).max(1) as f64).ln();
+        Segmenter {
+            builtin: Arc::clone(&self.builtin),
+            user,
+            log_total,
+        }
+    }
+
+    // None if `text` is neither a word nor the prefix of one
+    fn lookup(&self, text: &str) -> Option<u64> {
+        match (self.user.words.get(text), self.builtin.words.get(text)) {
+            (None, None) => None,
+            (user, builtin) => Some(user.copied().unwrap_or(0).max(builtin.copied().unwrap_or(0))),
+        }
+    }
+
+    // Byte ranges of the words in `run`, a string of CJK characters
+    pub fn cut(&self, run: &str) -> Vec<(usize, usize)> {
+        let mut bounds: Vec<usize> = run.char_indices().map(|(i, _)| i).collect();
+        bounds.push(run.len());
+        let chars = bounds.len() - 1;
+
+        // best[i] is the log probability of the best path from character i
+        // to the end, and next[i] the character its first word ends before
+        let mut best = vec![0.0; chars + 1];
+        let mut next = vec![chars; chars + 1];
+        for i in (0..chars).rev() {
+            // A lone character is always possible, however rare
+            let single = self.lookup(&run[bounds[i]..bounds[i + 1]]).unwrap_or(0).max(1);
+            best[i] = (single as f64).ln() - self.log_total + best[i + 1];
+            next[i] = i + 1;
+
+            for j in i + 2..=chars.min(i + MAX_WORD_CHARS) {
+                let frequency = match self.lookup(&run[bounds[i]..bounds[j]]) {
+                    Some(frequency) => frequency,
+                    None => break,
+                };
+                if frequency == 0 {
+                    continue;
+                }
+                // Ties go to the longer word
+                let score = (frequency as f64).ln() - self.log_total + best[j];
+                if score >= best[i] {
+                    best[i] = score;
+                    next[i] = j;
+                }
+            }
+        }
+
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < chars {
+            words.push((bounds[i], bounds[next[i]]));
+            i = next[i];
+        }
+        words
+    }
+
+    // Words are runs of letters and digits, keeping inner hyphens and
+    // apostrophes ("state-of-the-art", "don't"); runs of CJK characters are
+    // cut into dictionary words
+    pub fn tokenize<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        let mut chars = text.char_indices().enumerate().peekable();
+        let mut word: Option<(usize, usize)> = None; // byte and char start
+        let mut run: Option<(usize, usize)> = None;
+
+        while let Some((char_index, (byte_index, c))) = chars.next() {
+            if is_cjk(c) {
+                if run.is_none() {
+                    run = Some((byte_index, char_index));
+                }
+            } else if let Some((start, char_start)) = run.take() {
+                self.push_run(text, start, byte_index, char_start, &mut tokens);
+            }
+
+            if is_word_char(c) {
+                if word.is_none() {
+                    word = Some((byte_index, char_index));
+                }
+                continue;
+            }
+
+            let joins_word = word.is_some()
+                && matches!(c, '-' | '\'' | '’' | '_')
+                && chars.peek().is_some_and(|(_, (_, next))| is_word_char(*next));
+            if joins_word {
+                continue;
+            }
+
+            // A CJK run is only pushed once it ends, so a word that ends where
+            // a run starts still comes first
+            if let Some((start, char_start)) = word.take() {
+                tokens.push(Token {
+                    text: &text[start..byte_index],
+                    kind: TokenKind::Word,
+                    start,
+                    end: byte_index,
+                    char_start,
+                    char_end: char_index,
+                });
+            }
+        }
+        if let Some((start, char_start)) = run {
+            self.push_run(text, start, text.len(), char_start, &mut tokens);
+        }
+        if let Some((start, char_start)) = word {
+            tokens.push(Token {
+                text: &text[start..],
+                kind: TokenKind::Word,
+                start,
+                end: text.len(),
+                char_start,
+                char_end: text.chars().count(),
+            });
+        }
+        tokens
+    }
+
+    fn push_run<'a>(&self, text: &'a str, start: usize, end: usize, char_start: usize, tokens: &mut Vec<Token<'a>>) {
+        let mut char_index = char_start;
+        for (from, to) in self.cut(&text[start..end]) {
+            let piece = &text[start + from..start + to];
+            let chars = piece.chars().count();
+            tokens.push(Token {
+                text: piece,
+                kind: TokenKind::Cjk,
+                start: start + from,
+                end: start + to,
+                char_start: char_index,
+                char_end: char_index + chars,
+            });
+            char_index += chars;
+        }
+    }
+
+    pub fn count(&self, text: &str) -> TextCounts {
+        let mut counts = TextCounts {
+            characters: text.chars().filter(|c| !c.is_whitespace()).count() as i32,
+            ..TextCounts::default()
+        };
+        for token in self.tokenize(text) {
+            match token.kind {
+                TokenKind::Word => counts.latin_words += 1,
+                TokenKind::Cjk => counts.cjk_words += 1,
+            }
+        }
+        counts
+    }
+}
+
+impl Default for Segmenter {
+    fn default() -> Self {
+        Segmenter::new()
+    }
+}
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() && !is_cjk(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(segmenter: &Segmenter, text: &str) -> Vec<String> {
+        segmenter.tokenize(text).iter().map(|token| token.text.to_string()).collect()
+    }
+
+    #[test]
+    fn cuts_dictionary_words() {
+        let segmenter = Segmenter::new();
+        assert_eq!(words(&segmenter, "我们喜欢写作"), vec!["我们", "喜欢", "写作"]);
+        assert_eq!(words(&segmenter, "人工智能"), vec!["人工智能"]);
+    }
+
+    #[test]
+    fn unknown_characters_stand_alone() {
+        let segmenter = Segmenter::new();
+        assert_eq!(words(&segmenter, "林黛玉"), vec!["林", "黛", "玉"]);
+    }
+
+    #[test]
+    fn user_words_win_over_shorter_words() {
+        let segmenter = Segmenter::new().with_user_words(vec!["林黛玉", "写作课"]);
+        assert_eq!(words(&segmenter, "林黛玉喜欢写作课"), vec!["林黛玉", "喜欢", "写作课"]);
+        // The shared dictionary is unchanged
+        assert_eq!(words(&Segmenter::new(), "写作课"), vec!["写作", "课"]);
+    }
+
+    #[test]
+    fn loads_jieba_dictionaries() {
+        let segmenter = Segmenter::with_dictionary("\u{feff}# names\n林黛玉 50 nr\n贾宝玉 50\nbroken line\n").unwrap();
+        assert_eq!(segmenter.builtin.words.get("林黛玉"), Some(&50));
+        assert_eq!(words(&segmenter, "林黛玉和贾宝玉"), vec!["林黛玉", "和", "贾宝玉"]);
+        // Built-in words are still there
+        assert_eq!(words(&segmenter, "我们喜欢写作"), vec!["我们", "喜欢", "写作"]);
+
+        assert!(Segmenter::with_dictionary("# nothing\nno frequency\n").is_err());
+    }
+
+    #[test]
+    fn skips_unusable_user_words() {
+        let long = "黛".repeat(MAX_WORD_CHARS + 1);
+        let segmenter = Segmenter::new().with_user_words(vec!["黛", "黛玉x", long.as_str(), " "]);
+        assert!(segmenter.user.words.is_empty());
+    }
+
+    #[test]
+    fn tokenizes_mixed_text_with_offsets() {
+        let segmenter = Segmenter::new();
+        let text = "Don't use state-of-the-art 中国小说, ok";
+        let tokens = segmenter.tokenize(text);
+        let summary: Vec<(&str, TokenKind, usize, usize)> = tokens
+            .iter()
+            .map(|token| (token.text, token.kind, token.char_start, token.char_end))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Don't", TokenKind::Word, 0, 5),
+                ("use", TokenKind::Word, 6, 9),
+                ("state-of-the-art", TokenKind::Word, 10, 26),
+                ("中国", TokenKind::Cjk, 27, 29),
+                ("小说", TokenKind::Cjk, 29, 31),
+                ("ok", TokenKind::Word, 33, 35),
+            ]
+        );
+        for token in &tokens {
+            assert_eq!(&text[token.start..token.end], token.text);
+        }
+    }
+
+    #[test]
+    fn counts_characters_and_words() {
+        let counts = Segmenter::new().count("我们喜欢写作 and reading");
+        assert_eq!(
+            counts,
+            TextCounts {
+                characters: 16,
+                cjk_words: 3,
+                latin_words: 2,
+            }
+        );
+        assert_eq!(counts.words(), 5);
+    }
+}
@@ -0,0 +1,797 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Duration, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::database::Database;
+use crate::error::{AppError, Entity, Result, ResultExt};
+use crate::migrations;
+
+pub const SNAPSHOT_FORMAT: &str = "semantic-writing-assistant/library-snapshot";
+pub const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_SUFFIX: &str = ".snapshot.zip";
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "library.db";
+const BACKUPS_PREFIX: &str = "backups/";
+const FILES_PREFIX: &str = "files/";
+const DOCUMENTS_PREFIX: &str = "documents/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotTrigger {
+    Manual,
+    Scheduled,
+    PreRestore, // taken automatically before a restore overwrites the library
+}
+
+// How many snapshots to keep per hour, day and ISO week, newest first. A
+// snapshot kept by any rule survives; the newest one is always kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotRetention {
+    pub hourly: usize,
+    pub daily: usize,
+    pub weekly: usize,
+}
+
+impl Default for SnapshotRetention {
+    fn default() -> Self {
+        Self {
+            hourly: 24,
+            daily: 7,
+            weekly: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotEntry {
+    path: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotManifest {
+    format: String,
+    version: u32,
+    created_at: DateTime<Utc>,
+    trigger: SnapshotTrigger,
+    schema_version: u32,
+    document_count: usize,
+    folders: Vec<String>, // prefixes of the app data folders archived in full
+    entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub path: String,
+    pub created_at: DateTime<Utc>,
+    pub trigger: SnapshotTrigger,
+    pub size: u64, // archive size in bytes
+    pub document_count: usize,
+    pub schema_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotVerification {
+    pub id: String,
+    pub valid: bool,
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRestoreResult {
+    pub restored: SnapshotInfo,
+    pub safety_snapshot: SnapshotInfo, // the library as it was before the restore
+}
+
+pub struct SnapshotManager {
+    snapshots_dir: PathBuf,
+    folders: Vec<(&'static str, PathBuf)>, // archive prefix and the folder stored under it
+}
+
+impl SnapshotManager {
+    // Per-document backups, raw files and exported documents travel with
+    // each snapshot alongside the database
+    pub fn new(snapshots_dir: PathBuf, backups_dir: PathBuf, files_dir: PathBuf, documents_dir: PathBuf) -> Self {
+        Self {
+            snapshots_dir,
+            folders: vec![
+                (BACKUPS_PREFIX, backups_dir),
+                (FILES_PREFIX, files_dir),
+                (DOCUMENTS_PREFIX, documents_dir),
+            ],
+        }
+    }
+
+    pub fn create(&self, db: &Database, trigger: SnapshotTrigger) -> Result<SnapshotInfo> {
+        fs::create_dir_all(&self.snapshots_dir).context("Failed to create snapshots directory")?;
+        let created_at = Utc::now();
+        let id = self.unused_id(created_at);
+
+        // The online backup API gives a consistent copy even while the
+        // database is being written to
+        let temp_db = self.temp_path("db");
+        let result = db
+            .backup_to(&temp_db)
+            .context("Failed to copy database")
+            .and_then(|_| self.write_archive(&id, created_at, trigger, &temp_db));
+        let _ = fs::remove_file(&temp_db);
+        result
+    }
+
+    fn write_archive(
+        &self,
+        id: &str,
+        created_at: DateTime<Utc>,
+        trigger: SnapshotTrigger,
+        database_path: &Path,
+    ) -> Result<SnapshotInfo> {
+        let (schema_version, document_count) = inspect_database(database_path)?;
+
+        let mut sources = vec![(DATABASE_ENTRY.to_string(), database_path.to_path_buf())];
+        for (prefix, dir) in &self.folders {
+            sources.extend(folder_files(prefix, dir));
+        }
+
+        let temp_archive = self.temp_path("zip");
+        let written = (|| -> Result<()> {
+            let file = File::create(&temp_archive).context("Failed to create snapshot")?;
+            let mut zip = ZipWriter::new(file);
+            let options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .large_file(true);
+
+            let mut entries = Vec::new();
+            for (name, path) in &sources {
+                let (size, sha256) = hash_file(path)?;
+                zip.start_file(name.as_str(), options)
+                    .context("Failed to write snapshot")?;
+                let mut source = File::open(path).with_context(|| format!("Failed to read {}", name))?;
+                io::copy(&mut source, &mut zip).context("Failed to write snapshot")?;
+                entries.push(SnapshotEntry {
+                    path: name.clone(),
+                    size,
+                    sha256,
+                });
+            }
+
+            let manifest = SnapshotManifest {
+                format: SNAPSHOT_FORMAT.to_string(),
+                version: SNAPSHOT_VERSION,
+                created_at,
+                trigger,
+                schema_version,
+                document_count,
+                folders: self.folders.iter().map(|(prefix, _)| prefix.to_string()).collect(),
+                entries,
+            };
+            let manifest_json = serde_json::to_vec_pretty(&manifest)
+                .context("Failed to serialize snapshot manifest")?;
+            zip.start_file(MANIFEST_ENTRY, options)
+                .context("Failed to write snapshot")?;
+            zip.write_all(&manifest_json).context("Failed to write snapshot")?;
+
+            let file = zip.finish().context("Failed to write snapshot")?;
+            file.sync_all().context("Failed to write snapshot")
+        })();
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_archive);
+            return Err(e);
+        }
+
+        // Only complete archives ever carry the snapshot name
+        let path = self.snapshot_path(id);
+        fs::rename(&temp_archive, &path).context("Failed to save snapshot")?;
+        self.info(&path)
+    }
+
+    // Newest first
+    pub fn list(&self) -> Result<Vec<SnapshotInfo>> {
+        let entries = match fs::read_dir(&self.snapshots_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(AppError::from(e).context("Failed to read snapshots directory")),
+        };
+
+        let mut snapshots = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let is_snapshot = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(SNAPSHOT_SUFFIX));
+            // Unreadable archives are left for verify_snapshot to report
+            if is_snapshot {
+                if let Ok(info) = self.info(&path) {
+                    snapshots.push(info);
+                }
+            }
+        }
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+        Ok(snapshots)
+    }
+
+    pub fn latest(&self) -> Result<Option<SnapshotInfo>> {
+        Ok(self.list()?.into_iter().next())
+    }
+
+    pub fn verify(&self, id: &str) -> Result<SnapshotVerification> {
+        let path = self.existing_snapshot_path(id)?;
+        let mut problems = Vec::new();
+
+        let file = File::open(&path).context("Failed to open snapshot")?;
+        let mut archive = match ZipArchive::new(file) {
+            Ok(archive) => archive,
+            Err(e) => {
+                problems.push(format!("Archive is unreadable: {}", e));
+                return Ok(SnapshotVerification { id: id.to_string(), valid: false, problems });
+            }
+        };
+        let manifest = match read_manifest(&mut archive) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                problems.push(e.to_string());
+                return Ok(SnapshotVerification { id: id.to_string(), valid: false, problems });
+            }
+        };
+
+        if manifest.schema_version > migrations::latest_version() {
+            problems.push(format!(
+                "Snapshot uses database schema {} but this app supports up to {}",
+                manifest.schema_version,
+                migrations::latest_version()
+            ));
+        }
+        if !manifest.entries.iter().any(|entry| entry.path == DATABASE_ENTRY) {
+            problems.push("Snapshot has no database".to_string());
+        }
+
+        for entry in &manifest.entries {
+            let mut reader = match archive.by_name(&entry.path) {
+                Ok(reader) => reader,
+                Err(_) => {
+                    problems.push(format!("Missing file: {}", entry.path));
+                    continue;
+                }
+            };
+            match hash_reader(&mut reader) {
+                Ok((size, sha256)) if size == entry.size && sha256 == entry.sha256 => {}
+                Ok(_) => problems.push(format!("Checksum mismatch: {}", entry.path)),
+                Err(e) => problems.push(format!("Failed to read {}: {}", entry.path, e)),
+            }
+        }
+
+        // A database that hashes correctly can still have been written
+        // broken; let SQLite check it as well
+        if problems.is_empty() {
+            let temp_db = self.temp_path("db");
+            let checked = extract_entry(&mut archive, DATABASE_ENTRY, &temp_db).and_then(|_| integrity_check(&temp_db));
+            let _ = fs::remove_file(&temp_db);
+            if let Err(e) = checked {
+                problems.push(e.to_string());
+            }
+        }
+
+        Ok(SnapshotVerification {
+            id: id.to_string(),
+            valid: problems.is_empty(),
+            problems,
+        })
+    }
+
+    // Replaces the live database and every archived folder with the
+    // snapshot's. The current library is snapshotted first so a restore can
+    // itself be undone.
+    pub fn restore(&self, db: &mut Database, id: &str) -> Result<SnapshotRestoreResult> {
+        let verification = self.verify(id)?;
+        if !verification.valid {
+            return Err(AppError::Corrupt(verification.problems.join("; ")).context("Snapshot failed verification"));
+        }
+        let path = self.existing_snapshot_path(id)?;
+        let restored = self.info(&path)?;
+        let safety_snapshot = self.create(db, SnapshotTrigger::PreRestore)?;
+
+        let file = File::open(&path).context("Failed to open snapshot")?;
+        let mut archive = ZipArchive::new(file).context("Failed to read snapshot")?;
+        let manifest = read_manifest(&mut archive)?;
+
+        // Folders are extracted beside the live ones and only swapped in once
+        // the database is restored, so a failure leaves the library as it was
+        let mut staged = Vec::new();
+        let mut result = Ok(());
+        for (prefix, dir) in &self.folders {
+            if !manifest.folders.iter().any(|folder| folder == prefix) {
+                continue;
+            }
+            let staging = sibling_path(dir, "restore");
+            staged.push((dir, staging.clone()));
+            result = extract_folder(&mut archive, prefix, &staging);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let temp_db = self.temp_path("db");
+        let result = result.and_then(|_| {
+            extract_entry(&mut archive, DATABASE_ENTRY, &temp_db)
+                .and_then(|_| db.restore_from(&temp_db).context("Failed to restore database"))
+        });
+        let _ = fs::remove_file(&temp_db);
+        if let Err(e) = result {
+            for (_, staging) in &staged {
+                let _ = fs::remove_dir_all(staging);
+            }
+            return Err(e);
+        }
+
+        for (dir, staging) in staged {
+            replace_dir(dir, &staging)?;
+        }
+
+        Ok(SnapshotRestoreResult {
+            restored,
+            safety_snapshot,
+        })
+    }
+
+    // Deletes snapshots no retention rule keeps; `max_snapshots` caps the
+    // total (0 = no cap). Returns the ids that were removed.
+    pub fn apply_retention(&self, retention: &SnapshotRetention, max_snapshots: usize) -> Result<Vec<String>> {
+        let snapshots = self.list()?;
+        let keep = retained_ids(&snapshots, retention, max_snapshots);
+
+        let mut removed = Vec::new();
+        for snapshot in snapshots.iter().filter(|snapshot| !keep.contains(&snapshot.id)) {
+            fs::remove_file(&snapshot.path).context("Failed to delete snapshot")?;
+            removed.push(snapshot.id.clone());
+        }
+        Ok(removed)
+    }
+
+    fn info(&self, path: &Path) -> Result<SnapshotInfo> {
+        let file = File::open(path).context("Failed to open snapshot")?;
+        let size = file.metadata().context("Failed to read snapshot")?.len();
+        let mut archive = ZipArchive::new(file).context("Failed to read snapshot")?;
+        let manifest = read_manifest(&mut archive)?;
+        let id = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(SNAPSHOT_SUFFIX))
+            .ok_or_else(|| AppError::Internal(format!("Invalid snapshot file name: {}", path.display())))?
+            .to_string();
+
+        Ok(SnapshotInfo {
+            id,
+            path: path.to_string_lossy().to_string(),
+            created_at: manifest.created_at,
+            trigger: manifest.trigger,
+            size,
+            document_count: manifest.document_count,
+            schema_version: manifest.schema_version,
+        })
+    }
+
+    fn unused_id(&self, created_at: DateTime<Utc>) -> String {
+        let base = format!("library-{}", created_at.format("%Y%m%d-%H%M%S"));
+        let mut id = base.clone();
+        let mut counter = 1;
+        while self.snapshot_path(&id).exists() {
+            counter += 1;
+            id = format!("{}-{}", base, counter);
+        }
+        id
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        self.snapshots_dir.join(format!("{}{}", id, SNAPSHOT_SUFFIX))
+    }
+
+    // Ids come from the frontend; anything that is not a plain name is rejected
+    fn existing_snapshot_path(&self, id: &str) -> Result<PathBuf> {
+        let plain = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        let path = self.snapshot_path(id);
+        if !plain || !path.is_file() {
+            return Err(AppError::not_found_id(Entity::Snapshot, id));
+        }
+        Ok(path)
+    }
+
+    fn temp_path(&self, extension: &str) -> PathBuf {
+        self.snapshots_dir.join(format!(".tmp-{}.{}", Uuid::new_v4(), extension))
+    }
+}
+
+// Names the hour, day or week a snapshot falls into
+type PeriodKey = fn(&DateTime<Utc>) -> String;
+
+fn retained_ids(snapshots: &[SnapshotInfo], retention: &SnapshotRetention, max_snapshots: usize) -> HashSet<String> {
+    // `snapshots` is newest first, so the first snapshot seen in each
+    // period is the one kept for it
+    let mut keep: Vec<&SnapshotInfo> = Vec::new();
+    let rules: [(usize, PeriodKey); 3] = [
+        (retention.hourly, |time| time.format("%Y-%m-%d %H").to_string()),
+        (retention.daily, |time| time.format("%Y-%m-%d").to_string()),
+        (retention.weekly, |time| {
+            let week = time.iso_week();
+            format!("{}-W{}", week.year(), week.week())
+        }),
+    ];
+    for (count, period) in rules {
+        let mut seen = HashSet::new();
+        for snapshot in snapshots {
+            if seen.len() >= count {
+                break;
+            }
+            if seen.insert(period(&snapshot.created_at)) && !keep.iter().any(|kept| kept.id == snapshot.id) {
+                keep.push(snapshot);
+            }
+        }
+    }
+    if let Some(newest) = snapshots.first() {
+        if !keep.iter().any(|kept| kept.id == newest.id) {
+            keep.push(newest);
+        }
+    }
+
+    keep.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+    if max_snapshots > 0 {
+        keep.truncate(max_snapshots);
+    }
+    keep.into_iter().map(|snapshot| snapshot.id.clone()).collect()
+}
+
+// True when a scheduled snapshot is due
+pub fn snapshot_due(latest: Option<&SnapshotInfo>, interval_hours: u32, now: DateTime<Utc>) -> bool {
+    if interval_hours == 0 {
+        return false;
+    }
+    latest.is_none_or(|snapshot| now - snapshot.created_at >= Duration::hours(interval_hours as i64))
+}
+
+fn read_manifest<R: Read + io::Seek>(archive: &mut ZipArchive<R>) -> Result<SnapshotManifest> {
+    let mut text = String::new();
+    archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| AppError::Corrupt("Snapshot has no manifest".to_string()))?
+        .read_to_string(&mut text)
+        .context("Failed to read snapshot manifest")?;
+    let manifest: SnapshotManifest =
+        serde_json::from_str(&text).context("Invalid snapshot manifest")?;
+    if manifest.format != SNAPSHOT_FORMAT {
+        return Err(AppError::UnsupportedFormat(format!("Not a library snapshot: {}", manifest.format)));
+    }
+    if manifest.version > SNAPSHOT_VERSION {
+        return Err(AppError::UnsupportedFormat(format!("Snapshot version {} is newer than this app supports", manifest.version)));
+    }
+    Ok(manifest)
+}
+
+// Archive entries for every file under `dir`, in name order. Symlinks are
+// not followed, so only the folder's own files are archived.
+fn folder_files(prefix: &str, dir: &Path) -> Vec<(String, PathBuf)> {
+    WalkDir::new(dir)
+        .follow_links(false)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let relative = entry.path().strip_prefix(dir).ok()?;
+            let parts: Option<Vec<&str>> = relative.components().map(|part| part.as_os_str().to_str()).collect();
+            Some((format!("{}{}", prefix, parts?.join("/")), entry.path().to_path_buf()))
+        })
+        .collect()
+}
+
+// Extracts the entries under `prefix` into `target`. Entries whose path is
+// not plainly relative are skipped, so none can escape the folder.
+fn extract_folder<R: Read + io::Seek>(archive: &mut ZipArchive<R>, prefix: &str, target: &Path) -> Result<()> {
+    fs::create_dir_all(target).context("Failed to create restore directory")?;
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.starts_with(prefix) && !name.ends_with('/'))
+        .map(|name| name.to_string())
+        .collect();
+    for name in names {
+        let relative = Path::new(&name[prefix.len()..]);
+        if !relative.components().all(|part| matches!(part, Component::Normal(_))) {
+            continue;
+        }
+        let path = target.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create restore directory")?;
+        }
+        extract_entry(archive, &name, &path)?;
+    }
+    Ok(())
+}
+
+// Swaps the restored folder in for the live one
+fn replace_dir(dir: &Path, staging: &Path) -> Result<()> {
+    let old = sibling_path(dir, "old");
+    if dir.exists() {
+        fs::rename(dir, &old).with_context(|| format!("Failed to replace {}", dir.display()))?;
+    }
+    if let Err(e) = fs::rename(staging, dir) {
+        let _ = fs::rename(&old, dir);
+        return Err(AppError::from(e).context(format!("Failed to replace {}", dir.display())));
+    }
+    let _ = fs::remove_dir_all(&old);
+    Ok(())
+}
+
+fn sibling_path(dir: &Path, purpose: &str) -> PathBuf {
+    let name = dir.file_name().and_then(|name| name.to_str()).unwrap_or("folder");
+    dir.with_file_name(format!(".{}-{}-{}", name, purpose, Uuid::new_v4()))
+}
+
+fn extract_entry<R: Read + io::Seek>(archive: &mut ZipArchive<R>, name: &str, target: &Path) -> Result<()> {
+    let mut reader = archive.by_name(name).with_context(|| format!("Failed to read {}", name))?;
+    let mut file = File::create(target).with_context(|| format!("Failed to extract {}", name))?;
+    io::copy(&mut reader, &mut file).with_context(|| format!("Failed to extract {}", name))?;
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut file = File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    hash_reader(&mut file).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn hash_reader(reader: &mut impl Read) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn inspect_database(path: &Path) -> Result<(u32, usize)> {
+    let conn = Connection::open(path).context("Failed to open database copy")?;
+    let schema_version = migrations::current_version(&conn).context("Failed to read schema version")?;
+    let document_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM documents", [], |row| row.get(0))
+        .context("Failed to count documents")?;
+    Ok((schema_version, document_count as usize))
+}
+
+fn integrity_check(path: &Path) -> Result<()> {
+    let conn = Connection::open(path).context("Failed to open snapshot database")?;
+    let result: String = conn
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .context("Failed to check snapshot database")?;
+    if result == "ok" {
+        Ok(())
+    } else {
+        Err(AppError::Corrupt(format!("Snapshot database is corrupt: {}", result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Document, UNSORTED_COLLECTION_ID};
+    use chrono::TimeZone;
+    use std::collections::BTreeMap;
+
+    struct Fixture {
+        dir: PathBuf,
+        db: Database,
+        manager: SnapshotManager,
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn fixture(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("snapshot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let db = Database::new(&dir.join("library.db")).unwrap();
+        let manager = SnapshotManager::new(
+            dir.join("snapshots"),
+            dir.join("backups"),
+            dir.join("files"),
+            dir.join("documents"),
+        );
+        Fixture { dir, db, manager }
+    }
+
+    fn save_document(db: &Database, id: &str) {
+        db.save_document(&Document {
+            id: id.to_string(),
+            title: id.to_string(),
+            content: format!("Content of {}", id),
+            file_path: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            word_count: 3,
+            char_count: 10,
+            cjk_word_count: 0,
+            latin_word_count: 3,
+            collection_id: UNSORTED_COLLECTION_ID.to_string(),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
+        })
+        .unwrap();
+    }
+
+    fn write(path: PathBuf, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn document_ids(db: &Database) -> Vec<String> {
+        db.list_documents().unwrap().into_iter().map(|document| document.id).collect()
+    }
+
+    // Copies the snapshot's archive without the named entry
+    fn drop_entry(path: &str, name: &str) {
+        let bytes = fs::read(path).unwrap();
+        let mut archive = ZipArchive::new(io::Cursor::new(bytes)).unwrap();
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index).unwrap();
+            if entry.name() != name {
+                zip.raw_copy_file(entry).unwrap();
+            }
+        }
+        zip.finish().unwrap();
+    }
+
+    fn info(id: &str, created_at: DateTime<Utc>) -> SnapshotInfo {
+        SnapshotInfo {
+            id: id.to_string(),
+            path: String::new(),
+            created_at,
+            trigger: SnapshotTrigger::Scheduled,
+            size: 0,
+            document_count: 0,
+            schema_version: 0,
+        }
+    }
+
+    #[test]
+    fn restores_database_and_folders_in_full() {
+        let mut fixture = fixture("restore");
+        let dir = fixture.dir.clone();
+        save_document(&fixture.db, "kept");
+        write(dir.join("backups/kept.backup.json"), "old backup");
+        write(dir.join("files/notes/a.txt"), "first");
+        write(dir.join("documents/export.md"), "exported");
+        let snapshot = fixture.manager.create(&fixture.db, SnapshotTrigger::Manual).unwrap();
+        assert_eq!(snapshot.document_count, 1);
+
+        save_document(&fixture.db, "added");
+        write(dir.join("backups/kept.backup.json"), "new backup");
+        write(dir.join("files/notes/a.txt"), "second");
+        write(dir.join("files/new.txt"), "new");
+        fs::remove_file(dir.join("documents/export.md")).unwrap();
+
+        let result = fixture.manager.restore(&mut fixture.db, &snapshot.id).unwrap();
+        assert_eq!(result.restored.id, snapshot.id);
+        assert_eq!(result.safety_snapshot.document_count, 2);
+        assert_eq!(result.safety_snapshot.trigger, SnapshotTrigger::PreRestore);
+
+        assert_eq!(document_ids(&fixture.db), vec!["kept"]);
+        assert_eq!(fs::read_to_string(dir.join("backups/kept.backup.json")).unwrap(), "old backup");
+        assert_eq!(fs::read_to_string(dir.join("files/notes/a.txt")).unwrap(), "first");
+        assert!(!dir.join("files/new.txt").exists());
+        assert_eq!(fs::read_to_string(dir.join("documents/export.md")).unwrap(), "exported");
+        // No staging or replaced folders are left behind
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| !name.starts_with("library.db"))
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["backups", "documents", "files", "snapshots"]);
+
+        // The safety snapshot undoes the restore
+        fixture.manager.restore(&mut fixture.db, &result.safety_snapshot.id).unwrap();
+        assert_eq!(document_ids(&fixture.db).len(), 2);
+        assert_eq!(fs::read_to_string(dir.join("files/new.txt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn verify_flags_damaged_archives() {
+        let mut fixture = fixture("verify");
+        save_document(&fixture.db, "doc");
+        write(fixture.dir.join("files/a.txt"), "text");
+        let snapshot = fixture.manager.create(&fixture.db, SnapshotTrigger::Manual).unwrap();
+        assert!(fixture.manager.verify(&snapshot.id).unwrap().valid);
+
+        drop_entry(&snapshot.path, DATABASE_ENTRY);
+        let verification = fixture.manager.verify(&snapshot.id).unwrap();
+        assert!(!verification.valid);
+        assert_eq!(verification.problems, vec!["Missing file: library.db"]);
+        assert!(fixture.manager.restore(&mut fixture.db, &snapshot.id).is_err());
+
+        let snapshot = fixture.manager.create(&fixture.db, SnapshotTrigger::Manual).unwrap();
+        let mut bytes = fs::read(&snapshot.path).unwrap();
+        bytes.truncate(bytes.len() / 2);
+        fs::write(&snapshot.path, bytes).unwrap();
+        let verification = fixture.manager.verify(&snapshot.id).unwrap();
+        assert!(!verification.valid);
+        assert!(verification.problems[0].starts_with("Archive is unreadable"), "{:?}", verification.problems);
+
+        assert!(fixture.manager.verify("../library").is_err());
+    }
+
+    #[test]
+    fn retention_keeps_the_newest_snapshot_of_each_period() {
+        // 2024-05-15 is the Wednesday of ISO week 20
+        let at = |day, hour, minute| Utc.with_ymd_and_hms(2024, 5, day, hour, minute, 0).unwrap();
+        let snapshots = vec![
+            info("now", at(15, 12, 0)),
+            info("earlier-this-hour", at(15, 11, 30)),
+            info("same-hour", at(15, 11, 0)),
+            info("this-morning", at(15, 10, 0)),
+            info("yesterday", at(14, 12, 0)),
+            info("monday", at(13, 12, 0)),
+            info("last-week", at(7, 12, 0)),
+            info("two-weeks-ago", at(1, 12, 0)),
+        ];
+        let retention = SnapshotRetention { hourly: 2, daily: 2, weekly: 2 };
+
+        let mut kept: Vec<String> = retained_ids(&snapshots, &retention, 0).into_iter().collect();
+        kept.sort();
+        assert_eq!(kept, vec!["earlier-this-hour", "last-week", "now", "yesterday"]);
+
+        // The cap drops the oldest of the kept snapshots
+        let mut kept: Vec<String> = retained_ids(&snapshots, &retention, 2).into_iter().collect();
+        kept.sort();
+        assert_eq!(kept, vec!["earlier-this-hour", "now"]);
+
+        // The newest snapshot survives even when no rule keeps any
+        let none = SnapshotRetention { hourly: 0, daily: 0, weekly: 0 };
+        assert_eq!(retained_ids(&snapshots, &none, 0).into_iter().collect::<Vec<_>>(), vec!["now"]);
+    }
+
+    #[test]
+    fn apply_retention_deletes_unkept_archives() {
+        let fixture = fixture("retention");
+        for _ in 0..3 {
+            fixture.manager.create(&fixture.db, SnapshotTrigger::Manual).unwrap();
+        }
+        let retention = SnapshotRetention { hourly: 1, daily: 0, weekly: 0 };
+        let removed = fixture.manager.apply_retention(&retention, 0).unwrap();
+        assert_eq!(removed.len(), 2);
+        let remaining = fixture.manager.list().unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert!(!removed.contains(&remaining[0].id));
+    }
+
+    #[test]
+    fn snapshot_is_due_once_the_interval_has_passed() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+        let latest = info("latest", now - Duration::hours(24));
+        assert!(snapshot_due(None, 24, now));
+        assert!(snapshot_due(Some(&latest), 24, now));
+        assert!(!snapshot_due(Some(&latest), 24, now - Duration::seconds(1)));
+        assert!(!snapshot_due(Some(&latest), 25, now));
+        // 0 turns scheduled snapshots off
+        assert!(!snapshot_due(None, 0, now));
+    }
+}
@@ -1,19 +1,45 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 
-use crate::database::{Database, Document, SemanticTerm, ConsistencyRule, AnalysisCache};
-use crate::file_handler::{FileHandler, ImportResult};
+use crate::analysis::{self, DocumentAnalysis};
+use crate::database::{content_hash, Collection, CollectionDeleteMode, CollectionStats, Database, Document, DocumentListQuery, MetadataValue, TagInfo, UNSORTED_COLLECTION_ID, DocumentPage, DocumentRevision, RevisionSummary, SemanticTerm, ConsistencyRule, RuleScope, AnalysisCache};
+use crate::backup::{DocumentBackup, RestoreOptions};
+use crate::cache::LruCache;
+use crate::consistency::{self, CheckOptions, ConsistencyIssue, ConsistencyReport};
+use crate::diff::{self, DiffGranularity, TextDiff};
+use crate::epub::EpubMetadata;
+use crate::error::{AppError, Entity, Result, ResultExt};
+use crate::fixes::{self, AppliedFixes, FixEdit, FixPlan};
+use crate::file_handler::{self, ExportOptions, FileHandler, ImportResult};
+use crate::fs_scope::FsScope;
+use crate::importer::{DirectoryImportOptions, DirectoryImporter};
+use crate::pool::DbPool;
+use crate::search::{SearchHit, SearchQuery};
+use crate::segment::Segmenter;
+use crate::snapshot::{self, SnapshotInfo, SnapshotManager, SnapshotRestoreResult, SnapshotRetention, SnapshotTrigger, SnapshotVerification};
 
-#[derive(Debug, Serialize, Deserialize)]
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct StorageConfig {
-    pub app_data_dir: String,
+    #[serde(skip_deserializing)]
+    pub app_data_dir: String, // fixed by the app, never read from the file
     pub auto_save_interval: u64, // seconds
-    pub max_backups: usize,
+    pub max_backups: usize, // backup files kept per document, 0 = no cap
+    pub max_snapshots: usize, // library snapshots kept at most, 0 = no cap
     pub cache_size_limit: usize, // MB
+    pub max_revisions: usize, // per document, 0 = unlimited
+    pub revision_retention_days: u32, // 0 = keep forever
+    pub snapshot_interval_hours: u32, // scheduled library snapshots, 0 = off
+    pub snapshot_retention: SnapshotRetention,
+    pub keep_backup_files: bool, // keep `<name>.bak` when saving or exporting over a file
 }
 
 impl Default for StorageConfig {
@@ -22,43 +48,206 @@ impl Default for StorageConfig {
             app_data_dir: "".to_string(), // Will be set by the app
             auto_save_interval: 30, // 30 seconds
             max_backups: 10,
+            max_snapshots: 40,
             cache_size_limit: 100, // 100 MB
+            max_revisions: 50,
+            revision_retention_days: 0,
+            snapshot_interval_hours: 24,
+            snapshot_retention: SnapshotRetention::default(),
+            keep_backup_files: false,
         }
     }
 }
 
+// On-disk layout of config.json; settings missing from the file keep their defaults
+#[derive(Serialize, Deserialize)]
+struct ConfigFile {
+    version: u32,
+    #[serde(flatten)]
+    config: StorageConfig,
+}
+
+impl StorageConfig {
+    pub fn cache_capacity_bytes(&self) -> usize {
+        self.cache_size_limit * 1024 * 1024
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=3600).contains(&self.auto_save_interval) {
+            return Err(AppError::invalid("Auto-save interval must be between 1 and 3600 seconds", "自动保存间隔必须在 1 到 3600 秒之间"));
+        }
+        if !(1..=4096).contains(&self.cache_size_limit) {
+            return Err(AppError::invalid("Cache size limit must be between 1 and 4096 MB", "缓存上限必须在 1 到 4096 MB 之间"));
+        }
+        if self.max_backups > 1000 {
+            return Err(AppError::invalid("At most 1000 backups per document can be kept", "每篇文档最多只能保留 1000 个备份"));
+        }
+        if self.max_snapshots > 1000 {
+            return Err(AppError::invalid("At most 1000 snapshots can be kept", "最多只能保留 1000 个快照"));
+        }
+        if self.max_revisions > 10_000 {
+            return Err(AppError::invalid("At most 10000 revisions per document can be kept", "每篇文档最多只能保留 10000 个修订版本"));
+        }
+        if self.revision_retention_days > 36_500 {
+            return Err(AppError::invalid("Revision retention must be at most 36500 days", "修订版本最多保留 36500 天"));
+        }
+        if self.snapshot_interval_hours > 24 * 30 {
+            return Err(AppError::invalid("Snapshot interval must be at most 720 hours", "快照间隔不能超过 720 小时"));
+        }
+        let retention = &self.snapshot_retention;
+        if retention.hourly > 1000 || retention.daily > 1000 || retention.weekly > 1000 {
+            return Err(AppError::invalid("Snapshot retention counts must be at most 1000", "快照保留数量不能超过 1000"));
+        }
+        Ok(())
+    }
+
+    // A missing file means defaults; a damaged or invalid one is reported so
+    // the caller can decide whether to fall back
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(StorageConfig::default());
+        }
+        let text = fs::read_to_string(path).context("Failed to read config")?;
+        let file: ConfigFile = serde_json::from_str(&text).context("Invalid config file")?;
+        if file.version > CONFIG_VERSION {
+            return Err(AppError::UnsupportedFormat(format!(
+                "Config version {} is newer than this app supports",
+                file.version
+            )));
+        }
+        file.config.validate()?;
+        Ok(file.config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = ConfigFile {
+            version: CONFIG_VERSION,
+            config: self.clone(),
+        };
+        let text = serde_json::to_string_pretty(&file).context("Failed to serialize config")?;
+        file_handler::write_atomic(path, text.as_bytes(), false)
+            .context("Failed to write config")
+    }
+}
+
 pub struct StorageService {
-    db: Arc<Mutex<Database>>,
+    db: DbPool,
     file_handler: Arc<FileHandler>,
-    config: StorageConfig,
-    // In-memory cache for frequently accessed documents
-    document_cache: Arc<Mutex<HashMap<String, Document>>>,
+    config: RwLock<StorageConfig>,
+    snapshots: SnapshotManager,
+    // Guards the raw file commands; storage itself only touches app data
+    fs_scope: FsScope,
+    // Rebuilt with the consistency rule terms as user words whenever the
+    // rules change
+    segmenter: RwLock<Arc<Segmenter>>,
+    // In-memory cache for frequently accessed documents, bounded by cache_size_limit
+    document_cache: Arc<Mutex<LruCache<Document>>>,
 }
 
 impl StorageService {
-    pub fn new(app_data_dir: PathBuf) -> Result<Self, String> {
+    pub fn new(app_data_dir: PathBuf) -> Result<Self> {
         let file_handler = Arc::new(FileHandler::new(app_data_dir.clone()));
         let db_path = file_handler.get_database_path();
         
-        let database = Database::new(&db_path)
-            .map_err(|e| format!("Failed to initialize database: {}", e))?;
+        let db = DbPool::open(&db_path)?;
         
-        let config = StorageConfig {
-            app_data_dir: app_data_dir.to_string_lossy().to_string(),
-            ..Default::default()
-        };
+        let config_path = file_handler.get_config_path();
+        let mut config = StorageConfig::load(&config_path).unwrap_or_else(|e| {
+            // Keep the unreadable file for inspection instead of overwriting it
+            eprintln!("{}; using default settings", e);
+            let _ = fs::rename(&config_path, config_path.with_extension("json.invalid"));
+            StorageConfig::default()
+        });
+        config.app_data_dir = app_data_dir.to_string_lossy().to_string();
+        let cache_capacity = config.cache_capacity_bytes();
+        file_handler.set_keep_backups(config.keep_backup_files);
+
+        let snapshots = SnapshotManager::new(
+            file_handler.get_snapshots_dir(),
+            file_handler.get_backups_dir(),
+            file_handler.get_files_dir(),
+            file_handler.get_documents_dir(),
+        );
+        let fs_scope = FsScope::new(Arc::clone(&file_handler))?;
+        let segmenter = Self::load_segmenter(&file_handler.get_dictionary_path());
 
-        Ok(StorageService {
-            db: Arc::new(Mutex::new(database)),
+        let storage = StorageService {
+            db,
             file_handler,
-            config,
-            document_cache: Arc::new(Mutex::new(HashMap::new())),
+            config: RwLock::new(config),
+            snapshots,
+            fs_scope,
+            segmenter: RwLock::new(Arc::new(segmenter)),
+            document_cache: Arc::new(Mutex::new(LruCache::new(cache_capacity))),
+        };
+        storage.load_user_words()?;
+        storage.recount_documents()?;
+        Ok(storage)
+    }
+
+    pub fn fs_scope(&self) -> &FsScope {
+        &self.fs_scope
+    }
+
+    // A dictionary that cannot be read is skipped rather than keeping the
+    // app from starting; segmentation then uses the built-in words only
+    fn load_segmenter(dictionary_path: &Path) -> Segmenter {
+        let text = match fs::read_to_string(dictionary_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Segmenter::new(),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}; using the built-in dictionary", dictionary_path.display(), e);
+                return Segmenter::new();
+            }
+        };
+        Segmenter::with_dictionary(&text).unwrap_or_else(|e| {
+            eprintln!("{} in {}; using the built-in dictionary", e, dictionary_path.display());
+            Segmenter::new()
         })
     }
 
+    fn segmenter(&self) -> Result<Arc<Segmenter>> {
+        let segmenter = self.segmenter.read().map_err(|_| AppError::lock("segmenter"))?;
+        Ok(Arc::clone(&segmenter))
+    }
+
+    // Terms, preferred forms and alternatives of the active consistency
+    // rules are segmented as single words
+    fn load_user_words(&self) -> Result<()> {
+        let rules = self.get_consistency_rules()?;
+        let mut words = Vec::new();
+        for rule in rules {
+            words.push(rule.term);
+            words.push(rule.preferred_form);
+            words.extend(rule.alternatives);
+        }
+
+        let mut segmenter = self.segmenter.write().map_err(|_| AppError::lock("segmenter"))?;
+        *segmenter = Arc::new(segmenter.with_user_words(words));
+        Ok(())
+    }
+
+    // Counts documents stored before word segmentation existed, see
+    // migration 9
+    fn recount_documents(&self) -> Result<()> {
+        let segmenter = self.segmenter()?;
+        let db = self.db.writer()?;
+        let ids = db.get_uncounted_document_ids()
+            .context("Failed to get documents to count")?;
+        for id in ids {
+            let document = db.get_document(&id)
+                .context("Failed to get document")?;
+            if let Some(document) = document {
+                db.update_document_counts(&id, segmenter.count(&document.content))
+                    .context("Failed to update word counts")?;
+            }
+        }
+        Ok(())
+    }
+
     // Document operations
-    pub fn create_document(&self, title: String, content: String) -> Result<String, String> {
-        let word_count = content.split_whitespace().count() as i32;
+    pub fn create_document(&self, title: String, content: String, collection_id: Option<String>) -> Result<String> {
+        let counts = self.segmenter()?.count(&content);
         let now = Utc::now();
         
         let document = Document {
@@ -68,26 +257,32 @@ impl StorageService {
             file_path: None,
             created_at: now,
             updated_at: now,
-            word_count,
+            word_count: counts.words(),
+            char_count: counts.characters,
+            cjk_word_count: counts.cjk_words,
+            latin_word_count: counts.latin_words,
+            collection_id: collection_id.unwrap_or_else(|| UNSORTED_COLLECTION_ID.to_string()),
+            tags: Vec::new(),
+            metadata: BTreeMap::new(),
         };
 
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
-        db.save_document(&document)
-            .map_err(|e| format!("Failed to save document: {}", e))?;
+        let db = self.db.writer()?;
+        Self::require_collection(&db, &document.collection_id)?;
+        db.save_document_with_revision(&document, None)
+            .context("Failed to save document")?;
         
         // Update cache
-        let mut cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
-        cache.insert(document.id.clone(), document.clone());
+        self.cache_document(document.clone())?;
         
         Ok(document.id)
     }
 
-    pub fn update_document(&self, id: String, title: Option<String>, content: Option<String>) -> Result<(), String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn update_document(&self, id: String, title: Option<String>, content: Option<String>, author: Option<String>) -> Result<()> {
+        let db = self.db.writer()?;
         
         let mut document = db.get_document(&id)
-            .map_err(|e| format!("Failed to get document: {}", e))?
-            .ok_or("Document not found")?;
+            .context("Failed to get document")?
+            .ok_or(AppError::not_found(Entity::Document))?;
 
         if let Some(new_title) = title {
             document.title = new_title;
@@ -95,197 +290,1052 @@ impl StorageService {
         
         if let Some(new_content) = content {
             document.content = new_content;
-            document.word_count = document.content.split_whitespace().count() as i32;
+            document.set_counts(self.segmenter()?.count(&document.content));
         }
         
         document.updated_at = Utc::now();
 
-        db.save_document(&document)
-            .map_err(|e| format!("Failed to update document: {}", e))?;
+        db.save_document_with_revision(&document, author.as_deref())
+            .context("Failed to update document")?;
+        self.prune_revisions(&db, &document.id)?;
 
         // Update cache
-        let mut cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
-        cache.insert(document.id.clone(), document);
+        self.cache_document(document)?;
 
         Ok(())
     }
 
-    pub fn get_document(&self, id: &str) -> Result<Option<Document>, String> {
+    pub fn get_document(&self, id: &str) -> Result<Option<Document>> {
         // Check cache first
-        {
-            let cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
+        let generation = {
+            let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
             if let Some(document) = cache.get(id) {
-                return Ok(Some(document.clone()));
+                return Ok(Some(document));
             }
-        }
+            cache.generation()
+        };
 
         // If not in cache, get from database
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+        let db = self.db.reader()?;
         let document = db.get_document(id)
-            .map_err(|e| format!("Failed to get document: {}", e))?;
+            .context("Failed to get document")?;
 
-        // Update cache if document exists
+        // Update cache if document exists, unless a write changed the cache
+        // meanwhile; what we read may already be stale then
         if let Some(ref doc) = document {
-            let mut cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
-            cache.insert(doc.id.clone(), doc.clone());
+            let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+            cache.insert_if_unchanged(doc.id.clone(), doc.clone(), cached_size(doc), generation);
         }
 
         Ok(document)
     }
 
-    pub fn list_documents(&self) -> Result<Vec<Document>, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn list_documents(&self) -> Result<Vec<Document>> {
+        let db = self.db.reader()?;
         db.list_documents()
-            .map_err(|e| format!("Failed to list documents: {}", e))
+            .context("Failed to list documents")
+    }
+
+    pub fn list_document_summaries(&self, query: &DocumentListQuery) -> Result<DocumentPage> {
+        let db = self.db.reader()?;
+        db.list_document_summaries(query)
+            .context("Failed to list documents")
     }
 
-    pub fn delete_document(&self, id: &str) -> Result<(), String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn delete_document(&self, id: &str) -> Result<()> {
+        let db = self.db.writer()?;
         
         // Create backup before deletion
         if let Ok(Some(document)) = db.get_document(id) {
-            let _ = self.file_handler.create_backup(&document);
+            let _ = self.backup_document(&db, document);
         }
 
         db.delete_document(id)
-            .map_err(|e| format!("Failed to delete document: {}", e))?;
+            .context("Failed to delete document")?;
 
         // Remove from cache
-        let mut cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+        cache.remove(id);
+
+        Ok(())
+    }
+
+    pub fn search_documents(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchHit>> {
+        let segmenter = self.segmenter()?;
+        let query = SearchQuery::parse(query, &segmenter)
+            .map_err(|e| AppError::invalid(e, "搜索语句无效"))?;
+        let db = self.db.reader()?;
+        db.search_documents(&query, limit.unwrap_or(50))
+            .context("Failed to search documents")
+    }
+
+    // Tag operations
+    pub fn add_document_tags(&self, document_id: &str, tags: Vec<String>) -> Result<Vec<String>> {
+        let tags = Self::normalize_tags(tags)?;
+        let db = self.db.writer()?;
+        Self::require_document(&db, document_id)?;
+
+        db.add_document_tags(document_id, &tags)
+            .context("Failed to add tags")?;
+        self.invalidate_cached_document(document_id)?;
+
+        db.get_document_tags(document_id)
+            .context("Failed to get tags")
+    }
+
+    pub fn remove_document_tags(&self, document_id: &str, tags: Vec<String>) -> Result<Vec<String>> {
+        let tags = Self::normalize_tags(tags)?;
+        let db = self.db.writer()?;
+
+        db.remove_document_tags(document_id, &tags)
+            .context("Failed to remove tags")?;
+        self.invalidate_cached_document(document_id)?;
+
+        db.get_document_tags(document_id)
+            .context("Failed to get tags")
+    }
+
+    pub fn list_tags(&self) -> Result<Vec<TagInfo>> {
+        let db = self.db.reader()?;
+        db.list_tags()
+            .context("Failed to list tags")
+    }
+
+    pub fn rename_tag(&self, old_name: &str, new_name: String) -> Result<()> {
+        let new_name = Self::normalize_tags(vec![new_name])?.remove(0);
+        let db = self.db.writer()?;
+
+        db.rename_tag(old_name, &new_name)
+            .context("Failed to rename tag")?;
+        self.clear_document_cache()
+    }
+
+    pub fn merge_tags(&self, sources: Vec<String>, target: String) -> Result<()> {
+        let target = Self::normalize_tags(vec![target])?.remove(0);
+        let db = self.db.writer()?;
+
+        db.merge_tags(&sources, &target)
+            .context("Failed to merge tags")?;
+        self.clear_document_cache()
+    }
+
+    // Metadata operations. A `None` value removes the key.
+    pub fn set_document_metadata(&self, document_id: &str, key: &str, value: Option<MetadataValue>) -> Result<BTreeMap<String, MetadataValue>> {
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(AppError::invalid("Metadata key cannot be empty", "元数据键不能为空"));
+        }
+
+        let db = self.db.writer()?;
+        Self::require_document(&db, document_id)?;
+
+        db.set_document_metadata(document_id, key, value.as_ref())
+            .context("Failed to set metadata")?;
+        self.invalidate_cached_document(document_id)?;
+
+        db.get_document_metadata(document_id)
+            .context("Failed to get metadata")
+    }
+
+    fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>> {
+        let tags: Vec<String> = tags
+            .into_iter()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        if tags.is_empty() {
+            return Err(AppError::invalid("Tag name cannot be empty", "标签名不能为空"));
+        }
+        Ok(tags)
+    }
+
+    fn require_document(db: &Database, id: &str) -> Result<()> {
+        db.get_document(id)
+            .context("Failed to get document")?
+            .ok_or(AppError::not_found(Entity::Document))?;
+        Ok(())
+    }
+
+    fn invalidate_cached_document(&self, id: &str) -> Result<()> {
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
         cache.remove(id);
+        Ok(())
+    }
+
+    // Collection operations
+    pub fn create_collection(&self, name: String, parent_id: Option<String>) -> Result<Collection> {
+        let name = Self::validate_collection_name(&name)?;
+        let db = self.db.writer()?;
+        if let Some(parent_id) = &parent_id {
+            Self::require_collection(&db, parent_id)?;
+        }
+
+        let now = Utc::now();
+        let collection = Collection {
+            id: Uuid::new_v4().to_string(),
+            name,
+            parent_id,
+            created_at: now,
+            updated_at: now,
+        };
+
+        db.save_collection(&collection)
+            .context("Failed to save collection")?;
+        Ok(collection)
+    }
+
+    pub fn list_collections(&self) -> Result<Vec<Collection>> {
+        let db = self.db.reader()?;
+        db.list_collections()
+            .context("Failed to list collections")
+    }
+
+    pub fn rename_collection(&self, id: &str, name: String) -> Result<Collection> {
+        let name = Self::validate_collection_name(&name)?;
+        let db = self.db.writer()?;
+        let mut collection = Self::require_collection(&db, id)?;
+
+        collection.name = name;
+        collection.updated_at = Utc::now();
+        db.save_collection(&collection)
+            .context("Failed to rename collection")?;
+        Ok(collection)
+    }
+
+    pub fn move_collection(&self, id: &str, parent_id: Option<String>) -> Result<Collection> {
+        if id == UNSORTED_COLLECTION_ID {
+            return Err(AppError::conflict(
+                "The Unsorted collection cannot be moved",
+                "“未分类”文集不能移动",
+            ));
+        }
+
+        let db = self.db.writer()?;
+        let mut collection = Self::require_collection(&db, id)?;
+
+        if let Some(parent_id) = &parent_id {
+            Self::require_collection(&db, parent_id)?;
+            let subtree = db.get_collection_subtree(id)
+                .context("Failed to get collection tree")?;
+            if subtree.contains(parent_id) {
+                return Err(AppError::conflict(
+                    "A collection cannot be moved into itself or one of its subcollections",
+                    "文集不能移动到自身或其子文集中",
+                ));
+            }
+        }
+
+        collection.parent_id = parent_id;
+        collection.updated_at = Utc::now();
+        db.save_collection(&collection)
+            .context("Failed to move collection")?;
+        Ok(collection)
+    }
+
+    // Returns the ids of documents that were deleted along with the collection
+    pub fn delete_collection(&self, id: &str, mode: CollectionDeleteMode) -> Result<Vec<String>> {
+        if id == UNSORTED_COLLECTION_ID {
+            return Err(AppError::conflict(
+                "The Unsorted collection cannot be deleted",
+                "“未分类”文集不能删除",
+            ));
+        }
+
+        let db = self.db.writer()?;
+        Self::require_collection(&db, id)?;
+
+        // Back up documents before a cascading delete, as delete_document does
+        if let CollectionDeleteMode::Cascade = mode {
+            let document_ids = db.get_collection_document_ids(id)
+                .context("Failed to list documents")?;
+            for document_id in document_ids {
+                if let Ok(Some(document)) = db.get_document(&document_id) {
+                    let _ = self.backup_document(&db, document);
+                }
+            }
+        }
+
+        let deleted = db.delete_collection(id, mode)
+            .context("Failed to delete collection")?;
+
+        // Documents may have been deleted or moved; drop them from the cache
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+        cache.clear();
+
+        Ok(deleted)
+    }
+
+    pub fn move_document(&self, document_id: &str, collection_id: &str) -> Result<()> {
+        let db = self.db.writer()?;
+        Self::require_collection(&db, collection_id)?;
+
+        let moved = db.move_document_to_collection(document_id, collection_id)
+            .context("Failed to move document")?;
+        if !moved {
+            return Err(AppError::not_found_id(Entity::Document, document_id));
+        }
+
+        self.invalidate_cached_document(document_id)
+    }
+
+    pub fn get_collection_stats(&self, id: &str) -> Result<CollectionStats> {
+        let db = self.db.reader()?;
+        Self::require_collection(&db, id)?;
+        db.get_collection_stats(id)
+            .context("Failed to get collection stats")
+    }
+
+    fn require_collection(db: &Database, id: &str) -> Result<Collection> {
+        db.get_collection(id)
+            .context("Failed to get collection")?
+            .ok_or_else(|| AppError::not_found_id(Entity::Collection, id))
+    }
+
+    fn validate_collection_name(name: &str) -> Result<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AppError::invalid("Collection name cannot be empty", "文集名称不能为空"));
+        }
+        Ok(name.to_string())
+    }
+
+    // Revision history operations
+    pub fn list_revisions(&self, document_id: &str) -> Result<Vec<RevisionSummary>> {
+        let db = self.db.reader()?;
+        db.list_revisions(document_id)
+            .context("Failed to list revisions")
+    }
+
+    pub fn get_revision(&self, revision_id: &str) -> Result<Option<DocumentRevision>> {
+        let db = self.db.reader()?;
+        db.get_revision(revision_id)
+            .context("Failed to get revision")
+    }
+
+    pub fn diff_revisions(&self, from_revision_id: &str, to_revision_id: &str, granularity: Option<&str>) -> Result<TextDiff> {
+        let granularity = DiffGranularity::parse(granularity)
+            .map_err(|e| AppError::invalid(e, "不支持的比较粒度"))?;
+        let from = self.get_revision(from_revision_id)?
+            .ok_or(AppError::not_found(Entity::Revision))?;
+        let to = self.get_revision(to_revision_id)?
+            .ok_or(AppError::not_found(Entity::Revision))?;
+
+        if from.document_id != to.document_id {
+            return Err(AppError::conflict(
+                "Revisions belong to different documents",
+                "这两个修订版本属于不同的文档",
+            ));
+        }
+
+        Ok(diff::diff_text(&from.content, &to.content, granularity))
+    }
 
+    // Makes a past revision the current content. The restore is itself
+    // recorded as a new revision, so it can be undone the same way.
+    pub fn restore_revision(&self, revision_id: &str, author: Option<String>) -> Result<Document> {
+        let db = self.db.writer()?;
+
+        let revision = db.get_revision(revision_id)
+            .context("Failed to get revision")?
+            .ok_or(AppError::not_found(Entity::Revision))?;
+        let mut document = db.get_document(&revision.document_id)
+            .context("Failed to get document")?
+            .ok_or(AppError::not_found(Entity::Document))?;
+
+        document.title = revision.title;
+        document.content = revision.content;
+        document.set_counts(self.segmenter()?.count(&document.content));
+        document.updated_at = Utc::now();
+
+        db.save_document_with_revision(&document, author.as_deref())
+            .context("Failed to restore revision")?;
+        self.prune_revisions(&db, &document.id)?;
+
+        self.cache_document(document.clone())?;
+
+        Ok(document)
+    }
+
+    fn prune_revisions(&self, db: &Database, document_id: &str) -> Result<()> {
+        let config = self.config()?;
+        let older_than = match config.revision_retention_days {
+            0 => None,
+            days => Some(Utc::now() - Duration::days(days as i64)),
+        };
+
+        db.prune_revisions(document_id, config.max_revisions, older_than)
+            .context("Failed to prune revisions")?;
         Ok(())
     }
 
     // File operations
-    pub fn import_document(&self, file_path: &str) -> Result<ImportResult, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
-        self.file_handler.import_document(file_path, &*db)
+    // The file is read and parsed before the writer is taken, so a large
+    // import does not hold up other writes
+    pub fn import_document(&self, file_path: &str) -> Result<ImportResult> {
+        let segmenter = self.segmenter()?;
+        let document = self.file_handler.prepare_import(file_path, &segmenter)?;
+        self.db.writer()?
+            .save_document_with_revision(&document, None)
+            .context("Failed to save document")?;
+        self.invalidate_cached_document(&document.id)?;
+
+        // Failures are errors; an ImportResult always describes a success
+        Ok(ImportResult {
+            success: true,
+            document_id: Some(document.id),
+            message: "Document imported successfully".to_string(),
+        })
+    }
+
+    // The importer shares the database handle but not the storage lock
+    pub fn directory_importer(&self, options: DirectoryImportOptions) -> Result<DirectoryImporter> {
+        DirectoryImporter::new(self.db.clone(), self.segmenter()?, options)
     }
 
-    pub fn export_document(&self, id: &str, export_path: &str) -> Result<(), String> {
+    pub fn export_document(&self, id: &str, export_path: &str, options: Option<ExportOptions>) -> Result<()> {
         let document = self.get_document(id)?
-            .ok_or("Document not found")?;
+            .ok_or(AppError::not_found(Entity::Document))?;
         
-        self.file_handler.export_document(&document, export_path)
+        self.file_handler.export_document(&document, export_path, &options.unwrap_or_default())
+    }
+
+    // Chapters come from `document_ids` in the given order or, when none are
+    // given, from a collection and its subcollections in creation order.
+    pub fn export_epub(
+        &self,
+        document_ids: Vec<String>,
+        collection_id: Option<String>,
+        metadata: EpubMetadata,
+        export_path: &str,
+    ) -> Result<()> {
+        let from_collection = document_ids.is_empty();
+        let ids = if !from_collection {
+            document_ids
+        } else if let Some(collection_id) = collection_id {
+            let db = self.db.reader()?;
+            Self::require_collection(&db, &collection_id)?;
+            db.get_collection_document_ids(&collection_id)
+                .context("Failed to get collection documents")?
+        } else {
+            return Err(AppError::invalid("Either document ids or a collection must be given", "请指定文档或文集"));
+        };
+
+        let mut documents = Vec::with_capacity(ids.len());
+        for id in &ids {
+            let document = self.get_document(id)?
+                .ok_or_else(|| AppError::not_found_id(Entity::Document, id.as_str()))?;
+            documents.push(document);
+        }
+        if from_collection {
+            documents.sort_by_key(|document| document.created_at);
+        }
+
+        self.file_handler.export_epub(&documents, &metadata, export_path)
     }
 
     // Semantic analysis operations
-    pub fn save_semantic_terms(&self, document_id: &str, terms: Vec<SemanticTerm>) -> Result<(), String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn save_semantic_terms(&self, document_id: &str, terms: Vec<SemanticTerm>) -> Result<()> {
+        let db = self.db.writer()?;
         db.save_semantic_terms(&terms)
-            .map_err(|e| format!("Failed to save semantic terms: {}", e))
+            .context("Failed to save semantic terms")
     }
 
-    pub fn get_semantic_terms(&self, document_id: &str) -> Result<Vec<SemanticTerm>, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn get_semantic_terms(&self, document_id: &str) -> Result<Vec<SemanticTerm>> {
+        let db = self.db.reader()?;
         db.get_semantic_terms(document_id)
-            .map_err(|e| format!("Failed to get semantic terms: {}", e))
+            .context("Failed to get semantic terms")
+    }
+
+    // Extracts terms from the current content, stores them as the document's
+    // semantic terms and caches the result by content hash. Unchanged text is
+    // answered from the cache, even if the rest of the library has changed.
+    pub fn analyze_document(&self, id: &str) -> Result<DocumentAnalysis> {
+        let document = self.get_document(id)?
+            .ok_or_else(|| AppError::not_found_id(Entity::Document, id))?;
+        let hash = content_hash(&document.content);
+        let cache_key = format!("{}{}", analysis::CACHE_KEY_PREFIX, hash);
+
+        let analysis = {
+            let db = self.db.reader()?;
+            let cached = db.get_analysis_cache(id, &cache_key)
+                .context("Failed to get analysis cache")?;
+            if let Some(cached) = cached {
+                match serde_json::from_str::<DocumentAnalysis>(&cached.analysis_result) {
+                    Ok(mut analysis) => {
+                        analysis.cached = true;
+                        return Ok(analysis);
+                    }
+                    Err(e) => eprintln!("Ignoring unreadable analysis cache: {}", e),
+                }
+            }
+
+            let totals = db.get_document_totals()
+                .context("Failed to get document totals")?;
+            let segmenter = self.segmenter()?;
+            analysis::analyze(&segmenter, id, &document.content, &hash, totals.total_documents.saturating_sub(1), |terms| {
+                db.count_documents_containing(terms, id)
+                    .context("Failed to count documents")
+            })?
+        };
+
+        let db = self.db.writer()?;
+        db.replace_semantic_terms(id, &analysis.semantic_terms())
+            .context("Failed to save semantic terms")?;
+        db.delete_analysis_caches(id, analysis::CACHE_KEY_PREFIX)
+            .context("Failed to clear analysis cache")?;
+        db.save_analysis_cache(&AnalysisCache {
+            id: Uuid::new_v4().to_string(),
+            document_id: id.to_string(),
+            content_hash: cache_key,
+            analysis_result: serde_json::to_string(&analysis).context("Failed to serialize analysis")?,
+            created_at: analysis.analyzed_at,
+        })
+        .context("Failed to save analysis cache")?;
+
+        Ok(analysis)
+    }
+
+    // Reports where the document uses an alternative form from the active
+    // consistency rules scoped to it. Reports are cached by content hash
+    // together with the rules and options, so re-checking unchanged text is
+    // free.
+    pub fn check_consistency(&self, id: &str, options: CheckOptions) -> Result<ConsistencyReport> {
+        let document = self.get_document(id)?
+            .ok_or_else(|| AppError::not_found_id(Entity::Document, id))?;
+        let rules = self.get_document_rules(&document)?;
+        let hash = content_hash(&document.content);
+        let checked_against = serde_json::to_string(&(&rules, &options))
+            .context("Failed to serialize consistency rules")?;
+        let cache_key = format!("{}{}:{}", consistency::CACHE_KEY_PREFIX, hash, content_hash(&checked_against));
+
+        {
+            let db = self.db.reader()?;
+            let cached = db.get_analysis_cache(id, &cache_key)
+                .context("Failed to get analysis cache")?;
+            if let Some(cached) = cached {
+                match serde_json::from_str::<ConsistencyReport>(&cached.analysis_result) {
+                    Ok(mut report) => {
+                        report.cached = true;
+                        return Ok(report);
+                    }
+                    Err(e) => eprintln!("Ignoring unreadable consistency cache: {}", e),
+                }
+            }
+        }
+
+        let segmenter = self.segmenter()?;
+        let report = consistency::check(&segmenter, id, &document.content, &hash, &rules, options);
+
+        let db = self.db.writer()?;
+        db.delete_analysis_caches(id, consistency::CACHE_KEY_PREFIX)
+            .context("Failed to clear analysis cache")?;
+        db.save_analysis_cache(&AnalysisCache {
+            id: Uuid::new_v4().to_string(),
+            document_id: id.to_string(),
+            content_hash: cache_key,
+            analysis_result: serde_json::to_string(&report).context("Failed to serialize consistency report")?,
+            created_at: report.checked_at,
+        })
+        .context("Failed to save analysis cache")?;
+
+        Ok(report)
+    }
+
+    // Plans fixes for `issues`, typically from an earlier report, or for
+    // every issue a fresh check finds, limited to `rule_ids` when given
+    pub fn plan_fixes(
+        &self,
+        id: &str,
+        rule_ids: Option<Vec<String>>,
+        issues: Option<Vec<ConsistencyIssue>>,
+        options: CheckOptions,
+    ) -> Result<FixPlan> {
+        let document = self.get_document(id)?
+            .ok_or_else(|| AppError::not_found_id(Entity::Document, id))?;
+        let issues = match issues {
+            Some(issues) => issues,
+            None => self.check_consistency(id, options)?.issues,
+        };
+        let issues = match rule_ids {
+            Some(rule_ids) => issues.into_iter().filter(|issue| rule_ids.contains(&issue.rule_id)).collect(),
+            None => issues,
+        };
+        Ok(fixes::plan(id, &document.content, &content_hash(&document.content), issues))
+    }
+
+    // Applies the selected edits of a plan in one revision. Fails without
+    // changing anything if the document changed since `expected_hash` or
+    // any edit no longer applies.
+    pub fn apply_fixes(
+        &self,
+        id: &str,
+        expected_hash: &str,
+        edits: Vec<FixEdit>,
+        author: Option<String>,
+    ) -> Result<AppliedFixes> {
+        if edits.is_empty() {
+            return Err(AppError::invalid("No fixes selected", "未选择任何修正"));
+        }
+        let db = self.db.writer()?;
+
+        let mut document = db.get_document(id)
+            .context("Failed to get document")?
+            .ok_or_else(|| AppError::not_found_id(Entity::Document, id))?;
+        if content_hash(&document.content) != expected_hash {
+            return Err(AppError::conflict(
+                "The document has changed since the fixes were planned",
+                "规划修正后文档已被修改",
+            ));
+        }
+
+        document.content = fixes::apply(&document.content, &edits)?;
+        document.set_counts(self.segmenter()?.count(&document.content));
+        document.updated_at = Utc::now();
+
+        let revision = db.save_document_with_revision(&document, author.as_deref())
+            .context("Failed to apply fixes")?;
+        self.prune_revisions(&db, &document.id)?;
+
+        let applied = AppliedFixes {
+            document_id: document.id.clone(),
+            content_hash: content_hash(&document.content),
+            content: document.content.clone(),
+            revision_id: revision.id,
+            revision_number: revision.revision_number,
+            applied: edits.len(),
+        };
+        self.cache_document(document)?;
+        Ok(applied)
+    }
+
+    // Active rules whose scope covers the document
+    fn get_document_rules(&self, document: &Document) -> Result<Vec<ConsistencyRule>> {
+        let db = self.db.reader()?;
+        let mut collections = HashSet::new();
+        let mut next = Some(document.collection_id.clone());
+        while let Some(id) = next {
+            if !collections.insert(id.clone()) {
+                break;
+            }
+            next = db.get_collection(&id)
+                .context("Failed to get collection")?
+                .and_then(|collection| collection.parent_id);
+        }
+
+        let rules = db.get_consistency_rules()
+            .context("Failed to get consistency rules")?;
+        Ok(rules
+            .into_iter()
+            .filter(|rule| consistency::in_scope(&rule.scope, &document.id, &collections))
+            .collect())
     }
 
     // Consistency rules operations
-    pub fn save_consistency_rule(&self, rule: ConsistencyRule) -> Result<(), String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
-        db.save_consistency_rule(&rule)
-            .map_err(|e| format!("Failed to save consistency rule: {}", e))
+    pub fn save_consistency_rule(&self, rule: ConsistencyRule) -> Result<()> {
+        consistency::validate(&rule)?;
+        {
+            let db = self.db.writer()?;
+            match &rule.scope {
+                RuleScope::Global => {}
+                RuleScope::Collection(id) => {
+                    Self::require_collection(&db, id)?;
+                }
+                RuleScope::Document(id) => {
+                    db.get_document(id)
+                        .context("Failed to get document")?
+                        .ok_or_else(|| AppError::not_found_id(Entity::Document, id))?;
+                }
+            }
+            db.save_consistency_rule(&rule)
+                .context("Failed to save consistency rule")?;
+        }
+        self.load_user_words()
     }
 
-    pub fn get_consistency_rules(&self) -> Result<Vec<ConsistencyRule>, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn get_consistency_rules(&self) -> Result<Vec<ConsistencyRule>> {
+        let db = self.db.reader()?;
         db.get_consistency_rules()
-            .map_err(|e| format!("Failed to get consistency rules: {}", e))
+            .context("Failed to get consistency rules")
     }
 
     // Analysis cache operations
-    pub fn save_analysis_cache(&self, cache: AnalysisCache) -> Result<(), String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn save_analysis_cache(&self, cache: AnalysisCache) -> Result<()> {
+        let db = self.db.writer()?;
         db.save_analysis_cache(&cache)
-            .map_err(|e| format!("Failed to save analysis cache: {}", e))
+            .context("Failed to save analysis cache")
     }
 
-    pub fn get_analysis_cache(&self, document_id: &str, content_hash: &str) -> Result<Option<AnalysisCache>, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
+    pub fn get_analysis_cache(&self, document_id: &str, content_hash: &str) -> Result<Option<AnalysisCache>> {
+        let db = self.db.reader()?;
         db.get_analysis_cache(document_id, content_hash)
-            .map_err(|e| format!("Failed to get analysis cache: {}", e))
+            .context("Failed to get analysis cache")
     }
 
     // Backup operations
-    pub fn create_backup(&self, document_id: &str) -> Result<String, String> {
-        let document = self.get_document(document_id)?
-            .ok_or("Document not found")?;
-        
-        self.file_handler.create_backup(&document)
+    pub fn create_backup(&self, document_id: &str) -> Result<String> {
+        let db = self.db.reader()?;
+        let document = db.get_document(document_id)
+            .context("Failed to get document")?
+            .ok_or(AppError::not_found(Entity::Document))?;
+
+        self.backup_document(&db, document)
+    }
+
+    fn backup_document(&self, db: &Database, document: Document) -> Result<String> {
+        let semantic_terms = db.get_semantic_terms(&document.id)
+            .context("Failed to get semantic terms")?;
+        let analysis_cache = db.get_analysis_caches(&document.id)
+            .context("Failed to get analysis cache")?;
+
+        let max_backups = self.config()?.max_backups;
+        self.file_handler.create_backup(&DocumentBackup {
+            document,
+            semantic_terms,
+            analysis_cache,
+        }, max_backups)
     }
 
-    pub fn list_backups(&self) -> Result<Vec<crate::file_handler::FileInfo>, String> {
+    pub fn list_backups(&self) -> Result<Vec<crate::file_handler::FileInfo>> {
         self.file_handler.list_backups()
     }
 
-    pub fn restore_from_backup(&self, backup_path: &str) -> Result<ImportResult, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
-        self.file_handler.restore_from_backup(backup_path, &*db)
+    // Restores as a new document unless the original id is requested; an
+    // existing document with that id is replaced, keeping its history.
+    pub fn restore_from_backup(&self, backup_path: &str, options: Option<RestoreOptions>) -> Result<ImportResult> {
+        let options = options.unwrap_or_default();
+        let backup = self.file_handler.read_backup(backup_path)?;
+        let mut document = backup.document;
+        // Backups from older versions carry no per-script counts
+        document.set_counts(self.segmenter()?.count(&document.content));
+        let mut semantic_terms = backup.semantic_terms;
+        let mut analysis_cache = backup.analysis_cache;
+
+        let db = self.db.writer()?;
+
+        if options.restore_original_id {
+            let existing = db.get_document(&document.id)
+                .context("Failed to get document")?;
+            if existing.is_some() {
+                document.updated_at = Utc::now();
+            }
+        } else {
+            document.id = Uuid::new_v4().to_string();
+            for term in &mut semantic_terms {
+                term.id = Uuid::new_v4().to_string();
+                term.document_id = document.id.clone();
+            }
+            for cache in &mut analysis_cache {
+                cache.id = Uuid::new_v4().to_string();
+                cache.document_id = document.id.clone();
+            }
+        }
+
+        // The original collection may have been deleted since
+        let collection_exists = db.get_collection(&document.collection_id)
+            .context("Failed to get collection")?
+            .is_some();
+        if !collection_exists {
+            document.collection_id = UNSORTED_COLLECTION_ID.to_string();
+        }
+
+        db.restore_document(&document, &semantic_terms, &analysis_cache)
+            .context("Failed to restore document")?;
+
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+        cache.remove(&document.id);
+        Ok(ImportResult {
+            success: true,
+            document_id: Some(document.id),
+            message: "Document restored successfully".to_string(),
+        })
+    }
+
+    // Library snapshots
+    pub fn create_snapshot(&self) -> Result<SnapshotInfo> {
+        self.take_snapshot(SnapshotTrigger::Manual)
+    }
+
+    // Called periodically by the scheduler; returns the snapshot when one was due
+    pub fn run_scheduled_snapshot(&self) -> Result<Option<SnapshotInfo>> {
+        let latest = self.snapshots.latest()?;
+        let interval_hours = self.config()?.snapshot_interval_hours;
+        if !snapshot::snapshot_due(latest.as_ref(), interval_hours, Utc::now()) {
+            return Ok(None);
+        }
+        self.take_snapshot(SnapshotTrigger::Scheduled).map(Some)
+    }
+
+    fn take_snapshot(&self, trigger: SnapshotTrigger) -> Result<SnapshotInfo> {
+        let info = {
+            let db = self.db.reader()?;
+            self.snapshots.create(&db, trigger)?
+        };
+        let config = self.config()?;
+        self.snapshots.apply_retention(&config.snapshot_retention, config.max_snapshots)?;
+        Ok(info)
+    }
+
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
+        self.snapshots.list()
+    }
+
+    pub fn verify_snapshot(&self, id: &str) -> Result<SnapshotVerification> {
+        self.snapshots.verify(id)
+    }
+
+    pub fn restore_snapshot(&self, id: &str) -> Result<SnapshotRestoreResult> {
+        let result = {
+            let mut db = self.db.writer()?;
+            self.snapshots.restore(&mut db, id)?
+        };
+
+        // The snapshot may predate segmentation or hold other rules
+        self.load_user_words()?;
+        self.recount_documents()?;
+
+        // Every cached document may be stale now
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+        cache.clear();
+        Ok(result)
     }
 
     // Configuration
-    pub fn get_config(&self) -> &StorageConfig {
-        &self.config
+    pub fn get_config(&self) -> Result<StorageConfig> {
+        Ok(self.config()?.clone())
     }
 
-    pub fn update_config(&mut self, new_config: StorageConfig) {
-        self.config = new_config;
+    fn config(&self) -> Result<RwLockReadGuard<'_, StorageConfig>> {
+        self.config.read().map_err(|_| AppError::lock("config"))
+    }
+
+    // Validates and persists the new settings, then applies them; the app
+    // data directory cannot be changed this way
+    pub fn update_config(&self, mut new_config: StorageConfig) -> Result<StorageConfig> {
+        new_config.validate()?;
+        let mut config = self.config.write().map_err(|_| AppError::lock("config"))?;
+        new_config.app_data_dir = config.app_data_dir.clone();
+        new_config.save(&self.file_handler.get_config_path())?;
+        *config = new_config;
+        self.file_handler.set_keep_backups(config.keep_backup_files);
+
+        // A smaller cache takes effect right away; revision and snapshot
+        // limits apply the next time those run
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+        cache.set_capacity(config.cache_capacity_bytes());
+        Ok(config.clone())
     }
 
     // Cache management
-    pub fn clear_document_cache(&self) -> Result<(), String> {
-        let mut cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
+    pub fn clear_document_cache(&self) -> Result<()> {
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
         cache.clear();
         Ok(())
     }
 
-    pub fn get_cache_size(&self) -> Result<usize, String> {
-        let cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
+    // Least recently used documents are evicted once the cache outgrows
+    // cache_size_limit
+    fn cache_document(&self, document: Document) -> Result<()> {
+        let mut cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
+        let size = cached_size(&document);
+        cache.insert(document.id.clone(), document, size);
+        Ok(())
+    }
+
+    pub fn get_cache_size(&self) -> Result<usize> {
+        let cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?;
         Ok(cache.len())
     }
 
     // Utility functions
     pub fn calculate_content_hash(&self, content: &str) -> String {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        
-        let mut hasher = DefaultHasher::new();
-        content.hash(&mut hasher);
-        format!("{:x}", hasher.finish())
+        content_hash(content)
     }
 
-    pub fn get_storage_stats(&self) -> Result<StorageStats, String> {
-        let db = self.db.lock().map_err(|_| "Failed to acquire database lock")?;
-        let documents = db.list_documents()
-            .map_err(|e| format!("Failed to get documents: {}", e))?;
-
-        let total_documents = documents.len();
-        let total_words = documents.iter().map(|d| d.word_count as usize).sum();
-        let total_characters = documents.iter().map(|d| d.content.len()).sum();
+    pub fn get_storage_stats(&self) -> Result<StorageStats> {
+        let db = self.db.reader()?;
+        let totals = db.get_document_totals()
+            .context("Failed to get document totals")?;
 
-        let cache = self.document_cache.lock().map_err(|_| "Failed to acquire cache lock")?;
-        let cached_documents = cache.len();
+        let cache = self.document_cache.lock().map_err(|_| AppError::lock("cache"))?.stats();
 
         Ok(StorageStats {
-            total_documents,
-            total_words,
-            total_characters,
-            cached_documents,
+            total_documents: totals.total_documents,
+            total_words: totals.total_words,
+            total_characters: totals.total_characters,
+            cached_documents: cache.entries,
+            cache_bytes: cache.bytes,
+            cache_capacity_bytes: cache.capacity_bytes,
+            cache_hits: cache.hits,
+            cache_misses: cache.misses,
+            cache_evictions: cache.evictions,
             database_path: self.file_handler.get_database_path().to_string_lossy().to_string(),
-            app_data_dir: self.config.app_data_dir.clone(),
+            app_data_dir: self.config()?.app_data_dir.clone(),
         })
     }
 }
 
+// Approximate memory held by a cached document, including its strings
+fn cached_size(document: &Document) -> usize {
+    let metadata: usize = document
+        .metadata
+        .iter()
+        .map(|(key, value)| {
+            let text = match value {
+                MetadataValue::Text(text) => text.len(),
+                _ => 0,
+            };
+            key.len() + text + std::mem::size_of::<MetadataValue>()
+        })
+        .sum();
+    std::mem::size_of::<Document>()
+        + document.id.len()
+        + document.title.len()
+        + document.content.len()
+        + document.file_path.as_ref().map_or(0, |path| path.len())
+        + document.collection_id.len()
+        + document.tags.iter().map(|tag| tag.len() + std::mem::size_of::<String>()).sum::<usize>()
+        + metadata
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StorageStats {
     pub total_documents: usize,
     pub total_words: usize,
     pub total_characters: usize,
     pub cached_documents: usize,
+    pub cache_bytes: usize,
+    pub cache_capacity_bytes: usize,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub cache_evictions: u64,
     pub database_path: String,
     pub app_data_dir: String,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixture {
+        dir: PathBuf,
+        storage: StorageService,
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn fixture(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("storage-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let storage = StorageService::new(dir.clone()).unwrap();
+        Fixture { dir, storage }
+    }
+
+    fn is_conflict(result: Result<impl std::fmt::Debug>) -> bool {
+        matches!(result, Err(AppError::Conflict { .. }))
+    }
+
+    #[test]
+    fn keeps_collections_a_tree() {
+        let fixture = fixture("collections");
+        let storage = &fixture.storage;
+        let book = storage.create_collection(" Book ".to_string(), None).unwrap();
+        assert_eq!(book.name, "Book");
+        let part = storage.create_collection("Part".to_string(), Some(book.id.clone())).unwrap();
+
+        assert!(is_conflict(storage.move_collection(&book.id, Some(part.id.clone()))));
+        assert!(is_conflict(storage.move_collection(&book.id, Some(book.id.clone()))));
+        let moved = storage.move_collection(&part.id, None).unwrap();
+        assert_eq!(moved.parent_id, None);
+
+        assert!(is_conflict(storage.move_collection(UNSORTED_COLLECTION_ID, Some(book.id.clone()))));
+        assert!(is_conflict(storage.delete_collection(UNSORTED_COLLECTION_ID, CollectionDeleteMode::Cascade)));
+        assert!(storage.create_collection("  ".to_string(), None).is_err());
+        assert!(storage.create_collection("Orphan".to_string(), Some("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn files_documents_into_existing_collections() {
+        let fixture = fixture("filing");
+        let storage = &fixture.storage;
+        let book = storage.create_collection("Book".to_string(), None).unwrap();
+        let id = storage.create_document("Draft".to_string(), "text".to_string(), None).unwrap();
+        assert_eq!(storage.get_document(&id).unwrap().unwrap().collection_id, UNSORTED_COLLECTION_ID);
+
+        storage.move_document(&id, &book.id).unwrap();
+        // The cached copy is dropped on move
+        assert_eq!(storage.get_document(&id).unwrap().unwrap().collection_id, book.id);
+        assert!(storage.move_document(&id, "missing").is_err());
+        assert!(storage.move_document("missing", &book.id).is_err());
+        assert!(storage.create_document("X".to_string(), String::new(), Some("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn validates_tags_and_metadata_keys() {
+        let fixture = fixture("tags");
+        let storage = &fixture.storage;
+        let id = storage.create_document("Draft".to_string(), String::new(), None).unwrap();
+
+        let tags = storage.add_document_tags(&id, vec![" draft ".to_string(), String::new()]).unwrap();
+        assert_eq!(tags, vec!["draft"]);
+        assert!(storage.add_document_tags(&id, vec!["  ".to_string()]).is_err());
+        assert!(storage.add_document_tags("missing", vec!["draft".to_string()]).is_err());
+        assert_eq!(storage.get_document(&id).unwrap().unwrap().tags, vec!["draft"]);
+
+        let metadata = storage.set_document_metadata(&id, " status ", Some(MetadataValue::Boolean(true))).unwrap();
+        assert_eq!(metadata.get("status"), Some(&MetadataValue::Boolean(true)));
+        assert!(storage.set_document_metadata(&id, " ", Some(MetadataValue::Boolean(true))).is_err());
+        assert!(storage.set_document_metadata("missing", "status", None).is_err());
+        assert_eq!(storage.get_document(&id).unwrap().unwrap().metadata, metadata);
+    }
+
+    #[test]
+    fn skips_files_already_in_the_library() {
+        let fixture = fixture("import-duplicates");
+        let storage = &fixture.storage;
+        let existing = storage.create_document("Existing".to_string(), "same text".to_string(), None).unwrap();
+
+        let root = fixture.dir.join("import");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "same text").unwrap();
+        fs::write(root.join("b.txt"), "other text").unwrap();
+        fs::write(root.join("c.txt"), "other text").unwrap();
+
+        let importer = storage.directory_importer(DirectoryImportOptions::default()).unwrap();
+        let report = importer.run(&root.to_string_lossy(), |_| {}).unwrap();
+        assert_eq!((report.imported, report.duplicates), (1, 2));
+        assert_eq!(report.files[0].document_id, Some(existing));
+        assert_eq!(report.files[2].document_id, report.files[1].document_id);
+    }
+
+    #[test]
+    fn imports_a_single_file() {
+        let fixture = fixture("import-file");
+        let path = fixture.dir.join("chapter.md");
+        fs::write(&path, "Body text").unwrap();
+
+        let result = fixture.storage.import_document(&path.to_string_lossy()).unwrap();
+        let document = fixture.storage.get_document(&result.document_id.unwrap()).unwrap().unwrap();
+        assert_eq!((document.title.as_str(), document.content.as_str()), ("chapter", "Body text"));
+
+        let missing = fixture.dir.join("missing.md");
+        assert_eq!(fixture.storage.import_document(&missing.to_string_lossy()).unwrap_err().code(), "FILE_NOT_FOUND");
+        assert_eq!(fixture.storage.list_documents().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn limits_backups_and_snapshots_separately() {
+        let mut config = StorageConfig::default();
+        assert_eq!((config.max_backups, config.max_snapshots), (10, 40));
+        config.max_snapshots = 1001;
+        assert!(config.validate().is_err());
+        config.max_snapshots = 0;
+        config.max_backups = 1001;
+        assert!(config.validate().is_err());
+        config.max_backups = 1000;
+        assert!(config.validate().is_ok());
+    }
+}
@@ -5,6 +5,7 @@ import { RichTextEditor } from './RichTextEditor';
 import { useSemanticAnalysis } from '../../hooks/useSemanticAnalysis';
 import { useAutoSave } from '../../hooks/useAutoSave';
 import { workerManager } from '../../utils/workerManager';
+import { tauriService } from '../../services/tauriService';
 import type { Document } from '../../types';
 import { EditorToolbar } from './EditorToolbar';
 
@@ -17,6 +18,8 @@ const MainEditor: React.FC = () => {
     settings,
     loadDocuments,
     loadStorageStats,
+    loadStorageConfig,
+    setStorageConfig,
     createTauriDocument,
     setSemanticTerms,
     setConsistencyIssues,
@@ -38,13 +41,22 @@ const MainEditor: React.FC = () => {
       try {
         await loadDocuments();
         await loadStorageStats();
+        await loadStorageConfig();
       } catch (error) {
         console.error('Failed to load initial data:', error);
       }
     };
 
     loadInitialData();
-  }, [loadDocuments, loadStorageStats]);
+  }, [loadDocuments, loadStorageStats, loadStorageConfig]);
+
+  // Keep settings in sync when they are changed elsewhere
+  useEffect(() => {
+    const unlisten = tauriService.onStorageConfigChanged(setStorageConfig);
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, [setStorageConfig]);
 
   // Create new document if none exists
   useEffect(() => {
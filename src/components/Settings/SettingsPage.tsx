@@ -237,6 +237,20 @@ const SettingsPage: React.FC = () => {
                     </div>
                     <div className="text-sm text-gray-500 dark:text-gray-400">缓存文档</div>
                   </div>
+                  <div className="text-center p-4 bg-gray-50 dark:bg-dark-700 rounded-lg">
+                    <div className="text-lg font-semibold text-gray-900 dark:text-gray-100">
+                      {(storageStats.cache_bytes / 1024 / 1024).toFixed(1)} / {(storageStats.cache_capacity_bytes / 1024 / 1024).toFixed(0)} MB
+                    </div>
+                    <div className="text-sm text-gray-500 dark:text-gray-400">缓存占用</div>
+                  </div>
+                  <div className="text-center p-4 bg-gray-50 dark:bg-dark-700 rounded-lg">
+                    <div className="text-lg font-semibold text-gray-900 dark:text-gray-100">
+                      {storageStats.cache_hits + storageStats.cache_misses > 0
+                        ? `${Math.round((storageStats.cache_hits / (storageStats.cache_hits + storageStats.cache_misses)) * 100)}%`
+                        : '-'}
+                    </div>
+                    <div className="text-sm text-gray-500 dark:text-gray-400">缓存命中率</div>
+                  </div>
                 </div>
               )}
               
@@ -2,7 +2,9 @@ import { useEffect, useRef } from 'react';
 import { useAppStore } from '../stores/appStore';
 
 export const useAutoSave = (content: string, title: string) => {
-  const { currentDocument, updateDocument, settings } = useAppStore();
+  const { currentDocument, updateDocument, settings, storageConfig } = useAppStore();
+  // Seconds of inactivity before saving, from the storage settings
+  const interval = (storageConfig?.auto_save_interval ?? 30) * 1000;
   const timeoutRef = useRef<NodeJS.Timeout>();
 
   useEffect(() => {
@@ -23,12 +25,12 @@ export const useAutoSave = (content: string, title: string) => {
         });
         console.log('Document auto-saved');
       }
-    }, 2000); // Auto-save after 2 seconds of inactivity
+    }, interval);
 
     return () => {
       if (timeoutRef.current) {
         clearTimeout(timeoutRef.current);
       }
     };
-  }, [content, title, currentDocument?.id, currentDocument?.content, currentDocument?.title, updateDocument, settings.autoSave]);
+  }, [content, title, currentDocument?.id, currentDocument?.content, currentDocument?.title, updateDocument, settings.autoSave, interval]);
 };
\ No newline at end of file
@@ -18,12 +18,16 @@ export const useSemanticAnalysis = () => {
     setIsAnalyzing(true);
     
     try {
-      // Extract semantic terms
-      const terms = await semanticEngine.extractTerms(content);
+      // Extract semantic terms, natively once the document is stored
+      const terms = currentDocument
+        ? await semanticEngine.extractDocumentTerms(currentDocument.id, content)
+        : await semanticEngine.extractTerms(content);
       setSemanticTerms(terms);
 
-      // Detect consistency issues
-      const issues = await semanticEngine.detectInconsistencies(terms, content);
+      // Detect consistency issues, including the user's rules once stored
+      const issues = currentDocument
+        ? await semanticEngine.detectDocumentInconsistencies(currentDocument.id, terms, content)
+        : await semanticEngine.detectInconsistencies(terms, content);
       setConsistencyIssues(issues);
 
       // Generate suggestions
@@ -40,7 +44,7 @@ export const useSemanticAnalysis = () => {
     } finally {
       setIsAnalyzing(false);
     }
-  }, [isAnalyzing, setSemanticTerms, setConsistencyIssues, setSuggestions]);
+  }, [isAnalyzing, currentDocument, setSemanticTerms, setConsistencyIssues, setSuggestions]);
 
   return {
     analyze,
@@ -1,4 +1,6 @@
 import type { SemanticTerm, ConsistencyIssue, Suggestion, TermPosition } from '../types';
+import type { ConsistencyReport, DocumentAnalysis } from '../types/tauri';
+import { tauriService } from './tauriService';
 import { termExtractor } from '../utils/termExtractor';
 import { nlpUtils } from '../utils/nlp';
 
@@ -36,6 +38,52 @@ class SemanticEngine {
     }
   }
 
+  /**
+   * Extract terms from a saved document with the native engine, which scores
+   * them against the whole library. Falls back to in-browser extraction when
+   * the saved text no longer matches `content`.
+   */
+  async extractDocumentTerms(documentId: string, content: string): Promise<SemanticTerm[]> {
+    try {
+      const analysis = await tauriService.analyzeDocument(documentId);
+      const stale = analysis.terms.some(term => {
+        const { char_start, char_end } = term.occurrences[0].offset;
+        return content.slice(char_start, char_end).replace(/\s+/g, ' ').toLowerCase() !== term.term.toLowerCase();
+      });
+      if (stale) {
+        return this.extractTerms(content);
+      }
+      return this.fromDocumentAnalysis(analysis, content);
+    } catch (error) {
+      console.error('Native term extraction failed, falling back:', error);
+      return this.extractTerms(content);
+    }
+  }
+
+  private fromDocumentAnalysis(analysis: DocumentAnalysis, content: string): SemanticTerm[] {
+    // Offsets count code points; they equal string indices unless the text
+    // has characters outside the Basic Multilingual Plane
+    return analysis.terms.map(term => ({
+      id: crypto.randomUUID(),
+      term: term.term,
+      variants: [term.term],
+      category: this.categorizeterm(term.term),
+      context: term.occurrences.map(occurrence => occurrence.context),
+      frequency: term.frequency,
+      positions: term.occurrences.map(({ offset }) => this.positionAt(content, offset.char_start, offset.char_end))
+    }));
+  }
+
+  private positionAt(content: string, start: number, end: number): TermPosition {
+    const lines = content.slice(0, start).split('\n');
+    return {
+      start,
+      end,
+      line: lines.length,
+      column: lines[lines.length - 1].length + 1
+    };
+  }
+
   /**
    * Detect consistency issues in the document
    */
@@ -60,6 +108,44 @@ class SemanticEngine {
     return issues;
   }
 
+  /**
+   * Detect consistency issues in a saved document: violations of the user's
+   * consistency rules, checked natively, followed by the heuristic issues.
+   * Rule issues are skipped when the saved text no longer matches `content`.
+   */
+  async detectDocumentInconsistencies(
+    documentId: string,
+    terms: SemanticTerm[],
+    content: string
+  ): Promise<ConsistencyIssue[]> {
+    const issues = await this.detectInconsistencies(terms, content);
+    try {
+      const report = await tauriService.checkConsistency(documentId);
+      const stale = report.issues.some(issue =>
+        content.slice(issue.offset.char_start, issue.offset.char_end) !== issue.found
+      );
+      if (stale) {
+        return issues;
+      }
+      return [...this.fromConsistencyReport(report, content), ...issues];
+    } catch (error) {
+      console.error('Native consistency check failed:', error);
+      return issues;
+    }
+  }
+
+  private fromConsistencyReport(report: ConsistencyReport, content: string): ConsistencyIssue[] {
+    return report.issues.map((issue): ConsistencyIssue => ({
+      id: crypto.randomUUID(),
+      type: issue.category === 'style' || issue.category === 'punctuation' ? 'style' : 'terminology',
+      severity: issue.severity === 'warning' ? 'medium' : 'low',
+      message: `Use "${issue.replacement}" instead of "${issue.found}"`,
+      position: this.positionAt(content, issue.offset.char_start, issue.offset.char_end),
+      suggestions: [issue.replacement],
+      relatedTerms: [issue.term, issue.found]
+    }));
+  }
+
   /**
    * Generate suggestions based on detected issues
    */
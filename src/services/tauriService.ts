@@ -1,23 +1,54 @@
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import type { 
+  Collection,
+  CollectionDeleteMode,
+  CollectionStats,
   Document, 
+  DocumentListQuery,
+  DocumentPage,
+  MetadataValue,
+  TagInfo,
+  DocumentRevision,
+  RevisionSummary,
+  DiffGranularity,
+  DocumentAnalysis,
+  TextDiff,
   SemanticTerm, 
   ConsistencyRule, 
+  ConsistencyCheckOptions,
+  ConsistencyReport,
+  ConsistencyIssue,
+  FixEdit,
+  FixPlan,
+  AppliedFixes,
   AnalysisCache, 
+  AppError,
   FileInfo, 
   ImportResult, 
+  DirectoryImportOptions,
+  DirectoryImportReport,
+  ImportProgress,
+  EpubMetadata,
+  ExportOptions,
+  RestoreOptions,
+  SnapshotInfo,
+  SnapshotRestoreResult,
+  SnapshotVerification,
+  StorageConfig,
   StorageStats,
+  SearchHit,
   TauriAPI 
 } from '../types/tauri';
 
 class TauriService implements TauriAPI {
   // Document management
-  async createDocument(title: string, content: string): Promise<string> {
-    return await invoke('create_document', { title, content });
+  async createDocument(title: string, content: string, collectionId?: string): Promise<string> {
+    return await invoke('create_document', { title, content, collectionId });
   }
 
-  async updateDocument(id: string, title?: string, content?: string): Promise<void> {
-    return await invoke('update_document', { id, title, content });
+  async updateDocument(id: string, title?: string, content?: string, author?: string): Promise<void> {
+    return await invoke('update_document', { id, title, content, author });
   }
 
   async getDocument(id: string): Promise<Document | null> {
@@ -28,17 +59,118 @@ class TauriService implements TauriAPI {
     return await invoke('list_documents');
   }
 
+  async listDocumentSummaries(query?: DocumentListQuery): Promise<DocumentPage> {
+    return await invoke('list_document_summaries', { query });
+  }
+
   async deleteDocument(id: string): Promise<void> {
     return await invoke('delete_document', { id });
   }
 
+  async searchDocuments(query: string, limit?: number): Promise<SearchHit[]> {
+    return await invoke('search_documents', { query, limit });
+  }
+
+  // Tags and metadata
+  async addDocumentTags(documentId: string, tags: string[]): Promise<string[]> {
+    return await invoke('add_document_tags', { documentId, tags });
+  }
+
+  async removeDocumentTags(documentId: string, tags: string[]): Promise<string[]> {
+    return await invoke('remove_document_tags', { documentId, tags });
+  }
+
+  async listTags(): Promise<TagInfo[]> {
+    return await invoke('list_tags');
+  }
+
+  async renameTag(oldName: string, newName: string): Promise<void> {
+    return await invoke('rename_tag', { oldName, newName });
+  }
+
+  async mergeTags(sources: string[], target: string): Promise<void> {
+    return await invoke('merge_tags', { sources, target });
+  }
+
+  async setDocumentMetadata(documentId: string, key: string, value?: MetadataValue): Promise<Record<string, MetadataValue>> {
+    return await invoke('set_document_metadata', { documentId, key, value });
+  }
+
+  // Collections
+  async createCollection(name: string, parentId?: string): Promise<Collection> {
+    return await invoke('create_collection', { name, parentId });
+  }
+
+  async listCollections(): Promise<Collection[]> {
+    return await invoke('list_collections');
+  }
+
+  async renameCollection(id: string, name: string): Promise<Collection> {
+    return await invoke('rename_collection', { id, name });
+  }
+
+  async moveCollection(id: string, parentId?: string): Promise<Collection> {
+    return await invoke('move_collection', { id, parentId });
+  }
+
+  async deleteCollection(id: string, mode: CollectionDeleteMode): Promise<string[]> {
+    return await invoke('delete_collection', { id, mode });
+  }
+
+  async moveDocument(documentId: string, collectionId: string): Promise<void> {
+    return await invoke('move_document', { documentId, collectionId });
+  }
+
+  async getCollectionStats(id: string): Promise<CollectionStats> {
+    return await invoke('get_collection_stats', { id });
+  }
+
+  // Revision history
+  async listDocumentRevisions(documentId: string): Promise<RevisionSummary[]> {
+    return await invoke('list_document_revisions', { documentId });
+  }
+
+  async getDocumentRevision(revisionId: string): Promise<DocumentRevision | null> {
+    return await invoke('get_document_revision', { revisionId });
+  }
+
+  async diffDocumentRevisions(fromRevisionId: string, toRevisionId: string, granularity?: DiffGranularity): Promise<TextDiff> {
+    return await invoke('diff_document_revisions', { fromRevisionId, toRevisionId, granularity });
+  }
+
+  async restoreDocumentRevision(revisionId: string, author?: string): Promise<Document> {
+    return await invoke('restore_document_revision', { revisionId, author });
+  }
+
   // File operations
-  async importDocument(filePath: string): Promise<ImportResult> {
+  async importDocument(filePath?: string): Promise<ImportResult | null> {
     return await invoke('import_document', { filePath });
   }
 
-  async exportDocument(id: string, exportPath: string): Promise<void> {
-    return await invoke('export_document', { id, exportPath });
+  async importDirectory(dirPath?: string, options?: Partial<DirectoryImportOptions>): Promise<DirectoryImportReport | null> {
+    return await invoke('import_directory', { dirPath, options });
+  }
+
+  // Resolves to an unsubscribe function
+  async onImportProgress(handler: (progress: ImportProgress) => void): Promise<() => void> {
+    return await listen<ImportProgress>('import-progress', (event) => handler(event.payload));
+  }
+
+  async exportDocument(id: string, exportPath?: string, options?: Partial<ExportOptions>): Promise<string | null> {
+    return await invoke('export_document', { id, exportPath, options });
+  }
+
+  async exportEpub(
+    documentIds: string[],
+    collectionId: string | null,
+    metadata: Partial<EpubMetadata> & { title: string },
+    exportPath?: string
+  ): Promise<string | null> {
+    return await invoke('export_epub', { documentIds, collectionId, metadata, exportPath });
+  }
+
+  async convertHtmlToMarkdown(html: string): Promise<string> {
+    return await invoke('convert_html_to_markdown', { html });
   }
 
   async saveFile(path: string, contents: string): Promise<void> {
@@ -53,6 +185,19 @@ class TauriService implements TauriAPI {
     return await invoke('list_files', { dirPath });
   }
 
+  async listWorkspaceFolders(): Promise<string[]> {
+    return await invoke('list_workspace_folders');
+  }
+
+  // Resolves to null when the picker is cancelled
+  async addWorkspaceFolder(): Promise<string | null> {
+    return await invoke('add_workspace_folder');
+  }
+
+  async removeWorkspaceFolder(path: string): Promise<void> {
+    return await invoke('remove_workspace_folder', { path });
+  }
+
   // Semantic analysis
   async saveSemanticTerms(documentId: string, terms: SemanticTerm[]): Promise<void> {
     return await invoke('save_semantic_terms', { documentId, terms });
@@ -62,6 +207,10 @@ class TauriService implements TauriAPI {
     return await invoke('get_semantic_terms', { documentId });
   }
 
+  async analyzeDocument(id: string): Promise<DocumentAnalysis> {
+    return await invoke('analyze_document', { id });
+  }
+
   // Consistency rules
   async saveConsistencyRule(rule: ConsistencyRule): Promise<void> {
     return await invoke('save_consistency_rule', { rule });
@@ -71,6 +220,23 @@ class TauriService implements TauriAPI {
     return await invoke('get_consistency_rules');
   }
 
+  async checkConsistency(documentId: string, options?: ConsistencyCheckOptions): Promise<ConsistencyReport> {
+    return await invoke('check_consistency', { documentId, options });
+  }
+
+  async planFixes(
+    documentId: string,
+    ruleIds?: string[],
+    issues?: ConsistencyIssue[],
+    options?: ConsistencyCheckOptions
+  ): Promise<FixPlan> {
+    return await invoke('plan_fixes', { documentId, ruleIds, issues, options });
+  }
+
+  async applyFixes(documentId: string, contentHash: string, edits: FixEdit[], author?: string): Promise<AppliedFixes> {
+    return await invoke('apply_fixes', { documentId, contentHash, edits, author });
+  }
+
   // Analysis cache
   async saveAnalysisCache(cache: AnalysisCache): Promise<void> {
     return await invoke('save_analysis_cache', { cache });
@@ -89,8 +255,30 @@ class TauriService implements TauriAPI {
     return await invoke('list_backups');
   }
 
-  async restoreFromBackup(backupPath: string): Promise<ImportResult> {
-    return await invoke('restore_from_backup', { backupPath });
+  async restoreFromBackup(backupPath?: string, options?: Partial<RestoreOptions>): Promise<ImportResult | null> {
+    return await invoke('restore_from_backup', { backupPath, options });
+  }
+
+  // Library snapshots
+  async createSnapshot(): Promise<SnapshotInfo> {
+    return await invoke('create_snapshot');
+  }
+
+  async listSnapshots(): Promise<SnapshotInfo[]> {
+    return await invoke('list_snapshots');
+  }
+
+  async verifySnapshot(id: string): Promise<SnapshotVerification> {
+    return await invoke('verify_snapshot', { id });
+  }
+
+  async restoreSnapshot(id: string): Promise<SnapshotRestoreResult> {
+    return await invoke('restore_snapshot', { id });
+  }
+
+  // Fired for scheduled snapshots; resolves to an unsubscribe function
+  async onSnapshotCreated(handler: (snapshot: SnapshotInfo) => void): Promise<() => void> {
+    return await listen<SnapshotInfo>('snapshot-created', (event) => handler(event.payload));
   }
 
   // Utilities
@@ -106,6 +294,19 @@ class TauriService implements TauriAPI {
     return await invoke('clear_document_cache');
   }
 
+  // Settings
+  async getStorageConfig(): Promise<StorageConfig> {
+    return await invoke('get_storage_config');
+  }
+
+  async updateStorageConfig(config: StorageConfig): Promise<StorageConfig> {
+    return await invoke('update_storage_config', { config });
+  }
+
+  async onStorageConfigChanged(handler: (config: StorageConfig) => void): Promise<() => void> {
+    return await listen<StorageConfig>('storage-config-changed', (event) => handler(event.payload));
+  }
+
   // Helper methods
   async openFileDialog(filters?: { name: string; extensions: string[] }[]): Promise<string | null> {
     try {
@@ -176,6 +377,18 @@ class TauriService implements TauriAPI {
   }
 }
 
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === 'object' && error !== null && 'code' in error && 'message_en' in error;
+}
+
+// User-facing text for a rejected command; other errors fall back to their text
+export function errorMessage(error: unknown, language: string = 'en'): string {
+  if (isAppError(error)) {
+    return language.startsWith('zh') ? error.message_zh : error.message_en;
+  }
+  return error instanceof Error ? error.message : String(error);
+}
+
 // Create singleton instance
 export const tauriService = new TauriService();
 
@@ -1,12 +1,14 @@
 import { create } from 'zustand';
 import { devtools, persist } from 'zustand/middleware';
 import type { AppState, Document, SemanticTerm, ConsistencyIssue, Suggestion, AIProvider, AppSettings } from '../types';
-import type { Document as TauriDocument, StorageStats } from '../types/tauri';
-import { tauriService } from '../services/tauriService';
+import type { Document as TauriDocument, StorageConfig, StorageStats } from '../types/tauri';
+import { errorMessage, tauriService } from '../services/tauriService';
 
 interface AppStore extends AppState {
   // Storage stats
   storageStats: StorageStats | null;
+  // Backend settings, loaded from config.json
+  storageConfig: StorageConfig | null;
   
   // Document actions
   setCurrentDocument: (document: Document | null) => void;
@@ -58,6 +60,9 @@ interface AppStore extends AppState {
   // Storage management
   loadStorageStats: () => Promise<void>;
   clearCache: () => Promise<void>;
+  loadStorageConfig: () => Promise<void>;
+  setStorageConfig: (config: StorageConfig) => void;
+  updateStorageConfig: (updates: Partial<StorageConfig>) => Promise<void>;
   
   // Backup operations
   createBackup: (documentId: string) => Promise<string>;
@@ -89,7 +94,7 @@ const convertTauriDocument = (tauriDoc: TauriDocument): Document => ({
   format: 'markdown' as const,
   metadata: {
     wordCount: tauriDoc.word_count,
-    characterCount: tauriDoc.content.length,
+    characterCount: tauriDoc.char_count,
     tags: [],
     language: 'en'
   }
@@ -110,6 +115,7 @@ export const useAppStore = create<AppStore>()(
         isAnalyzing: false,
         settings: defaultSettings,
         storageStats: null,
+        storageConfig: null,
 
         // Document actions
         setCurrentDocument: (document) => 
@@ -223,36 +229,22 @@ export const useAppStore = create<AppStore>()(
         // File operations
         importDocument: async (filePath) => {
           try {
-            const path = filePath || await tauriService.openFileDialog([
-              { name: 'Text Files', extensions: ['txt', 'md', 'markdown', 'docx'] },
-              { name: 'All Files', extensions: ['*'] }
-            ]);
-            
-            if (path) {
-              const result = await tauriService.importDocument(path);
-              if (result.success) {
-                await get().loadDocuments();
-                await tauriService.showInfoDialog('Success', result.message);
-              } else {
-                await tauriService.showErrorDialog('Import Failed', result.message);
-              }
+            // Without a path the backend asks with a native dialog
+            const result = await tauriService.importDocument(filePath);
+            if (result) {
+              await get().loadDocuments();
+              await tauriService.showInfoDialog('Success', result.message);
             }
           } catch (error) {
             console.error('Failed to import document:', error);
-            await tauriService.showErrorDialog('Error', 'Failed to import document');
+            await tauriService.showErrorDialog('Import Failed', errorMessage(error, get().settings.language));
           }
         },
 
         exportDocument: async (id, exportPath) => {
           try {
-            const path = exportPath || await tauriService.saveFileDialog(undefined, [
-              { name: 'Markdown Files', extensions: ['md'] },
-              { name: 'Text Files', extensions: ['txt'] },
-              { name: 'All Files', extensions: ['*'] }
-            ]);
-            
+            const path = await tauriService.exportDocument(id, exportPath);
             if (path) {
-              await tauriService.exportDocument(id, path);
               await tauriService.showInfoDialog('Success', 'Document exported successfully');
             }
           } catch (error) {
@@ -407,6 +399,30 @@ export const useAppStore = create<AppStore>()(
           }
         },
 
+        loadStorageConfig: async () => {
+          try {
+            const config = await tauriService.getStorageConfig();
+            set({ storageConfig: config }, false, 'loadStorageConfig');
+          } catch (error) {
+            console.error('Failed to load storage config:', error);
+          }
+        },
+
+        setStorageConfig: (config) =>
+          set({ storageConfig: config }, false, 'setStorageConfig'),
+
+        updateStorageConfig: async (updates) => {
+          const current = get().storageConfig ?? await tauriService.getStorageConfig();
+          try {
+            const config = await tauriService.updateStorageConfig({ ...current, ...updates });
+            set({ storageConfig: config }, false, 'updateStorageConfig');
+          } catch (error) {
+            console.error('Failed to update storage config:', error);
+            await tauriService.showErrorDialog('Error', `Failed to save settings: ${errorMessage(error, get().settings.language)}`);
+            throw error;
+          }
+        },
+
         // Backup operations
         createBackup: async (documentId) => {
           try {
@@ -422,23 +438,14 @@ export const useAppStore = create<AppStore>()(
 
         restoreFromBackup: async (backupPath) => {
           try {
-            const path = backupPath || await tauriService.openFileDialog([
-              { name: 'Backup Files', extensions: ['backup.md'] },
-              { name: 'All Files', extensions: ['*'] }
-            ]);
-            
-            if (path) {
-              const result = await tauriService.restoreFromBackup(path);
-              if (result.success) {
-                await get().loadDocuments();
-                await tauriService.showInfoDialog('Success', result.message);
-              } else {
-                await tauriService.showErrorDialog('Restore Failed', result.message);
-              }
+            const result = await tauriService.restoreFromBackup(backupPath);
+            if (result) {
+              await get().loadDocuments();
+              await tauriService.showInfoDialog('Success', result.message);
             }
           } catch (error) {
             console.error('Failed to restore from backup:', error);
-            await tauriService.showErrorDialog('Error', 'Failed to restore from backup');
+            await tauriService.showErrorDialog('Restore Failed', errorMessage(error, get().settings.language));
           }
         },
       }),
@@ -6,7 +6,115 @@ export interface Document {
   file_path?: string;
   created_at: string;
   updated_at: string;
+  word_count: number; // cjk_word_count + latin_word_count
+  char_count: number; // not counting whitespace
+  cjk_word_count: number;
+  latin_word_count: number;
+  collection_id: string;
+  tags: string[];
+  metadata: Record<string, MetadataValue>;
+}
+
+export type MetadataValue =
+  | { type: 'text'; value: string }
+  | { type: 'number'; value: number }
+  | { type: 'boolean'; value: boolean }
+  | { type: 'date'; value: string };
+
+export interface MetadataPredicate {
+  key: string;
+  op: 'eq' | 'ne' | 'lt' | 'lte' | 'gt' | 'gte' | 'contains' | 'exists' | 'not_exists';
+  value?: MetadataValue;
+}
+
+export interface TagInfo {
+  id: string;
+  name: string;
+  document_count: number;
+}
+
+export interface Collection {
+  id: string;
+  name: string;
+  parent_id?: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export type CollectionDeleteMode = 'move_to_parent' | 'cascade';
+
+export interface CollectionStats {
+  collection_id: string;
+  document_count: number;
+  total_words: number;
+  total_characters: number;
+  subcollection_count: number;
+}
+
+export interface DocumentSummary {
+  id: string;
+  title: string;
+  excerpt: string;
+  file_path?: string;
+  created_at: string;
+  updated_at: string;
   word_count: number;
+  collection_id: string;
+  tags: string[];
+}
+
+export interface DocumentListQuery {
+  offset?: number;
+  limit?: number; // at most 500
+  sort_by?: 'updated_at' | 'created_at' | 'title' | 'word_count';
+  sort_order?: 'asc' | 'desc';
+  title_prefix?: string;
+  created_after?: string;
+  created_before?: string;
+  updated_after?: string;
+  updated_before?: string;
+  min_words?: number;
+  max_words?: number;
+  collection_id?: string;
+  include_subcollections?: boolean;
+  all_tags?: string[];
+  any_tags?: string[];
+  exclude_tags?: string[];
+  metadata?: MetadataPredicate[];
+}
+
+export interface DocumentPage {
+  items: DocumentSummary[];
+  total: number;
+  offset: number;
+  limit: number;
+}
+
+export interface DocumentRevision {
+  id: string;
+  document_id: string;
+  revision_number: number;
+  title: string;
+  content: string;
+  author?: string;
+  word_count: number;
+  created_at: string;
+}
+
+export type RevisionSummary = Omit<DocumentRevision, 'content'>;
+
+export type DiffGranularity = 'line' | 'word';
+
+export interface DiffChange {
+  kind: 'equal' | 'insert' | 'delete';
+  text: string;
+}
+
+export interface TextDiff {
+  granularity: DiffGranularity;
+  changes: DiffChange[];
+  insertions: number;
+  deletions: number;
 }
 
 export interface SemanticTerm {
@@ -17,17 +125,123 @@ export interface SemanticTerm {
   position: number;
   confidence: number;
   created_at: string;
+  offset?: MatchOffset | null; // missing for terms saved by older versions
+}
+
+export interface TermOccurrence {
+  offset: MatchOffset;
+  context: string;
+}
+
+export interface ExtractedTerm {
+  term: string; // the most frequent spelling in the document
+  frequency: number;
+  c_value: number;
+  tf_idf: number;
+  confidence: number; // 0-1, relative to the best term in the document
+  occurrences: TermOccurrence[];
+}
+
+// Result of `analyze_document`
+export interface DocumentAnalysis {
+  document_id: string;
+  content_hash: string;
+  analyzed_at: string;
+  token_count: number;
+  terms: ExtractedTerm[]; // best first
+  cached: boolean; // served from the analysis cache
 }
 
+// Which documents a rule applies to; a collection includes its subcollections
+export type RuleScope =
+  | { type: 'global' }
+  | { type: 'collection'; id: string }
+  | { type: 'document'; id: string };
+
+export type RuleCategory = 'terminology' | 'spelling' | 'style' | 'punctuation' | 'other';
+
 export interface ConsistencyRule {
   id: string;
   term: string;
   preferred_form: string;
-  alternatives: string; // JSON array of alternative forms
+  alternatives: string[];
+  pattern?: string | null; // regex matched in addition to the alternatives
+  replacement?: string | null; // template with $1 or ${name}, default preferred_form; needs a pattern
+  scope?: RuleScope; // default global
+  category?: RuleCategory; // default terminology
+  priority?: number; // -1000..1000, default 0; the higher one wins where fixes overlap
   is_active: boolean;
   created_at: string;
 }
 
+export interface ConsistencyCheckOptions {
+  case_sensitive?: boolean; // default false
+  whole_word?: boolean; // Latin matches may not start or end inside a word, default true
+  cjk_boundaries?: boolean; // CJK matches must align with segmented words, default true
+}
+
+export type ConsistencySeverity = 'warning' | 'info'; // info: only the letter case differs
+
+export interface ConsistencyIssue {
+  rule_id: string;
+  term: string;
+  found: string; // as written in the document
+  preferred_form: string;
+  replacement: string; // the preferred form, or the expanded pattern replacement
+  severity: ConsistencySeverity;
+  category: RuleCategory;
+  priority: number;
+  offset: MatchOffset;
+  context: string;
+}
+
+// Result of `check_consistency`
+export interface ConsistencyReport {
+  document_id: string;
+  content_hash: string;
+  checked_at: string;
+  rule_count: number;
+  issues: ConsistencyIssue[]; // in document order
+  cached: boolean; // served from the analysis cache
+}
+
+// One replacement planned by `plan_fixes`; pass the selected ones to `apply_fixes`
+export interface FixEdit {
+  id: string; // rule id and byte range, stable for unchanged content
+  rule_id: string;
+  offset: MatchOffset;
+  original: string;
+  replacement: string;
+  priority: number;
+  before: string; // context around the original text
+  after: string; // the same context with the replacement
+}
+
+export interface SkippedFix {
+  rule_id: string;
+  offset: MatchOffset;
+  original: string;
+  replacement: string;
+  conflicts_with: string | null; // the overlapping planned edit; null when the text no longer matches
+}
+
+// Non-overlapping edits: higher priority, then the longer match, then the earlier one wins
+export interface FixPlan {
+  document_id: string;
+  content_hash: string; // apply_fixes refuses edits planned for other content
+  edits: FixEdit[]; // in document order
+  skipped: SkippedFix[];
+}
+
+export interface AppliedFixes {
+  document_id: string;
+  content: string;
+  content_hash: string;
+  revision_id: string;
+  revision_number: number;
+  applied: number;
+}
+
 export interface AnalysisCache {
   id: string;
   document_id: string;
@@ -44,44 +258,279 @@ export interface FileInfo {
   extension?: string;
 }
 
+export interface HtmlExportOptions {
+  standalone: boolean; // full page, otherwise a body fragment
+  embed_stylesheet: boolean;
+}
+
+export type PageSize =
+  | 'a4'
+  | 'a5'
+  | 'letter'
+  | 'legal'
+  | { custom: { width_mm: number; height_mm: number } };
+
+export interface PdfExportOptions {
+  page_size: PageSize;
+  margins: { top_mm: number; right_mm: number; bottom_mm: number; left_mm: number };
+  font_size: number;
+  font_path?: string; // a CJK-capable system font is used when unset
+  title_page: boolean;
+  page_numbers: boolean;
+}
+
+export interface LatexExportOptions {
+  document_class: 'article' | 'report';
+  font_size_pt: number; // 10, 11 or 12
+  paper: string; // e.g. 'a4paper'
+  cjk: boolean; // loads ctex; compile with XeLaTeX
+  table_of_contents: boolean;
+  bibliography?: string; // .bib file for [@key] citations
+  bibliography_style: string;
+  standalone: boolean;
+}
+
+export interface PandocExportOptions {
+  include_timestamps: boolean;
+  include_tags: boolean;
+  include_metadata: boolean;
+}
+
+export type ExportFormat = 'markdown' | 'pandoc_markdown' | 'latex' | 'html' | 'pdf' | 'docx' | 'text';
+
+export interface ExportOptions {
+  format?: ExportFormat; // picked from the file extension when unset
+  html: Partial<HtmlExportOptions>;
+  pdf: Partial<PdfExportOptions>;
+  latex: Partial<LatexExportOptions>;
+  pandoc: Partial<PandocExportOptions>;
+}
+
+export interface EpubMetadata {
+  title: string;
+  author?: string;
+  language: string; // BCP 47, defaults to 'zh-CN'
+  description?: string;
+  cover_image_path?: string;
+  identifier?: string;
+}
+
 export interface ImportResult {
-  success: boolean;
+  success: boolean; // always true; failures reject with an AppError
   document_id?: string;
   message: string;
 }
 
+export interface DirectoryImportOptions {
+  include: string[]; // globs relative to the folder; empty takes every supported file
+  exclude: string[]; // matching folders are skipped entirely
+  recursive: boolean;
+  include_hidden: boolean;
+  skip_duplicates: boolean; // compared by content hash
+  collection_id?: string;
+  max_file_size_mb: number; // 0 = no limit
+}
+
+export type ImportFormat = 'markdown' | 'text' | 'docx' | 'html';
+
+export type FileImportStatus = 'imported' | 'duplicate' | 'unsupported' | 'failed';
+
+export interface FileImportReport {
+  path: string;
+  status: FileImportStatus;
+  document_id?: string; // the existing document for duplicates
+  format?: ImportFormat;
+  encoding?: string;
+  message: string;
+  error_code?: ErrorCode; // set for failed files
+}
+
+export interface DirectoryImportReport {
+  root: string;
+  total: number;
+  imported: number;
+  duplicates: number;
+  unsupported: number;
+  failed: number;
+  files: FileImportReport[];
+}
+
+// Payload of the `import-progress` event
+export interface ImportProgress {
+  current: number;
+  total: number;
+  file: FileImportReport;
+}
+
+export interface RestoreOptions {
+  restore_original_id: boolean; // otherwise restored as a new document
+}
+
+export type SnapshotTrigger = 'manual' | 'scheduled' | 'pre_restore';
+
+export interface SnapshotInfo {
+  id: string;
+  path: string;
+  created_at: string;
+  trigger: SnapshotTrigger;
+  size: number; // bytes
+  document_count: number;
+  schema_version: number;
+}
+
+export interface SnapshotVerification {
+  id: string;
+  valid: boolean;
+  problems: string[];
+}
+
+export interface SnapshotRestoreResult {
+  restored: SnapshotInfo;
+  safety_snapshot: SnapshotInfo; // the library as it was before the restore
+}
+
+export interface SnapshotRetention {
+  hourly: number;
+  daily: number;
+  weekly: number;
+}
+
+// Persisted in config.json in the app data directory
+export interface StorageConfig {
+  app_data_dir: string; // read-only
+  auto_save_interval: number; // seconds, 1-3600
+  max_backups: number; // backup files kept per document, 0-1000, 0 = no cap
+  max_snapshots: number; // library snapshots kept at most, 0-1000, 0 = no cap
+  cache_size_limit: number; // MB, 1-4096
+  max_revisions: number; // per document, 0 = unlimited
+  revision_retention_days: number; // 0 = keep forever
+  snapshot_interval_hours: number; // 0 = off
+  snapshot_retention: SnapshotRetention;
+  keep_backup_files: boolean; // keep `<name>.bak` when saving or exporting over a file
+}
+
 export interface StorageStats {
   total_documents: number;
   total_words: number;
   total_characters: number;
   cached_documents: number;
+  cache_bytes: number;
+  cache_capacity_bytes: number; // cache_size_limit in bytes
+  cache_hits: number;
+  cache_misses: number;
+  cache_evictions: number;
   database_path: string;
   app_data_dir: string;
 }
 
+export interface MatchOffset {
+  start: number; // byte offset
+  end: number;
+  char_start: number;
+  char_end: number;
+}
+
+export interface SearchHit {
+  document_id: string;
+  title: string;
+  snippet: string; // HTML-escaped, matches wrapped in <mark>
+  score: number;
+  title_matches: MatchOffset[];
+  content_matches: MatchOffset[];
+}
+
+// Stable codes; match on these rather than on message text
+export type ErrorCode =
+  | 'NOT_FOUND'
+  | 'INVALID_INPUT'
+  | 'CONFLICT'
+  | 'ACCESS_DENIED'
+  | 'UNSUPPORTED_FORMAT'
+  | 'CONVERSION_FAILED'
+  | 'CORRUPT_DATA'
+  | 'DISK_FULL'
+  | 'BUSY'
+  | 'PERMISSION_DENIED'
+  | 'FILE_NOT_FOUND'
+  | 'IO_ERROR'
+  | 'DATABASE_ERROR'
+  | 'LOCK_POISONED'
+  | 'INTERNAL';
+
+export type ErrorCategory = 'validation' | 'not_found' | 'conflict' | 'storage' | 'corruption' | 'conversion' | 'internal';
+
+// What every failing command rejects with
+export interface AppError {
+  code: ErrorCode;
+  category: ErrorCategory;
+  message_en: string;
+  message_zh: string;
+  details: string[]; // technical chain, outermost context first
+}
+
 // Tauri command interfaces
 export interface TauriAPI {
   // Document management
-  createDocument: (title: string, content: string) => Promise<string>;
-  updateDocument: (id: string, title?: string, content?: string) => Promise<void>;
+  createDocument: (title: string, content: string, collectionId?: string) => Promise<string>;
+  updateDocument: (id: string, title?: string, content?: string, author?: string) => Promise<void>;
   getDocument: (id: string) => Promise<Document | null>;
   listDocuments: () => Promise<Document[]>;
+  listDocumentSummaries: (query?: DocumentListQuery) => Promise<DocumentPage>;
   deleteDocument: (id: string) => Promise<void>;
+  searchDocuments: (query: string, limit?: number) => Promise<SearchHit[]>;
+
+  // Tags and metadata
+  addDocumentTags: (documentId: string, tags: string[]) => Promise<string[]>;
+  removeDocumentTags: (documentId: string, tags: string[]) => Promise<string[]>;
+  listTags: () => Promise<TagInfo[]>;
+  renameTag: (oldName: string, newName: string) => Promise<void>;
+  mergeTags: (sources: string[], target: string) => Promise<void>;
+  setDocumentMetadata: (documentId: string, key: string, value?: MetadataValue) => Promise<Record<string, MetadataValue>>;
+
+  // Collections
+  createCollection: (name: string, parentId?: string) => Promise<Collection>;
+  listCollections: () => Promise<Collection[]>;
+  renameCollection: (id: string, name: string) => Promise<Collection>;
+  moveCollection: (id: string, parentId?: string) => Promise<Collection>;
+  deleteCollection: (id: string, mode: CollectionDeleteMode) => Promise<string[]>;
+  moveDocument: (documentId: string, collectionId: string) => Promise<void>;
+  getCollectionStats: (id: string) => Promise<CollectionStats>;
+
+  // Revision history
+  listDocumentRevisions: (documentId: string) => Promise<RevisionSummary[]>;
+  getDocumentRevision: (revisionId: string) => Promise<DocumentRevision | null>;
+  diffDocumentRevisions: (fromRevisionId: string, toRevisionId: string, granularity?: DiffGranularity) => Promise<TextDiff>;
+  restoreDocumentRevision: (revisionId: string, author?: string) => Promise<Document>;
 
   // File operations
-  importDocument: (filePath: string) => Promise<ImportResult>;
-  exportDocument: (id: string, exportPath: string) => Promise<void>;
+  // Paths must lie in the files folder or a workspace folder; without one a
+  // native dialog asks the user, and the promise resolves to null if cancelled
+  importDocument: (filePath?: string) => Promise<ImportResult | null>;
+  importDirectory: (dirPath?: string, options?: Partial<DirectoryImportOptions>) => Promise<DirectoryImportReport | null>;
+  onImportProgress: (handler: (progress: ImportProgress) => void) => Promise<() => void>;
+  exportDocument: (id: string, exportPath?: string, options?: Partial<ExportOptions>) => Promise<string | null>; // the path written
+  exportEpub: (documentIds: string[], collectionId: string | null, metadata: Partial<EpubMetadata> & { title: string }, exportPath?: string) => Promise<string | null>;
+  convertHtmlToMarkdown: (html: string) => Promise<string>;
   saveFile: (path: string, contents: string) => Promise<void>;
   readFile: (path: string) => Promise<string>;
   listFiles: (dirPath: string) => Promise<string[]>;
+  // Raw file access is limited to the files folder in app data and these folders
+  listWorkspaceFolders: () => Promise<string[]>;
+  addWorkspaceFolder: () => Promise<string | null>; // opens a folder picker
+  removeWorkspaceFolder: (path: string) => Promise<void>;
 
   // Semantic analysis
   saveSemanticTerms: (documentId: string, terms: SemanticTerm[]) => Promise<void>;
   getSemanticTerms: (documentId: string) => Promise<SemanticTerm[]>;
+  // Extracts, stores and caches the terms of the saved document
+  analyzeDocument: (id: string) => Promise<DocumentAnalysis>;
 
   // Consistency rules
   saveConsistencyRule: (rule: ConsistencyRule) => Promise<void>;
   getConsistencyRules: () => Promise<ConsistencyRule[]>;
+  checkConsistency: (documentId: string, options?: ConsistencyCheckOptions) => Promise<ConsistencyReport>;
+  planFixes: (documentId: string, ruleIds?: string[], issues?: ConsistencyIssue[], options?: ConsistencyCheckOptions) => Promise<FixPlan>;
+  applyFixes: (documentId: string, contentHash: string, edits: FixEdit[], author?: string) => Promise<AppliedFixes>;
 
   // Analysis cache
   saveAnalysisCache: (cache: AnalysisCache) => Promise<void>;
@@ -90,10 +539,22 @@ export interface TauriAPI {
   // Backup operations
   createBackup: (documentId: string) => Promise<string>;
   listBackups: () => Promise<FileInfo[]>;
-  restoreFromBackup: (backupPath: string) => Promise<ImportResult>;
+  restoreFromBackup: (backupPath?: string, options?: Partial<RestoreOptions>) => Promise<ImportResult | null>; // also accepts list_backups paths
+
+  // Library snapshots
+  createSnapshot: () => Promise<SnapshotInfo>;
+  listSnapshots: () => Promise<SnapshotInfo[]>;
+  verifySnapshot: (id: string) => Promise<SnapshotVerification>;
+  restoreSnapshot: (id: string) => Promise<SnapshotRestoreResult>;
+  onSnapshotCreated: (handler: (snapshot: SnapshotInfo) => void) => Promise<() => void>;
 
   // Utilities
   calculateContentHash: (content: string) => Promise<string>;
   getStorageStats: () => Promise<StorageStats>;
   clearDocumentCache: () => Promise<void>;
+
+  // Settings
+  getStorageConfig: () => Promise<StorageConfig>;
+  updateStorageConfig: (config: StorageConfig) => Promise<StorageConfig>;
+  onStorageConfigChanged: (handler: (config: StorageConfig) => void) => Promise<() => void>;
 }
\ No newline at end of file